@@ -0,0 +1,200 @@
+use crate::{
+    fs::{EventKind, Watcher},
+    runtime::{BlockingClass, JoinHandle, blocking, spawn_local},
+    utils::watch,
+};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+/// The entries of a mirrored directory, by file name.
+pub type DirEntries = BTreeMap<OsString, EntryInfo>;
+
+/// What a [`DirMirror`] knows about one directory entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// Whether the entry is a directory, following symlinks.
+    pub is_dir: bool,
+    /// Size in bytes.
+    pub len: u64,
+    /// Last modification time, where the filesystem records one.
+    pub modified: Option<SystemTime>,
+}
+
+/// An in-memory copy of a directory's entries, kept current by inotify.
+///
+/// A background task applies each [`Watcher`] event to the snapshot,
+/// stat-ing changed entries on the blocking pool, and publishes the result
+/// through a [`watch`] channel, so subscribers see only the latest state
+/// and are woken once per burst of changes. A queue overflow triggers a
+/// full rescan. Once the directory itself goes away the snapshot is
+/// emptied and the channel closed.
+///
+/// Only direct entries are mirrored, not the contents of subdirectories.
+/// Dropping the mirror stops the task.
+#[derive(Debug)]
+pub struct DirMirror {
+    path: PathBuf,
+    rx: watch::Receiver<Rc<DirEntries>>,
+    task: JoinHandle<()>,
+}
+
+impl DirMirror {
+    /// Start mirroring `path`, returning once the first scan is complete.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside [`LocalExecutor::block_on`](crate::runtime::LocalExecutor::block_on).
+    pub async fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+
+        // Watch before scanning, so nothing changes unseen in between.
+        let mut watcher = Watcher::new()?;
+        watcher.watch(&path)?;
+        let entries = scan(path.clone()).await?;
+
+        let (tx, rx) = watch::channel(Rc::new(entries));
+        let task = spawn_local(follow(path.clone(), watcher, tx));
+
+        Ok(Self { path, rx, task })
+    }
+
+    /// The mirrored directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The entries as of the last applied change.
+    pub fn snapshot(&self) -> Rc<DirEntries> {
+        self.rx.borrow().clone()
+    }
+
+    /// A receiver notified after each change to the snapshot.
+    pub fn subscribe(&self) -> watch::Receiver<Rc<DirEntries>> {
+        let mut rx = self.rx.clone();
+        rx.borrow_and_update();
+        rx
+    }
+}
+
+impl Drop for DirMirror {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn follow(path: PathBuf, mut watcher: Watcher, tx: watch::Sender<Rc<DirEntries>>) {
+    let mut entries = tx.borrow().clone();
+
+    while let Ok(event) = watcher.next_event().await {
+        let name = event.path.file_name().map(OsString::from);
+
+        match (event.kind, name) {
+            (EventKind::Overflow, _) => match scan(path.clone()).await {
+                Ok(fresh) => entries = Rc::new(fresh),
+                Err(_) => break,
+            },
+            (EventKind::Unwatched, _) => break,
+            // The directory itself was deleted or moved; its watch ends next.
+            (_, _) if event.path == path => continue,
+            (EventKind::Delete | EventKind::MovedFrom { .. }, Some(name)) => {
+                if Rc::make_mut(&mut entries).remove(&name).is_none() {
+                    continue;
+                }
+            }
+            (_, Some(name)) => match stat(event.path.clone()).await {
+                Ok(info) => {
+                    if entries.get(&name) == Some(&info) {
+                        continue;
+                    }
+                    Rc::make_mut(&mut entries).insert(name, info);
+                }
+                // Gone again before we got to it; its delete event follows.
+                Err(_) => continue,
+            },
+            (_, None) => continue,
+        }
+
+        tx.send(entries.clone());
+    }
+
+    tx.send(Rc::default());
+}
+
+async fn scan(path: PathBuf) -> io::Result<DirEntries> {
+    blocking::run_in(BlockingClass::Metadata, move || {
+        let mut entries = DirEntries::new();
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+
+            // Skip entries removed mid-scan; their events are queued.
+            if let Ok(metadata) = fs::metadata(entry.path()) {
+                entries.insert(entry.file_name(), EntryInfo::from(&metadata));
+            }
+        }
+
+        Ok(entries)
+    })
+    .await
+}
+
+async fn stat(path: PathBuf) -> io::Result<EntryInfo> {
+    blocking::run_in(BlockingClass::Metadata, move || {
+        fs::metadata(path).map(|m| EntryInfo::from(&m))
+    })
+    .await
+}
+
+impl From<&fs::Metadata> for EntryInfo {
+    fn from(metadata: &fs::Metadata) -> Self {
+        Self {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir, time::timeout};
+    use std::{ffi::OsStr, time::Duration};
+
+    #[test]
+    fn tracks_created_changed_and_removed_entries() {
+        let dir = TempDir::new();
+        std::fs::write(dir.join("a"), "1").unwrap();
+
+        LocalExecutor::new().block_on(async {
+            let mirror = DirMirror::new(dir.path()).await.unwrap();
+            let mut rx = mirror.subscribe();
+            assert_eq!(mirror.snapshot().len(), 1);
+
+            std::fs::write(dir.join("b"), "22").unwrap();
+            std::fs::create_dir(dir.join("sub")).unwrap();
+            std::fs::remove_file(dir.join("a")).unwrap();
+
+            let expected = |entries: &DirEntries| {
+                entries.keys().collect::<Vec<_>>() == ["b", "sub"]
+                    && entries[OsStr::new("b")].len == 2
+                    && entries[OsStr::new("sub")].is_dir
+            };
+
+            while !expected(&rx.borrow_and_update()) {
+                timeout(Duration::from_secs(5), rx.changed())
+                    .await
+                    .expect("mirror did not catch up")
+                    .unwrap();
+            }
+
+            assert!(expected(&mirror.snapshot()));
+        });
+    }
+}
@@ -0,0 +1,364 @@
+use crate::{
+    io::AsyncWrite,
+    runtime::{
+        BlockingClass,
+        blocking::{self, Blocking},
+    },
+};
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    fs::{self, Mode, OFlags},
+};
+use std::{
+    fmt,
+    future::{Future, poll_fn},
+    io,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+/// A file whose I/O runs on the blocking thread pool.
+///
+/// Regular files are always "ready" to epoll, so their reads and writes
+/// would block the executor; each operation here is instead handed to the
+/// pool behind [`spawn_blocking`](crate::runtime::spawn_blocking) and
+/// awaited, copying data through a buffer owned by that operation.
+///
+/// [`read`](Self::read) and [`write`](Self::write) use a cursor kept by
+/// the `File` and advanced only when an operation completes, so dropping
+/// one of their futures leaves the cursor unchanged. A dropped write may
+/// still reach the file, as the pool finishes whatever it started.
+///
+/// As an [`AsyncWrite`], for instance behind an
+/// [`io::BufWriter`](crate::io::BufWriter), a `File` accepts each write
+/// right away and runs it in the background, one at a time. Failures are
+/// reported by the next write or flush, so flush before relying on the data;
+/// the other methods wait for a write in flight before they start.
+pub struct File {
+    fd: Arc<OwnedFd>,
+    pos: u64,
+    /// The write started by the last [`AsyncWrite::poll_write`], if it has
+    /// not been awaited yet.
+    in_flight: Option<Blocking<()>>,
+}
+
+impl File {
+    /// Open an existing file for reading.
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with(path.as_ref(), OFlags::RDONLY).await
+    }
+
+    /// Open a file for writing, creating it or truncating it.
+    pub async fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with(
+            path.as_ref(),
+            OFlags::WRONLY | OFlags::CREATE | OFlags::TRUNC,
+        )
+        .await
+    }
+
+    async fn open_with(path: &Path, flags: OFlags) -> io::Result<Self> {
+        let path = path.to_owned();
+
+        let fd = blocking::run_in(BlockingClass::Metadata, move || {
+            fs::open(&path, flags | OFlags::CLOEXEC, Mode::from_raw_mode(0o666))
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+        })
+        .await?;
+
+        Ok(Self::from(fd))
+    }
+
+    /// Read at the cursor, returning how many bytes were read; 0 at the end.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.settle().await?;
+        let n = self.pread(buf, self.pos).await?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    /// Write at the cursor, returning how many bytes were written.
+    pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.settle().await?;
+        let n = self.pwrite(buf, self.pos).await?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    /// Write all of `buf` at the cursor.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read at `offset`, leaving the cursor alone.
+    pub async fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.settle().await?;
+        self.pread(buf, offset).await
+    }
+
+    /// Write at `offset`, leaving the cursor alone.
+    pub async fn write_at(&mut self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.settle().await?;
+        self.pwrite(buf, offset).await
+    }
+
+    async fn pread(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let fd = self.fd.clone();
+        let len = buf.len();
+
+        let data = blocking::run(move || {
+            let mut data = vec![0; len];
+            let n = rustix::io::pread(&fd, &mut data[..], offset)
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+            data.truncate(n);
+            Ok::<_, io::Error>(data)
+        })
+        .await?;
+
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    async fn pwrite(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let fd = self.fd.clone();
+        let data = buf.to_vec();
+
+        blocking::run(move || {
+            rustix::io::pwrite(&fd, &data, offset)
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+        })
+        .await
+    }
+
+    /// Flush data and metadata to the device.
+    pub async fn sync_all(&mut self) -> io::Result<()> {
+        self.settle().await?;
+        let fd = self.fd.clone();
+
+        blocking::run(move || {
+            fs::fsync(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+        })
+        .await
+    }
+
+    /// Flush data to the device, skipping metadata not needed to read it.
+    pub async fn sync_data(&mut self) -> io::Result<()> {
+        self.settle().await?;
+        let fd = self.fd.clone();
+
+        blocking::run(move || {
+            fs::fdatasync(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+        })
+        .await
+    }
+
+    /// Current cursor position.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Move the cursor to `pos` bytes from the start.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// The fd, for work that outlives this borrow of the file.
+    pub(crate) fn shared_fd(&self) -> Arc<OwnedFd> {
+        self.fd.clone()
+    }
+
+    /// Wait for the write in flight, if any.
+    async fn settle(&mut self) -> io::Result<()> {
+        poll_fn(|cx| self.poll_settle(cx)).await
+    }
+
+    fn poll_settle(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Some(write) = &mut self.in_flight else {
+            return Poll::Ready(Ok(()));
+        };
+
+        let result = ready!(Pin::new(write).poll(cx));
+        self.in_flight = None;
+        Poll::Ready(result)
+    }
+}
+
+impl AsyncWrite for File {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(self.poll_settle(cx))?;
+
+        let fd = self.fd.clone();
+        let data = buf.to_vec();
+        let mut offset = self.pos;
+
+        self.in_flight = Some(blocking::run(move || {
+            let mut data = &data[..];
+
+            while !data.is_empty() {
+                match rustix::io::pwrite(&fd, data, offset) {
+                    Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                    Ok(n) => {
+                        data = &data[n..];
+                        offset += n as u64;
+                    }
+                    Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+                }
+            }
+
+            Ok(())
+        }));
+        self.pos += buf.len() as u64;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_settle(cx)
+    }
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("File")
+            .field("fd", &self.fd)
+            .field("pos", &self.pos)
+            .field("writing", &self.in_flight.is_some())
+            .finish()
+    }
+}
+
+impl From<OwnedFd> for File {
+    /// Wrap an open file, with the cursor at the start.
+    fn from(fd: OwnedFd) -> Self {
+        Self {
+            fd: Arc::new(fd),
+            pos: 0,
+            in_flight: None,
+        }
+    }
+}
+
+impl AsFd for File {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        io::{BufWriter, FlushPolicy},
+        runtime::LocalExecutor,
+        test_util::TempDir,
+    };
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let dir = TempDir::new();
+        let path = dir.join("data");
+
+        LocalExecutor::new().block_on(async {
+            let mut file = File::create(&path).await.unwrap();
+            file.write_all(b"hello world").await.unwrap();
+            assert_eq!(file.position(), 11);
+
+            let mut file = File::open(&path).await.unwrap();
+            let mut buf = [0; 5];
+            assert_eq!(file.read_at(&mut buf, 6).await.unwrap(), 5);
+            assert_eq!(&buf, b"world");
+            assert_eq!(file.position(), 0);
+
+            assert_eq!(file.read(&mut buf).await.unwrap(), 5);
+            assert_eq!(&buf, b"hello");
+            assert_eq!(file.position(), 5);
+        });
+    }
+
+    #[test]
+    fn positioned_io_waits_for_background_write() {
+        let dir = TempDir::new();
+        let path = dir.join("data");
+
+        LocalExecutor::new().block_on(async {
+            let fd = fs::open(
+                &path,
+                OFlags::RDWR | OFlags::CREATE | OFlags::CLOEXEC,
+                Mode::from_raw_mode(0o600),
+            )
+            .unwrap();
+            let mut file = File::from(fd);
+            let data = vec![b'a'; 1 << 20];
+
+            // Accepted, but still running on the pool.
+            assert_eq!(
+                poll_fn(|cx| file.poll_write(cx, &data)).await.unwrap(),
+                data.len()
+            );
+
+            let mut buf = [0; 4];
+            assert_eq!(file.read_at(&mut buf, (1 << 20) - 4).await.unwrap(), 4);
+            assert_eq!(&buf, b"aaaa");
+            assert!(file.in_flight.is_none());
+
+            assert_eq!(
+                poll_fn(|cx| file.poll_write(cx, &data)).await.unwrap(),
+                data.len()
+            );
+            // Lands over the start of the background write, not under it.
+            file.write_at(b"bb", 1 << 20).await.unwrap();
+            assert!(file.in_flight.is_none());
+            file.sync_data().await.unwrap();
+        });
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(contents.len(), 2 << 20);
+        assert_eq!(&contents[..3], b"aaa");
+        assert_eq!(&contents[1 << 20..(1 << 20) + 3], b"bba");
+    }
+
+    #[test]
+    fn works_behind_buf_writer() {
+        let dir = TempDir::new();
+        let path = dir.join("log");
+
+        LocalExecutor::new().block_on(async {
+            let file = File::create(&path).await.unwrap();
+            let writer = BufWriter::new(
+                file,
+                FlushPolicy {
+                    max_bytes: 4,
+                    max_delay: None,
+                },
+            );
+
+            for line in ["one\n", "two\n", "three\n"] {
+                writer.write(line.as_bytes()).await.unwrap();
+            }
+            writer.flush().await.unwrap();
+        });
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn write_error_surfaces_on_flush() {
+        LocalExecutor::new().block_on(async {
+            let mut file =
+                File::from(fs::open("/dev/full", OFlags::WRONLY, Mode::empty()).unwrap());
+
+            assert_eq!(poll_fn(|cx| file.poll_write(cx, b"x")).await.unwrap(), 1);
+            let err = crate::io::flush(&mut file).await.unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::ENOSPC));
+        });
+    }
+}
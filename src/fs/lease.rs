@@ -0,0 +1,249 @@
+use crate::{fs::File, reactor::AsyncFd};
+use std::{
+    cell::OnceCell,
+    fmt, io, mem,
+    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd},
+    rc::Rc,
+};
+
+// Not exported by libc for every Linux target; the values are the same
+// on all of them.
+const F_SETSIG: libc::c_int = 10;
+const F_SETOWN_EX: libc::c_int = 15;
+const F_OWNER_TID: libc::c_int = 0;
+
+#[repr(C)]
+struct FOwnerEx {
+    kind: libc::c_int,
+    pid: libc::pid_t,
+}
+
+thread_local! {
+    static SIGNALS: OnceCell<Rc<AsyncFd<OwnedFd>>> = const { OnceCell::new() };
+}
+
+/// The kind of lease held by a [`FileLease`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseKind {
+    /// Broken when another process opens the file for writing or truncates it.
+    Read,
+    /// Broken when another process opens the file at all.
+    Write,
+}
+
+impl LeaseKind {
+    fn lock_type(self) -> libc::c_int {
+        match self {
+            Self::Read => libc::F_RDLCK,
+            Self::Write => libc::F_WRLCK,
+        }
+    }
+}
+
+/// An `F_SETLEASE` lease on a [`File`], with async notice of lease breaks.
+///
+/// When another process wants an access the lease does not allow, the
+/// kernel holds it off for `/proc/sys/fs/lease-break-time` seconds and
+/// signals the holder, which should [`release`](Self::release) or
+/// [`downgrade`](Self::downgrade) the lease before then. The signal is
+/// `SIGIO`, directed at the thread that took the lease and read through
+/// a signalfd on its reactor; [`broken`](Self::broken) resolves once it
+/// arrives for this lease.
+///
+/// The first lease on a thread blocks `SIGIO` for that thread for good,
+/// so the signal stays queued for the signalfd instead of killing the
+/// process. Leases must be awaited on the thread that took them.
+pub struct FileLease {
+    file: Option<File>,
+    kind: LeaseKind,
+    signals: Rc<AsyncFd<OwnedFd>>,
+}
+
+impl FileLease {
+    /// Take a lease on `file`.
+    ///
+    /// A read lease needs the file opened read-only, a write lease needs
+    /// it to be opened by no other fd; both need the caller to own the
+    /// file or have `CAP_LEASE`.
+    pub fn acquire(file: File, kind: LeaseKind) -> io::Result<Self> {
+        let signals = signals()?;
+        let fd = file.as_fd().as_raw_fd();
+        let owner = FOwnerEx {
+            kind: F_OWNER_TID,
+            // SAFETY: gettid has no preconditions.
+            pid: unsafe { libc::gettid() },
+        };
+
+        // SAFETY: Plain fcntl calls on an open fd; `owner` outlives the call.
+        unsafe {
+            check(libc::fcntl(fd, F_SETSIG, libc::SIGIO))?;
+            check(libc::fcntl(fd, F_SETOWN_EX, &owner as *const FOwnerEx))?;
+            check(libc::fcntl(fd, libc::F_SETLEASE, kind.lock_type()))?;
+        }
+
+        Ok(Self {
+            file: Some(file),
+            kind,
+            signals,
+        })
+    }
+
+    /// The kind of lease held.
+    pub fn kind(&self) -> LeaseKind {
+        self.kind
+    }
+
+    /// The leased file.
+    pub fn file(&self) -> &File {
+        self.file.as_ref().expect("present until drop")
+    }
+
+    /// Whether a break has been requested, or the lease already revoked.
+    pub fn is_broken(&self) -> io::Result<bool> {
+        let fd = self.file().as_fd().as_raw_fd();
+        // SAFETY: A plain fcntl call on an open fd.
+        let held = check(unsafe { libc::fcntl(fd, libc::F_GETLEASE) })?;
+
+        // During a break the kernel reports the lease type it will leave.
+        Ok(held != self.kind.lock_type())
+    }
+
+    /// Wait until the lease is being broken.
+    pub async fn broken(&self) -> io::Result<()> {
+        loop {
+            if self.is_broken()? {
+                return Ok(());
+            }
+
+            // Every lease on the thread is woken by a signal, whichever fd
+            // it was for, and checks its own state again.
+            let mut guard = self.signals.readable().await;
+            let _ = guard.try_io(drain_signals);
+        }
+    }
+
+    /// Turn a write lease into a read lease, e.g. to answer a break by a
+    /// reader.
+    pub fn downgrade(&mut self) -> io::Result<()> {
+        let fd = self.file().as_fd().as_raw_fd();
+        // SAFETY: A plain fcntl call on an open fd.
+        check(unsafe { libc::fcntl(fd, libc::F_SETLEASE, libc::F_RDLCK) })?;
+        self.kind = LeaseKind::Read;
+        Ok(())
+    }
+
+    /// Give the lease up, letting a pending break proceed, and return the file.
+    pub fn release(mut self) -> File {
+        self.unlock();
+        self.file.take().expect("present until drop")
+    }
+
+    fn unlock(&self) {
+        if let Some(file) = &self.file {
+            // SAFETY: A plain fcntl call on an open fd.
+            unsafe { libc::fcntl(file.as_fd().as_raw_fd(), libc::F_SETLEASE, libc::F_UNLCK) };
+        }
+    }
+}
+
+impl Drop for FileLease {
+    fn drop(&mut self) {
+        self.unlock();
+    }
+}
+
+impl fmt::Debug for FileLease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLease")
+            .field("file", &self.file)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+/// This thread's `SIGIO` signalfd, created on first use.
+fn signals() -> io::Result<Rc<AsyncFd<OwnedFd>>> {
+    SIGNALS.with(|cell| {
+        if let Some(signals) = cell.get() {
+            return Ok(signals.clone());
+        }
+
+        // SAFETY: The set is initialised by sigemptyset before use, and
+        // signalfd returns a fresh fd that nothing else owns.
+        let fd = unsafe {
+            let mut set: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGIO);
+
+            match libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) {
+                0 => {}
+                e => return Err(io::Error::from_raw_os_error(e)),
+            }
+
+            let fd = check(libc::signalfd(
+                -1,
+                &set,
+                libc::SFD_NONBLOCK | libc::SFD_CLOEXEC,
+            ))?;
+            OwnedFd::from_raw_fd(fd)
+        };
+
+        let signals = Rc::new(AsyncFd::new(fd)?);
+        Ok(cell.get_or_init(|| signals).clone())
+    })
+}
+
+/// Read queued signals until none are left.
+fn drain_signals(fd: &OwnedFd) -> io::Result<()> {
+    let mut buf = [0u8; mem::size_of::<libc::signalfd_siginfo>() * 8];
+
+    loop {
+        rustix::io::read(fd, &mut buf)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+    }
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        ret => Ok(ret),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir, time::timeout};
+    use std::{fs::OpenOptions, os::unix::fs::OpenOptionsExt, thread, time::Duration};
+
+    #[test]
+    fn writer_breaks_a_read_lease() {
+        let dir = TempDir::new();
+        let path = dir.join("leased");
+        std::fs::write(&path, "data").unwrap();
+
+        LocalExecutor::new().block_on(async {
+            let file = File::open(&path).await.unwrap();
+            let lease = FileLease::acquire(file, LeaseKind::Read).unwrap();
+            assert!(!lease.is_broken().unwrap());
+
+            let writer = thread::spawn(move || {
+                // Non-blocking, so the open fails with EWOULDBLOCK after
+                // starting the break instead of waiting for it.
+                OpenOptions::new()
+                    .write(true)
+                    .custom_flags(libc::O_NONBLOCK)
+                    .open(path)
+            });
+
+            timeout(Duration::from_secs(5), lease.broken())
+                .await
+                .expect("no lease break noticed")
+                .unwrap();
+
+            let err = writer.join().unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+            drop(lease.release());
+        });
+    }
+}
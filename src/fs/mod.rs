@@ -0,0 +1,76 @@
+mod dir_mirror;
+mod file;
+mod lease;
+mod spill;
+mod sync_batcher;
+mod tail;
+mod watcher;
+
+pub use dir_mirror::{DirEntries, DirMirror, EntryInfo};
+pub use file::File;
+pub use lease::{FileLease, LeaseKind};
+pub use spill::{SpillBuffer, SpillReader};
+pub use sync_batcher::SyncBatcher;
+pub use tail::{Tail, tail};
+pub use watcher::{Event, EventKind, WatchId, Watcher};
+
+use crate::runtime::{BlockingClass, blocking};
+use std::{fs::Metadata, io, path::Path};
+
+/// Query the metadata of `path`, following symlinks, on the blocking pool.
+///
+/// Runs as a [`BlockingClass::Metadata`] job, so it is not held up by
+/// large reads and writes.
+pub async fn metadata(path: impl AsRef<Path>) -> io::Result<Metadata> {
+    let path = path.as_ref().to_owned();
+    blocking::run_in(BlockingClass::Metadata, move || std::fs::metadata(path)).await
+}
+
+/// Read the whole file at `path`, on the blocking pool.
+pub async fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let path = path.as_ref().to_owned();
+    blocking::run(move || std::fs::read(path)).await
+}
+
+/// Read the whole file at `path` as UTF-8, on the blocking pool.
+pub async fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    let path = path.as_ref().to_owned();
+    blocking::run(move || std::fs::read_to_string(path)).await
+}
+
+/// Write `contents` to the file at `path`, creating or truncating it, on
+/// the blocking pool.
+///
+/// The data is copied first, so the write completes even if the returned
+/// future is dropped once started.
+pub async fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref().to_owned();
+    let contents = contents.as_ref().to_vec();
+    blocking::run(move || std::fs::write(path, contents)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir};
+
+    #[test]
+    fn convenience_functions_round_trip() {
+        let dir = TempDir::new();
+        let path = dir.join("data");
+
+        LocalExecutor::new().block_on(async {
+            write(&path, "héllo").await.unwrap();
+            assert_eq!(read(&path).await.unwrap(), "héllo".as_bytes());
+            assert_eq!(read_to_string(&path).await.unwrap(), "héllo");
+
+            write(&path, [0xff]).await.unwrap();
+            let err = read_to_string(&path).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            let err = read(dir.join("missing")).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            assert_eq!(metadata(&path).await.unwrap().len(), 1);
+        });
+    }
+}
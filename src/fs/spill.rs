@@ -0,0 +1,282 @@
+use crate::{
+    fs::File,
+    io::AsyncRead,
+    runtime::{
+        BlockingClass,
+        blocking::{self, Blocking},
+    },
+};
+use rustix::{
+    fd::OwnedFd,
+    fs::{self, Mode, OFlags},
+};
+use std::{
+    fmt,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+/// Bytes fetched from the spill file per blocking read.
+const CHUNK: usize = 64 * 1024;
+
+/// A write-then-replay buffer that moves to disk once it grows too large.
+///
+/// Data stays in memory up to `threshold` bytes. The write that would go
+/// past it moves everything into an unlinked `O_TMPFILE` in the spill
+/// directory, and all later writes go there through a [`File`], so the
+/// pool does the disk I/O. The file has no name and disappears when the
+/// buffer is dropped, even if the process dies.
+///
+/// [`reader`](Self::reader) replays the contents from the start as an
+/// [`AsyncRead`], as often as needed.
+pub struct SpillBuffer {
+    threshold: usize,
+    dir: PathBuf,
+    memory: Vec<u8>,
+    file: Option<File>,
+    len: u64,
+}
+
+impl SpillBuffer {
+    /// Create an empty buffer spilling to [`std::env::temp_dir`] beyond
+    /// `threshold` bytes.
+    pub fn new(threshold: usize) -> Self {
+        Self::with_dir(threshold, std::env::temp_dir())
+    }
+
+    /// Create an empty buffer spilling into `dir` beyond `threshold` bytes.
+    ///
+    /// The filesystem under `dir` must support `O_TMPFILE`.
+    pub fn with_dir(threshold: usize, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            threshold,
+            dir: dir.into(),
+            memory: Vec::new(),
+            file: None,
+            len: 0,
+        }
+    }
+
+    /// Append all of `data`, spilling to disk if this crosses the threshold.
+    pub async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.file.is_none() && self.memory.len() + data.len() > self.threshold {
+            let mut file = File::from(open_tmpfile(&self.dir).await?);
+            file.write_all(&self.memory).await?;
+            self.memory = Vec::new();
+            self.file = Some(file);
+        }
+
+        match &mut self.file {
+            Some(file) => file.write_all(data).await?,
+            None => self.memory.extend_from_slice(data),
+        }
+
+        self.len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Number of bytes written so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the contents have moved to disk.
+    pub fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Read the contents back from the start.
+    pub fn reader(&self) -> SpillReader<'_> {
+        let source = match &self.file {
+            Some(file) => Source::File {
+                fd: file.shared_fd(),
+                in_flight: None,
+                chunk: Vec::new(),
+                consumed: 0,
+            },
+            None => Source::Memory(&self.memory),
+        };
+
+        SpillReader {
+            source,
+            pos: 0,
+            len: self.len,
+        }
+    }
+}
+
+impl fmt::Debug for SpillBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpillBuffer")
+            .field("threshold", &self.threshold)
+            .field("dir", &self.dir)
+            .field("len", &self.len)
+            .field("spilled", &self.is_spilled())
+            .finish()
+    }
+}
+
+async fn open_tmpfile(dir: &Path) -> io::Result<OwnedFd> {
+    let dir = dir.to_owned();
+
+    blocking::run_in(BlockingClass::Metadata, move || {
+        fs::open(
+            &dir,
+            OFlags::TMPFILE | OFlags::RDWR | OFlags::CLOEXEC,
+            Mode::from_raw_mode(0o600),
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    })
+    .await
+}
+
+/// Replays a [`SpillBuffer`]; returned by [`SpillBuffer::reader`].
+pub struct SpillReader<'a> {
+    source: Source<'a>,
+    pos: u64,
+    len: u64,
+}
+
+enum Source<'a> {
+    Memory(&'a [u8]),
+    File {
+        fd: Arc<OwnedFd>,
+        in_flight: Option<Blocking<Vec<u8>>>,
+        /// The last chunk read from the file, served from `consumed` on.
+        chunk: Vec<u8>,
+        consumed: usize,
+    },
+}
+
+impl fmt::Debug for SpillReader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpillReader")
+            .field("pos", &self.pos)
+            .field("len", &self.len)
+            .field("spilled", &matches!(self.source, Source::File { .. }))
+            .finish()
+    }
+}
+
+impl AsyncRead for SpillReader<'_> {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() || self.pos >= self.len {
+            return Poll::Ready(Ok(0));
+        }
+
+        let n = match &mut self.source {
+            Source::Memory(data) => {
+                let rest = &data[self.pos as usize..];
+                let n = rest.len().min(buf.len());
+                buf[..n].copy_from_slice(&rest[..n]);
+                n
+            }
+            Source::File {
+                fd,
+                in_flight,
+                chunk,
+                consumed,
+            } => {
+                if *consumed == chunk.len() {
+                    let job = in_flight.get_or_insert_with(|| {
+                        let fd = fd.clone();
+                        let offset = self.pos;
+                        let want = (self.len - offset).min(CHUNK as u64) as usize;
+
+                        blocking::run(move || {
+                            let mut data = vec![0; want];
+                            let n = rustix::io::pread(&fd, &mut data[..], offset)
+                                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+                            data.truncate(n);
+                            Ok(data)
+                        })
+                    });
+
+                    let result = ready!(Pin::new(job).poll(cx));
+                    *in_flight = None;
+                    *chunk = result?;
+                    *consumed = 0;
+
+                    if chunk.is_empty() {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                }
+
+                let rest = &chunk[*consumed..];
+                let n = rest.len().min(buf.len());
+                buf[..n].copy_from_slice(&rest[..n]);
+                *consumed += n;
+                n
+            }
+        };
+
+        self.pos += n as u64;
+        Poll::Ready(Ok(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io, runtime::LocalExecutor, test_util::TempDir};
+
+    async fn read_all(buffer: &SpillBuffer) -> Vec<u8> {
+        let mut reader = buffer.reader();
+        let mut out = Vec::new();
+        let mut buf = [0; 7];
+
+        loop {
+            match io::read(&mut reader, &mut buf).await.unwrap() {
+                0 => return out,
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn stays_in_memory_below_threshold() {
+        LocalExecutor::new().block_on(async {
+            let mut buffer = SpillBuffer::new(16);
+            buffer.write_all(b"hello ").await.unwrap();
+            buffer.write_all(b"world").await.unwrap();
+
+            assert!(!buffer.is_spilled());
+            assert_eq!(buffer.len(), 11);
+            assert_eq!(read_all(&buffer).await, b"hello world");
+        });
+    }
+
+    #[test]
+    fn spills_past_threshold_and_replays() {
+        let dir = TempDir::new();
+
+        LocalExecutor::new().block_on(async {
+            let mut buffer = SpillBuffer::with_dir(8, dir.path());
+            buffer.write_all(b"hello ").await.unwrap();
+            assert!(!buffer.is_spilled());
+
+            buffer.write_all(b"world").await.unwrap();
+            let big = vec![b'x'; CHUNK + 3];
+            buffer.write_all(&big).await.unwrap();
+            assert!(buffer.is_spilled());
+
+            let mut expected = b"hello world".to_vec();
+            expected.extend_from_slice(&big);
+            assert_eq!(read_all(&buffer).await, expected);
+            // Replays from the start each time.
+            assert_eq!(read_all(&buffer).await.len(), expected.len());
+        });
+
+        // The spill file was never linked into the directory.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    fs::File,
+    runtime::{blocking, spawn_local},
+    time::sleep,
+    utils::{tracked_cell::TrackedRefCell, waiters::WaiterList},
+};
+use rustix::{
+    fd::{AsFd, OwnedFd},
+    fs,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Identifies what one sync covers: an inode, and whether only its data.
+type Key = (u64, u64, bool);
+
+/// Coalesces `fsync` requests into one syscall per file and window.
+///
+/// The first request for a file opens a batch that closes `window` later;
+/// the sync then runs once on the blocking pool, and every request in the
+/// batch resolves with its result. Requests made while that sync runs join
+/// the next batch, since it may not cover data they wrote. Files are told
+/// apart by inode, so requests through different [`File`]s of one file
+/// share a sync.
+///
+/// This trades up to `window` of added latency for far fewer syncs when
+/// many writers commit at once, as in a journal.
+pub struct SyncBatcher {
+    window: Duration,
+    pending: Rc<TrackedRefCell<HashMap<Key, Rc<TrackedRefCell<Batch>>>>>,
+}
+
+#[derive(Default)]
+struct Batch {
+    /// The errno of a failed sync, once done.
+    result: Option<Result<(), i32>>,
+    waiters: WaiterList,
+}
+
+impl SyncBatcher {
+    /// Batch requests arriving within `window` of the first.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Rc::default(),
+        }
+    }
+
+    /// Flush `file`'s data and metadata to the device, like
+    /// [`File::sync_all`], sharing the syscall with other requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside [`LocalExecutor::block_on`](crate::runtime::LocalExecutor::block_on).
+    pub async fn sync_all(&self, file: &File) -> io::Result<()> {
+        self.sync(file, false)?.await
+    }
+
+    /// Flush `file`'s data to the device, like [`File::sync_data`],
+    /// sharing the syscall with other requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside [`LocalExecutor::block_on`](crate::runtime::LocalExecutor::block_on).
+    pub async fn sync_data(&self, file: &File) -> io::Result<()> {
+        self.sync(file, true)?.await
+    }
+
+    /// Join the open batch for `file`, opening one if there is none.
+    fn sync(&self, file: &File, data_only: bool) -> io::Result<Wait> {
+        let stat =
+            fs::fstat(file.as_fd()).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        let key = (stat.st_dev, stat.st_ino, data_only);
+
+        let batch = self
+            .pending
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| {
+                let batch = Rc::default();
+                drop(spawn_local(run_batch(
+                    self.pending.clone(),
+                    key,
+                    file.shared_fd(),
+                    self.window,
+                )));
+                batch
+            })
+            .clone();
+
+        Ok(Wait { batch, key: None })
+    }
+
+    /// The batching window.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+/// Close the batch for `key` after `window` and sync it.
+async fn run_batch(
+    pending: Rc<TrackedRefCell<HashMap<Key, Rc<TrackedRefCell<Batch>>>>>,
+    key: Key,
+    fd: Arc<OwnedFd>,
+    window: Duration,
+) {
+    sleep(window).await;
+
+    let Some(batch) = pending.borrow_mut().remove(&key) else {
+        return;
+    };
+
+    let result = blocking::run(move || {
+        match key.2 {
+            true => fs::fdatasync(&fd),
+            false => fs::fsync(&fd),
+        }
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    })
+    .await
+    .map_err(|e| e.raw_os_error().unwrap_or(libc::EIO));
+
+    let waiters = {
+        let mut batch = batch.borrow_mut();
+        batch.result = Some(result);
+        batch.waiters.take_all()
+    };
+
+    for waiter in waiters {
+        waiter.wake();
+    }
+}
+
+/// A request waiting for its batch to be synced.
+struct Wait {
+    batch: Rc<TrackedRefCell<Batch>>,
+    key: Option<u64>,
+}
+
+impl Future for Wait {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let mut batch = this.batch.borrow_mut();
+
+        match batch.result {
+            Some(result) => Poll::Ready(result.map_err(io::Error::from_raw_os_error)),
+            None => {
+                batch.waiters.register(&mut this.key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Wait {
+    fn drop(&mut self) {
+        self.batch.borrow_mut().waiters.remove(self.key);
+    }
+}
+
+impl fmt::Debug for SyncBatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncBatcher")
+            .field("window", &self.window)
+            .field("open_batches", &self.pending.borrow().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir};
+
+    #[test]
+    fn concurrent_requests_share_a_batch() {
+        let dir = TempDir::new();
+
+        LocalExecutor::new().block_on(async {
+            let batcher = SyncBatcher::new(Duration::from_millis(5));
+            let mut a = File::create(dir.join("a")).await.unwrap();
+            let b = File::open(dir.join("a")).await.unwrap();
+            let c = File::create(dir.join("c")).await.unwrap();
+            a.write_all(b"data").await.unwrap();
+
+            let (ra, rb, rc, rd) = crate::join!(
+                batcher.sync_data(&a),
+                batcher.sync_data(&b),
+                batcher.sync_data(&c),
+                async {
+                    assert_eq!(batcher.pending.borrow().len(), 2);
+                    batcher.sync_all(&a).await
+                },
+            );
+
+            ra.unwrap();
+            rb.unwrap();
+            rc.unwrap();
+            rd.unwrap();
+            assert!(batcher.pending.borrow().is_empty());
+        });
+    }
+
+    #[test]
+    fn sync_errors_reach_every_request() {
+        LocalExecutor::new().block_on(async {
+            let batcher = SyncBatcher::new(Duration::from_millis(1));
+            let (reader, _writer) = rustix::pipe::pipe().unwrap();
+            let pipe = File::from(reader);
+
+            let (a, b) = crate::join!(batcher.sync_all(&pipe), batcher.sync_all(&pipe));
+            assert_eq!(a.unwrap_err().raw_os_error(), Some(libc::EINVAL));
+            assert_eq!(b.unwrap_err().raw_os_error(), Some(libc::EINVAL));
+        });
+    }
+}
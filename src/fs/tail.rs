@@ -0,0 +1,312 @@
+use crate::fs::{EventKind, Watcher};
+use rustix::{
+    fd::OwnedFd,
+    fs::{self, Mode, OFlags},
+    io::Errno,
+};
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    future::poll_fn,
+    io,
+    path::{Path, PathBuf},
+    task::{Context, Poll, ready},
+};
+
+/// Bytes read from the file per call.
+const CHUNK: usize = 8 * 1024;
+
+/// Follow `path` like `tail -f`, yielding lines appended from now on.
+///
+/// See [`Tail`].
+pub fn tail(path: impl AsRef<Path>) -> io::Result<Tail> {
+    Tail::new(path.as_ref())
+}
+
+/// Lines appended to a file, such as another daemon's log.
+///
+/// The file's directory is watched through inotify and new data is read at
+/// the followed position, so nothing is polled on a timer. Truncation, as
+/// done by `copytruncate` rotation, restarts from the beginning. When the
+/// path is renamed away or deleted, the old file is read to its end and the
+/// file that next appears at the path is followed from its beginning.
+///
+/// Lines are decoded lossily as UTF-8 without their line terminator. A
+/// final line without one is held back until it is completed, unless the
+/// file is replaced.
+#[derive(Debug)]
+pub struct Tail {
+    path: PathBuf,
+    name: OsString,
+    watcher: Watcher,
+    file: Option<Followed>,
+    partial: Vec<u8>,
+    lines: VecDeque<String>,
+    buf: Box<[u8]>,
+}
+
+#[derive(Debug)]
+struct Followed {
+    fd: OwnedFd,
+    /// Device and inode, to notice the path being replaced.
+    id: (u64, u64),
+    pos: u64,
+}
+
+impl Tail {
+    fn new(path: &Path) -> io::Result<Self> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+            .to_owned();
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        // Watch before opening, so no append between the two is missed.
+        let mut watcher = Watcher::new()?;
+        watcher.watch(dir)?;
+
+        let file = match Followed::open(path) {
+            Ok(mut file) => {
+                file.pos = fs::fstat(&file.fd)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?
+                    .st_size as u64;
+                Some(file)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            name,
+            watcher,
+            file,
+            partial: Vec::new(),
+            lines: VecDeque::new(),
+            buf: vec![0; CHUNK].into_boxed_slice(),
+        })
+    }
+
+    /// The followed path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wait for the next appended line.
+    pub async fn next_line(&mut self) -> io::Result<String> {
+        poll_fn(|cx| self.poll_next_line(cx)).await
+    }
+
+    /// Poll-based [`next_line`](Self::next_line).
+    pub fn poll_next_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<String>> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return Poll::Ready(Ok(line));
+            }
+
+            if self.read_more()? {
+                continue;
+            }
+
+            // Every change after the read above is still queued in inotify.
+            loop {
+                let event = ready!(self.watcher.poll_next_event(cx))?;
+
+                if event.kind == EventKind::Overflow
+                    || event.path.file_name() == Some(self.name.as_os_str())
+                {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Read new data or switch files; `false` if there was nothing to do.
+    fn read_more(&mut self) -> io::Result<bool> {
+        let current = match fs::stat(&self.path) {
+            Ok(stat) => Some((stat.st_dev, stat.st_ino)),
+            Err(Errno::NOENT) => None,
+            Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        };
+
+        if let Some(file) = &mut self.file {
+            let size = fs::fstat(&file.fd)
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?
+                .st_size as u64;
+
+            if size < file.pos {
+                file.pos = 0;
+                self.partial.clear();
+            }
+
+            let n = rustix::io::pread(&file.fd, &mut self.buf[..], file.pos)
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+            if n > 0 {
+                file.pos += n as u64;
+                self.push(n);
+                return Ok(true);
+            }
+
+            if current == Some(file.id) {
+                return Ok(false);
+            }
+
+            // Replaced or removed, and read to the end.
+            self.file = None;
+
+            if !self.partial.is_empty() {
+                let line = String::from_utf8_lossy(&self.partial).into_owned();
+                self.lines.push_back(line);
+                self.partial.clear();
+            }
+        }
+
+        if current.is_some() {
+            match Followed::open(&self.path) {
+                Ok(file) => {
+                    self.file = Some(file);
+                    return Ok(true);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(!self.lines.is_empty())
+    }
+
+    /// Split the first `n` bytes of the buffer into complete lines.
+    fn push(&mut self, n: usize) {
+        let mut data = &self.buf[..n];
+
+        while let Some(end) = data.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&data[..end]);
+
+            if self.partial.last() == Some(&b'\r') {
+                self.partial.pop();
+            }
+
+            let line = String::from_utf8_lossy(&self.partial).into_owned();
+            self.lines.push_back(line);
+            self.partial.clear();
+            data = &data[end + 1..];
+        }
+
+        self.partial.extend_from_slice(data);
+    }
+}
+
+/// Never ends; the file is followed until the stream is dropped.
+#[cfg(feature = "futures")]
+impl futures_core::Stream for Tail {
+    type Item = io::Result<String>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<String>>> {
+        self.get_mut().poll_next_line(cx).map(Some)
+    }
+}
+
+impl Followed {
+    /// Open the file at `path`, positioned at its start.
+    fn open(path: &Path) -> io::Result<Self> {
+        let fd = fs::open(path, OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        let stat = fs::fstat(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            fd,
+            id: (stat.st_dev, stat.st_ino),
+            pos: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir, time::timeout};
+    use std::{fs::OpenOptions, io::Write, time::Duration};
+
+    fn append(path: &Path, data: &[u8]) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data).unwrap();
+    }
+
+    async fn next(tail: &mut Tail) -> String {
+        timeout(Duration::from_secs(5), tail.next_line())
+            .await
+            .expect("no line within 5s")
+            .unwrap()
+    }
+
+    #[test]
+    fn yields_appended_lines_only() {
+        let dir = TempDir::new();
+        let path = dir.join("log");
+        append(&path, b"old\n");
+
+        LocalExecutor::new().block_on(async {
+            let mut tail = tail(&path).unwrap();
+
+            append(&path, b"one\r\ntw");
+            assert_eq!(next(&mut tail).await, "one");
+
+            // The partial line is held back until completed.
+            let pending = timeout(Duration::from_millis(20), tail.next_line()).await;
+            assert!(pending.is_err());
+
+            append(&path, b"o\nthree\n");
+            assert_eq!(next(&mut tail).await, "two");
+            assert_eq!(next(&mut tail).await, "three");
+        });
+    }
+
+    #[test]
+    fn restarts_after_truncation() {
+        let dir = TempDir::new();
+        let path = dir.join("log");
+        append(&path, b"old line\n");
+
+        LocalExecutor::new().block_on(async {
+            let mut tail = tail(&path).unwrap();
+
+            std::fs::write(&path, b"new\n").unwrap();
+            assert_eq!(next(&mut tail).await, "new");
+        });
+    }
+
+    #[test]
+    fn follows_replaced_and_created_files() {
+        let dir = TempDir::new();
+        let path = dir.join("log");
+
+        LocalExecutor::new().block_on(async {
+            // Not there yet.
+            let mut tail = tail(&path).unwrap();
+
+            append(&path, b"first\n");
+            assert_eq!(next(&mut tail).await, "first");
+
+            // The rotated file is read to its end, including a final
+            // unterminated line, before the new one is followed.
+            append(&path, b"last");
+            std::fs::rename(&path, dir.join("log.1")).unwrap();
+            append(&path, b"fresh\n");
+
+            assert_eq!(next(&mut tail).await, "last");
+            assert_eq!(next(&mut tail).await, "fresh");
+        });
+    }
+}
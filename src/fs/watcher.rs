@@ -0,0 +1,349 @@
+use crate::reactor::AsyncFd;
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    fs::inotify::{self, CreateFlags, ReadFlags, Reader, WatchFlags},
+    io::Errno,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsStr,
+    future::poll_fn,
+    io,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    task::{Context, Poll, ready},
+};
+
+/// Bytes read from inotify at once; room for at least 15 maximal events.
+const BUF_SIZE: usize = 4096;
+
+/// Watches files and directories for changes through inotify.
+///
+/// A watched directory reports changes to its direct entries; a watched
+/// file reports changes to itself. Editors often replace a file by
+/// renaming a new one over it, which ends a watch on the file itself, so
+/// config files are best watched through their directory, reacting to
+/// [`EventKind::CloseWrite`] and [`EventKind::MovedTo`] for their path
+/// (taking a lock with [`Flock::lock_async`](crate::utils::flock::Flock::lock_async)
+/// before re-reading it).
+#[derive(Debug)]
+pub struct Watcher {
+    fd: AsyncFd<OwnedFd>,
+    watches: HashMap<i32, PathBuf>,
+    pending: VecDeque<Event>,
+    buf: Box<[MaybeUninit<u8>]>,
+}
+
+/// Identifies a watch added with [`Watcher::watch`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct WatchId(i32);
+
+/// A change reported by a [`Watcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// What happened.
+    pub kind: EventKind,
+    /// The affected path: an entry of a watched directory, or the watched
+    /// path itself.
+    pub path: PathBuf,
+    /// Whether the affected path is a directory.
+    pub is_dir: bool,
+    /// The watch that reported the change.
+    pub watch: WatchId,
+}
+
+/// The kind of an [`Event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// An entry was created in a watched directory.
+    Create,
+    /// A file was written to.
+    Modify,
+    /// A file opened for writing was closed, typically after a complete update.
+    CloseWrite,
+    /// An entry was deleted, or the watched path itself was.
+    Delete,
+    /// An entry was moved away, or the watched path itself was.
+    ///
+    /// The matching [`MovedTo`](Self::MovedTo), if the destination is
+    /// watched too, carries the same cookie. Moves of the watched path
+    /// itself have a cookie of 0.
+    MovedFrom {
+        /// Pairs the two halves of a rename.
+        cookie: u32,
+    },
+    /// An entry was moved into a watched directory.
+    MovedTo {
+        /// Pairs the two halves of a rename.
+        cookie: u32,
+    },
+    /// The watch ended, because of [`Watcher::unwatch`] or because the
+    /// watched path was deleted or unmounted. No more events follow for it.
+    Unwatched,
+    /// The kernel's event queue overflowed and events were lost.
+    ///
+    /// The event's path is empty; rescan whatever is being tracked.
+    Overflow,
+}
+
+impl Watcher {
+    /// Create a watcher registered with this thread's reactor.
+    pub fn new() -> io::Result<Self> {
+        let fd = inotify::init(CreateFlags::CLOEXEC | CreateFlags::NONBLOCK)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            watches: HashMap::new(),
+            pending: VecDeque::new(),
+            buf: vec![MaybeUninit::uninit(); BUF_SIZE].into_boxed_slice(),
+        })
+    }
+
+    /// Start watching `path`.
+    ///
+    /// Watching the same path again returns the same [`WatchId`].
+    pub fn watch(&mut self, path: impl AsRef<Path>) -> io::Result<WatchId> {
+        let path = path.as_ref();
+
+        let flags = WatchFlags::CREATE
+            | WatchFlags::MODIFY
+            | WatchFlags::CLOSE_WRITE
+            | WatchFlags::DELETE
+            | WatchFlags::DELETE_SELF
+            | WatchFlags::MOVE
+            | WatchFlags::MOVE_SELF;
+
+        let wd = inotify::add_watch(self.fd.get_ref(), path, flags)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        self.watches.insert(wd, path.to_owned());
+
+        Ok(WatchId(wd))
+    }
+
+    /// Stop watching; an [`EventKind::Unwatched`] event follows.
+    pub fn unwatch(&mut self, id: WatchId) -> io::Result<()> {
+        inotify::remove_watch(self.fd.get_ref(), id.0)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    /// The path a watch was added for, while it is active.
+    pub fn path(&self, id: WatchId) -> Option<&Path> {
+        self.watches.get(&id.0).map(PathBuf::as_path)
+    }
+
+    /// Wait for the next change.
+    pub async fn next_event(&mut self) -> io::Result<Event> {
+        poll_fn(|cx| self.poll_next_event(cx)).await
+    }
+
+    /// Poll-based [`next_event`](Self::next_event).
+    pub fn poll_next_event(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Event>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Ok(event));
+            }
+
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            let result = ready.try_io(|fd| {
+                let mut reader = Reader::new(fd, &mut self.buf);
+
+                loop {
+                    let event = match reader.next() {
+                        Ok(event) => event,
+                        Err(Errno::AGAIN) => return Err(io::ErrorKind::WouldBlock.into()),
+                        Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+                    };
+
+                    let name = event.file_name().map(|n| OsStr::from_bytes(n.to_bytes()));
+                    self.pending.extend(convert(
+                        &mut self.watches,
+                        event.wd(),
+                        event.events(),
+                        event.cookie(),
+                        name,
+                    ));
+
+                    if reader.is_buffer_empty() {
+                        return Ok(());
+                    }
+                }
+            });
+
+            if let Ok(Err(e)) = result {
+                return Poll::Ready(Err(e));
+            }
+        }
+    }
+}
+
+impl AsFd for Watcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+fn convert(
+    watches: &mut HashMap<i32, PathBuf>,
+    wd: i32,
+    flags: ReadFlags,
+    cookie: u32,
+    name: Option<&OsStr>,
+) -> Option<Event> {
+    if flags.contains(ReadFlags::QUEUE_OVERFLOW) {
+        return Some(Event {
+            kind: EventKind::Overflow,
+            path: PathBuf::new(),
+            is_dir: false,
+            watch: WatchId(wd),
+        });
+    }
+
+    let kind = if flags.contains(ReadFlags::IGNORED) {
+        EventKind::Unwatched
+    } else if flags.contains(ReadFlags::CREATE) {
+        EventKind::Create
+    } else if flags.contains(ReadFlags::MODIFY) {
+        EventKind::Modify
+    } else if flags.contains(ReadFlags::CLOSE_WRITE) {
+        EventKind::CloseWrite
+    } else if flags.intersects(ReadFlags::DELETE | ReadFlags::DELETE_SELF) {
+        EventKind::Delete
+    } else if flags.contains(ReadFlags::MOVED_FROM) {
+        EventKind::MovedFrom { cookie }
+    } else if flags.contains(ReadFlags::MOVE_SELF) {
+        EventKind::MovedFrom { cookie: 0 }
+    } else if flags.contains(ReadFlags::MOVED_TO) {
+        EventKind::MovedTo { cookie }
+    } else {
+        return None;
+    };
+
+    let watched = if kind == EventKind::Unwatched {
+        watches.remove(&wd)?
+    } else {
+        watches.get(&wd)?.clone()
+    };
+
+    Some(Event {
+        kind,
+        path: match name {
+            Some(name) => watched.join(name),
+            None => watched,
+        },
+        is_dir: flags.contains(ReadFlags::ISDIR),
+        watch: WatchId(wd),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir, time::timeout};
+    use std::time::Duration;
+
+    async fn next(watcher: &mut Watcher) -> Event {
+        timeout(Duration::from_secs(5), watcher.next_event())
+            .await
+            .expect("no event within 5s")
+            .unwrap()
+    }
+
+    #[test]
+    fn reports_directory_entry_changes() {
+        let dir = TempDir::new();
+        let path = dir.join("config");
+
+        LocalExecutor::new().block_on(async {
+            let mut watcher = Watcher::new().unwrap();
+            let id = watcher.watch(dir.path()).unwrap();
+            assert_eq!(watcher.watch(dir.path()).unwrap(), id);
+            assert_eq!(watcher.path(id), Some(dir.path()));
+
+            std::fs::write(&path, b"x").unwrap();
+
+            let kinds = [
+                next(&mut watcher).await,
+                next(&mut watcher).await,
+                next(&mut watcher).await,
+            ]
+            .map(|event| {
+                assert_eq!(event.path, path);
+                assert_eq!(event.watch, id);
+                assert!(!event.is_dir);
+                event.kind
+            });
+            assert_eq!(
+                kinds,
+                [EventKind::Create, EventKind::Modify, EventKind::CloseWrite]
+            );
+
+            std::fs::create_dir(dir.join("sub")).unwrap();
+            let event = next(&mut watcher).await;
+            assert_eq!(event.kind, EventKind::Create);
+            assert!(event.is_dir);
+
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(next(&mut watcher).await.kind, EventKind::Delete);
+        });
+    }
+
+    #[test]
+    fn pairs_renames_by_cookie() {
+        let dir = TempDir::new();
+        std::fs::write(dir.join("a"), b"").unwrap();
+
+        LocalExecutor::new().block_on(async {
+            let mut watcher = Watcher::new().unwrap();
+            watcher.watch(dir.path()).unwrap();
+
+            std::fs::rename(dir.join("a"), dir.join("b")).unwrap();
+
+            let from = next(&mut watcher).await;
+            let to = next(&mut watcher).await;
+            let (EventKind::MovedFrom { cookie: a }, EventKind::MovedTo { cookie: b }) =
+                (from.kind, to.kind)
+            else {
+                panic!("unexpected events: {from:?}, {to:?}");
+            };
+            assert_eq!(a, b);
+            assert_eq!(from.path, dir.join("a"));
+            assert_eq!(to.path, dir.join("b"));
+        });
+    }
+
+    #[test]
+    fn unwatch_ends_the_watch() {
+        let dir = TempDir::new();
+
+        LocalExecutor::new().block_on(async {
+            let mut watcher = Watcher::new().unwrap();
+            let id = watcher.watch(dir.path()).unwrap();
+
+            watcher.unwatch(id).unwrap();
+            let event = next(&mut watcher).await;
+            assert_eq!(event.kind, EventKind::Unwatched);
+            assert_eq!(event.watch, id);
+            assert_eq!(watcher.path(id), None);
+        });
+    }
+
+    #[test]
+    fn converts_overflow_and_ignores_unknown_watches() {
+        let mut watches = HashMap::new();
+
+        let event = convert(&mut watches, -1, ReadFlags::QUEUE_OVERFLOW, 0, None).unwrap();
+        assert_eq!(event.kind, EventKind::Overflow);
+        assert_eq!(event.path, PathBuf::new());
+
+        assert_eq!(convert(&mut watches, 7, ReadFlags::CREATE, 0, None), None);
+
+        watches.insert(7, PathBuf::from("/watched"));
+        let event = convert(&mut watches, 7, ReadFlags::MOVE_SELF, 0, None).unwrap();
+        assert_eq!(event.kind, EventKind::MovedFrom { cookie: 0 });
+        assert_eq!(event.path, Path::new("/watched"));
+    }
+}
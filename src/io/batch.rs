@@ -0,0 +1,158 @@
+use rustix::fd::BorrowedFd;
+use std::io;
+
+/// Non-blocking reads and writes queued up and executed in one pass.
+///
+/// Operations are queued with [`read`](Self::read),
+/// [`write`](Self::write) and their positioned variants, each returning
+/// its index in the batch, and all of them run, in order, when the batch
+/// is [`submit`](Self::submit)ted. Nothing waits: an fd that is not ready
+/// yields `WouldBlock` in its slot. Submitting from
+/// [`LocalExecutor::on_iteration_end`](crate::runtime::LocalExecutor::on_iteration_end)
+/// flushes whatever the tasks of an iteration queued in one go.
+///
+/// The fds should be non-blocking, or a single slow one stalls the pass.
+#[derive(Debug, Default)]
+pub struct Batch<'a> {
+    ops: Vec<Op<'a>>,
+}
+
+#[derive(Debug)]
+enum Op<'a> {
+    Read {
+        fd: BorrowedFd<'a>,
+        buf: &'a mut [u8],
+        offset: Option<u64>,
+    },
+    Write {
+        fd: BorrowedFd<'a>,
+        buf: &'a [u8],
+        offset: Option<u64>,
+    },
+}
+
+impl<'a> Batch<'a> {
+    /// An empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a read from `fd` into `buf`.
+    pub fn read(&mut self, fd: BorrowedFd<'a>, buf: &'a mut [u8]) -> usize {
+        self.push(Op::Read {
+            fd,
+            buf,
+            offset: None,
+        })
+    }
+
+    /// Queue a read from `fd` at `offset`, leaving its file position alone.
+    pub fn read_at(&mut self, fd: BorrowedFd<'a>, buf: &'a mut [u8], offset: u64) -> usize {
+        self.push(Op::Read {
+            fd,
+            buf,
+            offset: Some(offset),
+        })
+    }
+
+    /// Queue a write of `buf` to `fd`.
+    pub fn write(&mut self, fd: BorrowedFd<'a>, buf: &'a [u8]) -> usize {
+        self.push(Op::Write {
+            fd,
+            buf,
+            offset: None,
+        })
+    }
+
+    /// Queue a write to `fd` at `offset`, leaving its file position alone.
+    pub fn write_at(&mut self, fd: BorrowedFd<'a>, buf: &'a [u8], offset: u64) -> usize {
+        self.push(Op::Write {
+            fd,
+            buf,
+            offset: Some(offset),
+        })
+    }
+
+    /// Number of queued operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Run every queued operation, returning the bytes each transferred,
+    /// by index.
+    pub fn submit(self) -> Vec<io::Result<usize>> {
+        self.ops.into_iter().map(Op::run).collect()
+    }
+
+    fn push(&mut self, op: Op<'a>) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+}
+
+impl Op<'_> {
+    fn run(self) -> io::Result<usize> {
+        let result = match self {
+            Op::Read {
+                fd,
+                buf,
+                offset: None,
+            } => rustix::io::read(fd, buf),
+            Op::Read {
+                fd,
+                buf,
+                offset: Some(offset),
+            } => rustix::io::pread(fd, buf, offset),
+            Op::Write {
+                fd,
+                buf,
+                offset: None,
+            } => rustix::io::write(fd, buf),
+            Op::Write {
+                fd,
+                buf,
+                offset: Some(offset),
+            } => rustix::io::pwrite(fd, buf, offset),
+        };
+
+        result.map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustix::{
+        fd::AsFd,
+        pipe::{PipeFlags, pipe_with},
+    };
+
+    #[test]
+    fn runs_every_operation_in_one_pass() {
+        let (r1, w1) = pipe_with(PipeFlags::NONBLOCK).unwrap();
+        let (r2, _w2) = pipe_with(PipeFlags::NONBLOCK).unwrap();
+        rustix::io::write(&w1, b"ready").unwrap();
+
+        let mut ready = [0; 8];
+        let mut empty = [0; 8];
+        let mut batch = Batch::new();
+        let a = batch.read(r1.as_fd(), &mut ready);
+        let b = batch.read(r2.as_fd(), &mut empty);
+        let c = batch.write(w1.as_fd(), b"more");
+        assert_eq!(batch.len(), 3);
+
+        let results = batch.submit();
+        assert_eq!(results[a].as_ref().unwrap(), &5);
+        assert_eq!(
+            results[b].as_ref().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert_eq!(results[c].as_ref().unwrap(), &4);
+        assert_eq!(&ready[..5], b"ready");
+    }
+}
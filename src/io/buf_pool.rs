@@ -0,0 +1,183 @@
+use crate::utils::tracked_cell::TrackedRefCell;
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+/// A pool of fixed-size receive buffers, reused instead of reallocated.
+///
+/// [`get`](Self::get) hands out a [`PooledBuf`], which goes back to the
+/// pool when dropped; up to `max_idle` returned buffers are kept for reuse
+/// and the rest freed. The `recv_into` methods of the socket types read
+/// straight into a pooled buffer and return it, so a loop that receives
+/// a message and passes it on neither copies nor allocates once the pool
+/// is warm. Cloning the pool shares it.
+#[derive(Clone)]
+pub struct BufPool {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    size: usize,
+    max_idle: usize,
+    idle: TrackedRefCell<Vec<Box<[u8]>>>,
+}
+
+impl BufPool {
+    /// Create a pool of `size`-byte buffers, keeping up to `max_idle` spare.
+    pub fn new(size: usize, max_idle: usize) -> Self {
+        Self {
+            inner: Rc::new(Inner {
+                size,
+                max_idle,
+                idle: TrackedRefCell::default(),
+            }),
+        }
+    }
+
+    /// Take an empty buffer, allocating one only if none is idle.
+    pub fn get(&self) -> PooledBuf {
+        let buf = self
+            .inner
+            .idle
+            .borrow_mut()
+            .pop()
+            .unwrap_or_else(|| vec![0; self.inner.size].into_boxed_slice());
+
+        PooledBuf {
+            buf: Some(buf),
+            len: 0,
+            pool: self.inner.clone(),
+        }
+    }
+
+    /// Size of each buffer.
+    pub fn buf_size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// Number of buffers waiting for reuse.
+    pub fn idle(&self) -> usize {
+        self.inner.idle.borrow().len()
+    }
+}
+
+impl fmt::Debug for BufPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufPool")
+            .field("size", &self.inner.size)
+            .field("max_idle", &self.inner.max_idle)
+            .field("idle", &self.idle())
+            .finish()
+    }
+}
+
+/// A buffer from a [`BufPool`], returned to it on drop.
+///
+/// Derefs to the filled part, which `recv_into` sets to the bytes received.
+pub struct PooledBuf {
+    buf: Option<Box<[u8]>>,
+    len: usize,
+    pool: Rc<Inner>,
+}
+
+impl PooledBuf {
+    /// Size of the whole buffer, filled or not.
+    pub fn capacity(&self) -> usize {
+        self.storage().len()
+    }
+
+    /// Shorten the filled part to `len` bytes; longer lengths do nothing.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    /// The whole buffer, for a read to fill from the start.
+    pub(crate) fn storage_mut(&mut self) -> &mut [u8] {
+        self.buf.as_mut().expect("present until drop")
+    }
+
+    /// Mark the first `len` bytes as filled.
+    pub(crate) fn set_filled(&mut self, len: usize) {
+        assert!(len <= self.capacity(), "filled past the end of the buffer");
+        self.len = len;
+    }
+
+    fn storage(&self) -> &[u8] {
+        self.buf.as_ref().expect("present until drop")
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.storage()[..self.len]
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let len = self.len;
+        &mut self.storage_mut()[..len]
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        let mut idle = self.pool.idle.borrow_mut();
+
+        if idle.len() < self.pool.max_idle {
+            idle.extend(self.buf.take());
+        }
+    }
+}
+
+impl fmt::Debug for PooledBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledBuf")
+            .field("len", &self.len)
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{net::UnixDatagram, runtime::LocalExecutor};
+
+    #[test]
+    fn buffers_are_reused_up_to_max_idle() {
+        let pool = BufPool::new(16, 1);
+        let a = pool.get();
+        let b = pool.get();
+        let a_ptr = a.storage().as_ptr();
+
+        drop(a);
+        drop(b);
+        assert_eq!(pool.idle(), 1);
+
+        let c = pool.get();
+        assert_eq!(c.storage().as_ptr(), a_ptr);
+        assert!(c.is_empty());
+        assert_eq!(c.capacity(), 16);
+    }
+
+    #[test]
+    fn recv_into_fills_a_pooled_buffer() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixDatagram::pair().unwrap();
+            let pool = BufPool::new(64, 4);
+
+            for msg in [&b"one"[..], b"three"] {
+                a.send(msg).await.unwrap();
+                let buf = b.recv_into(&pool).await.unwrap();
+                assert_eq!(&*buf, msg);
+            }
+
+            assert_eq!(pool.idle(), 1);
+        });
+    }
+}
@@ -0,0 +1,310 @@
+use crate::io::AsyncRead;
+use std::{
+    fmt,
+    future::poll_fn,
+    io, mem,
+    task::{Context, Poll, ready},
+};
+
+/// Default size of the read buffer.
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// Adds buffering and line reading to an [`AsyncRead`].
+///
+/// Reads go to the inner reader in chunks of the buffer's capacity, which
+/// suits line-oriented control protocols or a child's stdout, where the
+/// data arrives in small pieces but is consumed a line at a time.
+pub struct BufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: AsyncRead> BufReader<R> {
+    /// Buffer reads from `inner` with the default capacity of 8 KiB.
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+
+    /// Buffer reads from `inner`, `capacity` bytes at a time.
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    /// Return the buffered data, reading more first if there is none.
+    ///
+    /// An empty slice means end of stream. Call [`consume`](Self::consume)
+    /// with the number of bytes used.
+    pub fn poll_fill_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        if self.pos == self.filled {
+            let n = ready!(self.inner.poll_read(cx, &mut self.buf))?;
+            self.pos = 0;
+            self.filled = n;
+        }
+
+        Poll::Ready(Ok(&self.buf[self.pos..self.filled]))
+    }
+
+    /// Mark `n` buffered bytes as used.
+    pub fn consume(&mut self, n: usize) {
+        self.pos = (self.pos + n).min(self.filled);
+    }
+
+    /// Append bytes to `buf` up to and including `delim`, or to the end of
+    /// the stream, returning how many were appended.
+    ///
+    /// Whatever has been appended stays in `buf` if the future is dropped.
+    pub async fn read_until(&mut self, delim: u8, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        poll_fn(|cx| self.poll_read_until(cx, delim, buf)).await?;
+        Ok(buf.len() - start)
+    }
+
+    /// Append a line, including its `\n`, to `line`, returning the number
+    /// of bytes appended; 0 at the end of the stream.
+    ///
+    /// Fails with [`InvalidData`](io::ErrorKind::InvalidData) if the line is
+    /// not UTF-8, leaving `line` unchanged. A dropped future loses the part
+    /// of the line read so far; use [`lines`](Self::lines) where that
+    /// matters.
+    pub async fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        let mut bytes = Vec::new();
+        self.read_until(b'\n', &mut bytes).await?;
+        let text = String::from_utf8(bytes).map_err(|_| invalid_utf8())?;
+        line.push_str(&text);
+        Ok(text.len())
+    }
+
+    /// Turn the reader into a stream of lines, without their line endings.
+    pub fn lines(self) -> Lines<R> {
+        Lines {
+            reader: self,
+            line: Vec::new(),
+        }
+    }
+
+    fn poll_read_until(
+        &mut self,
+        cx: &mut Context<'_>,
+        delim: u8,
+        buf: &mut Vec<u8>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let available = ready!(self.poll_fill_buf(cx))?;
+
+            if available.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let (n, done) = match available.iter().position(|&b| b == delim) {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+
+            buf.extend_from_slice(&available[..n]);
+            self.consume(n);
+
+            if done {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+
+    /// The data read but not consumed yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Get a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader.
+    ///
+    /// Reading from it directly skips over whatever is buffered.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner reader, discarding any buffered data.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for BufReader<R> {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        // Reads at least as large as the buffer gain nothing from it.
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return self.inner.poll_read(cx, buf);
+        }
+
+        let available = ready!(self.poll_fill_buf(cx))?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for BufReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufReader")
+            .field("inner", &self.inner)
+            .field("buffered", &(self.filled - self.pos))
+            .field("capacity", &self.buf.len())
+            .finish()
+    }
+}
+
+/// Lines of a [`BufReader`], from [`BufReader::lines`].
+///
+/// A partial line read before a future was dropped is kept for the next
+/// call, so [`next_line`](Self::next_line) can be raced against other
+/// futures.
+#[derive(Debug)]
+pub struct Lines<R> {
+    reader: BufReader<R>,
+    line: Vec<u8>,
+}
+
+impl<R: AsyncRead> Lines<R> {
+    /// The next line without its `\n` or `\r\n`; `None` at the end of the
+    /// stream.
+    pub async fn next_line(&mut self) -> io::Result<Option<String>> {
+        poll_fn(|cx| self.poll_next_line(cx)).await
+    }
+
+    /// Poll-based [`next_line`](Self::next_line).
+    pub fn poll_next_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<String>>> {
+        ready!(self.reader.poll_read_until(cx, b'\n', &mut self.line))?;
+
+        if self.line.is_empty() {
+            return Poll::Ready(Ok(None));
+        }
+
+        let mut line = mem::take(&mut self.line);
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+
+        Poll::Ready(
+            String::from_utf8(line)
+                .map(Some)
+                .map_err(|_| invalid_utf8()),
+        )
+    }
+
+    /// Get back the underlying reader.
+    pub fn into_inner(self) -> BufReader<R> {
+        self.reader
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<R: AsyncRead + Unpin> futures_core::Stream for Lines<R> {
+    type Item = io::Result<String>;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_line(cx).map(Result::transpose)
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Stream did not contain valid UTF-8",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::read, runtime::LocalExecutor, test_util::CountingWaker};
+    use std::{future::Future, pin::pin};
+
+    /// Hands out one chunk per read; `None` stalls a single read.
+    struct Chunks(Vec<Option<&'static [u8]>>);
+
+    impl AsyncRead for Chunks {
+        fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            if self.0.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            match self.0.remove(0) {
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Poll::Ready(Ok(chunk.len()))
+                }
+                None => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_lines_across_chunks() {
+        LocalExecutor::new().block_on(async {
+            let mut reader = BufReader::new(Chunks(vec![Some(b"one\ntw"), Some(b"o\r\nthree")]));
+
+            let mut line = String::new();
+            assert_eq!(reader.read_line(&mut line).await.unwrap(), 4);
+            assert_eq!(line, "one\n");
+
+            let mut lines = reader.lines();
+            assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("two"));
+            assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("three"));
+            assert_eq!(lines.next_line().await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn dropped_next_line_keeps_partial_line() {
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut lines = BufReader::new(Chunks(vec![Some(b"hel"), None, Some(b"lo\n")])).lines();
+
+        {
+            let next = pin!(lines.next_line());
+            assert!(next.poll(&mut cx).is_pending());
+        }
+
+        let next = pin!(lines.next_line());
+        match next.poll(&mut cx) {
+            Poll::Ready(line) => assert_eq!(line.unwrap().as_deref(), Some("hello")),
+            Poll::Pending => panic!("line not ready"),
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_is_rejected() {
+        LocalExecutor::new().block_on(async {
+            let mut reader = BufReader::new(Chunks(vec![Some(b"\xff\n"), Some(b"ok")]));
+
+            let mut line = String::new();
+            let err = reader.read_line(&mut line).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(line.is_empty());
+
+            let mut buf = [0; 4];
+            assert_eq!(read(&mut reader, &mut buf).await.unwrap(), 2);
+        });
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{
+    io::AsyncWrite,
+    runtime::{JoinHandle, spawn_local},
+    time::sleep,
+    utils::{lock::Mutex, tracked_cell::TrackedRefCell},
+};
+use std::{
+    fmt,
+    future::poll_fn,
+    io,
+    rc::Rc,
+    task::{Poll, ready},
+    time::Duration,
+};
+
+/// When a [`BufWriter`] flushes on its own.
+#[derive(Debug, Clone)]
+pub struct FlushPolicy {
+    /// Flush once this many bytes are buffered.
+    pub max_bytes: usize,
+    /// Flush once the oldest buffered byte has waited this long.
+    pub max_delay: Option<Duration>,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: 8 * 1024,
+            max_delay: Some(Duration::from_millis(5)),
+        }
+    }
+}
+
+/// A shared write buffer that batches small writes to an [`AsyncWrite`].
+///
+/// Buffered data is flushed once [`FlushPolicy::max_bytes`] is reached,
+/// inline in the [`write`](Self::write) that crosses it, or by a timer task
+/// once [`FlushPolicy::max_delay`] has passed since the first byte was
+/// buffered, whichever comes first. Clones share the buffer, so handlers can
+/// each hold one and never flush by hand.
+///
+/// Errors from a timer flush are returned by the next `write` or `flush`.
+/// Whatever the sink has not accepted stays buffered, also when a flush is
+/// cancelled, and goes out with the next flush. Data still buffered when the
+/// last clone is dropped is written by a pending timer, or lost if there is
+/// none.
+pub struct BufWriter<W> {
+    shared: Rc<Shared<W>>,
+}
+
+struct Shared<W> {
+    inner: Mutex<W>,
+    policy: FlushPolicy,
+    state: TrackedRefCell<State>,
+}
+
+#[derive(Default)]
+struct State {
+    buf: Vec<u8>,
+    /// The pending timer flush; only present while it is still sleeping.
+    timer: Option<JoinHandle<()>>,
+    error: Option<io::Error>,
+}
+
+impl<W: AsyncWrite + 'static> BufWriter<W> {
+    /// Buffer writes to `inner` according to `policy`.
+    ///
+    /// With a `max_delay`, the writer must be used inside a
+    /// [`LocalExecutor`](crate::runtime::LocalExecutor), which runs the timer.
+    pub fn new(inner: W, policy: FlushPolicy) -> Self {
+        Self {
+            shared: Rc::new(Shared {
+                inner: Mutex::new(inner),
+                policy,
+                state: TrackedRefCell::default(),
+            }),
+        }
+    }
+
+    /// Buffer all of `buf`, flushing if the policy calls for it.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<()> {
+        let len = {
+            let mut state = self.shared.state.borrow_mut();
+
+            if let Some(e) = state.error.take() {
+                return Err(e);
+            }
+
+            state.buf.extend_from_slice(buf);
+            state.buf.len()
+        };
+
+        if len >= self.shared.policy.max_bytes {
+            return self.flush().await;
+        }
+
+        if let Some(delay) = self.shared.policy.max_delay {
+            let mut state = self.shared.state.borrow_mut();
+
+            if state.timer.is_none() && !state.buf.is_empty() {
+                state.timer = Some(spawn_local(flush_after(self.shared.clone(), delay)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write out everything buffered so far and flush the sink.
+    pub async fn flush(&self) -> io::Result<()> {
+        if let Some(timer) = self.shared.state.borrow_mut().timer.take() {
+            timer.abort();
+        }
+
+        self.shared.flush().await
+    }
+
+    /// Number of bytes waiting to be written.
+    pub fn buffered(&self) -> usize {
+        self.shared.state.borrow().buf.len()
+    }
+
+    /// The flush policy.
+    pub fn policy(&self) -> &FlushPolicy {
+        &self.shared.policy
+    }
+}
+
+impl<W: AsyncWrite> Shared<W> {
+    async fn flush(&self) -> io::Result<()> {
+        // Held throughout, so concurrent flushes keep the order of the data.
+        let mut inner = self.inner.lock().await;
+
+        if let Some(e) = self.state.borrow_mut().error.take() {
+            return Err(e);
+        }
+
+        // Bytes leave the buffer only once the sink has taken them, so a
+        // cancelled or failed flush leaves the rest for the next one.
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+
+            while !state.buf.is_empty() {
+                match ready!(inner.poll_write(cx, &state.buf))? {
+                    0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                    n => drop(state.buf.drain(..n)),
+                }
+            }
+
+            Poll::Ready(Ok::<_, io::Error>(()))
+        })
+        .await?;
+
+        crate::io::flush(&mut *inner).await
+    }
+}
+
+async fn flush_after<W: AsyncWrite>(shared: Rc<Shared<W>>, delay: Duration) {
+    sleep(delay).await;
+
+    // Detach before flushing: from here on an explicit flush must not abort
+    // us halfway through a write.
+    drop(shared.state.borrow_mut().timer.take());
+
+    if let Err(e) = shared.flush().await {
+        shared.state.borrow_mut().error = Some(e);
+    }
+}
+
+impl<W> Clone for BufWriter<W> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<W> fmt::Debug for BufWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.shared.state.borrow();
+
+        f.debug_struct("BufWriter")
+            .field("policy", &self.shared.policy)
+            .field("buffered", &state.buf.len())
+            .field("timer", &state.timer.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, time::timeout};
+    use std::task::Context;
+
+    /// Records each write; fails every write once `broken` is set.
+    #[derive(Clone, Default)]
+    struct Sink {
+        writes: Rc<TrackedRefCell<Vec<Vec<u8>>>>,
+        broken: Rc<std::cell::Cell<bool>>,
+    }
+
+    impl AsyncWrite for Sink {
+        fn poll_write(&mut self, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            if self.broken.get() {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+
+            self.writes.borrow_mut().push(buf.to_vec());
+            Poll::Ready(Ok(buf.len()))
+        }
+    }
+
+    #[test]
+    fn flushes_once_max_bytes_is_reached() {
+        LocalExecutor::new().block_on(async {
+            let sink = Sink::default();
+            let policy = FlushPolicy {
+                max_bytes: 4,
+                max_delay: None,
+            };
+            let writer = BufWriter::new(sink.clone(), policy);
+
+            writer.write(b"ab").await.unwrap();
+            assert_eq!(writer.buffered(), 2);
+            assert!(sink.writes.borrow().is_empty());
+
+            writer.write(b"cd").await.unwrap();
+            assert_eq!(writer.buffered(), 0);
+            assert_eq!(*sink.writes.borrow(), [b"abcd".to_vec()]);
+        });
+    }
+
+    #[test]
+    fn flushes_after_max_delay() {
+        LocalExecutor::new().block_on(async {
+            let sink = Sink::default();
+            let policy = FlushPolicy {
+                max_bytes: 1024,
+                max_delay: Some(Duration::from_millis(2)),
+            };
+            let writer = BufWriter::new(sink.clone(), policy);
+
+            writer.write(b"a").await.unwrap();
+            writer.write(b"b").await.unwrap();
+            sleep(Duration::from_millis(10)).await;
+
+            assert_eq!(*sink.writes.borrow(), [b"ab".to_vec()]);
+        });
+    }
+
+    #[test]
+    fn timer_flush_error_surfaces_on_next_write() {
+        LocalExecutor::new().block_on(async {
+            let sink = Sink::default();
+            sink.broken.set(true);
+            let policy = FlushPolicy {
+                max_bytes: 1024,
+                max_delay: Some(Duration::from_millis(1)),
+            };
+            let writer = BufWriter::new(sink, policy);
+
+            writer.write(b"lost").await.unwrap();
+            sleep(Duration::from_millis(10)).await;
+
+            let err = writer.write(b"next").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        });
+    }
+
+    /// Accepts up to `open` bytes in total, then stalls.
+    #[derive(Clone, Default)]
+    struct Gate {
+        written: Rc<TrackedRefCell<Vec<u8>>>,
+        open: Rc<std::cell::Cell<usize>>,
+    }
+
+    impl AsyncWrite for Gate {
+        fn poll_write(&mut self, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            let n = buf.len().min(self.open.get());
+
+            if n == 0 {
+                return Poll::Pending;
+            }
+
+            self.open.set(self.open.get() - n);
+            self.written.borrow_mut().extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    #[test]
+    fn cancelled_flush_keeps_unwritten_bytes() {
+        LocalExecutor::new().block_on(async {
+            let gate = Gate::default();
+            gate.open.set(5);
+            let policy = FlushPolicy {
+                max_bytes: 1024,
+                max_delay: None,
+            };
+            let writer = BufWriter::new(gate.clone(), policy);
+
+            writer.write(b"hello world").await.unwrap();
+
+            let stalled = timeout(Duration::from_millis(5), writer.flush()).await;
+            assert!(stalled.is_err());
+            assert_eq!(*gate.written.borrow(), b"hello");
+            assert_eq!(writer.buffered(), 6);
+
+            gate.open.set(usize::MAX);
+            writer.write(b"!").await.unwrap();
+            writer.flush().await.unwrap();
+            assert_eq!(*gate.written.borrow(), b"hello world!");
+            assert_eq!(writer.buffered(), 0);
+        });
+    }
+
+    #[test]
+    fn failed_flush_keeps_unwritten_bytes() {
+        LocalExecutor::new().block_on(async {
+            let sink = Sink::default();
+            sink.broken.set(true);
+            let policy = FlushPolicy {
+                max_bytes: 1024,
+                max_delay: None,
+            };
+            let writer = BufWriter::new(sink.clone(), policy);
+
+            writer.write(b"kept").await.unwrap();
+            let err = writer.flush().await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+            assert_eq!(writer.buffered(), 4);
+
+            sink.broken.set(false);
+            writer.flush().await.unwrap();
+            assert_eq!(*sink.writes.borrow(), [b"kept".to_vec()]);
+        });
+    }
+}
@@ -0,0 +1,241 @@
+use crate::io::{AsyncRead, AsyncWrite};
+use std::{
+    fmt,
+    future::poll_fn,
+    io,
+    task::{Context, Poll, ready},
+};
+
+/// Default limit on the length of a frame.
+const DEFAULT_MAX_FRAME: usize = 8 * 1024 * 1024;
+
+/// Bytes of the length prefix.
+const PREFIX: usize = 4;
+
+/// Most bytes read from the stream at once.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Length-prefixed frames over a byte stream.
+///
+/// Each frame is sent as a 4-byte big-endian length followed by that many
+/// bytes. Frames longer than the limit are refused on send and treated as
+/// a protocol error on receipt, as the stream cannot be resynchronised
+/// after one.
+///
+/// Both directions keep their progress in the `Framed`, so a dropped
+/// [`send`](Self::send) or [`next_frame`](Self::next_frame) future neither
+/// loses nor tears a frame: a partly written frame is finished by the next
+/// send or [`flush`](Self::flush), and a partly read one by the next read.
+pub struct Framed<T> {
+    io: T,
+    max_frame: usize,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+}
+
+impl<T> Framed<T> {
+    /// Frame `io`, accepting frames of up to 8 MiB.
+    pub fn new(io: T) -> Self {
+        Self::with_max_frame(io, DEFAULT_MAX_FRAME)
+    }
+
+    /// Frame `io`, accepting frames of up to `max_frame` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_frame` does not fit the 32-bit length prefix.
+    pub fn with_max_frame(io: T, max_frame: usize) -> Self {
+        assert!(
+            u32::try_from(max_frame).is_ok(),
+            "max_frame must fit in the 32-bit length prefix"
+        );
+
+        Self {
+            io,
+            max_frame,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+        }
+    }
+
+    /// The largest frame accepted.
+    pub fn max_frame(&self) -> usize {
+        self.max_frame
+    }
+
+    /// Get a reference to the underlying stream.
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Unwrap the underlying stream, discarding buffered frame data.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T: AsyncRead> Framed<T> {
+    /// Receive the next frame; `None` once the stream ends between frames.
+    ///
+    /// Fails with [`InvalidData`](io::ErrorKind::InvalidData) if a frame is
+    /// longer than the limit, and with
+    /// [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) if the stream ends
+    /// inside one.
+    pub async fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        poll_fn(|cx| self.poll_next_frame(cx)).await
+    }
+
+    /// Poll-based [`next_frame`](Self::next_frame).
+    pub fn poll_next_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Vec<u8>>>> {
+        loop {
+            let mut needed = PREFIX;
+
+            if let Some(prefix) = self.read_buf.first_chunk::<PREFIX>() {
+                let len = u32::from_be_bytes(*prefix) as usize;
+
+                if len > self.max_frame {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Frame exceeds the maximum length",
+                    )));
+                }
+
+                needed = PREFIX + len;
+
+                if self.read_buf.len() >= needed {
+                    let frame = self.read_buf[PREFIX..needed].to_vec();
+                    self.read_buf.drain(..needed);
+                    return Poll::Ready(Ok(Some(frame)));
+                }
+            }
+
+            let start = self.read_buf.len();
+            let want = (needed - start).max(READ_CHUNK);
+            self.read_buf.resize(start + want, 0);
+
+            let read = self.io.poll_read(cx, &mut self.read_buf[start..]);
+            let n = match &read {
+                Poll::Ready(Ok(n)) => *n,
+                _ => 0,
+            };
+            self.read_buf.truncate(start + n);
+
+            match ready!(read)? {
+                0 if start == 0 => return Poll::Ready(Ok(None)),
+                0 => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite> Framed<T> {
+    /// Send `frame` and flush the stream.
+    ///
+    /// Fails with [`InvalidInput`](io::ErrorKind::InvalidInput) without
+    /// sending anything if `frame` is longer than the limit.
+    pub async fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        if frame.len() > self.max_frame {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Frame exceeds the maximum length",
+            ));
+        }
+
+        self.write_buf
+            .extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        self.write_buf.extend_from_slice(frame);
+        self.flush().await
+    }
+
+    /// Finish writing any frame left over by a dropped send, and flush.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Poll-based [`flush`](Self::flush).
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_buf.is_empty() {
+            match ready!(self.io.poll_write(cx, &self.write_buf))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                n => drop(self.write_buf.drain(..n)),
+            }
+        }
+
+        self.io.poll_flush(cx)
+    }
+}
+
+/// Yields received frames until the stream ends.
+#[cfg(feature = "futures")]
+impl<T: AsyncRead + Unpin> futures_core::Stream for Framed<T> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_frame(cx).map(Result::transpose)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Framed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Framed")
+            .field("io", &self.io)
+            .field("max_frame", &self.max_frame)
+            .field("read_buffered", &self.read_buf.len())
+            .field("write_buffered", &self.write_buf.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{net::UnixStream, runtime::LocalExecutor};
+
+    #[test]
+    fn frames_round_trip() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            let mut tx = Framed::new(a);
+            let mut rx = Framed::new(b);
+
+            tx.send(b"hello").await.unwrap();
+            tx.send(b"").await.unwrap();
+            tx.send(&[7; 20_000]).await.unwrap();
+            drop(tx);
+
+            assert_eq!(rx.next_frame().await.unwrap().unwrap(), b"hello");
+            assert_eq!(rx.next_frame().await.unwrap().unwrap(), b"");
+            assert_eq!(rx.next_frame().await.unwrap().unwrap(), [7; 20_000]);
+            assert_eq!(rx.next_frame().await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn enforces_max_frame() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            let mut tx = Framed::with_max_frame(a, 4);
+            let mut rx = Framed::with_max_frame(b, 2);
+
+            let err = tx.send(b"toolong").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+            tx.send(b"abc").await.unwrap();
+            let err = rx.next_frame().await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test]
+    fn eof_inside_a_frame_is_an_error() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            a.write_all(&[0, 0, 0, 5, b'x']).await.unwrap();
+            drop(a);
+
+            let err = Framed::new(b).next_frame().await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        });
+    }
+}
@@ -0,0 +1,254 @@
+mod batch;
+mod buf_pool;
+mod buf_reader;
+mod buf_writer;
+mod framed;
+mod stdio;
+mod tty;
+
+pub use batch::Batch;
+pub use buf_pool::{BufPool, PooledBuf};
+pub use buf_reader::{BufReader, Lines};
+pub use buf_writer::{BufWriter, FlushPolicy};
+pub use framed::Framed;
+pub use stdio::{Stderr, Stdin, Stdout, stderr, stdin, stdout};
+pub use tty::{RawMode, Tty, WinSize};
+
+use crate::net::{TcpStream, UnixStream};
+use std::{
+    future::poll_fn,
+    io,
+    os::fd::AsFd,
+    task::{Context, Poll},
+};
+
+/// A non-blocking byte source.
+pub trait AsyncRead {
+    /// Read into `buf`, returning the number of bytes read; 0 means end of
+    /// stream.
+    ///
+    /// Returns `Pending` and arranges a wakeup if no data is available.
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+impl<R: AsyncRead + ?Sized> AsyncRead for &mut R {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        (**self).poll_read(cx, buf)
+    }
+}
+
+impl<R: AsyncRead + ?Sized> AsyncRead for Box<R> {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        (**self).poll_read(cx, buf)
+    }
+}
+
+impl AsyncRead for UnixStream {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        UnixStream::poll_read(self, cx, buf)
+    }
+}
+
+impl AsyncRead for &UnixStream {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        UnixStream::poll_read(self, cx, buf)
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        TcpStream::poll_read(self, cx, buf)
+    }
+}
+
+impl AsyncRead for &TcpStream {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        TcpStream::poll_read(self, cx, buf)
+    }
+}
+
+/// A non-blocking byte sink.
+pub trait AsyncWrite {
+    /// Write some of `buf`, returning the number of bytes written.
+    ///
+    /// Returns `Pending` and arranges a wakeup if no progress can be made.
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>;
+
+    /// Push out anything the sink buffers internally.
+    fn poll_flush(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + ?Sized> AsyncWrite for &mut W {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        (**self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        (**self).poll_flush(cx)
+    }
+}
+
+impl<W: AsyncWrite + ?Sized> AsyncWrite for Box<W> {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        (**self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        (**self).poll_flush(cx)
+    }
+}
+
+impl AsyncWrite for UnixStream {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        UnixStream::poll_write(self, cx, buf)
+    }
+}
+
+impl AsyncWrite for &UnixStream {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        UnixStream::poll_write(self, cx, buf)
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        TcpStream::poll_write(self, cx, buf)
+    }
+}
+
+impl AsyncWrite for &TcpStream {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        TcpStream::poll_write(self, cx, buf)
+    }
+}
+
+/// Read from `r` into `buf`, returning the number of bytes read.
+pub async fn read<R: AsyncRead + ?Sized>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    poll_fn(|cx| r.poll_read(cx, buf)).await
+}
+
+/// Write all of `buf` to `w`.
+pub async fn write_all<W: AsyncWrite + ?Sized>(w: &mut W, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match poll_fn(|cx| w.poll_write(cx, buf)).await? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => buf = &buf[n..],
+        }
+    }
+
+    Ok(())
+}
+
+/// Flush `w`.
+pub async fn flush<W: AsyncWrite + ?Sized>(w: &mut W) -> io::Result<()> {
+    poll_fn(|cx| w.poll_flush(cx)).await
+}
+
+/// Create two connected in-process streams, for wiring protocol code to
+/// tests or running a client and server on one executor.
+///
+/// Backed by a `socketpair`, with each direction buffering roughly
+/// `max_buf` bytes, so a writer that outruns its reader waits as it would
+/// over a real connection. The kernel rounds `max_buf` up to its minimum.
+pub fn duplex(max_buf: usize) -> io::Result<(UnixStream, UnixStream)> {
+    let (a, b) = UnixStream::pair()?;
+
+    for (tx, rx) in [(&a, &b), (&b, &a)] {
+        rustix::net::sockopt::set_socket_send_buffer_size(tx.as_fd(), max_buf)
+            .and_then(|()| rustix::net::sockopt::set_socket_recv_buffer_size(rx.as_fd(), max_buf))
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+    }
+
+    Ok((a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{LocalExecutor, spawn_local};
+
+    /// Accepts at most `limit` bytes per write.
+    struct Trickle {
+        data: Vec<u8>,
+        limit: usize,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(&mut self, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            let n = buf.len().min(self.limit);
+            self.data.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    #[test]
+    fn write_all_loops_over_short_writes() {
+        LocalExecutor::new().block_on(async {
+            let mut sink = Trickle {
+                data: Vec::new(),
+                limit: 3,
+            };
+
+            write_all(&mut sink, b"hello world").await.unwrap();
+            flush(&mut &mut sink).await.unwrap();
+            assert_eq!(sink.data, b"hello world");
+        });
+    }
+
+    #[test]
+    fn write_all_fails_on_zero_write() {
+        LocalExecutor::new().block_on(async {
+            let mut sink: Box<dyn AsyncWrite> = Box::new(Trickle {
+                data: Vec::new(),
+                limit: 0,
+            });
+
+            let err = write_all(&mut sink, b"x").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+            write_all(&mut sink, b"").await.unwrap();
+        });
+    }
+
+    #[test]
+    fn unix_stream_is_a_sink() {
+        LocalExecutor::new().block_on(async {
+            let (mut a, b) = UnixStream::pair().unwrap();
+
+            write_all(&mut a, b"a").await.unwrap();
+            write_all(&mut &a, b"b").await.unwrap();
+
+            let mut buf = [0; 2];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 2);
+            assert_eq!(&buf, b"ab");
+        });
+    }
+
+    #[test]
+    fn duplex_applies_backpressure() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = duplex(4096).unwrap();
+            let data = vec![7; 1 << 20];
+
+            // Far more than the buffers hold, so one write comes up short.
+            let first = a.write(&data).await.unwrap();
+            assert!(first < data.len());
+
+            let reader = spawn_local(async move {
+                let mut total = 0;
+                let mut buf = [0; 8192];
+                loop {
+                    match b.read(&mut buf).await.unwrap() {
+                        0 => return total,
+                        n => total += n,
+                    }
+                }
+            });
+
+            a.write_all(&data).await.unwrap();
+            drop(a);
+            assert_eq!(reader.await.unwrap(), first + data.len());
+        });
+    }
+}
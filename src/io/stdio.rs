@@ -0,0 +1,332 @@
+use crate::{
+    io::{AsyncRead, AsyncWrite},
+    reactor::AsyncFd,
+    runtime::blocking::{self, Blocking},
+};
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    fs::{self, FileType},
+    net::{self, RecvFlags, SendFlags},
+};
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+/// Largest read handed to the blocking pool at once.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// The standard input of the process, as an [`AsyncRead`].
+///
+/// If stdin is a socket, it is read with `MSG_DONTWAIT` and waited on by
+/// the reactor. Anything else, such as a tty, a pipe or a file, shares its
+/// open file description with other processes, so it is not switched to
+/// non-blocking mode; reads go to the blocking pool instead. Such a read
+/// keeps running when its future is dropped, and what it returns is kept
+/// for the next read on the same `Stdin`, so keep one for the whole
+/// program.
+pub fn stdin() -> io::Result<Stdin> {
+    Stdin::new(io::stdin().as_fd())
+}
+
+/// The standard output of the process, as an [`AsyncWrite`].
+///
+/// Works like [`stdin`]: sockets are written without blocking, anything
+/// else through the blocking pool, as with [`fs::File`](crate::fs::File).
+/// Writes are not buffered beyond that; wrap it in a
+/// [`BufWriter`](super::BufWriter) for that.
+pub fn stdout() -> io::Result<Stdout> {
+    Ok(Stdout(Writer::new(io::stdout().as_fd())?))
+}
+
+/// The standard error of the process, as an [`AsyncWrite`].
+///
+/// See [`stdout`].
+pub fn stderr() -> io::Result<Stderr> {
+    Ok(Stderr(Writer::new(io::stderr().as_fd())?))
+}
+
+/// How a standard stream is driven.
+enum Backend<T> {
+    Socket(AsyncFd<OwnedFd>),
+    Blocking {
+        fd: Arc<OwnedFd>,
+        /// The operation started on the pool and not awaited yet.
+        in_flight: Option<Blocking<T>>,
+    },
+}
+
+impl<T> Backend<T> {
+    /// Take a private copy of `fd`, registering it if it is a socket.
+    fn new(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        let fd = rustix::io::fcntl_dupfd_cloexec(fd, 0)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        let stat = fs::fstat(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        if FileType::from_raw_mode(stat.st_mode) == FileType::Socket {
+            return Ok(Self::Socket(AsyncFd::new(fd)?));
+        }
+
+        Ok(Self::Blocking {
+            fd: Arc::new(fd),
+            in_flight: None,
+        })
+    }
+
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Socket(fd) => fd.as_fd(),
+            Self::Blocking { fd, .. } => fd.as_fd(),
+        }
+    }
+}
+
+/// Async handle to standard input; see [`stdin`].
+pub struct Stdin {
+    backend: Backend<Vec<u8>>,
+    /// Data read by the pool beyond what the last caller asked for.
+    leftover: Vec<u8>,
+}
+
+impl Stdin {
+    fn new(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        Ok(Self {
+            backend: Backend::new(fd)?,
+            leftover: Vec::new(),
+        })
+    }
+}
+
+impl AsyncRead for Stdin {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if !self.leftover.is_empty() {
+            let n = buf.len().min(self.leftover.len());
+            buf[..n].copy_from_slice(&self.leftover[..n]);
+            self.leftover.drain(..n);
+            return Poll::Ready(Ok(n));
+        }
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        match &mut self.backend {
+            Backend::Socket(fd) => loop {
+                let mut ready = ready!(fd.poll_read_ready(cx));
+
+                if let Ok(result) = ready.try_io_bytes(|fd| {
+                    net::recv(fd, &mut *buf, RecvFlags::DONTWAIT)
+                        .map(|(n, _)| n)
+                        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+                }) {
+                    return Poll::Ready(result);
+                }
+            },
+            Backend::Blocking { fd, in_flight } => {
+                let read = in_flight.get_or_insert_with(|| {
+                    let fd = fd.clone();
+                    let len = buf.len().min(READ_CHUNK);
+
+                    blocking::run(move || {
+                        let mut data = vec![0; len];
+                        let n = rustix::io::read(&fd, &mut data[..])
+                            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+                        data.truncate(n);
+                        Ok(data)
+                    })
+                });
+
+                let result = ready!(Pin::new(read).poll(cx));
+                *in_flight = None;
+
+                let mut data = result?;
+                let n = buf.len().min(data.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                data.drain(..n);
+                self.leftover = data;
+
+                Poll::Ready(Ok(n))
+            }
+        }
+    }
+}
+
+impl AsFd for Stdin {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.backend.as_fd()
+    }
+}
+
+impl fmt::Debug for Stdin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stdin")
+            .field("fd", &self.as_fd())
+            .field("buffered", &self.leftover.len())
+            .finish()
+    }
+}
+
+/// The shared half of [`Stdout`] and [`Stderr`].
+struct Writer {
+    backend: Backend<()>,
+}
+
+impl Writer {
+    fn new(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        Ok(Self {
+            backend: Backend::new(fd)?,
+        })
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match &mut self.backend {
+            Backend::Socket(fd) => loop {
+                let mut ready = ready!(fd.poll_write_ready(cx));
+
+                if let Ok(result) = ready.try_io_bytes(|fd| {
+                    net::send(fd, buf, SendFlags::DONTWAIT | SendFlags::NOSIGNAL)
+                        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+                }) {
+                    return Poll::Ready(result);
+                }
+            },
+            Backend::Blocking { fd, in_flight } => {
+                ready!(poll_settle(in_flight, cx))?;
+
+                let fd = fd.clone();
+                let data = buf.to_vec();
+
+                *in_flight = Some(blocking::run(move || {
+                    let mut data = &data[..];
+
+                    while !data.is_empty() {
+                        match rustix::io::write(&fd, data) {
+                            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                            Ok(n) => data = &data[n..],
+                            Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+                        }
+                    }
+
+                    Ok(())
+                }));
+
+                Poll::Ready(Ok(buf.len()))
+            }
+        }
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.backend {
+            Backend::Socket(_) => Poll::Ready(Ok(())),
+            Backend::Blocking { in_flight, .. } => poll_settle(in_flight, cx),
+        }
+    }
+}
+
+/// Wait for the write in flight, if any.
+fn poll_settle(in_flight: &mut Option<Blocking<()>>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    let Some(write) = in_flight else {
+        return Poll::Ready(Ok(()));
+    };
+
+    let result = ready!(Pin::new(write).poll(cx));
+    *in_flight = None;
+    Poll::Ready(result)
+}
+
+/// Async handle to standard output; see [`stdout`].
+///
+/// Writes to a non-socket stdout run in the background like those of
+/// [`fs::File`](crate::fs::File): failures show up on the next write or
+/// flush.
+pub struct Stdout(Writer);
+
+/// Async handle to standard error; see [`stderr`].
+pub struct Stderr(Writer);
+
+macro_rules! writer_impls {
+    ($($ty:ident),*) => {$(
+        impl AsyncWrite for $ty {
+            fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+                self.0.poll_write(cx, buf)
+            }
+
+            fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                self.0.poll_flush(cx)
+            }
+        }
+
+        impl AsFd for $ty {
+            fn as_fd(&self) -> BorrowedFd<'_> {
+                self.0.backend.as_fd()
+            }
+        }
+
+        impl fmt::Debug for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_tuple(stringify!($ty)).field(&self.as_fd()).finish()
+            }
+        }
+    )*};
+}
+
+writer_impls!(Stdout, Stderr);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        io::{flush, read, write_all},
+        net::UnixStream,
+        runtime::LocalExecutor,
+    };
+    use rustix::pipe::{PipeFlags, pipe_with};
+
+    #[test]
+    fn pipes_go_through_the_pool() {
+        LocalExecutor::new().block_on(async {
+            let (reader, writer) = pipe_with(PipeFlags::CLOEXEC).unwrap();
+            let mut stdin = Stdin::new(reader.as_fd()).unwrap();
+            let mut stdout = Stdout(Writer::new(writer.as_fd()).unwrap());
+            drop(writer);
+            assert!(matches!(stdin.backend, Backend::Blocking { .. }));
+
+            write_all(&mut stdout, b"hello").await.unwrap();
+            flush(&mut stdout).await.unwrap();
+            drop(stdout);
+
+            let mut buf = [0; 2];
+            assert_eq!(read(&mut stdin, &mut buf).await.unwrap(), 2);
+            assert_eq!(&buf, b"he");
+
+            // The rest of the pool's read is kept, not lost.
+            let mut buf = [0; 8];
+            assert_eq!(read(&mut stdin, &mut buf).await.unwrap(), 3);
+            assert_eq!(&buf[..3], b"llo");
+            assert_eq!(read(&mut stdin, &mut buf).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn sockets_use_the_reactor() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            let mut stdin = Stdin::new(a.as_fd()).unwrap();
+            let mut stderr = Stderr(Writer::new(a.as_fd()).unwrap());
+            assert!(matches!(stdin.backend, Backend::Socket(_)));
+
+            write_all(&mut stderr, b"out").await.unwrap();
+            let mut buf = [0; 3];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 3);
+            assert_eq!(&buf, b"out");
+
+            b.write_all(b"in").await.unwrap();
+            assert_eq!(read(&mut stdin, &mut buf).await.unwrap(), 2);
+            assert_eq!(&buf[..2], b"in");
+        });
+    }
+}
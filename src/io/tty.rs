@@ -0,0 +1,415 @@
+use crate::{
+    io::{AsyncRead, AsyncWrite},
+    reactor::AsyncFd,
+};
+use rustix::{
+    event::{EventfdFlags, eventfd},
+    fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+    fs::{self, Mode, OFlags},
+    io::Errno,
+};
+use std::{
+    cell::OnceCell,
+    future::poll_fn,
+    io, mem,
+    path::Path,
+    sync::{
+        OnceLock,
+        atomic::{AtomicI32, Ordering},
+    },
+    task::{Context, Poll, ready},
+};
+
+/// The eventfd the `SIGWINCH` handler writes to; -1 until installed.
+static RESIZE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// A terminal driven by the reactor.
+///
+/// Terminals poll like sockets, but the file description of an inherited
+/// stdin or stdout is shared with the shell and other processes, so
+/// setting `O_NONBLOCK` on it would break them. [`open`](Self::open) and
+/// [`open_path`](Self::open_path) therefore open the device anew, getting
+/// a description of their own to make non-blocking. A hang-up, which
+/// fails reads with `EIO`, reads as end of stream.
+#[derive(Debug)]
+pub struct Tty {
+    fd: AsyncFd<OwnedFd>,
+    /// Registered on the first [`resized`](Self::resized).
+    resize: OnceCell<AsyncFd<OwnedFd>>,
+}
+
+/// A terminal's size in character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WinSize {
+    /// Number of rows.
+    pub rows: u16,
+    /// Number of columns.
+    pub cols: u16,
+}
+
+impl Tty {
+    /// Open the process's controlling terminal, `/dev/tty`.
+    pub fn open() -> io::Result<Self> {
+        Self::open_path("/dev/tty")
+    }
+
+    /// Open the terminal device at `path`, e.g. a pty's secondary side,
+    /// without making it the controlling terminal.
+    pub fn open_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let fd = fs::open(
+            path.as_ref(),
+            OFlags::RDWR | OFlags::NOCTTY | OFlags::NONBLOCK | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Self::new(fd)
+    }
+
+    /// Wrap an open terminal, switching it to non-blocking mode.
+    ///
+    /// Only for descriptors nobody else uses; see the type docs.
+    pub fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        let flags = rustix::fs::fcntl_getfl(&fd)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        rustix::fs::fcntl_setfl(&fd, flags | OFlags::NONBLOCK)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Self::new(fd)
+    }
+
+    fn new(fd: OwnedFd) -> io::Result<Self> {
+        // SAFETY: `fd` is a valid descriptor.
+        if unsafe { libc::isatty(fd.as_raw_fd()) } != 1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            resize: OnceCell::new(),
+        })
+    }
+
+    /// Read into `buf`, returning the number of bytes read; 0 means end of
+    /// stream or hang-up.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    /// Write some of `buf`, returning the number of bytes written.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    /// Write all of `buf`.
+    pub async fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Poll-based [`read`](Self::read), for implementing I/O traits.
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| match rustix::io::read(fd, &mut *buf) {
+                Ok(n) => Ok(n),
+                Err(Errno::IO) => Ok(0),
+                Err(e) => Err(io::Error::from_raw_os_error(e.raw_os_error())),
+            }) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Poll-based [`write`](Self::write), for implementing I/O traits.
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_write_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| {
+                rustix::io::write(fd, buf)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            }) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// The terminal's current size.
+    pub fn size(&self) -> io::Result<WinSize> {
+        // SAFETY: `winsize` is a plain C struct for which all-zeroes is valid.
+        let mut ws: libc::winsize = unsafe { mem::zeroed() };
+
+        // SAFETY: The fd is valid and `ws` outlives the call.
+        if unsafe { libc::ioctl(self.fd.get_ref().as_raw_fd(), libc::TIOCGWINSZ, &mut ws) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(WinSize {
+            rows: ws.ws_row,
+            cols: ws.ws_col,
+        })
+    }
+
+    /// Switch the terminal to raw mode until the returned guard is dropped.
+    ///
+    /// Input is then passed on byte by byte, without echo, line editing or
+    /// signal keys, and output without newline translation.
+    pub fn raw_mode(&self) -> io::Result<RawMode<'_>> {
+        let fd = self.fd.get_ref().as_raw_fd();
+
+        // SAFETY: `termios` is a plain C struct for which all-zeroes is
+        // valid, and it outlives the calls.
+        unsafe {
+            let mut saved: libc::termios = mem::zeroed();
+
+            if libc::tcgetattr(fd, &mut saved) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = saved;
+            libc::cfmakeraw(&mut raw);
+
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(RawMode { tty: self, saved })
+        }
+    }
+
+    /// Wait until the terminal may have been resized, returning its size.
+    ///
+    /// Relies on `SIGWINCH`, for which the first call installs a handler;
+    /// resizes before that are missed. Installing fails with `Unsupported`
+    /// if the application already handles the signal. Every resize in the
+    /// process counts, and only one task in the process should wait for
+    /// them at a time.
+    pub async fn resized(&self) -> io::Result<WinSize> {
+        let resize = match self.resize.get() {
+            Some(resize) => resize,
+            None => {
+                let fd = resize_fd()?;
+                let dup = rustix::io::fcntl_dupfd_cloexec(fd, 0)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+                let _ = self.resize.set(AsyncFd::new(dup)?);
+                self.resize.get().unwrap()
+            }
+        };
+
+        loop {
+            let mut ready = resize.readable().await;
+
+            let result = ready.try_io(|fd| {
+                let mut buf = [0; 8];
+                rustix::io::read(fd, &mut buf)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            });
+
+            if let Ok(Ok(_)) = result {
+                return self.size();
+            }
+        }
+    }
+}
+
+impl AsFd for Tty {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsyncRead for Tty {
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Tty::poll_read(self, cx, buf)
+    }
+}
+
+impl AsyncWrite for Tty {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Tty::poll_write(self, cx, buf)
+    }
+}
+
+/// Keeps a [`Tty`] in raw mode; restores the previous settings on drop.
+#[derive(Debug)]
+#[must_use = "the terminal leaves raw mode when the guard is dropped"]
+pub struct RawMode<'a> {
+    tty: &'a Tty,
+    saved: libc::termios,
+}
+
+impl Drop for RawMode<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The fd is valid and `saved` came from `tcgetattr`.
+        unsafe {
+            libc::tcsetattr(
+                self.tty.fd.get_ref().as_raw_fd(),
+                libc::TCSANOW,
+                &self.saved,
+            )
+        };
+    }
+}
+
+/// The eventfd written on each `SIGWINCH`, installing the handler on
+/// first use.
+fn resize_fd() -> io::Result<BorrowedFd<'static>> {
+    static FD: OnceLock<Result<OwnedFd, i32>> = OnceLock::new();
+
+    let fd = FD
+        .get_or_init(|| {
+            let fd = eventfd(0, EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK)
+                .map_err(|e| e.raw_os_error())?;
+
+            RESIZE_FD.store(fd.as_raw_fd(), Ordering::Release);
+
+            if install_handler() {
+                Ok(fd)
+            } else {
+                RESIZE_FD.store(-1, Ordering::Release);
+                Err(-1)
+            }
+        })
+        .as_ref()
+        .map_err(|&e| match e {
+            -1 => io::Error::new(io::ErrorKind::Unsupported, "SIGWINCH is already handled"),
+            e => io::Error::from_raw_os_error(e),
+        })?;
+
+    Ok(fd.as_fd())
+}
+
+/// Install the `SIGWINCH` handler, unless the signal already has one.
+fn install_handler() -> bool {
+    extern "C" fn on_resize(_: libc::c_int) {
+        let fd = RESIZE_FD.load(Ordering::Acquire);
+
+        if fd >= 0 {
+            // SAFETY: write(2) and errno access are async-signal-safe, and
+            // the eventfd stays open for the life of the process.
+            unsafe {
+                let errno = *libc::__errno_location();
+                let one = 1u64;
+                libc::write(fd, (&raw const one).cast(), 8);
+                *libc::__errno_location() = errno;
+            }
+        }
+    }
+
+    // SAFETY: The handler only does async-signal-safe work, and the
+    // sigaction structs are fully initialised before use.
+    unsafe {
+        let mut old: libc::sigaction = mem::zeroed();
+
+        if libc::sigaction(libc::SIGWINCH, std::ptr::null(), &mut old) != 0
+            || (old.sa_sigaction != libc::SIG_DFL && old.sa_sigaction != libc::SIG_IGN)
+        {
+            return false;
+        }
+
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGWINCH, &action, std::ptr::null_mut()) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, time::timeout};
+    use std::{ffi::CStr, time::Duration};
+
+    /// Open a pty, returning its primary side and the path of the other.
+    fn pty() -> (OwnedFd, String) {
+        // SAFETY: Standard pty setup; `ptsname` is read before any other
+        // call could overwrite its buffer.
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+            assert!(fd >= 0, "{}", io::Error::last_os_error());
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+            let name = CStr::from_ptr(libc::ptsname(fd))
+                .to_str()
+                .unwrap()
+                .to_owned();
+            (std::os::fd::FromRawFd::from_raw_fd(fd), name)
+        }
+    }
+
+    fn lflag(tty: &Tty) -> libc::tcflag_t {
+        // SAFETY: As in `raw_mode`.
+        unsafe {
+            let mut t: libc::termios = mem::zeroed();
+            assert_eq!(libc::tcgetattr(tty.fd.get_ref().as_raw_fd(), &mut t), 0);
+            t.c_lflag
+        }
+    }
+
+    #[test]
+    fn raw_mode_is_restored_on_drop() {
+        let (_primary, path) = pty();
+        LocalExecutor::new().block_on(async {
+            let tty = Tty::open_path(&path).unwrap();
+            assert_ne!(lflag(&tty) & libc::ICANON, 0);
+
+            let raw = tty.raw_mode().unwrap();
+            assert_eq!(lflag(&tty) & (libc::ICANON | libc::ECHO), 0);
+            drop(raw);
+
+            assert_ne!(lflag(&tty) & libc::ICANON, 0);
+        });
+    }
+
+    #[test]
+    fn reads_input_and_reports_resizes() {
+        let (primary, path) = pty();
+        LocalExecutor::new().block_on(async {
+            let tty = Tty::open_path(&path).unwrap();
+            let _raw = tty.raw_mode().unwrap();
+
+            rustix::io::write(&primary, b"q").unwrap();
+            let mut buf = [0; 4];
+            assert_eq!(tty.read(&mut buf).await.unwrap(), 1);
+            assert_eq!(buf[0], b'q');
+
+            let ws = libc::winsize {
+                ws_row: 24,
+                ws_col: 80,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            // SAFETY: `primary` is a valid pty and `ws` outlives the call.
+            assert_eq!(
+                unsafe { libc::ioctl(primary.as_raw_fd(), libc::TIOCSWINSZ, &ws) },
+                0
+            );
+
+            // Not our controlling terminal, so raise the signal ourselves,
+            // once the first poll has installed the handler.
+            let (size, ()) = crate::join!(timeout(Duration::from_secs(5), tty.resized()), async {
+                crate::runtime::yield_now().await;
+                // SAFETY: raise() has no preconditions.
+                unsafe { libc::raise(libc::SIGWINCH) };
+            });
+            let size = size.unwrap().unwrap();
+            assert_eq!(size, WinSize { rows: 24, cols: 80 });
+        });
+    }
+
+    #[test]
+    fn rejects_non_terminals() {
+        LocalExecutor::new().block_on(async {
+            assert!(Tty::open_path("/dev/null").is_err());
+        });
+    }
+}
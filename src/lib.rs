@@ -1 +1,13 @@
+pub mod fs;
+pub mod io;
+#[doc(hidden)]
+pub mod macros;
+pub mod net;
+pub mod prelude;
+pub mod process;
+pub mod reactor;
+pub mod runtime;
+#[cfg(test)]
+mod test_util;
+pub mod time;
 pub mod utils;
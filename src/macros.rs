@@ -0,0 +1,342 @@
+//! Support code for [`select!`](crate::select) and [`join!`](crate::join).
+
+use std::{
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Wait on several futures at once, running the branch of the first to finish.
+///
+/// ```text
+/// select! {
+///     biased;                          // optional: poll in order, not round-robin
+///     Some(msg) = rx.recv() => handle(msg),
+///     _ = sleep(timeout) => { on_timeout(); }
+///     default => idle(),               // optional: run if nothing is ready yet
+/// }
+/// ```
+///
+/// Each branch is `pattern = future => handler`. Futures are evaluated once,
+/// pinned on the stack and polled in place, so `&mut`-borrowing futures such
+/// as `rx.recv()` need no fusing or boxing. When one completes, its output
+/// is matched against the pattern: on a match, every future is dropped and
+/// the handler runs outside the macro's own loop, so `break`, `continue`,
+/// `return` and `?` act on the surrounding code. Otherwise the branch is
+/// disabled and the rest keep waiting.
+///
+/// Branches are polled starting from a rotating position, so an always-ready
+/// branch cannot starve the others; `biased;` polls them in order instead.
+/// A `default` branch runs when no branch is ready on the first poll.
+///
+/// Patterns are checked by reference first, so they cannot use `mut`
+/// bindings; rebind inside the handler instead.
+///
+/// # Panics
+///
+/// Panics if every branch is disabled and there is no `default` branch.
+#[macro_export]
+macro_rules! select {
+    (@parse [$($biased:tt)*] [$($br:tt)*] [$($def:tt)*]) => {
+        $crate::select!(@emit [$($biased)*] [$($br)*] [$($def)*])
+    };
+    (@parse $biased:tt $br:tt [] default => $body:block $(,)? $($rest:tt)*) => {
+        $crate::select!(@parse $biased $br [$body] $($rest)*)
+    };
+    (@parse $biased:tt $br:tt [] default => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::select!(@parse $biased $br [{ $body }] $($($rest)*)?)
+    };
+    (@parse $biased:tt [$($br:tt)*] $def:tt $p:pat = $f:expr => $body:block $(,)? $($rest:tt)*) => {
+        $crate::select!(@parse $biased [$($br)* ((fut out dis) ($p) ($f) ($body))] $def $($rest)*)
+    };
+    (@parse $biased:tt [$($br:tt)*] $def:tt $p:pat = $f:expr => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::select!(@parse $biased [$($br)* ((fut out dis) ($p) ($f) ($body))] $def $($($rest)*)?)
+    };
+
+    (@has_default) => { false };
+    (@has_default $body:block) => { true };
+
+    (@default) => { ::core::unreachable!() };
+    (@default $body:block) => { $body };
+
+    (@emit [$biased:expr] [$( (($fut:ident $out:ident $dis:ident) ($p:pat) ($f:expr) ($body:expr)) )*] [$($def:block)?]) => {{
+        use ::core::{future::Future as _, task::Poll};
+
+        $( let mut $out = ::core::option::Option::None; )*
+
+        let __default = {
+            $( let mut $fut = ::core::pin::pin!($f); let mut $dis = false; )*
+
+            const __N: usize = [$(::core::stringify!($fut)),*].len();
+            let __start = if $biased { 0 } else { $crate::macros::rotate(__N) };
+
+            ::core::future::poll_fn(|__cx| {
+                for __i in 0..__N {
+                    let __pick = $crate::macros::nth(__start, __i, __N);
+                    let mut __k = 0usize;
+
+                    $(
+                        if __pick == __k && !$dis {
+                            if let Poll::Ready(__v) = $fut.as_mut().poll(__cx) {
+                                #[allow(unused_variables, unreachable_patterns)]
+                                let __matched = ::core::matches!(&__v, $p);
+
+                                if __matched {
+                                    $out = ::core::option::Option::Some(__v);
+                                    return Poll::Ready(false);
+                                }
+
+                                $dis = true;
+                            }
+                        }
+
+                        __k += 1;
+                    )*
+                }
+
+                if $crate::select!(@has_default $($def)?) {
+                    return Poll::Ready(true);
+                }
+
+                if true $(&& $dis)* {
+                    ::core::panic!("all select! branches are disabled and there is no default");
+                }
+
+                Poll::Pending
+            })
+            .await
+        };
+
+        if __default {
+            $crate::select!(@default $($def)?)
+        }
+        $(
+            else if let ::core::option::Option::Some(__v) = $out {
+                #[allow(unreachable_patterns)]
+                match __v {
+                    $p => $body,
+                    _ => ::core::unreachable!(),
+                }
+            }
+        )*
+        else {
+            ::core::unreachable!()
+        }
+    }};
+
+    (biased; $($t:tt)*) => {
+        $crate::select!(@parse [true] [] [] $($t)*)
+    };
+    ($($t:tt)*) => {
+        $crate::select!(@parse [false] [] [] $($t)*)
+    };
+}
+
+/// Wait for several futures concurrently, returning a tuple of their outputs.
+///
+/// `join!(a, b, c)` polls every unfinished future each time the task is
+/// woken and resolves to `(a_out, b_out, c_out)` once all have completed.
+/// Like [`select!`], it must be used inside an async context and pins the
+/// futures on the stack, so they need not be `Unpin`, `Send` or fused.
+#[macro_export]
+macro_rules! join {
+    (@acc [$( ($fut:ident $f:expr) )*]) => {{
+        use ::core::task::Poll;
+
+        $( let mut $fut = ::core::pin::pin!($crate::macros::MaybeDone::new($f)); )*
+
+        ::core::future::poll_fn(|__cx| {
+            let mut __done = true;
+            $( __done &= $fut.as_mut().poll_done(__cx); )*
+
+            if !__done {
+                return Poll::Pending;
+            }
+
+            Poll::Ready(($( $fut.as_mut().take_output(), )*))
+        })
+        .await
+    }};
+    (@acc [$($acc:tt)*] $f:expr $(, $($rest:tt)*)?) => {
+        $crate::join!(@acc [$($acc)* (fut $f)] $($($rest)*)?)
+    };
+    ($($t:tt)+) => {
+        $crate::join!(@acc [] $($t)+)
+    };
+}
+
+thread_local! {
+    static NEXT_START: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Where an unbiased [`select!`] with `n` branches starts polling.
+#[doc(hidden)]
+pub fn rotate(n: usize) -> usize {
+    if n == 0 {
+        return 0;
+    }
+
+    let start = NEXT_START.get();
+    NEXT_START.set(start.wrapping_add(1));
+    start % n
+}
+
+/// The `i`th branch to poll when starting from `start`.
+#[doc(hidden)]
+pub fn nth(start: usize, i: usize, n: usize) -> usize {
+    (start + i) % n
+}
+
+/// A future in [`join!`], keeping its output once it completes.
+#[doc(hidden)]
+pub enum MaybeDone<F: Future> {
+    Future(F),
+    Done(F::Output),
+    Taken,
+}
+
+impl<F: Future> MaybeDone<F> {
+    pub fn new(fut: F) -> Self {
+        Self::Future(fut)
+    }
+
+    /// Poll the future if it is still running; `true` once it has completed.
+    pub fn poll_done(self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
+        // SAFETY: The future is never moved out; on completion it is dropped
+        // in place by overwriting the enum, which pinning allows.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if let Self::Future(fut) = this {
+            // SAFETY: `fut` is pinned because `self` is.
+            match unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                Poll::Ready(out) => *this = Self::Done(out),
+                Poll::Pending => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Take the output of a completed future.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future has not completed or its output was taken.
+    pub fn take_output(self: Pin<&mut Self>) -> F::Output {
+        // SAFETY: Only the `Done` variant, which holds no pinned data, is
+        // moved out.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        match this {
+            Self::Done(_) => match std::mem::replace(this, Self::Taken) {
+                Self::Done(out) => out,
+                _ => unreachable!(),
+            },
+            _ => panic!("join! output taken before completion"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        runtime::{LocalExecutor, spawn_local, yield_now},
+        time::sleep,
+        utils::channel::channel,
+    };
+    use std::{future::pending, time::Duration};
+
+    #[test]
+    fn select_runs_first_ready_branch() {
+        LocalExecutor::new().block_on(async {
+            let out = crate::select! {
+                _ = sleep(Duration::from_millis(50)) => "slow",
+                _ = sleep(Duration::from_millis(1)) => "fast",
+            };
+
+            assert_eq!(out, "fast");
+        });
+    }
+
+    #[test]
+    fn select_skips_branch_whose_pattern_fails() {
+        LocalExecutor::new().block_on(async {
+            let (tx, mut rx) = channel::<u32>();
+            drop(tx);
+
+            let out = crate::select! {
+                Some(n) = rx.recv() => n,
+                _ = sleep(Duration::from_millis(1)) => 0,
+            };
+
+            assert_eq!(out, 0);
+        });
+    }
+
+    #[test]
+    fn select_default_and_biased() {
+        LocalExecutor::new().block_on(async {
+            let out = crate::select! {
+                _ = pending::<()>() => 1,
+                default => 2,
+            };
+            assert_eq!(out, 2);
+
+            for _ in 0..4 {
+                let out = crate::select! {
+                    biased;
+                    _ = async {} => 1,
+                    _ = async {} => 2,
+                };
+                assert_eq!(out, 1);
+            }
+        });
+    }
+
+    #[test]
+    fn select_handler_controls_surrounding_loop() {
+        LocalExecutor::new().block_on(async {
+            let (tx, mut rx) = channel();
+            let producer = spawn_local(async move {
+                for i in 0..3 {
+                    tx.send(i).unwrap();
+                    yield_now().await;
+                }
+            });
+
+            let mut seen = Vec::new();
+
+            loop {
+                crate::select! {
+                    msg = rx.recv() => match msg {
+                        Some(i) => seen.push(i),
+                        None => break,
+                    },
+                }
+            }
+
+            producer.await.unwrap();
+            assert_eq!(seen, [0, 1, 2]);
+        });
+    }
+
+    #[test]
+    fn join_waits_for_all() {
+        LocalExecutor::new().block_on(async {
+            let (a, b, c) = crate::join!(
+                async {
+                    sleep(Duration::from_millis(2)).await;
+                    1
+                },
+                async { "two" },
+                async {
+                    yield_now().await;
+                    3.0
+                },
+            );
+
+            assert_eq!((a, b, c), (1, "two", 3.0));
+        });
+    }
+}
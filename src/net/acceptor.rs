@@ -0,0 +1,293 @@
+use crate::{
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    time::sleep,
+    utils::{
+        semaphore::{OwnedPermit, Semaphore},
+        tracked_cell::TrackedRefCell,
+        waiters::WaiterList,
+    },
+};
+use std::{
+    fmt,
+    future::poll_fn,
+    io,
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// A listening socket an [`Acceptor`] can take connections from.
+pub trait Listener {
+    /// What an accepted connection yields.
+    type Conn;
+
+    /// Poll for the next connection.
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Self::Conn>>;
+}
+
+impl Listener for UnixListener {
+    type Conn = UnixStream;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<UnixStream>> {
+        UnixListener::poll_accept(self, cx)
+    }
+}
+
+impl Listener for TcpListener {
+    type Conn = (TcpStream, SocketAddr);
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Self::Conn>> {
+        TcpListener::poll_accept(self, cx)
+    }
+}
+
+/// Accepts connections from a listener under a connection limit.
+///
+/// Each accepted [`Connection`] holds a permit of an async
+/// [`Semaphore`] until dropped, so [`accept`](Self::accept) waits once
+/// `max_connections` are open. Accepting can be [`pause`](Self::pause)d
+/// under pressure, leaving new connections in the kernel's backlog.
+/// Errors from running out of fds or memory are not returned but retried
+/// after a backoff, doubling from 5ms up to 1s, so the loop neither dies
+/// nor spins while the condition lasts.
+pub struct Acceptor<L> {
+    listener: L,
+    limit: Rc<Semaphore>,
+    pause: TrackedRefCell<Pause>,
+    backoff: TrackedRefCell<Option<BackoffHook>>,
+}
+
+struct Pause {
+    paused: bool,
+    waiters: WaiterList,
+}
+
+/// A connection taken by an [`Acceptor`], counted against its limit until
+/// dropped.
+pub struct Connection<C> {
+    conn: C,
+    _permit: OwnedPermit,
+}
+
+type BackoffHook = Box<dyn FnMut(&io::Error, Duration)>;
+
+/// First retry delay after a resource error.
+const MIN_BACKOFF: Duration = Duration::from_millis(5);
+/// Longest retry delay after consecutive resource errors.
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+impl<L: Listener> Acceptor<L> {
+    /// Accept from `listener`, keeping at most `max_connections` open.
+    pub fn new(listener: L, max_connections: usize) -> Self {
+        Self {
+            listener,
+            limit: Rc::new(Semaphore::new(max_connections)),
+            pause: TrackedRefCell::new(Pause {
+                paused: false,
+                waiters: WaiterList::new(),
+            }),
+            backoff: TrackedRefCell::new(None),
+        }
+    }
+
+    /// Wait for room under the limit and for accepting to be resumed, then
+    /// accept the next connection.
+    ///
+    /// Fails only with errors other than the resource errors that are
+    /// retried.
+    pub async fn accept(&self) -> io::Result<Connection<L::Conn>> {
+        let mut delay = MIN_BACKOFF;
+
+        loop {
+            let permit = self.limit.clone().acquire_owned(1).await;
+            let mut waiter = None;
+
+            let result = poll_fn(|cx| {
+                let mut pause = self.pause.borrow_mut();
+
+                if pause.paused {
+                    pause.waiters.register(&mut waiter, cx.waker());
+                    return Poll::Pending;
+                }
+
+                pause.waiters.remove(waiter.take());
+                drop(pause);
+                self.listener.poll_accept(cx)
+            })
+            .await;
+
+            match result {
+                Ok(conn) => {
+                    return Ok(Connection {
+                        conn,
+                        _permit: permit,
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::ConnectionAborted => {}
+                Err(e) if is_resource_error(&e) => {
+                    // Free the slot while backing off.
+                    drop(permit);
+
+                    if let Some(hook) = self.backoff.borrow_mut().as_mut() {
+                        hook(&e, delay);
+                    }
+
+                    sleep(delay).await;
+                    delay = (delay * 2).min(MAX_BACKOFF);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Stop accepting until [`resume`](Self::resume) is called.
+    ///
+    /// Pending connections wait in the listener's backlog.
+    pub fn pause(&self) {
+        self.pause.borrow_mut().paused = true;
+    }
+
+    /// Accept again after [`pause`](Self::pause).
+    pub fn resume(&self) {
+        let waiters = {
+            let mut pause = self.pause.borrow_mut();
+            pause.paused = false;
+            pause.waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+
+    /// Whether accepting is paused.
+    pub fn is_paused(&self) -> bool {
+        self.pause.borrow().paused
+    }
+
+    /// Call `f` with each resource error and the delay before the retry,
+    /// e.g. to log it.
+    pub fn on_backoff(&self, f: impl FnMut(&io::Error, Duration) + 'static) {
+        *self.backoff.borrow_mut() = Some(Box::new(f));
+    }
+
+    /// Raise the connection limit by `n`.
+    pub fn add_capacity(&self, n: usize) {
+        self.limit.add_permits(n);
+    }
+
+    /// Number of further connections the limit allows now.
+    pub fn available(&self) -> usize {
+        self.limit.available_permits()
+    }
+
+    /// The wrapped listener.
+    pub fn get_ref(&self) -> &L {
+        &self.listener
+    }
+}
+
+impl<L> fmt::Debug for Acceptor<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Acceptor")
+            .field("available", &self.limit.available_permits())
+            .field("paused", &self.pause.borrow().paused)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C> Connection<C> {
+    /// Take the connection out; it no longer counts against the limit.
+    pub fn into_inner(self) -> C {
+        self.conn
+    }
+}
+
+impl<C> Deref for Connection<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.conn
+    }
+}
+
+impl<C> DerefMut for Connection<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.conn
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for Connection<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Connection").field(&self.conn).finish()
+    }
+}
+
+fn is_resource_error(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, spawn_local, yield_now},
+        test_util::TempDir,
+    };
+
+    #[test]
+    fn limits_open_connections() {
+        let dir = TempDir::new();
+        let path = dir.join("sock");
+
+        LocalExecutor::new().block_on(async {
+            let acceptor = Rc::new(Acceptor::new(UnixListener::bind(&path).unwrap(), 1));
+            let _a = UnixStream::connect(&path).await.unwrap();
+            let _b = UnixStream::connect(&path).await.unwrap();
+
+            let first = acceptor.accept().await.unwrap();
+            assert_eq!(acceptor.available(), 0);
+
+            let second = spawn_local({
+                let acceptor = acceptor.clone();
+                async move { acceptor.accept().await.map(|_| ()) }
+            });
+
+            yield_now().await;
+            assert!(!second.is_finished());
+
+            drop(first);
+            second.await.unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn pause_holds_accepts_back() {
+        let dir = TempDir::new();
+        let path = dir.join("sock");
+
+        LocalExecutor::new().block_on(async {
+            let acceptor = Rc::new(Acceptor::new(UnixListener::bind(&path).unwrap(), 8));
+            acceptor.pause();
+            let _client = UnixStream::connect(&path).await.unwrap();
+
+            let accepted = spawn_local({
+                let acceptor = acceptor.clone();
+                async move { acceptor.accept().await.map(Connection::into_inner) }
+            });
+
+            yield_now().await;
+            yield_now().await;
+            assert!(!accepted.is_finished());
+
+            acceptor.resume();
+            accepted.await.unwrap().unwrap();
+            assert_eq!(acceptor.available(), 8);
+        });
+    }
+}
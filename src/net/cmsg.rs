@@ -0,0 +1,326 @@
+use crate::net::MAX_FDS;
+use rustix::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::{
+    io, mem, ptr,
+    time::{Duration, SystemTime},
+};
+
+/// Control messages to send along with data on a Unix socket.
+///
+/// Built up with [`fds`](Self::fds) and [`credentials`](Self::credentials)
+/// and passed to `send_with_control` on
+/// [`UnixStream`](super::UnixStream::send_with_control) or
+/// [`UnixDatagram`](super::UnixDatagram::send_with_control); the
+/// `cmsghdr` layout is taken care of.
+#[derive(Debug, Default, Clone)]
+pub struct Control<'fd> {
+    fds: Vec<BorrowedFd<'fd>>,
+    credentials: Option<Credentials>,
+}
+
+/// Process credentials passed as `SCM_CREDENTIALS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    /// Process id.
+    pub pid: i32,
+    /// User id.
+    pub uid: u32,
+    /// Group id.
+    pub gid: u32,
+}
+
+/// A control message received along with data, see `recv_with_control`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ControlMessage {
+    /// Fds passed by the sender (`SCM_RIGHTS`), close-on-exec.
+    Fds(Vec<OwnedFd>),
+    /// The sender's credentials, with `set_pass_credentials` enabled.
+    Credentials(Credentials),
+    /// When the message arrived, with `set_timestamps` enabled.
+    Timestamp(SystemTime),
+}
+
+impl<'fd> Control<'fd> {
+    /// No control messages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass `fds` to the receiver.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this makes more than [`MAX_FDS`] fds.
+    pub fn fds(mut self, fds: &[BorrowedFd<'fd>]) -> Self {
+        assert!(
+            self.fds.len() + fds.len() <= MAX_FDS,
+            "at most {MAX_FDS} fds per message"
+        );
+
+        self.fds.extend_from_slice(fds);
+        self
+    }
+
+    /// Send `credentials`; only privileged processes may send others than
+    /// [`Credentials::current`].
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+}
+
+impl Credentials {
+    /// The credentials of this process.
+    pub fn current() -> Self {
+        Self {
+            pid: rustix::process::getpid().as_raw_nonzero().get(),
+            uid: rustix::process::getuid().as_raw(),
+            gid: rustix::process::getgid().as_raw(),
+        }
+    }
+}
+
+/// Send `buf` with the messages of `control` on a non-blocking socket.
+pub(crate) fn send(fd: BorrowedFd<'_>, buf: &[u8], control: &Control<'_>) -> io::Result<usize> {
+    let fds_len = mem::size_of_val(control.fds.as_slice());
+    let mut space = 0;
+
+    if !control.fds.is_empty() {
+        space += cmsg_space(fds_len);
+    }
+    if control.credentials.is_some() {
+        space += cmsg_space(mem::size_of::<libc::ucred>());
+    }
+
+    // `u64`s keep the buffer aligned for `cmsghdr`.
+    let mut buffer = vec![0u64; space.div_ceil(8)];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut _,
+        iov_len: buf.len(),
+    };
+
+    // SAFETY: all-zeroes is a valid `msghdr`.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    if space > 0 {
+        msg.msg_control = buffer.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+    }
+
+    // SAFETY: the buffer holds exactly the headers written here, each
+    // followed by its data, as laid out by the `CMSG_*` helpers.
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        if !control.fds.is_empty() {
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len as _) as _;
+
+            let data = libc::CMSG_DATA(cmsg).cast::<libc::c_int>();
+            for (i, fd) in control.fds.iter().enumerate() {
+                ptr::write_unaligned(data.add(i), fd.as_raw_fd());
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+
+        if let Some(creds) = control.credentials {
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_CREDENTIALS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<libc::ucred>() as _) as _;
+
+            let ucred = libc::ucred {
+                pid: creds.pid,
+                uid: creds.uid,
+                gid: creds.gid,
+            };
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast(), ucred);
+        }
+    }
+
+    // SAFETY: `msg` points at `iov`, `buf` and `buffer`, all alive here.
+    let n = unsafe { libc::sendmsg(fd.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) };
+
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(n as usize)
+}
+
+/// Receive into `buf` on a non-blocking socket, appending the control
+/// messages that came along to `out`.
+pub(crate) fn recv(
+    fd: BorrowedFd<'_>,
+    buf: &mut [u8],
+    out: &mut Vec<ControlMessage>,
+) -> io::Result<usize> {
+    let space = cmsg_space(MAX_FDS * mem::size_of::<libc::c_int>())
+        + cmsg_space(mem::size_of::<libc::ucred>())
+        + cmsg_space(mem::size_of::<libc::timeval>());
+
+    let mut buffer = vec![0u64; space.div_ceil(8)];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+
+    // SAFETY: all-zeroes is a valid `msghdr`.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = buffer.as_mut_ptr().cast();
+    msg.msg_controllen = space as _;
+
+    // SAFETY: `msg` points at `iov`, `buf` and `buffer`, all alive here.
+    let n = unsafe { libc::recvmsg(fd.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: the kernel filled in `msg_controllen` bytes of well-formed
+    // headers, which the `CMSG_*` helpers walk within bounds.
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        while !cmsg.is_null() {
+            let data = libc::CMSG_DATA(cmsg);
+            let len = (*cmsg).cmsg_len as usize - (data as usize - cmsg as usize);
+
+            match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                (libc::SOL_SOCKET, libc::SCM_RIGHTS) => {
+                    let data = data.cast::<libc::c_int>();
+                    let fds = (0..len / mem::size_of::<libc::c_int>())
+                        .map(|i| OwnedFd::from_raw_fd(ptr::read_unaligned(data.add(i))))
+                        .collect();
+
+                    out.push(ControlMessage::Fds(fds));
+                }
+                (libc::SOL_SOCKET, libc::SCM_CREDENTIALS) => {
+                    let ucred: libc::ucred = ptr::read_unaligned(data.cast());
+
+                    out.push(ControlMessage::Credentials(Credentials {
+                        pid: ucred.pid,
+                        uid: ucred.uid,
+                        gid: ucred.gid,
+                    }));
+                }
+                (libc::SOL_SOCKET, libc::SCM_TIMESTAMP) => {
+                    let tv: libc::timeval = ptr::read_unaligned(data.cast());
+                    let since_epoch = Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+
+                    out.push(ControlMessage::Timestamp(
+                        SystemTime::UNIX_EPOCH + since_epoch,
+                    ));
+                }
+                _ => {}
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "ancillary data truncated",
+        ));
+    }
+
+    Ok(n as usize)
+}
+
+/// Set a boolean `SOL_SOCKET` option.
+pub(crate) fn set_flag(fd: BorrowedFd<'_>, option: libc::c_int, on: bool) -> io::Result<()> {
+    let value = libc::c_int::from(on);
+
+    // SAFETY: `value` outlives the call and its size is passed along.
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            (&value as *const libc::c_int).cast(),
+            mem::size_of_val(&value) as _,
+        )
+    };
+
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+fn cmsg_space(len: usize) -> usize {
+    // SAFETY: pure arithmetic.
+    unsafe { libc::CMSG_SPACE(len as _) as usize }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::{UnixDatagram, UnixStream},
+        runtime::LocalExecutor,
+    };
+    use rustix::fd::AsFd;
+
+    #[test]
+    fn passes_fds_and_credentials() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            let (r, w) = rustix::pipe::pipe().unwrap();
+            b.set_pass_credentials(true).unwrap();
+
+            let control = Control::new()
+                .fds(&[r.as_fd(), w.as_fd()])
+                .credentials(Credentials::current());
+            assert_eq!(a.send_with_control(b"x", &control).await.unwrap(), 1);
+
+            let mut buf = [0; 4];
+            let mut out = Vec::new();
+            assert_eq!(b.recv_with_control(&mut buf, &mut out).await.unwrap(), 1);
+
+            let mut fds = None;
+            let mut creds = None;
+            for message in out {
+                match message {
+                    ControlMessage::Fds(received) => fds = Some(received),
+                    ControlMessage::Credentials(c) => creds = Some(c),
+                    ControlMessage::Timestamp(_) => unreachable!(),
+                }
+            }
+
+            assert_eq!(fds.unwrap().len(), 2);
+            assert_eq!(creds, Some(Credentials::current()));
+
+            let err = a.send_with_control(b"", &Control::new()).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        });
+    }
+
+    #[test]
+    fn stamps_datagrams() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixDatagram::pair().unwrap();
+            b.set_timestamps(true).unwrap();
+
+            let before = SystemTime::now();
+            a.send_with_control(b"", &Control::new()).await.unwrap();
+
+            let mut out = Vec::new();
+            assert_eq!(b.recv_with_control(&mut [0; 4], &mut out).await.unwrap(), 0);
+
+            let [ControlMessage::Timestamp(at)] = out.as_slice() else {
+                panic!("no timestamp: {out:?}");
+            };
+            assert!(*at + Duration::from_millis(1) >= before);
+        });
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    time::timeout,
+    utils::{cancel::CancellationToken, tracked_cell::TrackedRefCell, waiters::WaiterList},
+};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Coordinates a graceful shutdown of a server's connections.
+///
+/// Each connection holds a [`DrainGuard`] from [`track`](Self::track).
+/// [`drain`](Self::drain) first cancels the [`stop`](Self::stop) token, so
+/// accept loops selecting on it quit and no further guards are handed out,
+/// then waits for the guards to be dropped. Connections still open once
+/// the grace period is over have their guard's
+/// [`cancelled`](DrainGuard::cancelled) future resolve, to be closed by
+/// whatever task serves them.
+///
+/// Built with [`with_parent`](Self::with_parent) from the executor's
+/// [`shutdown_token`](crate::runtime::shutdown_token), accepting stops as
+/// soon as the executor starts shutting down.
+pub struct Drain {
+    stop: CancellationToken,
+    abort: CancellationToken,
+    state: Rc<TrackedRefCell<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    active: usize,
+    idle: WaiterList,
+}
+
+impl Drain {
+    /// Create a drain with no connections.
+    pub fn new() -> Self {
+        Self::with_parent(&CancellationToken::new())
+    }
+
+    /// Create a drain whose [`stop`](Self::stop) token is cancelled along
+    /// with `parent`.
+    pub fn with_parent(parent: &CancellationToken) -> Self {
+        Self {
+            stop: parent.child_token(),
+            abort: CancellationToken::new(),
+            state: Rc::new(TrackedRefCell::new(State {
+                active: 0,
+                idle: WaiterList::new(),
+            })),
+        }
+    }
+
+    /// Cancelled once draining starts; accept loops should stop on it.
+    pub fn stop(&self) -> &CancellationToken {
+        &self.stop
+    }
+
+    /// Whether draining has started.
+    pub fn is_draining(&self) -> bool {
+        self.stop.is_cancelled()
+    }
+
+    /// Register a connection, keeping the drain waiting until the guard
+    /// is dropped.
+    ///
+    /// Returns `None` once draining has started.
+    pub fn track(&self) -> Option<DrainGuard> {
+        if self.is_draining() {
+            return None;
+        }
+
+        self.state.borrow_mut().active += 1;
+
+        Some(DrainGuard {
+            abort: self.abort.clone(),
+            state: self.state.clone(),
+        })
+    }
+
+    /// Number of connections whose guards are alive.
+    pub fn active(&self) -> usize {
+        self.state.borrow().active
+    }
+
+    /// Wait until no guards are alive.
+    pub fn idle(&self) -> impl Future<Output = ()> + '_ {
+        Idle {
+            state: &self.state,
+            key: None,
+        }
+    }
+
+    /// Stop accepting, wait up to `grace` for connections to finish, then
+    /// cancel the rest.
+    ///
+    /// Returns how many connections were still open and got cancelled. It
+    /// does not wait for them to close after that; await
+    /// [`idle`](Self::idle) for that.
+    pub async fn drain(&self, grace: Duration) -> usize {
+        self.stop.cancel();
+
+        if timeout(grace, self.idle()).await.is_ok() {
+            return 0;
+        }
+
+        let remaining = self.active();
+        self.abort.cancel();
+        remaining
+    }
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Drain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drain")
+            .field("active", &self.active())
+            .field("draining", &self.is_draining())
+            .finish()
+    }
+}
+
+struct Idle<'a> {
+    state: &'a TrackedRefCell<State>,
+    key: Option<u64>,
+}
+
+impl Future for Idle<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        let mut state = this.state.borrow_mut();
+
+        if state.active == 0 {
+            state.idle.remove(this.key.take());
+            return Poll::Ready(());
+        }
+
+        state.idle.register(&mut this.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Idle<'_> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.state.borrow_mut().idle.remove(self.key);
+        }
+    }
+}
+
+/// Keeps a [`Drain`] waiting for its connection until dropped.
+pub struct DrainGuard {
+    abort: CancellationToken,
+    state: Rc<TrackedRefCell<State>>,
+}
+
+impl DrainGuard {
+    /// Resolves once the drain's grace period is over and the connection
+    /// should be closed.
+    pub fn cancelled(&self) -> impl Future<Output = ()> + '_ {
+        self.abort.cancelled()
+    }
+
+    /// Whether the connection should be closed.
+    pub fn is_cancelled(&self) -> bool {
+        self.abort.is_cancelled()
+    }
+}
+
+impl Drop for DrainGuard {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut state = self.state.borrow_mut();
+            state.active -= 1;
+
+            if state.active > 0 {
+                return;
+            }
+
+            state.idle.take_all()
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl fmt::Debug for DrainGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DrainGuard")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, spawn_local},
+        time::sleep,
+    };
+
+    #[test]
+    fn waits_for_connections_that_finish_in_time() {
+        LocalExecutor::new().block_on(async {
+            let drain = Drain::new();
+            let guard = drain.track().unwrap();
+
+            drop(spawn_local(async move {
+                sleep(Duration::from_millis(5)).await;
+                drop(guard);
+            }));
+
+            assert_eq!(drain.drain(Duration::from_secs(5)).await, 0);
+            assert_eq!(drain.active(), 0);
+            assert!(drain.track().is_none());
+        });
+    }
+
+    #[test]
+    fn cancels_connections_past_the_grace_period() {
+        LocalExecutor::new().block_on(async {
+            let drain = Drain::new();
+            let guard = drain.track().unwrap();
+
+            let conn = spawn_local(async move {
+                guard.cancelled().await;
+                "closed"
+            });
+
+            assert_eq!(drain.drain(Duration::from_millis(5)).await, 1);
+            assert_eq!(conn.await.unwrap(), "closed");
+            drain.idle().await;
+        });
+    }
+
+    #[test]
+    fn parent_cancellation_stops_accepting() {
+        let parent = CancellationToken::new();
+        let drain = Drain::with_parent(&parent);
+
+        parent.cancel();
+        assert!(drain.is_draining());
+        assert!(drain.track().is_none());
+    }
+}
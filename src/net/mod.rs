@@ -0,0 +1,16 @@
+mod acceptor;
+mod cmsg;
+mod drain;
+pub mod netlink;
+mod resolve;
+mod tcp;
+mod unix;
+mod unix_datagram;
+
+pub use acceptor::{Acceptor, Connection, Listener};
+pub use cmsg::{Control, ControlMessage, Credentials};
+pub use drain::{Drain, DrainGuard};
+pub use resolve::{resolve, resolve_with};
+pub use tcp::{TcpListener, TcpStream};
+pub use unix::{MAX_FDS, UnixListener, UnixStream};
+pub use unix_datagram::UnixDatagram;
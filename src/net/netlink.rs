@@ -0,0 +1,402 @@
+use crate::reactor::AsyncFd;
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    net::{
+        self, AddressFamily, RecvFlags, SendFlags, SocketFlags, SocketType,
+        netlink::{self, SocketAddrNetlink},
+    },
+};
+use std::{cell::Cell, collections::HashMap, io};
+
+/// Size of a netlink message header (`NLMSG_HDRLEN`).
+const HEADER_LEN: usize = 16;
+
+/// Size of a route attribute header.
+const ATTR_HEADER_LEN: usize = 4;
+
+/// Large enough for any one datagram the kernel sends by default.
+const RECV_BUF: usize = 32 * 1024;
+
+/// Message type acknowledging a request or reporting its failure.
+const NLMSG_ERROR: u16 = libc::NLMSG_ERROR as u16;
+
+/// An async netlink socket, for talking to the kernel.
+///
+/// [`route`](Self::route) sockets query and watch network interfaces,
+/// addresses and routes; [`uevent`](Self::uevent) sockets watch devices
+/// come and go. Messages are built and parsed with [`NetlinkMessage`] and
+/// [`attributes`].
+#[derive(Debug)]
+pub struct NetlinkSocket {
+    fd: AsyncFd<OwnedFd>,
+    seq: Cell<u32>,
+}
+
+impl NetlinkSocket {
+    /// Open a `NETLINK_ROUTE` socket subscribed to the `RTMGRP_*` multicast
+    /// `groups`, or to none for plain requests.
+    pub fn route(groups: u32) -> io::Result<Self> {
+        Self::open(None, groups)
+    }
+
+    /// Open a `NETLINK_KOBJECT_UEVENT` socket subscribed to `groups`; group
+    /// 1 carries the kernel's events.
+    pub fn uevent(groups: u32) -> io::Result<Self> {
+        Self::open(Some(netlink::KOBJECT_UEVENT), groups)
+    }
+
+    fn open(protocol: Option<net::Protocol>, groups: u32) -> io::Result<Self> {
+        let fd = net::socket_with(
+            AddressFamily::NETLINK,
+            SocketType::DGRAM,
+            SocketFlags::NONBLOCK | SocketFlags::CLOEXEC,
+            protocol,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        // Port 0 lets the kernel pick a unique one.
+        net::bind(&fd, &SocketAddrNetlink::new(0, groups))
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            seq: Cell::new(0),
+        })
+    }
+
+    /// Send `message` to the kernel, returning the sequence number it was
+    /// given.
+    ///
+    /// `NLM_F_REQUEST` is added to its flags, and its sequence number is
+    /// replaced by the next of this socket's, to match up replies.
+    pub async fn request(&self, mut message: NetlinkMessage) -> io::Result<u32> {
+        let seq = self.seq.get().wrapping_add(1);
+        self.seq.set(seq);
+
+        message.flags |= libc::NLM_F_REQUEST as u16;
+        message.seq = seq;
+        self.send(&message.encode()).await?;
+        Ok(seq)
+    }
+
+    /// Send raw, already encoded messages to the kernel.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let kernel = SocketAddrNetlink::new(0, 0);
+
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| {
+                net::sendto(fd, buf, SendFlags::empty(), &kernel)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            }) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive one raw datagram, which may hold several messages.
+    ///
+    /// Fails with `InvalidData` if it did not fit in `buf`.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| {
+                let (n, len) = net::recv(fd, &mut *buf, RecvFlags::TRUNC)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+                if len > n {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Netlink message truncated",
+                    ));
+                }
+
+                Ok(n)
+            }) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive the messages of the next datagram.
+    ///
+    /// An error reply from the kernel is returned as that error; an
+    /// acknowledgement, an `NLMSG_ERROR` with code 0, is returned as a
+    /// message.
+    pub async fn recv_messages(&self) -> io::Result<Vec<NetlinkMessage>> {
+        let mut buf = vec![0; RECV_BUF];
+        let n = self.recv(&mut buf).await?;
+        let messages = NetlinkMessage::parse(&buf[..n])?;
+
+        for message in &messages {
+            if message.ty == NLMSG_ERROR {
+                let code = message
+                    .payload
+                    .first_chunk::<4>()
+                    .map(|code| i32::from_ne_bytes(*code))
+                    .ok_or_else(|| invalid("Short netlink error message"))?;
+
+                if code < 0 {
+                    return Err(io::Error::from_raw_os_error(-code));
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Receive the next kernel device event.
+    ///
+    /// Messages that are not kernel uevents, such as udev's rebroadcasts
+    /// on group 2, fail with `InvalidData`.
+    pub async fn recv_uevent(&self) -> io::Result<Uevent> {
+        let mut buf = vec![0; RECV_BUF];
+        let n = self.recv(&mut buf).await?;
+        Uevent::parse(&buf[..n])
+    }
+}
+
+impl AsFd for NetlinkSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// A netlink message: a header and a payload specific to its type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetlinkMessage {
+    /// Message type, such as `RTM_GETLINK`.
+    pub ty: u16,
+    /// `NLM_F_*` flags.
+    pub flags: u16,
+    /// Sequence number, echoed in replies.
+    pub seq: u32,
+    /// Port of the sender; 0 for the kernel.
+    pub pid: u32,
+    /// The type-specific payload, without header padding.
+    pub payload: Vec<u8>,
+}
+
+impl NetlinkMessage {
+    /// A message of type `ty` with `flags` and `payload`.
+    pub fn new(ty: u16, flags: u16, payload: Vec<u8>) -> Self {
+        Self {
+            ty,
+            flags,
+            payload,
+            ..Self::default()
+        }
+    }
+
+    /// Encode the message, padded to netlink's 4-byte alignment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the payload is too large for the 32-bit length field.
+    pub fn encode(&self) -> Vec<u8> {
+        let len =
+            u32::try_from(HEADER_LEN + self.payload.len()).expect("netlink message too large");
+
+        let mut buf = Vec::with_capacity(align(len as usize));
+        buf.extend_from_slice(&len.to_ne_bytes());
+        buf.extend_from_slice(&self.ty.to_ne_bytes());
+        buf.extend_from_slice(&self.flags.to_ne_bytes());
+        buf.extend_from_slice(&self.seq.to_ne_bytes());
+        buf.extend_from_slice(&self.pid.to_ne_bytes());
+        buf.extend_from_slice(&self.payload);
+        buf.resize(align(buf.len()), 0);
+        buf
+    }
+
+    /// Split a datagram into its messages.
+    pub fn parse(mut buf: &[u8]) -> io::Result<Vec<Self>> {
+        let mut messages = Vec::new();
+
+        while buf.len() >= HEADER_LEN {
+            let field = |at: usize| u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap());
+            let len = field(0) as usize;
+
+            if len < HEADER_LEN || len > buf.len() {
+                return Err(invalid("Bad netlink message length"));
+            }
+
+            messages.push(Self {
+                ty: u16::from_ne_bytes([buf[4], buf[5]]),
+                flags: u16::from_ne_bytes([buf[6], buf[7]]),
+                seq: field(8),
+                pid: field(12),
+                payload: buf[HEADER_LEN..len].to_vec(),
+            });
+
+            buf = &buf[align(len).min(buf.len())..];
+        }
+
+        Ok(messages)
+    }
+
+    /// Whether this ends a multi-part reply (`NLMSG_DONE`).
+    pub fn is_done(&self) -> bool {
+        self.ty == libc::NLMSG_DONE as u16
+    }
+}
+
+/// Iterate over the `(type, data)` attributes packed in `buf`, as found
+/// after the fixed part of a route message.
+///
+/// Stops at the first malformed attribute.
+pub fn attributes(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < ATTR_HEADER_LEN {
+            return None;
+        }
+
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]);
+
+        if len < ATTR_HEADER_LEN || len > buf.len() {
+            return None;
+        }
+
+        let data = &buf[ATTR_HEADER_LEN..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((ty, data))
+    })
+}
+
+/// Append an attribute of type `ty` holding `data` to `buf`.
+///
+/// # Panics
+///
+/// Panics if `data` is too large for the 16-bit length field.
+pub fn push_attribute(buf: &mut Vec<u8>, ty: u16, data: &[u8]) {
+    let len = u16::try_from(ATTR_HEADER_LEN + data.len()).expect("netlink attribute too large");
+
+    buf.resize(align(buf.len()), 0);
+    buf.extend_from_slice(&len.to_ne_bytes());
+    buf.extend_from_slice(&ty.to_ne_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(align(buf.len()), 0);
+}
+
+/// A kernel device event from a [`uevent`](NetlinkSocket::uevent) socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uevent {
+    /// What happened, such as `add`, `remove` or `change`.
+    pub action: String,
+    /// The device's path below `/sys`.
+    pub devpath: String,
+    /// All `KEY=value` variables, including `ACTION` and `DEVPATH`.
+    pub vars: HashMap<String, String>,
+}
+
+impl Uevent {
+    /// Parse a kernel uevent: `action@devpath` and `KEY=value` strings,
+    /// each NUL-terminated.
+    pub fn parse(buf: &[u8]) -> io::Result<Self> {
+        let mut fields = buf
+            .split(|&b| b == 0)
+            .filter(|field| !field.is_empty())
+            .map(|field| std::str::from_utf8(field).map_err(|_| invalid("Uevent is not UTF-8")));
+
+        let header = fields.next().ok_or_else(|| invalid("Empty uevent"))??;
+        let (action, devpath) = header
+            .split_once('@')
+            .ok_or_else(|| invalid("Not a kernel uevent"))?;
+
+        let mut vars = HashMap::new();
+
+        for field in fields {
+            if let Some((key, value)) = field?.split_once('=') {
+                vars.insert(key.to_owned(), value.to_owned());
+            }
+        }
+
+        Ok(Self {
+            action: action.to_owned(),
+            devpath: devpath.to_owned(),
+            vars,
+        })
+    }
+}
+
+/// Round `len` up to netlink's 4-byte alignment.
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+
+    #[test]
+    fn messages_and_attributes_round_trip() {
+        let mut payload = vec![1, 2, 3];
+        push_attribute(&mut payload, 3, b"lo\0");
+        push_attribute(&mut payload, 4, &[]);
+
+        let mut message = NetlinkMessage::new(16, 2, payload);
+        message.seq = 9;
+        let mut buf = message.encode();
+        buf.extend(NetlinkMessage::new(3, 0, Vec::new()).encode());
+
+        let parsed = NetlinkMessage::parse(&buf).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0], message);
+        assert!(parsed[1].is_done());
+
+        let attrs: Vec<_> = attributes(&parsed[0].payload[4..]).collect();
+        assert_eq!(attrs, [(3, &b"lo\0"[..]), (4, &[][..])]);
+    }
+
+    #[test]
+    fn parses_kernel_uevents() {
+        let event = Uevent::parse(
+            b"add@/devices/virtual/net/tap0\0ACTION=add\0DEVPATH=/devices/virtual/net/tap0\0SUBSYSTEM=net\0",
+        )
+        .unwrap();
+
+        assert_eq!(event.action, "add");
+        assert_eq!(event.devpath, "/devices/virtual/net/tap0");
+        assert_eq!(event.vars["SUBSYSTEM"], "net");
+        assert!(Uevent::parse(b"libudev\0\xfe\xed").is_err());
+    }
+
+    #[test]
+    fn route_dump_lists_loopback() {
+        LocalExecutor::new().block_on(async {
+            let socket = NetlinkSocket::route(0).unwrap();
+
+            // struct ifinfomsg, all zero: any family, any interface.
+            let request =
+                NetlinkMessage::new(libc::RTM_GETLINK, libc::NLM_F_DUMP as u16, vec![0; 16]);
+            let seq = socket.request(request).await.unwrap();
+
+            let mut names = Vec::new();
+
+            'dump: loop {
+                for message in socket.recv_messages().await.unwrap() {
+                    assert_eq!(message.seq, seq);
+
+                    if message.is_done() {
+                        break 'dump;
+                    }
+
+                    names.extend(
+                        attributes(&message.payload[16..])
+                            .filter(|&(ty, _)| ty == libc::IFLA_IFNAME)
+                            .map(|(_, name)| name.to_vec()),
+                    );
+                }
+            }
+
+            assert!(names.contains(&b"lo\0".to_vec()), "{names:?}");
+        });
+    }
+}
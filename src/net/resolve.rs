@@ -0,0 +1,149 @@
+use crate::{
+    runtime::blocking,
+    time,
+    utils::{cancel::CancellationToken, tracked_cell::TrackedRefCell},
+};
+use std::{
+    collections::HashMap,
+    future::{Future, poll_fn},
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    pin::pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+/// How long a successful lookup is reused; `getaddrinfo` reports no TTL.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Addresses for a `(host, port)`, with when they were looked up.
+type Cache = HashMap<(String, u16), (Instant, Vec<SocketAddr>)>;
+
+thread_local! {
+    static CACHE: TrackedRefCell<Option<Cache>> = const { TrackedRefCell::new(None) };
+}
+
+/// Resolve `host` to the addresses to try for `port`, in the order
+/// `getaddrinfo` prefers them.
+///
+/// IP literals are parsed in place. Names are looked up on the blocking
+/// pool, so the executor keeps running meanwhile, and successful lookups
+/// are cached on this thread for 30 seconds. Dropping the future stops
+/// waiting, but the lookup itself runs to completion in the background.
+///
+/// # Panics
+///
+/// Panics if a lookup is needed outside
+/// [`LocalExecutor::block_on`](crate::runtime::LocalExecutor::block_on).
+pub async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    resolve_with(host, port, &CancellationToken::new()).await
+}
+
+/// Like [`resolve`], but gives up with `Interrupted` once `token` is
+/// cancelled.
+pub async fn resolve_with(
+    host: &str,
+    port: u16,
+    token: &CancellationToken,
+) -> io::Result<Vec<SocketAddr>> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+
+    let key = (host.to_owned(), port);
+
+    let cached = CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let cache = cache.get_or_insert_default();
+
+        match cache.get(&key) {
+            Some((at, addrs)) if time::now().saturating_duration_since(*at) < CACHE_TTL => {
+                Some(addrs.clone())
+            }
+            Some(_) => {
+                cache.remove(&key);
+                None
+            }
+            None => None,
+        }
+    });
+
+    if let Some(addrs) = cached {
+        return Ok(addrs);
+    }
+
+    let mut cancelled = pin!(token.cancelled());
+    let mut lookup = pin!(blocking::run({
+        let key = key.clone();
+        move || key.to_socket_addrs().map(Iterator::collect::<Vec<_>>)
+    }));
+
+    let addrs = poll_fn(|cx| {
+        if cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Resolution cancelled",
+            )));
+        }
+
+        lookup.as_mut().poll(cx)
+    })
+    .await?;
+
+    CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .get_or_insert_default()
+            .insert(key, (time::now(), addrs.clone()))
+    });
+
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn literals_skip_the_pool() {
+        let addrs = LocalExecutor::new().block_on(resolve("::1", 443)).unwrap();
+        assert_eq!(addrs, [SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 443)]);
+    }
+
+    #[test]
+    fn resolves_and_caches_localhost() {
+        LocalExecutor::new().block_on(async {
+            let addrs = resolve("localhost", 80).await.unwrap();
+            assert!(
+                addrs
+                    .iter()
+                    .all(|addr| addr.ip().is_loopback() && addr.port() == 80)
+            );
+
+            let cached = CACHE.with(|cache| {
+                cache
+                    .borrow()
+                    .as_ref()
+                    .unwrap()
+                    .contains_key(&("localhost".into(), 80))
+            });
+            assert!(cached);
+            assert_eq!(resolve("localhost", 80).await.unwrap(), addrs);
+        });
+    }
+
+    #[test]
+    fn cancellation_interrupts_the_wait() {
+        LocalExecutor::new().block_on(async {
+            let token = CancellationToken::new();
+            token.cancel();
+
+            let err = resolve_with("example.invalid", 80, &token)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        });
+    }
+}
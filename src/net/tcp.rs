@@ -0,0 +1,430 @@
+use crate::{
+    io::{BufPool, PooledBuf},
+    reactor::AsyncFd,
+};
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    io::Errno,
+    net::{self, AddressFamily, SendAncillaryBuffer, SendFlags, SocketFlags, SocketType, sockopt},
+};
+use std::{
+    io::{self, IoSlice, IoSliceMut},
+    net::{Shutdown, SocketAddr, TcpListener as StdTcpListener, TcpStream as StdTcpStream},
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+/// Pending connections a listener queues up.
+const BACKLOG: i32 = 128;
+
+/// An async TCP stream.
+#[derive(Debug)]
+pub struct TcpStream {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl TcpStream {
+    /// Connect to `addr`.
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let stream = Self::new(socket(&addr)?)?;
+
+        match net::connect(stream.fd.get_ref(), &addr) {
+            Ok(()) => return Ok(stream),
+            Err(Errno::INPROGRESS) => {}
+            Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        }
+
+        // Completion of a non-blocking connect is signalled by writability.
+        let _ready = stream.fd.writable().await;
+
+        match sockopt::socket_error(stream.fd.get_ref()) {
+            Ok(Ok(())) => Ok(stream),
+            Ok(Err(e)) | Err(e) => Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        }
+    }
+
+    /// Resolve `host` with [`resolve`](super::resolve) and connect to the
+    /// first of its addresses that accepts, returning the last error if
+    /// none does.
+    pub async fn connect_host(host: &str, port: u16) -> io::Result<Self> {
+        let mut last = None;
+
+        for addr in super::resolve(host, port).await? {
+            match Self::connect(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last = Some(e),
+            }
+        }
+
+        Err(last
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Host has no addresses")))
+    }
+
+    /// Wrap a connected std stream, switching it to non-blocking mode.
+    pub fn from_std(stream: StdTcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Self::new(stream.into())
+    }
+
+    fn new(fd: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Read into `buf`, returning the number of bytes read; 0 means end of stream.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| read(fd, buf)) {
+                return result;
+            }
+        }
+    }
+
+    /// Read into a buffer taken from `pool`, returning it with the bytes
+    /// read; an empty buffer means end of stream.
+    pub async fn recv_into(&self, pool: &BufPool) -> io::Result<PooledBuf> {
+        let mut buf = pool.get();
+        let n = self.read(buf.storage_mut()).await?;
+        buf.set_filled(n);
+        Ok(buf)
+    }
+
+    /// Read into several buffers in turn, returning the total number of bytes
+    /// read.
+    pub async fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| {
+                rustix::io::readv(fd, bufs)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            }) {
+                return result;
+            }
+        }
+    }
+
+    /// Write some of `buf`, returning the number of bytes written.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| write(fd, buf)) {
+                return result;
+            }
+        }
+    }
+
+    /// Write from several buffers in turn, returning the total number of
+    /// bytes written.
+    pub async fn write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| {
+                net::sendmsg(
+                    fd,
+                    bufs,
+                    &mut SendAncillaryBuffer::default(),
+                    SendFlags::NOSIGNAL,
+                )
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            }) {
+                return result;
+            }
+        }
+    }
+
+    /// Write all of `buf`.
+    pub async fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Poll-based [`read`](Self::read), for implementing I/O traits.
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| read(fd, buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Poll-based [`write`](Self::write), for implementing I/O traits.
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_write_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| write(fd, buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Disable or re-enable Nagle's algorithm (`TCP_NODELAY`).
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        sockopt::set_tcp_nodelay(self.fd.get_ref(), nodelay)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    /// Whether Nagle's algorithm is disabled.
+    pub fn nodelay(&self) -> io::Result<bool> {
+        sockopt::tcp_nodelay(self.fd.get_ref())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    /// Send keepalive probes once the connection has been idle for `idle`,
+    /// or stop sending them with `None`.
+    pub fn set_keepalive(&self, idle: Option<Duration>) -> io::Result<()> {
+        let fd = self.fd.get_ref();
+
+        if let Some(idle) = idle {
+            sockopt::set_tcp_keepidle(fd, idle)
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        }
+
+        sockopt::set_socket_keepalive(fd, idle.is_some())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    /// The local address of the connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        local_addr(self.fd.get_ref())
+    }
+
+    /// The address of the peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        net::getpeername(self.fd.get_ref())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?
+            .ok_or_else(|| io::ErrorKind::NotConnected.into())
+            .and_then(to_std)
+    }
+
+    /// Shut down the read, write or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let how = match how {
+            Shutdown::Read => net::Shutdown::Read,
+            Shutdown::Write => net::Shutdown::Write,
+            Shutdown::Both => net::Shutdown::Both,
+        };
+
+        net::shutdown(self.fd.get_ref(), how)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+}
+
+impl AsFd for TcpStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// An async TCP socket listening for connections.
+#[derive(Debug)]
+pub struct TcpListener {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl TcpListener {
+    /// Bind to `addr` and start listening.
+    ///
+    /// Port 0 picks a free port; see [`local_addr`](Self::local_addr).
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let fd = socket(&addr)?;
+
+        // As std does, so a restarted server can rebind while old
+        // connections linger in TIME_WAIT.
+        sockopt::set_socket_reuseaddr(&fd, true)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        net::bind(&fd, &addr).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        net::listen(&fd, BACKLOG).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Wrap a listening std socket, switching it to non-blocking mode.
+    pub fn from_std(listener: StdTcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            fd: AsyncFd::new(listener.into())?,
+        })
+    }
+
+    /// Accept the next connection, along with the peer's address.
+    ///
+    /// The socket is created non-blocking and close-on-exec in the same
+    /// `accept4` call, so a concurrent fork never inherits it.
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (fd, addr) = self.accept_raw().await?;
+
+        Ok((TcpStream::new(fd)?, addr))
+    }
+
+    /// Accept the next connection without registering it with the reactor.
+    ///
+    /// For sockets handed to another thread or process: the fd is
+    /// non-blocking and close-on-exec, and can be registered later with
+    /// [`TcpStream::from_std`].
+    pub async fn accept_raw(&self) -> io::Result<(OwnedFd, SocketAddr)> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io(accept) {
+                return result;
+            }
+        }
+    }
+
+    /// Poll-based [`accept`](Self::accept).
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            if let Ok(result) = ready.try_io(accept) {
+                return Poll::Ready(result.and_then(|(fd, addr)| Ok((TcpStream::new(fd)?, addr))));
+            }
+        }
+    }
+
+    /// The address the listener is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        local_addr(self.fd.get_ref())
+    }
+}
+
+/// Yields accepted connections; never ends.
+#[cfg(feature = "futures")]
+impl futures_core::Stream for TcpListener {
+    type Item = io::Result<(TcpStream, SocketAddr)>;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_accept(cx).map(Some)
+    }
+}
+
+impl AsFd for TcpListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+fn socket(addr: &SocketAddr) -> io::Result<OwnedFd> {
+    let family = match addr {
+        SocketAddr::V4(_) => AddressFamily::INET,
+        SocketAddr::V6(_) => AddressFamily::INET6,
+    };
+
+    net::socket_with(
+        family,
+        SocketType::STREAM,
+        SocketFlags::NONBLOCK | SocketFlags::CLOEXEC,
+        None,
+    )
+    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn accept(fd: &OwnedFd) -> io::Result<(OwnedFd, SocketAddr)> {
+    let (fd, addr) = net::acceptfrom_with(fd, SocketFlags::NONBLOCK | SocketFlags::CLOEXEC)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+    let addr = addr.ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+
+    Ok((fd, to_std(addr)?))
+}
+
+fn local_addr(fd: &OwnedFd) -> io::Result<SocketAddr> {
+    net::getsockname(fd)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+        .and_then(to_std)
+}
+
+fn to_std(addr: net::SocketAddrAny) -> io::Result<SocketAddr> {
+    SocketAddr::try_from(addr).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn read(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+    rustix::io::read(fd, buf).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn write(fd: &OwnedFd, buf: &[u8]) -> io::Result<usize> {
+    // `send` rather than `write`, so a closed peer yields EPIPE instead of SIGPIPE.
+    net::send(fd, buf, SendFlags::NOSIGNAL)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{LocalExecutor, spawn_local};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn connects_and_echoes() {
+        LocalExecutor::new().block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let server = spawn_local(async move {
+                let (stream, peer) = listener.accept().await.unwrap();
+                let mut buf = [0; 16];
+                let n = stream.read(&mut buf).await.unwrap();
+                stream.write_all(&buf[..n]).await.unwrap();
+                peer
+            });
+
+            let client = TcpStream::connect_host("127.0.0.1", addr.port())
+                .await
+                .unwrap();
+            client.set_nodelay(true).unwrap();
+            assert!(client.nodelay().unwrap());
+            client.set_keepalive(Some(Duration::from_secs(30))).unwrap();
+            assert_eq!(client.peer_addr().unwrap(), addr);
+
+            let parts = [IoSlice::new(b"hello "), IoSlice::new(b"world")];
+            assert_eq!(client.write_vectored(&parts).await.unwrap(), 11);
+            client.shutdown(Shutdown::Write).unwrap();
+
+            // The server closes after echoing, so read to the end.
+            let (mut head, mut tail) = ([0; 6], [0; 16]);
+            let mut n = client
+                .read_vectored(&mut [IoSliceMut::new(&mut head), IoSliceMut::new(&mut tail)])
+                .await
+                .unwrap();
+            let mut echoed = [&head[..], &tail[..]].concat();
+            loop {
+                match client.read(&mut echoed[n..]).await.unwrap() {
+                    0 => break,
+                    m => n += m,
+                }
+            }
+            assert_eq!(&echoed[..n], b"hello world");
+
+            assert_eq!(server.await.unwrap(), client.local_addr().unwrap());
+        });
+    }
+
+    #[test]
+    fn connect_to_closed_port_fails() {
+        LocalExecutor::new().block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+            let addr = listener.local_addr().unwrap();
+            drop(listener);
+
+            let err = TcpStream::connect(addr).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        });
+    }
+}
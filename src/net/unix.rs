@@ -0,0 +1,612 @@
+use crate::{
+    io::{BufPool, PooledBuf},
+    net::{Control, ControlMessage, cmsg},
+    reactor::AsyncFd,
+    utils::flock::Flock,
+};
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    io::Errno,
+    net::{
+        self, AddressFamily, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, ReturnFlags,
+        SendAncillaryBuffer, SendAncillaryMessage, SendFlags, SocketAddrUnix, SocketFlags,
+        SocketType, sockopt,
+    },
+};
+use std::{
+    fs::Permissions,
+    io::{self, IoSlice, IoSliceMut},
+    mem::MaybeUninit,
+    net::Shutdown,
+    os::unix::{fs::FileTypeExt, fs::PermissionsExt, net as std_net},
+    path::{Path, PathBuf},
+    task::{Context, Poll, ready},
+};
+
+/// Most fds the kernel passes in one message (`SCM_MAX_FD`).
+pub const MAX_FDS: usize = 253;
+
+/// Pending connections a listener queues up.
+const BACKLOG: i32 = 128;
+
+/// An async Unix stream socket.
+#[derive(Debug)]
+pub struct UnixStream {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl UnixStream {
+    /// Connect to the socket bound at `path`.
+    pub async fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let addr = SocketAddrUnix::new(path.as_ref())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        let stream = Self::new(socket()?)?;
+
+        // A full backlog fails with EAGAIN rather than completing later.
+        match net::connect(stream.fd.get_ref(), &addr) {
+            Ok(()) => return Ok(stream),
+            Err(Errno::INPROGRESS) => {}
+            Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        }
+
+        // Completion of a non-blocking connect is signalled by writability.
+        let _ready = stream.fd.writable().await;
+
+        match sockopt::socket_error(stream.fd.get_ref()) {
+            Ok(Ok(())) => Ok(stream),
+            Ok(Err(e)) | Err(e) => Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        }
+    }
+
+    /// Create a pair of connected streams.
+    pub fn pair() -> io::Result<(Self, Self)> {
+        let (a, b) = net::socketpair(
+            AddressFamily::UNIX,
+            SocketType::STREAM,
+            SocketFlags::NONBLOCK | SocketFlags::CLOEXEC,
+            None,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok((Self::new(a)?, Self::new(b)?))
+    }
+
+    /// Wrap a connected std stream, switching it to non-blocking mode.
+    pub fn from_std(stream: std_net::UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Self::new(stream.into())
+    }
+
+    fn new(fd: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Read into `buf`, returning the number of bytes read; 0 means end of stream.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| read(fd, buf)) {
+                return result;
+            }
+        }
+    }
+
+    /// Read into a buffer taken from `pool`, returning it with the bytes
+    /// read; an empty buffer means end of stream.
+    pub async fn recv_into(&self, pool: &BufPool) -> io::Result<PooledBuf> {
+        let mut buf = pool.get();
+        let n = self.read(buf.storage_mut()).await?;
+        buf.set_filled(n);
+        Ok(buf)
+    }
+
+    /// Write some of `buf`, returning the number of bytes written.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| write(fd, buf)) {
+                return result;
+            }
+        }
+    }
+
+    /// Write all of `buf`.
+    pub async fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Poll-based [`read`](Self::read), for implementing I/O traits.
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| read(fd, buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Poll-based [`write`](Self::write), for implementing I/O traits.
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_write_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| write(fd, buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Send `buf` along with `fds` (`SCM_RIGHTS`).
+    ///
+    /// The fds travel with the first byte written, so `buf` must not be
+    /// empty. Returns the number of bytes written; if it is short, the
+    /// rest must be sent without the fds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than [`MAX_FDS`] fds are passed.
+    pub async fn send_with_fds(&self, buf: &[u8], fds: &[BorrowedFd<'_>]) -> io::Result<usize> {
+        assert!(fds.len() <= MAX_FDS, "at most {MAX_FDS} fds per message");
+
+        if buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "fds must be sent with at least one byte",
+            ));
+        }
+
+        let mut space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(fds.len()))];
+
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            let result = ready.try_io_bytes(|fd| {
+                let mut control = SendAncillaryBuffer::new(&mut space);
+                control.push(SendAncillaryMessage::ScmRights(fds));
+
+                net::sendmsg(fd, &[IoSlice::new(buf)], &mut control, SendFlags::NOSIGNAL)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            });
+
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Receive into `buf`, appending any fds passed along to `fds`.
+    ///
+    /// Received fds are close-on-exec. Fails with `InvalidData` if the
+    /// sender passed more fds than fit in one message.
+    pub async fn recv_with_fds(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> io::Result<usize> {
+        let mut space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(MAX_FDS))];
+
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            let result = ready.try_io_bytes(|fd| {
+                let mut control = RecvAncillaryBuffer::new(&mut space);
+
+                let msg = net::recvmsg(
+                    fd,
+                    &mut [IoSliceMut::new(buf)],
+                    &mut control,
+                    RecvFlags::CMSG_CLOEXEC,
+                )
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+                for message in control.drain() {
+                    if let RecvAncillaryMessage::ScmRights(received) = message {
+                        fds.extend(received);
+                    }
+                }
+
+                if msg.flags.contains(ReturnFlags::CTRUNC) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "ancillary data truncated",
+                    ));
+                }
+
+                Ok(msg.bytes)
+            });
+
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Send `buf` along with the control messages in `control`.
+    ///
+    /// Fails with `InvalidInput` if `buf` is empty. Returns the number of
+    /// bytes written; if it is short, the rest must be sent without them.
+    pub async fn send_with_control(&self, buf: &[u8], control: &Control<'_>) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "control messages must be sent with at least one byte",
+            ));
+        }
+
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| cmsg::send(fd.as_fd(), buf, control)) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive into `buf`, appending the control messages that came along
+    /// to `out`.
+    ///
+    /// Credentials and timestamps are only received once enabled with
+    /// [`set_pass_credentials`](Self::set_pass_credentials) and
+    /// [`set_timestamps`](Self::set_timestamps). Fails with `InvalidData`
+    /// if the control messages did not fit.
+    pub async fn recv_with_control(
+        &self,
+        buf: &mut [u8],
+        out: &mut Vec<ControlMessage>,
+    ) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| cmsg::recv(fd.as_fd(), buf, out)) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive the sender's credentials with each message (`SO_PASSCRED`).
+    pub fn set_pass_credentials(&self, on: bool) -> io::Result<()> {
+        cmsg::set_flag(self.fd.as_fd(), libc::SO_PASSCRED, on)
+    }
+
+    /// Receive the arrival time with each message (`SO_TIMESTAMP`).
+    pub fn set_timestamps(&self, on: bool) -> io::Result<()> {
+        cmsg::set_flag(self.fd.as_fd(), libc::SO_TIMESTAMP, on)
+    }
+
+    /// Shut down the read, write or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let how = match how {
+            Shutdown::Read => net::Shutdown::Read,
+            Shutdown::Write => net::Shutdown::Write,
+            Shutdown::Both => net::Shutdown::Both,
+        };
+
+        net::shutdown(self.fd.get_ref(), how)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+}
+
+impl AsFd for UnixStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// An async Unix stream socket listening for connections.
+#[derive(Debug)]
+pub struct UnixListener {
+    fd: AsyncFd<OwnedFd>,
+    /// Held by [`bind_replace`](Self::bind_replace) for the listener's lifetime.
+    _lock: Option<Flock>,
+}
+
+impl UnixListener {
+    /// Bind a new socket at `path` and start listening.
+    ///
+    /// Fails with `AddrInUse` if `path` already exists.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let addr = SocketAddrUnix::new(path.as_ref())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        let fd = socket()?;
+
+        net::bind(&fd, &addr).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        net::listen(&fd, BACKLOG).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            _lock: None,
+        })
+    }
+
+    /// Bind at `path`, replacing a stale socket left by a dead process.
+    ///
+    /// An exclusive [`Flock`] on `<path>.lock` is taken first and held for
+    /// the listener's lifetime, so two instances cannot race for the path.
+    /// An existing socket at `path` is probed with a connection attempt:
+    /// if anything accepts it, this fails with `AddrInUse`; if `path` is
+    /// not a socket at all, with `AlreadyExists`. The new socket is bound
+    /// under a temporary name, given `mode`, and listening before it is
+    /// renamed over `path`, so clients never see a half-set-up socket and
+    /// the stale one is replaced atomically.
+    pub async fn bind_replace(path: impl AsRef<Path>, mode: u32) -> io::Result<Self> {
+        let path = path.as_ref();
+        let lock = Flock::lock(&with_suffix(path, "lock"))?;
+
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if !meta.file_type().is_socket() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "Path exists and is not a socket",
+                ));
+            }
+            Ok(_) => match UnixStream::connect(path).await {
+                Ok(_) => {
+                    return Err(io::Error::new(io::ErrorKind::AddrInUse, "Socket is in use"));
+                }
+                // EAGAIN: the backlog is full, so someone is listening.
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Err(io::Error::new(io::ErrorKind::AddrInUse, "Socket is in use"));
+                }
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+                Err(e) => return Err(e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let tmp = with_suffix(path, &format!("{}.tmp", std::process::id()));
+
+        // Left over from an earlier attempt of a process with our PID.
+        match std::fs::remove_file(&tmp) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        let mut listener = Self::bind(&tmp)?;
+
+        let placed = std::fs::set_permissions(&tmp, Permissions::from_mode(mode))
+            .and_then(|()| std::fs::rename(&tmp, path));
+
+        if let Err(e) = placed {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e);
+        }
+
+        listener._lock = Some(lock);
+
+        Ok(listener)
+    }
+
+    /// Wrap a listening std socket, switching it to non-blocking mode.
+    pub fn from_std(listener: std_net::UnixListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            fd: AsyncFd::new(listener.into())?,
+            _lock: None,
+        })
+    }
+
+    /// Accept the next connection.
+    ///
+    /// The socket is created non-blocking and close-on-exec in the same
+    /// `accept4` call, so a concurrent fork never inherits it.
+    pub async fn accept(&self) -> io::Result<UnixStream> {
+        UnixStream::new(self.accept_raw().await?)
+    }
+
+    /// Accept the next connection without registering it with the reactor.
+    ///
+    /// For sockets handed to another thread or process: the fd is
+    /// non-blocking and close-on-exec, and can be registered later with
+    /// [`UnixStream::from_std`].
+    pub async fn accept_raw(&self) -> io::Result<OwnedFd> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io(accept) {
+                return result;
+            }
+        }
+    }
+
+    /// Poll-based [`accept`](Self::accept).
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<UnixStream>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            if let Ok(result) = ready.try_io(accept) {
+                return Poll::Ready(result.and_then(UnixStream::new));
+            }
+        }
+    }
+}
+
+/// Yields accepted connections; never ends.
+#[cfg(feature = "futures")]
+impl futures_core::Stream for UnixListener {
+    type Item = io::Result<UnixStream>;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_accept(cx).map(Some)
+    }
+}
+
+impl AsFd for UnixListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".");
+    s.push(suffix);
+    s.into()
+}
+
+fn socket() -> io::Result<OwnedFd> {
+    net::socket_with(
+        AddressFamily::UNIX,
+        SocketType::STREAM,
+        SocketFlags::NONBLOCK | SocketFlags::CLOEXEC,
+        None,
+    )
+    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn accept(fd: &OwnedFd) -> io::Result<OwnedFd> {
+    net::accept_with(fd, SocketFlags::NONBLOCK | SocketFlags::CLOEXEC)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn read(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+    rustix::io::read(fd, buf).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn write(fd: &OwnedFd, buf: &[u8]) -> io::Result<usize> {
+    // `send` rather than `write`, so a closed peer yields EPIPE instead of SIGPIPE.
+    net::send(fd, buf, SendFlags::NOSIGNAL)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir};
+
+    #[test]
+    fn accept_raw_leaves_the_fd_unregistered() {
+        let dir = TempDir::new();
+        let path = dir.join("sock");
+
+        LocalExecutor::new().block_on(async {
+            let listener = UnixListener::bind(&path).unwrap();
+            let client = UnixStream::connect(&path).await.unwrap();
+
+            let fd = listener.accept_raw().await.unwrap();
+            let flags = rustix::io::fcntl_getfd(&fd).unwrap();
+            assert!(flags.contains(rustix::io::FdFlags::CLOEXEC));
+
+            let server = UnixStream::from_std(fd.into()).unwrap();
+            client.write_all(b"hi").await.unwrap();
+            let mut buf = [0; 2];
+            assert_eq!(server.read(&mut buf).await.unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn pair_reads_writes_and_shuts_down() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+
+            a.write_all(b"ping").await.unwrap();
+            a.shutdown(Shutdown::Write).unwrap();
+
+            let mut buf = [0; 8];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 4);
+            assert_eq!(&buf[..4], b"ping");
+            assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn passes_fds() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            let (read, write) = rustix::pipe::pipe().unwrap();
+
+            let empty = a.send_with_fds(b"", &[read.as_fd()]).await.unwrap_err();
+            assert_eq!(empty.kind(), io::ErrorKind::InvalidInput);
+
+            assert_eq!(a.send_with_fds(b"x", &[read.as_fd()]).await.unwrap(), 1);
+            drop(read);
+
+            let mut buf = [0; 1];
+            let mut fds = Vec::new();
+            assert_eq!(b.recv_with_fds(&mut buf, &mut fds).await.unwrap(), 1);
+            let [received] = <[OwnedFd; 1]>::try_from(fds).unwrap();
+
+            rustix::io::write(&write, b"y").unwrap();
+            assert_eq!(rustix::io::read(&received, &mut buf), Ok(1));
+            assert_eq!(&buf, b"y");
+        });
+    }
+
+    #[test]
+    fn listener_accepts_connections() {
+        let dir = TempDir::new();
+        let path = dir.join("sock");
+
+        LocalExecutor::new().block_on(async {
+            let listener = UnixListener::bind(&path).unwrap();
+            let err = UnixListener::bind(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+            let client = UnixStream::connect(&path).await.unwrap();
+            let server = listener.accept().await.unwrap();
+
+            client.write_all(b"hi").await.unwrap();
+            let mut buf = [0; 2];
+            assert_eq!(server.read(&mut buf).await.unwrap(), 2);
+            assert_eq!(&buf, b"hi");
+        });
+    }
+
+    #[test]
+    fn connect_to_missing_socket_fails() {
+        let dir = TempDir::new();
+
+        LocalExecutor::new().block_on(async {
+            let err = UnixStream::connect(dir.join("sock")).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        });
+    }
+
+    #[test]
+    fn bind_replace_takes_over_stale_socket_only() {
+        let dir = TempDir::new();
+        let path = dir.join("sock");
+
+        LocalExecutor::new().block_on(async {
+            // Left behind by a dead process.
+            drop(std_net::UnixListener::bind(&path).unwrap());
+
+            let listener = UnixListener::bind_replace(&path, 0o600).await.unwrap();
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            let _client = UnixStream::connect(&path).await.unwrap();
+            listener.accept().await.unwrap();
+
+            // The lock is held by the live listener.
+            let err = UnixListener::bind_replace(&path, 0o600).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        });
+    }
+
+    #[test]
+    fn bind_replace_refuses_live_socket_and_other_files() {
+        let dir = TempDir::new();
+        let live = dir.join("live");
+        let file = dir.join("file");
+
+        LocalExecutor::new().block_on(async {
+            let _other = std_net::UnixListener::bind(&live).unwrap();
+            let err = UnixListener::bind_replace(&live, 0o600).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+            std::fs::write(&file, b"").unwrap();
+            let err = UnixListener::bind_replace(&file, 0o600).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        });
+    }
+}
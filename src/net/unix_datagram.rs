@@ -0,0 +1,379 @@
+use crate::{
+    io::{BufPool, PooledBuf},
+    net::{Control, ControlMessage, MAX_FDS, cmsg},
+    reactor::AsyncFd,
+};
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    net::{
+        self, AddressFamily, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, ReturnFlags,
+        SendAncillaryBuffer, SendAncillaryMessage, SendFlags, SocketAddrUnix, SocketFlags,
+        SocketType,
+    },
+};
+use std::{
+    ffi::OsStr,
+    io::{self, IoSlice, IoSliceMut},
+    mem::MaybeUninit,
+    os::unix::{ffi::OsStrExt, net as std_net},
+    path::{Path, PathBuf},
+    task::{Context, Poll, ready},
+};
+
+/// An async Unix datagram socket.
+///
+/// Each send is delivered whole as one message, or not at all, which
+/// suits fire-and-forget control messages between processes. A socket can
+/// send to any address with [`send_to`](Self::send_to), or be
+/// [`connect`](Self::connect)ed to one peer for [`send`](Self::send) and
+/// [`recv`](Self::recv).
+#[derive(Debug)]
+pub struct UnixDatagram {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl UnixDatagram {
+    /// Bind a new socket at `path`.
+    ///
+    /// Fails with `AddrInUse` if `path` already exists.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let socket = Self::unbound()?;
+        net::bind(socket.fd.get_ref(), &addr(path.as_ref())?)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        Ok(socket)
+    }
+
+    /// Create a socket not bound to any path.
+    ///
+    /// It can send, but receives replies only once connected or bound.
+    pub fn unbound() -> io::Result<Self> {
+        let fd = net::socket_with(
+            AddressFamily::UNIX,
+            SocketType::DGRAM,
+            SocketFlags::NONBLOCK | SocketFlags::CLOEXEC,
+            None,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Self::new(fd)
+    }
+
+    /// Create a pair of connected sockets.
+    pub fn pair() -> io::Result<(Self, Self)> {
+        let (a, b) = net::socketpair(
+            AddressFamily::UNIX,
+            SocketType::DGRAM,
+            SocketFlags::NONBLOCK | SocketFlags::CLOEXEC,
+            None,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok((Self::new(a)?, Self::new(b)?))
+    }
+
+    /// Wrap a std socket, switching it to non-blocking mode.
+    pub fn from_std(socket: std_net::UnixDatagram) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Self::new(socket.into())
+    }
+
+    fn new(fd: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Send only to, and receive only from, the socket bound at `path`.
+    pub fn connect(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        net::connect(self.fd.get_ref(), &addr(path.as_ref())?)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    /// Send `buf` as one message to the socket bound at `path`.
+    pub async fn send_to(&self, buf: &[u8], path: impl AsRef<Path>) -> io::Result<usize> {
+        let addr = addr(path.as_ref())?;
+
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| {
+                net::sendto(fd, buf, SendFlags::NOSIGNAL, &addr)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            }) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive one message into `buf`, along with the sender's path.
+    ///
+    /// The path is `None` for unbound or abstract senders. A message longer
+    /// than `buf` is truncated.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, Option<PathBuf>)> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            let result = ready.try_io(|fd| {
+                let (n, _, from) = net::recvfrom(fd, &mut *buf, RecvFlags::empty())
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+                let path = from
+                    .and_then(|from| SocketAddrUnix::try_from(from).ok())
+                    .and_then(|from| {
+                        from.path_bytes()
+                            .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+                    });
+
+                Ok((n, path))
+            });
+
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Send `buf` as one message to the connected peer.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| send(fd, buf)) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive one message from the connected peer into `buf`.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| recv(fd, buf)) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive one message from the connected peer into a buffer taken
+    /// from `pool`, returning it with the message.
+    ///
+    /// A message longer than the pool's buffers is truncated.
+    pub async fn recv_into(&self, pool: &BufPool) -> io::Result<PooledBuf> {
+        let mut buf = pool.get();
+        let n = self.recv(buf.storage_mut()).await?;
+        buf.set_filled(n);
+        Ok(buf)
+    }
+
+    /// Poll-based [`send`](Self::send).
+    pub fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_write_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| send(fd, buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Poll-based [`recv`](Self::recv).
+    pub fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            if let Ok(result) = ready.try_io_bytes(|fd| recv(fd, buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Send `buf` to the connected peer along with `fds` (`SCM_RIGHTS`).
+    ///
+    /// Unlike on a stream, `buf` may be empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than [`MAX_FDS`] fds are passed.
+    pub async fn send_with_fds(&self, buf: &[u8], fds: &[BorrowedFd<'_>]) -> io::Result<usize> {
+        assert!(fds.len() <= MAX_FDS, "at most {MAX_FDS} fds per message");
+
+        let mut space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(fds.len()))];
+
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            let result = ready.try_io_bytes(|fd| {
+                let mut control = SendAncillaryBuffer::new(&mut space);
+                control.push(SendAncillaryMessage::ScmRights(fds));
+
+                net::sendmsg(fd, &[IoSlice::new(buf)], &mut control, SendFlags::NOSIGNAL)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            });
+
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Receive one message into `buf`, appending any fds passed along to
+    /// `fds`.
+    ///
+    /// Received fds are close-on-exec. Fails with `InvalidData` if the
+    /// sender passed more fds than fit in one message.
+    pub async fn recv_with_fds(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> io::Result<usize> {
+        let mut space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(MAX_FDS))];
+
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            let result = ready.try_io_bytes(|fd| {
+                let mut control = RecvAncillaryBuffer::new(&mut space);
+
+                let msg = net::recvmsg(
+                    fd,
+                    &mut [IoSliceMut::new(buf)],
+                    &mut control,
+                    RecvFlags::CMSG_CLOEXEC,
+                )
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+                for message in control.drain() {
+                    if let RecvAncillaryMessage::ScmRights(received) = message {
+                        fds.extend(received);
+                    }
+                }
+
+                if msg.flags.contains(ReturnFlags::CTRUNC) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "ancillary data truncated",
+                    ));
+                }
+
+                Ok(msg.bytes)
+            });
+
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Send `buf` along with the control messages in `control`.
+    ///
+    /// Unlike on a stream, `buf` may be empty.
+    pub async fn send_with_control(&self, buf: &[u8], control: &Control<'_>) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.writable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| cmsg::send(fd.as_fd(), buf, control)) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive into `buf`, appending the control messages that came along
+    /// to `out`.
+    ///
+    /// Credentials and timestamps are only received once enabled with
+    /// [`set_pass_credentials`](Self::set_pass_credentials) and
+    /// [`set_timestamps`](Self::set_timestamps). Fails with `InvalidData`
+    /// if the control messages did not fit.
+    pub async fn recv_with_control(
+        &self,
+        buf: &mut [u8],
+        out: &mut Vec<ControlMessage>,
+    ) -> io::Result<usize> {
+        loop {
+            let mut ready = self.fd.readable().await;
+
+            if let Ok(result) = ready.try_io_bytes(|fd| cmsg::recv(fd.as_fd(), buf, out)) {
+                return result;
+            }
+        }
+    }
+
+    /// Receive the sender's credentials with each message (`SO_PASSCRED`).
+    pub fn set_pass_credentials(&self, on: bool) -> io::Result<()> {
+        cmsg::set_flag(self.fd.as_fd(), libc::SO_PASSCRED, on)
+    }
+
+    /// Receive the arrival time with each message (`SO_TIMESTAMP`).
+    pub fn set_timestamps(&self, on: bool) -> io::Result<()> {
+        cmsg::set_flag(self.fd.as_fd(), libc::SO_TIMESTAMP, on)
+    }
+}
+
+impl AsFd for UnixDatagram {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+fn addr(path: &Path) -> io::Result<SocketAddrUnix> {
+    SocketAddrUnix::new(path).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn send(fd: &OwnedFd, buf: &[u8]) -> io::Result<usize> {
+    net::send(fd, buf, SendFlags::NOSIGNAL)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+fn recv(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+    net::recv(fd, buf, RecvFlags::empty())
+        .map(|(n, _)| n)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir};
+    use rustix::pipe::pipe;
+
+    #[test]
+    fn send_to_and_recv_from_bound_sockets() {
+        let dir = TempDir::new();
+
+        LocalExecutor::new().block_on(async {
+            let server = UnixDatagram::bind(dir.join("server")).unwrap();
+            let client = UnixDatagram::bind(dir.join("client")).unwrap();
+
+            client.send_to(b"ping", dir.join("server")).await.unwrap();
+            let mut buf = [0; 8];
+            let (n, from) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"ping");
+            assert_eq!(from, Some(dir.join("client")));
+
+            client.connect(dir.join("server")).unwrap();
+            client.send(b"a").await.unwrap();
+            client.send(b"bc").await.unwrap();
+            assert_eq!(server.recv(&mut buf).await.unwrap(), 1);
+            assert_eq!(server.recv(&mut buf).await.unwrap(), 2);
+
+            let anonymous = UnixDatagram::unbound().unwrap();
+            anonymous.send_to(b"x", dir.join("server")).await.unwrap();
+            assert_eq!(server.recv_from(&mut buf).await.unwrap(), (1, None));
+        });
+    }
+
+    #[test]
+    fn passes_fds() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixDatagram::pair().unwrap();
+            let (reader, writer) = pipe().unwrap();
+
+            a.send_with_fds(b"", &[writer.as_fd()]).await.unwrap();
+            let mut fds = Vec::new();
+            let mut buf = [0; 4];
+            assert_eq!(b.recv_with_fds(&mut buf, &mut fds).await.unwrap(), 0);
+            assert_eq!(fds.len(), 1);
+
+            drop(writer);
+            rustix::io::write(&fds[0], b"hi").unwrap();
+            assert_eq!(rustix::io::read(&reader, &mut buf).unwrap(), 2);
+        });
+    }
+}
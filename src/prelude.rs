@@ -0,0 +1,52 @@
+//! The runtime, spawn functions, I/O traits and common primitives in one
+//! import, `use ars::prelude::*;`.
+//!
+//! Traits are exported by name so their methods are in scope and they can
+//! be implemented without another `use`.
+
+pub use crate::{
+    io::{AsyncRead, AsyncWrite},
+    join,
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    runtime::{
+        JoinHandle, LocalExecutor, spawn_blocking, spawn_blocking_with, spawn_local, yield_now,
+    },
+    select,
+    time::{interval, sleep, sleep_until, timeout},
+    utils::{
+        cancel::CancellationToken,
+        lock::{Mutex, RwLock},
+        semaphore::Semaphore,
+    },
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn covers_a_small_program() {
+        LocalExecutor::new().block_on(async {
+            let token = CancellationToken::new();
+            let lock = std::rc::Rc::new(Mutex::new(0));
+
+            let task = spawn_local({
+                let lock = lock.clone();
+                async move { *lock.lock().await += 1 }
+            });
+
+            let (a, b) = join!(task, spawn_blocking(|| 2));
+            a.unwrap();
+            assert_eq!(b.unwrap(), 2);
+
+            token.cancel();
+            assert!(
+                timeout(Duration::from_secs(1), token.cancelled())
+                    .await
+                    .is_ok()
+            );
+            assert_eq!(*lock.lock().await, 1);
+        });
+    }
+}
@@ -0,0 +1,453 @@
+use crate::{io::AsyncWrite, reactor::AsyncFd};
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    process::{Pid, PidfdFlags, pidfd_open},
+};
+use std::{
+    ffi::OsStr,
+    future::poll_fn,
+    io,
+    path::Path,
+    process::{self, ExitStatus, Output, Stdio},
+    task::{Context, Poll, ready},
+};
+
+/// Bytes read per call while collecting a child's output.
+const CHUNK: usize = 8 * 1024;
+
+/// A builder for child processes that are awaited on the reactor.
+///
+/// Mirrors [`std::process::Command`], but [`spawn`](Self::spawn) returns a
+/// [`Child`] whose exit and pipes can be awaited without blocking the
+/// thread.
+#[derive(Debug)]
+pub struct Command {
+    std: process::Command,
+}
+
+impl Command {
+    /// Start building a command running `program`.
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        Self {
+            std: process::Command::new(program),
+        }
+    }
+
+    /// Add an argument.
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.std.arg(arg);
+        self
+    }
+
+    /// Add several arguments.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.std.args(args);
+        self
+    }
+
+    /// Set an environment variable.
+    pub fn env(&mut self, key: impl AsRef<OsStr>, val: impl AsRef<OsStr>) -> &mut Self {
+        self.std.env(key, val);
+        self
+    }
+
+    /// Remove an environment variable.
+    pub fn env_remove(&mut self, key: impl AsRef<OsStr>) -> &mut Self {
+        self.std.env_remove(key);
+        self
+    }
+
+    /// Start from an empty environment.
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.std.env_clear();
+        self
+    }
+
+    /// Set the working directory.
+    pub fn current_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.std.current_dir(dir);
+        self
+    }
+
+    /// Configure the child's stdin; use [`Stdio::piped`] for a [`ChildStdin`].
+    pub fn stdin(&mut self, cfg: impl Into<Stdio>) -> &mut Self {
+        self.std.stdin(cfg);
+        self
+    }
+
+    /// Configure the child's stdout; use [`Stdio::piped`] for a [`ChildStdout`].
+    pub fn stdout(&mut self, cfg: impl Into<Stdio>) -> &mut Self {
+        self.std.stdout(cfg);
+        self
+    }
+
+    /// Configure the child's stderr; use [`Stdio::piped`] for a [`ChildStderr`].
+    pub fn stderr(&mut self, cfg: impl Into<Stdio>) -> &mut Self {
+        self.std.stderr(cfg);
+        self
+    }
+
+    /// The underlying std command, for settings not mirrored here.
+    pub fn as_std_mut(&mut self) -> &mut process::Command {
+        &mut self.std
+    }
+
+    /// Spawn the child, registering it and its pipes with this thread's reactor.
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        Child::new(self.std.spawn()?)
+    }
+
+    /// Spawn the child and wait for it to exit.
+    ///
+    /// Stdio is inherited unless configured otherwise.
+    pub async fn status(&mut self) -> io::Result<ExitStatus> {
+        self.spawn()?.wait().await
+    }
+
+    /// Spawn the child and collect its output.
+    ///
+    /// Like [`std::process::Command::output`], but the defaults are applied
+    /// to this builder: stdin is set to null and stdout and stderr are piped.
+    pub async fn output(&mut self) -> io::Result<Output> {
+        self.std
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        self.spawn()?.wait_with_output().await
+    }
+}
+
+impl From<process::Command> for Command {
+    fn from(std: process::Command) -> Self {
+        Self { std }
+    }
+}
+
+/// A spawned child process.
+///
+/// Dropping a `Child` neither kills nor reaps the process; call
+/// [`wait`](Self::wait) to avoid leaving a zombie behind.
+#[derive(Debug)]
+pub struct Child {
+    /// The child's stdin, if piped.
+    pub stdin: Option<ChildStdin>,
+    /// The child's stdout, if piped.
+    pub stdout: Option<ChildStdout>,
+    /// The child's stderr, if piped.
+    pub stderr: Option<ChildStderr>,
+    std: process::Child,
+    pidfd: AsyncFd<OwnedFd>,
+}
+
+impl Child {
+    fn new(mut std: process::Child) -> io::Result<Self> {
+        let pid = Pid::from_raw(std.id() as i32).expect("child PIDs are positive");
+
+        // The child is not reaped until we wait, so the PID cannot be reused.
+        let pidfd = pidfd_open(pid, PidfdFlags::empty())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            stdin: std.stdin.take().map(pipe).transpose()?.map(ChildStdin),
+            stdout: std.stdout.take().map(pipe).transpose()?.map(ChildStdout),
+            stderr: std.stderr.take().map(pipe).transpose()?.map(ChildStderr),
+            pidfd: AsyncFd::new(pidfd)?,
+            std,
+        })
+    }
+
+    /// The child's PID.
+    pub fn id(&self) -> u32 {
+        self.std.id()
+    }
+
+    /// Wait for the child to exit and reap it.
+    ///
+    /// Stdin is closed first, so a child reading it to the end can finish.
+    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.stdin = None;
+
+        loop {
+            let mut ready = self.pidfd.readable().await;
+
+            let result = ready.try_io(|_| match self.std.try_wait() {
+                Ok(Some(status)) => Ok(status),
+                Ok(None) => Err(io::ErrorKind::WouldBlock.into()),
+                Err(e) => Err(e),
+            });
+
+            if let Ok(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Reap the child if it has exited, without waiting.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.std.try_wait()
+    }
+
+    /// Send `SIGKILL` to the child. It still has to be waited for.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.std.kill()
+    }
+
+    /// Wait for the child to exit while collecting its stdout and stderr.
+    ///
+    /// Both pipes are drained concurrently, so a child filling one of them
+    /// cannot deadlock against us reading the other.
+    pub async fn wait_with_output(mut self) -> io::Result<Output> {
+        self.stdin = None;
+
+        let stdout = self.stdout.take();
+        let stderr = self.stderr.take();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        poll_fn(|cx| {
+            let out_done = drain(stdout.as_ref().map(|p| &p.0), cx, &mut out)?;
+            let err_done = drain(stderr.as_ref().map(|p| &p.0), cx, &mut err)?;
+
+            if out_done && err_done {
+                Poll::Ready(Ok::<_, io::Error>(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await?;
+
+        Ok(Output {
+            status: self.wait().await?,
+            stdout: out,
+            stderr: err,
+        })
+    }
+}
+
+/// The writing end of a child's stdin.
+#[derive(Debug)]
+pub struct ChildStdin(AsyncFd<OwnedFd>);
+
+impl ChildStdin {
+    /// Write some of `buf`, returning the number of bytes written.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    /// Write all of `buf`.
+    pub async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        crate::io::write_all(self, buf).await
+    }
+
+    /// Poll-based [`write`](Self::write).
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let mut ready = ready!(self.0.poll_write_ready(cx));
+
+            let result = ready.try_io_bytes(|fd| {
+                rustix::io::write(fd, buf)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+            });
+
+            if let Ok(result) = result {
+                return Poll::Ready(result);
+            }
+        }
+    }
+}
+
+impl AsyncWrite for ChildStdin {
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ChildStdin::poll_write(self, cx, buf)
+    }
+}
+
+impl AsFd for ChildStdin {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+/// The reading end of a child's stdout.
+#[derive(Debug)]
+pub struct ChildStdout(AsyncFd<OwnedFd>);
+
+impl ChildStdout {
+    /// Read into `buf`, returning the number of bytes read; 0 means end of stream.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| poll_read(&self.0, cx, buf)).await
+    }
+
+    /// Poll-based [`read`](Self::read).
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        poll_read(&self.0, cx, buf)
+    }
+}
+
+impl AsFd for ChildStdout {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+/// The reading end of a child's stderr.
+#[derive(Debug)]
+pub struct ChildStderr(AsyncFd<OwnedFd>);
+
+impl ChildStderr {
+    /// Read into `buf`, returning the number of bytes read; 0 means end of stream.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| poll_read(&self.0, cx, buf)).await
+    }
+
+    /// Poll-based [`read`](Self::read).
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        poll_read(&self.0, cx, buf)
+    }
+}
+
+impl AsFd for ChildStderr {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+/// Switch our end of a pipe to non-blocking mode and register it.
+fn pipe(end: impl Into<OwnedFd>) -> io::Result<AsyncFd<OwnedFd>> {
+    let fd = end.into();
+
+    rustix::io::ioctl_fionbio(&fd, true)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+    AsyncFd::new(fd)
+}
+
+fn poll_read(
+    fd: &AsyncFd<OwnedFd>,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<usize>> {
+    loop {
+        let mut ready = ready!(fd.poll_read_ready(cx));
+
+        let result = ready.try_io_bytes(|fd| {
+            rustix::io::read(fd, &mut *buf)
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+        });
+
+        if let Ok(result) = result {
+            return Poll::Ready(result);
+        }
+    }
+}
+
+/// Read `fd` to the end into `out`; `true` once it is exhausted or absent.
+fn drain(
+    fd: Option<&AsyncFd<OwnedFd>>,
+    cx: &mut Context<'_>,
+    out: &mut Vec<u8>,
+) -> io::Result<bool> {
+    let Some(fd) = fd else {
+        return Ok(true);
+    };
+
+    let mut chunk = [0; CHUNK];
+
+    loop {
+        match poll_read(fd, cx, &mut chunk) {
+            Poll::Ready(Ok(0)) => return Ok(true),
+            Poll::Ready(Ok(n)) => out.extend_from_slice(&chunk[..n]),
+            Poll::Ready(Err(e)) => return Err(e),
+            Poll::Pending => return Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn output_collects_both_pipes_and_status() {
+        LocalExecutor::new().block_on(async {
+            let output = Command::new("sh")
+                .args(["-c", "echo out; echo err >&2; exit 3"])
+                .output()
+                .await
+                .unwrap();
+
+            assert_eq!(output.status.code(), Some(3));
+            assert_eq!(output.stdout, b"out\n");
+            assert_eq!(output.stderr, b"err\n");
+        });
+    }
+
+    #[test]
+    fn output_drains_pipes_beyond_their_capacity() {
+        LocalExecutor::new().block_on(async {
+            let output = Command::new("sh")
+                .args([
+                    "-c",
+                    "head -c 200000 /dev/zero >&2; head -c 300000 /dev/zero",
+                ])
+                .output()
+                .await
+                .unwrap();
+
+            assert!(output.status.success());
+            assert_eq!(output.stdout.len(), 300_000);
+            assert_eq!(output.stderr.len(), 200_000);
+        });
+    }
+
+    #[test]
+    fn stdin_is_written_and_closed() {
+        LocalExecutor::new().block_on(async {
+            let mut child = Command::new("cat")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+
+            child
+                .stdin
+                .as_mut()
+                .unwrap()
+                .write_all(b"echo")
+                .await
+                .unwrap();
+
+            // `cat` only exits once stdin is closed by waiting.
+            let output = child.wait_with_output().await.unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, b"echo");
+        });
+    }
+
+    #[test]
+    fn wait_reaps_killed_child() {
+        LocalExecutor::new().block_on(async {
+            let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+            assert!(child.try_wait().unwrap().is_none());
+
+            child.kill().unwrap();
+            let status = child.wait().await.unwrap();
+            assert_eq!(status.signal(), Some(libc::SIGKILL));
+        });
+    }
+
+    #[test]
+    fn spawn_fails_for_missing_program() {
+        LocalExecutor::new().block_on(async {
+            let err = Command::new("/nonexistent/program").spawn().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        });
+    }
+}
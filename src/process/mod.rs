@@ -0,0 +1,143 @@
+mod child;
+
+pub use child::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+
+use rustix::{
+    fd::AsRawFd,
+    pipe::{self, PipeFlags},
+};
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    process::{self, Stdio},
+};
+
+/// Spawn `cmd` fully detached from the current process.
+///
+/// The command is double-forked into a new session, so it is reparented
+/// to init (or the nearest subreaper) and never becomes a zombie of ours.
+/// Its stdio is redirected to `/dev/null` and every inherited fd above
+/// stderr is closed on exec. Returns the PID of the detached process.
+///
+/// The command is consumed, since the fork that detaches it is installed
+/// as a `pre_exec` hook and must not run again for a later spawn.
+pub fn spawn_detached(mut cmd: process::Command) -> io::Result<u32> {
+    let (reader, writer) = pipe::pipe_with(PipeFlags::CLOEXEC)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+    let writer_fd = writer.as_raw_fd();
+
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // SAFETY: The hook only makes async-signal-safe calls and does not
+    // allocate.
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setsid() < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            match libc::fork() {
+                -1 => Err(io::Error::last_os_error()),
+                0 => {
+                    let closed = libc::syscall(
+                        libc::SYS_close_range,
+                        3,
+                        libc::c_uint::MAX,
+                        libc::CLOSE_RANGE_CLOEXEC,
+                    );
+
+                    // CLOSE_RANGE_CLOEXEC needs Linux 5.11; mark each fd
+                    // up to the limit instead.
+                    if closed < 0 {
+                        let mut limit = std::mem::zeroed::<libc::rlimit>();
+
+                        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) < 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+
+                        let max = limit.rlim_cur.min(libc::c_int::MAX as libc::rlim_t);
+
+                        for fd in 3..max as libc::c_int {
+                            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                        }
+                    }
+
+                    Ok(())
+                }
+                pid => {
+                    let bytes = pid.to_ne_bytes();
+                    libc::write(writer_fd, bytes.as_ptr().cast(), bytes.len());
+                    libc::_exit(0)
+                }
+            }
+        });
+    }
+
+    // `spawn` returns once the grandchild has exec'd, or with its exec error.
+    let mut intermediate = cmd.spawn()?;
+    drop(writer);
+    intermediate.wait()?;
+
+    let mut bytes = [0; size_of::<libc::pid_t>()];
+    File::from(reader).read_exact(&mut bytes)?;
+
+    Ok(libc::pid_t::from_ne_bytes(bytes) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn detached_process_runs_in_own_session() {
+        let dir = TempDir::new();
+        let out = dir.join("out");
+
+        let mut cmd = process::Command::new("sh");
+        cmd.args([
+            "-c",
+            "echo $$ $PPID $(cut -d' ' -f6 /proc/$$/stat) > \"$1.tmp\" && mv \"$1.tmp\" \"$1\"",
+            "sh",
+            out.to_str().unwrap(),
+        ]);
+
+        let pid = spawn_detached(cmd).unwrap();
+
+        let started = Instant::now();
+        let contents = loop {
+            match std::fs::read_to_string(&out) {
+                Ok(contents) => break contents,
+                Err(_) if started.elapsed() < Duration::from_secs(5) => {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                Err(e) => panic!("detached process did not report: {e}"),
+            }
+        };
+
+        let fields: Vec<u32> = contents
+            .split_whitespace()
+            .map(|f| f.parse().unwrap())
+            .collect();
+        let [own, parent, session] = fields[..] else {
+            panic!("unexpected report: {contents:?}");
+        };
+
+        assert_eq!(own, pid);
+        assert_ne!(parent, process::id());
+        // Field 6 of /proc/<pid>/stat is the session id.
+        let stat = std::fs::read_to_string("/proc/self/stat").unwrap();
+        let own_session: u32 = stat.split(' ').nth(5).unwrap().parse().unwrap();
+        assert_ne!(session, own_session);
+    }
+
+    #[test]
+    fn exec_failure_is_reported() {
+        let err = spawn_detached(process::Command::new("/nonexistent/program")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,373 @@
+use crate::reactor::{
+    ERROR, READ_CLOSED, READABLE, Reactor, Source, WRITABLE, WRITE_CLOSED, local_reactor,
+};
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    io,
+    os::fd::AsFd,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+/// Readiness bits that satisfy a read or write interest.
+const READ_MASK: u8 = READABLE | READ_CLOSED | ERROR;
+const WRITE_MASK: u8 = WRITABLE | WRITE_CLOSED | ERROR;
+
+/// A non-blocking fd registered with the thread's [`Reactor`].
+///
+/// Await [`readable`](Self::readable) or [`writable`](Self::writable), then
+/// perform the I/O through [`ReadyGuard::try_io`]. The registration is
+/// edge-triggered: readiness is only cleared when an operation reports
+/// `WouldBlock`, so the fd must be in non-blocking mode.
+pub struct AsyncFd<T: AsFd> {
+    inner: Option<T>,
+    reactor: Rc<Reactor>,
+    token: u64,
+    source: Rc<Source>,
+}
+
+impl<T: AsFd> AsyncFd<T> {
+    /// Register `inner` with this thread's reactor.
+    pub fn new(inner: T) -> io::Result<Self> {
+        Self::with_reactor(inner, local_reactor()?)
+    }
+
+    /// Register `inner` with a specific reactor.
+    pub fn with_reactor(inner: T, reactor: Rc<Reactor>) -> io::Result<Self> {
+        let (token, source) = reactor.register(inner.as_fd())?;
+
+        Ok(Self {
+            inner: Some(inner),
+            reactor,
+            token,
+            source,
+        })
+    }
+
+    /// The wrapped value.
+    pub fn get_ref(&self) -> &T {
+        self.inner.as_ref().expect("present until drop")
+    }
+
+    /// The wrapped value, mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.as_mut().expect("present until drop")
+    }
+
+    /// Deregister and return the wrapped value.
+    pub fn into_inner(mut self) -> T {
+        let inner = self.inner.take().expect("present until drop");
+        self.reactor.deregister(self.token, inner.as_fd());
+        inner
+    }
+
+    /// Wait until the fd may be readable.
+    pub fn readable(&self) -> impl Future<Output = ReadyGuard<'_, T>> + '_ {
+        Readiness {
+            fd: self,
+            mask: READ_MASK,
+            key: None,
+        }
+    }
+
+    /// Wait until the fd may be writable.
+    pub fn writable(&self) -> impl Future<Output = ReadyGuard<'_, T>> + '_ {
+        Readiness {
+            fd: self,
+            mask: WRITE_MASK,
+            key: None,
+        }
+    }
+
+    /// Poll for read readiness without registering a named future.
+    ///
+    /// Only the waker of the latest call is kept, as for `poll_*` methods in
+    /// general; this is meant for implementing I/O traits.
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<ReadyGuard<'_, T>> {
+        self.poll_ready(READ_MASK, cx)
+    }
+
+    /// Poll for write readiness; see [`poll_read_ready`](Self::poll_read_ready).
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<ReadyGuard<'_, T>> {
+        self.poll_ready(WRITE_MASK, cx)
+    }
+
+    /// This fd's I/O counters.
+    #[cfg(feature = "fd-stats")]
+    pub fn stats(&self) -> crate::reactor::FdStats {
+        self.source.stats.get()
+    }
+
+    fn poll_ready(&self, mask: u8, cx: &mut Context<'_>) -> Poll<ReadyGuard<'_, T>> {
+        let mut state = self.source.state.borrow_mut();
+
+        if state.ready & mask != 0 {
+            return Poll::Ready(ReadyGuard {
+                fd: self,
+                mask,
+                tick: state.tick,
+            });
+        }
+
+        let slot = if mask == READ_MASK {
+            &mut state.poll_reader
+        } else {
+            &mut state.poll_writer
+        };
+
+        match slot {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            slot => *slot = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T: AsFd> AsFd for AsyncFd<T> {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.get_ref().as_fd()
+    }
+}
+
+impl<T: AsFd> Drop for AsyncFd<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = &self.inner {
+            self.reactor.deregister(self.token, inner.as_fd());
+        }
+    }
+}
+
+impl<T: AsFd + fmt::Debug> fmt::Debug for AsyncFd<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncFd")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Readiness<'a, T: AsFd> {
+    fd: &'a AsyncFd<T>,
+    mask: u8,
+    key: Option<u64>,
+}
+
+impl<T: AsFd> Readiness<'_, T> {
+    fn remove(&mut self) {
+        let mut state = self.fd.source.state.borrow_mut();
+
+        if self.mask == READ_MASK {
+            state.readers.remove(self.key.take());
+        } else {
+            state.writers.remove(self.key.take());
+        }
+    }
+}
+
+impl<'a, T: AsFd> Future for Readiness<'a, T> {
+    type Output = ReadyGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this.fd.source.state.borrow_mut();
+
+        if state.ready & this.mask != 0 {
+            let tick = state.tick;
+            drop(state);
+            this.remove();
+
+            return Poll::Ready(ReadyGuard {
+                fd: this.fd,
+                mask: this.mask,
+                tick,
+            });
+        }
+
+        if this.mask == READ_MASK {
+            state.readers.register(&mut this.key, cx.waker());
+        } else {
+            state.writers.register(&mut this.key, cx.waker());
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T: AsFd> Drop for Readiness<'_, T> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.remove();
+        }
+    }
+}
+
+/// Observed readiness of an [`AsyncFd`].
+#[must_use = "readiness is only cleared through the guard"]
+pub struct ReadyGuard<'a, T: AsFd> {
+    fd: &'a AsyncFd<T>,
+    mask: u8,
+    tick: u64,
+}
+
+impl<'a, T: AsFd> ReadyGuard<'a, T> {
+    /// The wrapped value.
+    pub fn get_ref(&self) -> &'a T {
+        self.fd.get_ref()
+    }
+
+    /// Whether the peer closed its end, or an error is pending on the fd.
+    pub fn is_closed(&self) -> bool {
+        let closed = if self.mask == READ_MASK {
+            READ_CLOSED
+        } else {
+            WRITE_CLOSED
+        };
+
+        self.fd.source.state.borrow().ready & (closed | ERROR) != 0
+    }
+
+    /// Mark the fd as no longer ready, so the next wait blocks.
+    ///
+    /// Does nothing if an event arrived since this readiness was observed.
+    pub fn clear_ready(&mut self) {
+        let mut state = self.fd.source.state.borrow_mut();
+
+        if state.tick == self.tick {
+            // Closed and error states are sticky; only the plain bit clears.
+            state.ready &= !(self.mask & (READABLE | WRITABLE));
+        }
+    }
+
+    /// Run a non-blocking operation, clearing readiness on `WouldBlock`.
+    ///
+    /// Returns `Err(TryIoError)` if the operation would have blocked, in which
+    /// case the caller should wait for readiness again.
+    pub fn try_io<R>(
+        &mut self,
+        f: impl FnOnce(&T) -> io::Result<R>,
+    ) -> Result<io::Result<R>, TryIoError> {
+        match f(self.get_ref()) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                #[cfg(feature = "fd-stats")]
+                self.fd.source.update_stats(|stats| stats.would_block += 1);
+
+                self.clear_ready();
+                Err(TryIoError)
+            }
+            result => {
+                #[cfg(feature = "fd-stats")]
+                if result.is_ok() {
+                    self.count_op();
+                }
+
+                Ok(result)
+            }
+        }
+    }
+
+    /// [`try_io`](Self::try_io) for an operation returning a byte count.
+    ///
+    /// With the `fd-stats` feature the count is added to the fd's
+    /// [`FdStats`](crate::reactor::FdStats).
+    pub fn try_io_bytes(
+        &mut self,
+        f: impl FnOnce(&T) -> io::Result<usize>,
+    ) -> Result<io::Result<usize>, TryIoError> {
+        let result = self.try_io(f);
+
+        #[cfg(feature = "fd-stats")]
+        if let Ok(Ok(n)) = result {
+            self.count_bytes(n);
+        }
+
+        result
+    }
+
+    #[cfg(feature = "fd-stats")]
+    fn count_op(&self) {
+        let read = self.mask == READ_MASK;
+
+        self.fd.source.update_stats(|stats| match read {
+            true => stats.read_ops += 1,
+            false => stats.write_ops += 1,
+        });
+    }
+
+    #[cfg(feature = "fd-stats")]
+    fn count_bytes(&self, n: usize) {
+        let read = self.mask == READ_MASK;
+
+        self.fd.source.update_stats(|stats| match read {
+            true => stats.read_bytes += n as u64,
+            false => stats.write_bytes += n as u64,
+        });
+    }
+}
+
+impl<T: AsFd> fmt::Debug for ReadyGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadyGuard")
+            .field("tick", &self.tick)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returned by [`ReadyGuard::try_io`] when the operation would block.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TryIoError;
+
+impl fmt::Display for TryIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("operation would block")
+    }
+}
+
+impl Error for TryIoError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+    use rustix::{
+        fd::OwnedFd,
+        pipe::{PipeFlags, pipe_with},
+    };
+
+    fn read(fd: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+        rustix::io::read(fd, buf).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    #[test]
+    fn readable_after_write_and_clears_on_would_block() {
+        LocalExecutor::new().block_on(async {
+            let (reader, writer) = pipe_with(PipeFlags::NONBLOCK | PipeFlags::CLOEXEC).unwrap();
+            let reader = AsyncFd::new(reader).unwrap();
+
+            rustix::io::write(&writer, b"hi").unwrap();
+
+            let mut guard = reader.readable().await;
+            let mut buf = [0; 8];
+            assert_eq!(guard.try_io(|fd| read(fd, &mut buf)).unwrap().unwrap(), 2);
+            assert!(guard.try_io(|fd| read(fd, &mut buf)).is_err());
+            assert!(!guard.is_closed());
+        });
+    }
+
+    #[test]
+    fn reports_closed_peer() {
+        LocalExecutor::new().block_on(async {
+            let (reader, writer) = pipe_with(PipeFlags::NONBLOCK | PipeFlags::CLOEXEC).unwrap();
+            let reader = AsyncFd::new(reader).unwrap();
+
+            drop(writer);
+
+            let mut guard = reader.readable().await;
+            assert!(guard.is_closed());
+            let mut buf = [0; 8];
+            assert_eq!(guard.try_io(|fd| read(fd, &mut buf)).unwrap().unwrap(), 0);
+        });
+    }
+}
@@ -0,0 +1,119 @@
+//! The default backend: one edge-triggered epoll instance.
+
+use super::{ERROR, READ_CLOSED, READABLE, Source, WRITABLE, WRITE_CLOSED};
+use crate::utils::tracked_cell::TrackedRefCell;
+use rustix::{
+    buffer::spare_capacity,
+    event::{
+        Timespec,
+        epoll::{self, CreateFlags, EventData, EventFlags},
+    },
+    fd::{BorrowedFd, OwnedFd},
+    io::Errno,
+};
+use std::{collections::HashMap, fmt, io, rc::Rc};
+
+/// Events fetched per `epoll_wait`.
+const EVENTS_CAPACITY: usize = 256;
+
+pub(super) struct Selector {
+    epoll: OwnedFd,
+    events: TrackedRefCell<Vec<epoll::Event>>,
+}
+
+impl Selector {
+    pub(super) fn new(unpark: BorrowedFd<'_>, unpark_token: u64) -> io::Result<Self> {
+        let epoll = epoll::create(CreateFlags::CLOEXEC)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        epoll::add(
+            &epoll,
+            unpark,
+            EventData::new_u64(unpark_token),
+            EventFlags::IN | EventFlags::ET,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            epoll,
+            events: TrackedRefCell::new(Vec::with_capacity(EVENTS_CAPACITY)),
+        })
+    }
+
+    pub(super) fn add(&self, fd: BorrowedFd<'_>, token: u64) -> io::Result<()> {
+        epoll::add(
+            &self.epoll,
+            fd,
+            EventData::new_u64(token),
+            EventFlags::IN | EventFlags::OUT | EventFlags::RDHUP | EventFlags::ET,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    pub(super) fn delete(&self, _token: u64, fd: BorrowedFd<'_>) {
+        // The fd may already have been closed behind our back; nothing to undo then.
+        let _ = epoll::delete(&self.epoll, fd);
+    }
+
+    /// Fetch pending events; the registered sources are not needed, as
+    /// epoll keeps the interest list itself.
+    pub(super) fn wait(
+        &self,
+        timeout: Option<&Timespec>,
+        _sources: &HashMap<u64, Rc<Source>>,
+    ) -> io::Result<()> {
+        let mut events = self.events.borrow_mut();
+        events.clear();
+
+        match epoll::wait(&self.epoll, spare_capacity(&mut events), timeout) {
+            Ok(_) | Err(Errno::INTR) => Ok(()),
+            Err(e) => Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        }
+    }
+
+    /// Hand each fetched event to `f` as a token and readiness bits.
+    pub(super) fn for_each_event(&self, mut f: impl FnMut(u64, u8)) {
+        let events = std::mem::take(&mut *self.events.borrow_mut());
+
+        for event in &events {
+            f(event.data.u64(), ready_from(event.flags));
+        }
+
+        // Hand the buffer back for the next wait.
+        *self.events.borrow_mut() = events;
+    }
+}
+
+impl fmt::Debug for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Selector")
+            .field("epoll", &self.epoll)
+            .finish_non_exhaustive()
+    }
+}
+
+fn ready_from(flags: EventFlags) -> u8 {
+    let mut ready = 0;
+
+    if flags.intersects(EventFlags::IN | EventFlags::PRI) {
+        ready |= READABLE;
+    }
+
+    if flags.contains(EventFlags::OUT) {
+        ready |= WRITABLE;
+    }
+
+    if flags.contains(EventFlags::RDHUP) {
+        ready |= READ_CLOSED;
+    }
+
+    if flags.contains(EventFlags::HUP) {
+        ready |= READ_CLOSED | WRITE_CLOSED;
+    }
+
+    if flags.contains(EventFlags::ERR) {
+        ready |= ERROR;
+    }
+
+    ready
+}
@@ -0,0 +1,322 @@
+//! A per-thread epoll reactor.
+//!
+//! Each file descriptor is registered once, edge-triggered for both read
+//! and write interest, and its readiness is cached until an operation
+//! reports `EAGAIN`. That makes draining to `EAGAIN` mandatory, which
+//! [`ReadyGuard::try_io`] enforces by clearing readiness only on
+//! `WouldBlock`. An eventfd under a reserved token lets other threads cut a
+//! wait short.
+//!
+//! A wait fetches at most 256 events, leaving the rest for the next one,
+//! and its timeout is rounded up to whole milliseconds and capped at
+//! `i32::MAX` ms, past which some kernels reject it; longer sleeps simply
+//! wait again.
+//!
+//! With the `poll-reactor` feature, `poll(2)` takes the place of epoll for
+//! sandboxes that do not allow it; see the `poll` backend for how it keeps
+//! the same semantics. [`AsyncFd`] works the same either way.
+
+mod async_fd;
+#[cfg(not(feature = "poll-reactor"))]
+mod epoll;
+#[cfg(feature = "poll-reactor")]
+mod poll;
+
+pub use async_fd::{AsyncFd, ReadyGuard, TryIoError};
+
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+#[cfg(not(feature = "poll-reactor"))]
+use epoll::Selector;
+#[cfg(feature = "poll-reactor")]
+use poll::Selector;
+use rustix::{
+    event::{EventfdFlags, Timespec, eventfd},
+    fd::{AsFd, BorrowedFd, OwnedFd},
+};
+use std::{
+    cell::{Cell, OnceCell},
+    collections::HashMap,
+    fmt, io,
+    rc::Rc,
+    sync::Arc,
+    task::Waker,
+    time::Duration,
+};
+
+/// Token of the reactor's own eventfd.
+const UNPARK_TOKEN: u64 = 0;
+
+/// Longest single wait; older kernels reject timeouts past `c_int::MAX` ms.
+const MAX_WAIT: Duration = Duration::from_millis(i32::MAX as u64);
+
+const READABLE: u8 = 1 << 0;
+const WRITABLE: u8 = 1 << 1;
+const READ_CLOSED: u8 = 1 << 2;
+const WRITE_CLOSED: u8 = 1 << 3;
+const ERROR: u8 = 1 << 4;
+
+thread_local! {
+    static LOCAL: OnceCell<Rc<Reactor>> = const { OnceCell::new() };
+}
+
+/// An epoll-based I/O reactor.
+///
+/// File descriptors are registered edge-triggered through [`AsyncFd`].
+/// [`poll`](Self::poll) waits for events and wakes the tasks interested
+/// in them; [`LocalExecutor`](crate::runtime::LocalExecutor) does this
+/// whenever it runs out of work, so a custom loop only needs to drive the
+/// reactor itself if it does not use the executor.
+pub struct Reactor {
+    selector: Selector,
+    unparker: Unparker,
+    sources: TrackedRefCell<HashMap<u64, Rc<Source>>>,
+    next_token: Cell<u64>,
+}
+
+/// Readiness of one registered fd, with the tasks waiting on it.
+#[derive(Debug, Default)]
+pub(crate) struct Source {
+    pub(crate) state: TrackedRefCell<SourceState>,
+    #[cfg(feature = "fd-stats")]
+    pub(crate) fd: std::os::fd::RawFd,
+    #[cfg(feature = "fd-stats")]
+    pub(crate) stats: Cell<FdStats>,
+}
+
+/// I/O counters of one registered fd, kept with the `fd-stats` feature.
+///
+/// Operations and bytes are counted by [`ReadyGuard::try_io`] and
+/// [`ReadyGuard::try_io_bytes`]; only the latter knows the byte count.
+#[cfg(feature = "fd-stats")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FdStats {
+    /// Readiness events delivered by epoll.
+    pub events: u64,
+    /// Completed operations while waiting for read readiness.
+    pub read_ops: u64,
+    /// Completed operations while waiting for write readiness.
+    pub write_ops: u64,
+    /// Bytes read through [`ReadyGuard::try_io_bytes`].
+    pub read_bytes: u64,
+    /// Bytes written through [`ReadyGuard::try_io_bytes`].
+    pub write_bytes: u64,
+    /// Operations that reported `WouldBlock`.
+    pub would_block: u64,
+}
+
+#[cfg(feature = "fd-stats")]
+impl Source {
+    pub(crate) fn update_stats(&self, f: impl FnOnce(&mut FdStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct SourceState {
+    pub(crate) ready: u8,
+    /// Bumped on every event, so a stale observation cannot clear newer readiness.
+    pub(crate) tick: u64,
+    pub(crate) readers: WaiterList,
+    pub(crate) writers: WaiterList,
+    /// Wakers of the `poll_*_ready` methods, which have no future to hold a key.
+    pub(crate) poll_reader: Option<Waker>,
+    pub(crate) poll_writer: Option<Waker>,
+}
+
+/// The thread's reactor, created on first use.
+pub fn local_reactor() -> io::Result<Rc<Reactor>> {
+    LOCAL.with(|local| {
+        if let Some(reactor) = local.get() {
+            return Ok(reactor.clone());
+        }
+
+        let reactor = Rc::new(Reactor::new()?);
+        Ok(local.get_or_init(|| reactor).clone())
+    })
+}
+
+impl Reactor {
+    /// Create a reactor with its own epoll instance, or poll set with the
+    /// `poll-reactor` feature.
+    pub fn new() -> io::Result<Self> {
+        let unpark = eventfd(0, EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            selector: Selector::new(unpark.as_fd(), UNPARK_TOKEN)?,
+            unparker: Unparker {
+                fd: Arc::new(unpark),
+            },
+            sources: TrackedRefCell::default(),
+            next_token: Cell::new(UNPARK_TOKEN),
+        })
+    }
+
+    /// Wait up to `timeout` for events and wake the tasks they concern.
+    ///
+    /// `None` waits until an event arrives or the reactor is unparked.
+    /// Returns the number of fds that became ready.
+    pub fn poll(&self, timeout: Option<Duration>) -> io::Result<usize> {
+        self.wait(timeout)?;
+        Ok(self.dispatch())
+    }
+
+    /// A handle that interrupts a blocking [`poll`](Self::poll) from any thread.
+    pub fn unparker(&self) -> Unparker {
+        self.unparker.clone()
+    }
+
+    /// Fetch pending events, blocking up to `timeout`.
+    pub(crate) fn wait(&self, timeout: Option<Duration>) -> io::Result<()> {
+        // Round up so a sub-millisecond remainder does not become a busy loop.
+        let timeout = timeout.map(|t| {
+            let t = t.min(MAX_WAIT);
+            let ms = t.as_millis() + u128::from(!t.subsec_nanos().is_multiple_of(1_000_000));
+            Timespec::try_from(Duration::from_millis(ms as u64)).expect("bounded by MAX_WAIT")
+        });
+
+        self.selector.wait(timeout.as_ref(), &self.sources.borrow())
+    }
+
+    /// Apply the events fetched by [`wait`](Self::wait) and wake their tasks.
+    pub(crate) fn dispatch(&self) -> usize {
+        let mut wakers: Vec<Waker> = Vec::new();
+        let mut count = 0;
+
+        self.selector.for_each_event(|token, ready| {
+            if token == UNPARK_TOKEN {
+                self.unparker.drain();
+                return;
+            }
+
+            let Some(source) = self.sources.borrow().get(&token).cloned() else {
+                return;
+            };
+
+            #[cfg(feature = "fd-stats")]
+            source.update_stats(|stats| stats.events += 1);
+
+            let mut state = source.state.borrow_mut();
+            state.ready |= ready;
+            state.tick += 1;
+            count += 1;
+
+            if ready & (READABLE | READ_CLOSED | ERROR) != 0 {
+                wakers.extend(state.readers.take_all());
+                wakers.extend(state.poll_reader.take());
+            }
+
+            if ready & (WRITABLE | WRITE_CLOSED | ERROR) != 0 {
+                wakers.extend(state.writers.take_all());
+                wakers.extend(state.poll_writer.take());
+            }
+        });
+
+        for waker in wakers {
+            waker.wake();
+        }
+
+        count
+    }
+
+    pub(crate) fn register(&self, fd: BorrowedFd<'_>) -> io::Result<(u64, Rc<Source>)> {
+        let token = self.next_token.get() + 1;
+        self.next_token.set(token);
+
+        let source = Rc::new(Source {
+            #[cfg(feature = "fd-stats")]
+            fd: std::os::fd::AsRawFd::as_raw_fd(&fd),
+            ..Source::default()
+        });
+
+        self.selector.add(fd, token)?;
+        self.sources.borrow_mut().insert(token, source.clone());
+
+        Ok((token, source))
+    }
+
+    /// Counters of every registered fd, keyed by its raw fd number.
+    ///
+    /// Sorted by the number of completed operations, busiest first, to
+    /// point at the connection saturating the loop.
+    #[cfg(feature = "fd-stats")]
+    pub fn fd_stats(&self) -> Vec<(std::os::fd::RawFd, FdStats)> {
+        let mut all: Vec<_> = self
+            .sources
+            .borrow()
+            .values()
+            .map(|source| (source.fd, source.stats.get()))
+            .collect();
+
+        all.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.read_ops + stats.write_ops));
+        all
+    }
+
+    pub(crate) fn deregister(&self, token: u64, fd: BorrowedFd<'_>) {
+        self.sources.borrow_mut().remove(&token);
+        self.selector.delete(token, fd);
+    }
+}
+
+impl fmt::Debug for Reactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reactor")
+            .field("selector", &self.selector)
+            .field("sources", &self.sources.borrow().len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Wakes a [`Reactor`] blocked in [`Reactor::poll`].
+#[derive(Debug, Clone)]
+pub struct Unparker {
+    fd: Arc<OwnedFd>,
+}
+
+impl Unparker {
+    /// Interrupt the reactor's current or next wait.
+    pub fn unpark(&self) {
+        // Only fails if the counter would overflow, i.e. a wakeup is pending anyway.
+        let _ = rustix::io::write(self.fd.as_fd(), &1u64.to_ne_bytes());
+    }
+
+    fn drain(&self) {
+        let mut buf = [0; 8];
+        let _ = rustix::io::read(self.fd.as_fd(), &mut buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Instant};
+
+    #[test]
+    fn poll_times_out_without_events() {
+        let reactor = Reactor::new().unwrap();
+        let started = Instant::now();
+
+        assert_eq!(reactor.poll(Some(Duration::from_millis(5))).unwrap(), 0);
+        assert!(started.elapsed() >= Duration::from_millis(5));
+    }
+
+    #[test]
+    fn unparker_interrupts_poll_from_another_thread() {
+        let reactor = Reactor::new().unwrap();
+        let unparker = reactor.unparker();
+
+        let thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            unparker.unpark();
+        });
+
+        // The wakeup itself is not counted as an fd event.
+        assert_eq!(reactor.poll(None).unwrap(), 0);
+        thread.join().unwrap();
+
+        // The wakeup was drained, so the next poll times out again.
+        assert_eq!(reactor.poll(Some(Duration::ZERO)).unwrap(), 0);
+    }
+}
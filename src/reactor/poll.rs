@@ -0,0 +1,151 @@
+//! The `poll-reactor` backend: `poll(2)` over every registered fd, for
+//! sandboxes whose seccomp profile forbids epoll.
+//!
+//! `poll` is level-triggered, so each wait asks only for the readiness a
+//! source has not cached yet. A readable fd stays out of the read set until
+//! an operation hits `EAGAIN` and clears its readiness, which gives the
+//! same one-event-per-transition behaviour as epoll's edge triggering. An
+//! fd with everything cached is left out entirely. Each wait builds the
+//! poll set afresh, so it costs time linear in the number of fds.
+
+use super::{ERROR, READ_CLOSED, READABLE, Source, WRITABLE, WRITE_CLOSED};
+use crate::utils::tracked_cell::TrackedRefCell;
+use rustix::{
+    event::{PollFd, PollFlags, Timespec, poll},
+    fd::{AsRawFd, BorrowedFd, RawFd},
+    io::Errno,
+};
+use std::{collections::HashMap, io, rc::Rc};
+
+#[derive(Debug)]
+pub(super) struct Selector {
+    unpark: RawFd,
+    unpark_token: u64,
+    fds: TrackedRefCell<HashMap<u64, RawFd>>,
+    events: TrackedRefCell<Vec<(u64, u8)>>,
+}
+
+impl Selector {
+    pub(super) fn new(unpark: BorrowedFd<'_>, unpark_token: u64) -> io::Result<Self> {
+        Ok(Self {
+            unpark: unpark.as_raw_fd(),
+            unpark_token,
+            fds: TrackedRefCell::default(),
+            events: TrackedRefCell::default(),
+        })
+    }
+
+    pub(super) fn add(&self, fd: BorrowedFd<'_>, token: u64) -> io::Result<()> {
+        self.fds.borrow_mut().insert(token, fd.as_raw_fd());
+        Ok(())
+    }
+
+    pub(super) fn delete(&self, token: u64, _fd: BorrowedFd<'_>) {
+        self.fds.borrow_mut().remove(&token);
+    }
+
+    /// Wait for any registered fd to gain readiness its source lacks.
+    pub(super) fn wait(
+        &self,
+        timeout: Option<&Timespec>,
+        sources: &HashMap<u64, Rc<Source>>,
+    ) -> io::Result<()> {
+        let fds = self.fds.borrow();
+        let mut tokens = vec![self.unpark_token];
+        let mut raw = vec![(self.unpark, PollFlags::IN)];
+
+        for (&token, &fd) in fds.iter() {
+            let Some(source) = sources.get(&token) else {
+                continue;
+            };
+
+            let interest = interest(source.state.borrow().ready);
+
+            if !interest.is_empty() {
+                tokens.push(token);
+                raw.push((fd, interest));
+            }
+        }
+
+        let mut poll_fds: Vec<_> = raw
+            .iter()
+            .map(|&(fd, flags)| {
+                // SAFETY: Registered fds are deregistered before their owner
+                // closes them, and the eventfd lives as long as the reactor.
+                PollFd::from_borrowed_fd(unsafe { BorrowedFd::borrow_raw(fd) }, flags)
+            })
+            .collect();
+
+        let mut events = self.events.borrow_mut();
+        events.clear();
+
+        match poll(&mut poll_fds, timeout) {
+            Ok(_) => {}
+            Err(Errno::INTR) => return Ok(()),
+            Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        }
+
+        for (poll_fd, &token) in poll_fds.iter().zip(&tokens) {
+            let ready = ready_from(poll_fd.revents());
+
+            if ready != 0 {
+                events.push((token, ready));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hand each fetched event to `f` as a token and readiness bits.
+    pub(super) fn for_each_event(&self, mut f: impl FnMut(u64, u8)) {
+        let events = std::mem::take(&mut *self.events.borrow_mut());
+
+        for &(token, ready) in &events {
+            f(token, ready);
+        }
+
+        // Hand the buffer back for the next wait.
+        *self.events.borrow_mut() = events;
+    }
+}
+
+/// The poll flags for readiness not yet cached in `ready`.
+fn interest(ready: u8) -> PollFlags {
+    let mut flags = PollFlags::empty();
+
+    if ready & (READABLE | READ_CLOSED | ERROR) == 0 {
+        flags |= PollFlags::IN | PollFlags::RDHUP;
+    }
+
+    if ready & (WRITABLE | WRITE_CLOSED | ERROR) == 0 {
+        flags |= PollFlags::OUT;
+    }
+
+    flags
+}
+
+fn ready_from(flags: PollFlags) -> u8 {
+    let mut ready = 0;
+
+    if flags.intersects(PollFlags::IN | PollFlags::PRI) {
+        ready |= READABLE;
+    }
+
+    if flags.contains(PollFlags::OUT) {
+        ready |= WRITABLE;
+    }
+
+    if flags.contains(PollFlags::RDHUP) {
+        ready |= READ_CLOSED;
+    }
+
+    if flags.contains(PollFlags::HUP) {
+        ready |= READ_CLOSED | WRITE_CLOSED;
+    }
+
+    if flags.intersects(PollFlags::ERR | PollFlags::NVAL) {
+        ready |= ERROR;
+    }
+
+    ready
+}
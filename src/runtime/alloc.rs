@@ -0,0 +1,110 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    /// Allocations and bytes requested on this thread so far.
+    static ALLOCATED: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+/// A global allocator that charges allocations to the task being polled.
+///
+/// Install it to fill in [`TaskInfo::allocations`](super::TaskInfo::allocations):
+///
+/// ```text
+/// #[global_allocator]
+/// static ALLOC: CountingAlloc = CountingAlloc(std::alloc::System);
+/// ```
+///
+/// Counting costs a thread-local update per allocation; frees are not
+/// tracked.
+#[derive(Debug, Default)]
+pub struct CountingAlloc<A = System>(pub A);
+
+// SAFETY: Every call is forwarded to the wrapped allocator unchanged.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        // SAFETY: Forwarded with the caller's guarantees.
+        unsafe { self.0.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        // SAFETY: Forwarded with the caller's guarantees.
+        unsafe { self.0.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        // SAFETY: Forwarded with the caller's guarantees.
+        unsafe { self.0.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: Forwarded with the caller's guarantees.
+        unsafe { self.0.dealloc(ptr, layout) }
+    }
+}
+
+fn count(size: usize) {
+    // The const initialiser needs no allocation, and the cell has no
+    // destructor, so this cannot recurse or fail during thread exit.
+    let _ = ALLOCATED.try_with(|a| {
+        let (count, bytes) = a.get();
+        a.set((count + 1, bytes + size as u64));
+    });
+}
+
+/// Allocations and bytes counted on this thread so far.
+pub(crate) fn allocated() -> (u64, u64) {
+    ALLOCATED.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{LocalExecutor, yield_now};
+
+    static ALLOC: CountingAlloc = CountingAlloc(System);
+
+    #[test]
+    fn tasks_are_charged_for_their_polls() {
+        let executor = LocalExecutor::new();
+
+        let handle = executor.spawn(async {
+            let layout = Layout::from_size_align(64, 8).unwrap();
+
+            for _ in 0..2 {
+                // SAFETY: The layout is non-zero sized; the block is freed
+                // with the same layout.
+                unsafe { ALLOC.dealloc(ALLOC.alloc(layout), layout) };
+                yield_now().await;
+            }
+
+            std::future::pending::<()>().await;
+        });
+        let id = handle.id();
+        let idle = executor.spawn(std::future::pending::<()>());
+
+        executor.block_on(async {
+            while executor
+                .dump()
+                .iter()
+                .any(|task| task.id == id && task.polls < 2)
+            {
+                yield_now().await;
+            }
+
+            let dump = executor.dump();
+            let busy = dump.iter().find(|task| task.id == id).unwrap();
+            assert_eq!(busy.allocations, 2);
+            assert_eq!(busy.allocated_bytes, 128);
+
+            let idle = dump.iter().find(|task| task.id == idle.id()).unwrap();
+            assert_eq!(idle.polls, 1);
+            assert_eq!(idle.allocations, 0);
+        });
+    }
+}
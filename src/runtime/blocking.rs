@@ -0,0 +1,634 @@
+use crate::{
+    runtime::{JoinHandle, spawn_local},
+    utils::cancel::CancellationToken,
+};
+use std::{
+    collections::VecDeque,
+    future::{Future, poll_fn},
+    io,
+    panic::{self, AssertUnwindSafe},
+    pin::{Pin, pin},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+/// Most threads the pool runs at once by default; further jobs queue.
+const MAX_THREADS: usize = 64;
+
+/// Most [`BlockingClass::Data`] jobs running at once by default, leaving
+/// threads for metadata operations during a burst of large transfers.
+const MAX_DATA_JOBS: usize = 48;
+
+/// How long an idle thread waits for work before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+/// Run `f` on a background thread, returning a handle to its result.
+///
+/// Use this for CPU-heavy work or blocking syscalls that would otherwise
+/// stall every task on the executor. Threads are started on demand, up to
+/// [a limit](set_max_blocking_threads) beyond which jobs queue, and exit
+/// after being idle for a while. `f` runs as a [`BlockingClass::Data`] job.
+/// A panic in `f` surfaces through the handle, and so does a failure
+/// to start a thread for it, as a panic whose payload is the [`io::Error`].
+///
+/// Aborting the handle stops waiting for the result, but `f` itself runs
+/// to completion. Use [`spawn_blocking_with`] for work that can stop early.
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`](super::LocalExecutor::block_on).
+pub fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_blocking_in(BlockingClass::Data, f)
+}
+
+/// Like [`spawn_blocking`], but queues `f` in the given class.
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`](super::LocalExecutor::block_on).
+pub fn spawn_blocking_in<F, T>(class: BlockingClass, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_local(async move {
+        run_in(class, move || Ok(f()))
+            .await
+            .unwrap_or_else(spawn_failed)
+    })
+}
+
+/// Kinds of blocking jobs, each queued separately and capped on its own.
+///
+/// Free threads take queued metadata jobs first, and at most a limited
+/// number of data jobs run at once, so a burst of large reads and writes
+/// cannot hold up quick calls such as `open` or `stat` behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockingClass {
+    /// Quick filesystem calls: opening files, `stat`, locks and the like.
+    Metadata,
+    /// Everything else, including file contents and CPU-heavy work.
+    Data,
+}
+
+impl BlockingClass {
+    const ALL: [Self; 2] = [Self::Metadata, Self::Data];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Limit the blocking pool to `n` threads; 64 by default.
+///
+/// Threads beyond a lowered limit finish their current job first.
+///
+/// # Panics
+///
+/// Panics if `n` is 0.
+pub fn set_max_blocking_threads(n: usize) {
+    assert!(n > 0, "the blocking pool needs at least one thread");
+    POOL.get_or_init(Pool::default).set_max_threads(n);
+}
+
+/// Limit how many jobs of `class` run at once.
+///
+/// By default data jobs are capped at 48 and metadata jobs only by the
+/// thread limit.
+///
+/// # Panics
+///
+/// Panics if `n` is 0.
+pub fn set_blocking_class_limit(class: BlockingClass, n: usize) {
+    assert!(n > 0, "a blocking class needs room for at least one job");
+    POOL.get_or_init(Pool::default).set_class_limit(class, n);
+}
+
+/// Like [`spawn_blocking`], but lets `f` notice when it is no longer wanted.
+///
+/// `f` receives an [`Interrupt`] that is set once `token` is cancelled, or
+/// the handle is aborted, or the task is otherwise dropped. Setting it also
+/// sends `SIGRTMIN` to the worker thread while `f` runs, so a blocking
+/// syscall such as `flock` or `read` fails with `EINTR` instead of waiting
+/// on. The first call installs a no-op handler for that signal, without
+/// `SA_RESTART`. If the application already handles or ignores `SIGRTMIN`,
+/// its disposition is left alone and no signal is sent: only the flag is set.
+///
+/// Threads cannot be stopped from outside, so `f` has to check the flag
+/// after each `EINTR` and between units of work, and return early by
+/// itself. A signal that lands just before a syscall starts is missed, so
+/// syscalls that may block indefinitely are best given a timeout too.
+/// After cancellation the handle still resolves to whatever `f` returned;
+/// after an abort the result is discarded.
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`](super::LocalExecutor::block_on).
+pub fn spawn_blocking_with<F, T>(token: &CancellationToken, f: F) -> JoinHandle<T>
+where
+    F: FnOnce(&Interrupt) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let token = token.clone();
+
+    spawn_local(async move {
+        let interrupt = Interrupt::default();
+        // Also fires when the task is aborted or dropped mid-wait.
+        let _guard = InterruptOnDrop(interrupt.clone());
+
+        let mut job = pin!(run({
+            let interrupt = interrupt.clone();
+            move || {
+                let _attached = interrupt.attach();
+                Ok(f(&interrupt))
+            }
+        }));
+        let mut cancelled = pin!(token.cancelled());
+
+        poll_fn(|cx| {
+            if !interrupt.is_set() && cancelled.as_mut().poll(cx).is_ready() {
+                interrupt.set();
+            }
+
+            job.as_mut().poll(cx)
+        })
+        .await
+        .unwrap_or_else(spawn_failed)
+    })
+}
+
+/// Report a job that never ran by panicking the task waiting for it.
+fn spawn_failed<T>(e: io::Error) -> T {
+    panic::resume_unwind(Box::new(e))
+}
+
+/// A flag telling a job started by [`spawn_blocking_with`] to stop early.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    state: Arc<InterruptState>,
+}
+
+#[derive(Debug, Default)]
+struct InterruptState {
+    set: AtomicBool,
+    /// The worker thread running the job, while it runs.
+    thread: Mutex<Option<libc::pthread_t>>,
+}
+
+impl Interrupt {
+    /// Whether the job should stop.
+    pub fn is_set(&self) -> bool {
+        self.state.set.load(Ordering::Acquire)
+    }
+
+    fn set(&self) {
+        self.state.set.store(true, Ordering::Release);
+
+        // Holding the lock keeps the thread from moving on to another job
+        // before the signal is sent.
+        if let Some(thread) = *lock(&self.state.thread) {
+            // SAFETY: `thread` is a live pool thread, registered by `attach`
+            // for as long as the job runs on it.
+            unsafe { libc::pthread_kill(thread, libc::SIGRTMIN()) };
+        }
+    }
+
+    /// Make the calling thread the target of the signal until the returned
+    /// guard is dropped.
+    fn attach(&self) -> Attached<'_> {
+        static HANDLER: OnceLock<bool> = OnceLock::new();
+
+        if *HANDLER.get_or_init(install_handler) {
+            // SAFETY: pthread_self() has no preconditions.
+            *lock(&self.state.thread) = Some(unsafe { libc::pthread_self() });
+        }
+
+        Attached(self)
+    }
+}
+
+struct Attached<'a>(&'a Interrupt);
+
+impl Drop for Attached<'_> {
+    fn drop(&mut self) {
+        *lock(&self.0.state.thread) = None;
+    }
+}
+
+struct InterruptOnDrop(Interrupt);
+
+impl Drop for InterruptOnDrop {
+    fn drop(&mut self) {
+        self.0.set();
+    }
+}
+
+/// Install a no-op handler for the interrupt signal, so it cuts syscalls
+/// short instead of killing the process.
+///
+/// Returns `false` if the signal already had a disposition other than the
+/// default, which is kept.
+fn install_handler() -> bool {
+    extern "C" fn noop(_: libc::c_int) {}
+
+    // SAFETY: The handler does nothing, which is async-signal-safe, and the
+    // sigaction structs are fully initialised before use.
+    unsafe {
+        let mut old: libc::sigaction = std::mem::zeroed();
+
+        if libc::sigaction(libc::SIGRTMIN(), std::ptr::null(), &mut old) != 0
+            || old.sa_sigaction != libc::SIG_DFL
+        {
+            return false;
+        }
+
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = noop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGRTMIN(), &action, std::ptr::null_mut()) == 0
+    }
+}
+
+/// Run `f` on the pool as a [`BlockingClass::Data`] job, resolving to its
+/// result on the calling task.
+///
+/// A panic in `f` is resumed when the future is polled. If no thread can be
+/// started to run `f`, the future resolves to that error instead.
+pub(crate) fn run<F, T>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    run_in(BlockingClass::Data, f)
+}
+
+/// Like [`run`], but queues `f` in the given class.
+pub(crate) fn run_in<F, T>(class: BlockingClass, f: F) -> Blocking<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(Slot {
+        result: None,
+        waker: None,
+    }));
+    let done = slot.clone();
+
+    let spawned = POOL.get_or_init(Pool::default).execute(
+        class,
+        Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+            let waker = {
+                let mut slot = lock(&done);
+                slot.result = Some(result);
+                slot.waker.take()
+            };
+
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }),
+    );
+
+    if let Err(e) = spawned {
+        lock(&slot).result = Some(Ok(Err(e)));
+    }
+
+    Blocking { slot }
+}
+
+/// The result of a job, handed from the worker thread to the waiting task.
+struct Slot<T> {
+    result: Option<thread::Result<io::Result<T>>>,
+    waker: Option<Waker>,
+}
+
+/// Future returned by [`run`].
+pub(crate) struct Blocking<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = io::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<T>> {
+        let mut slot = lock(&self.slot);
+
+        match slot.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(payload)) => {
+                drop(slot);
+                panic::resume_unwind(payload)
+            }
+            None => {
+                match &mut slot.waker {
+                    Some(waker) if waker.will_wake(cx.waker()) => {}
+                    waker => *waker = Some(cx.waker().clone()),
+                }
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pool {
+    state: Mutex<PoolState>,
+    work: Condvar,
+}
+
+struct PoolState {
+    /// Queued jobs, by class.
+    queues: [VecDeque<Job>; 2],
+    /// Jobs running, by class.
+    running: [usize; 2],
+    /// Most jobs running at once, by class.
+    limits: [usize; 2],
+    max_threads: usize,
+    threads: usize,
+    idle: usize,
+}
+
+impl Default for PoolState {
+    fn default() -> Self {
+        Self {
+            queues: Default::default(),
+            running: [0; 2],
+            limits: [MAX_THREADS, MAX_DATA_JOBS],
+            max_threads: MAX_THREADS,
+            threads: 0,
+            idle: 0,
+        }
+    }
+}
+
+impl PoolState {
+    /// Queued jobs that a free thread could start now.
+    fn runnable(&self) -> usize {
+        BlockingClass::ALL
+            .iter()
+            .map(|class| {
+                let i = class.index();
+                self.queues[i]
+                    .len()
+                    .min(self.limits[i].saturating_sub(self.running[i]))
+            })
+            .sum()
+    }
+
+    /// Dequeue the next job allowed to start, metadata first.
+    fn next(&mut self) -> Option<(BlockingClass, Job)> {
+        BlockingClass::ALL.into_iter().find_map(|class| {
+            let i = class.index();
+
+            if self.running[i] >= self.limits[i] {
+                return None;
+            }
+
+            let job = self.queues[i].pop_front()?;
+            self.running[i] += 1;
+            Some((class, job))
+        })
+    }
+}
+
+impl Pool {
+    /// Queue `job`, starting a thread for it if none is idle.
+    ///
+    /// Fails, taking the job back out, if no thread could be started and
+    /// none is left to run it.
+    fn execute(&'static self, class: BlockingClass, job: Job) -> io::Result<()> {
+        let mut state = lock(&self.state);
+        state.queues[class.index()].push_back(job);
+
+        if state.runnable() > state.idle && state.threads < state.max_threads {
+            state.threads += 1;
+
+            let spawned = thread::Builder::new()
+                .name("ars-blocking".into())
+                .spawn(move || self.work());
+
+            if let Err(e) = spawned {
+                state.threads -= 1;
+
+                // Otherwise the job is picked up by one of the busy threads.
+                if state.threads == 0 {
+                    drop(state.queues[class.index()].pop_back());
+                    return Err(e);
+                }
+            }
+        }
+
+        self.work.notify_one();
+        Ok(())
+    }
+
+    fn set_max_threads(&self, n: usize) {
+        lock(&self.state).max_threads = n;
+        // Idle threads above the limit exit.
+        self.work.notify_all();
+    }
+
+    fn set_class_limit(&'static self, class: BlockingClass, n: usize) {
+        let mut state = lock(&self.state);
+        state.limits[class.index()] = n;
+
+        // Jobs held back by the old limit may start now.
+        while state.runnable() > state.idle && state.threads < state.max_threads {
+            state.threads += 1;
+
+            let spawned = thread::Builder::new()
+                .name("ars-blocking".into())
+                .spawn(move || self.work());
+
+            if spawned.is_err() {
+                state.threads -= 1;
+                break;
+            }
+        }
+
+        self.work.notify_all();
+    }
+
+    fn work(&self) {
+        let mut state = lock(&self.state);
+
+        loop {
+            if state.threads > state.max_threads {
+                state.threads -= 1;
+                return;
+            }
+
+            if let Some((class, job)) = state.next() {
+                drop(state);
+                job();
+                state = lock(&self.state);
+                state.running[class.index()] -= 1;
+
+                // The finished job may have held back one of its class.
+                if state.runnable() > 0 {
+                    self.work.notify_one();
+                }
+                continue;
+            }
+
+            state.idle += 1;
+            let (guard, timeout) = self
+                .work
+                .wait_timeout(state, KEEP_ALIVE)
+                .unwrap_or_else(PoisonError::into_inner);
+            state = guard;
+            state.idle -= 1;
+
+            if timeout.timed_out() && state.runnable() == 0 {
+                state.threads -= 1;
+                return;
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, time::sleep};
+    use std::sync::mpsc;
+
+    /// Wait on the calling thread until `interrupt` is set, or give up.
+    fn wait_for(interrupt: &Interrupt) -> bool {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+
+        while !interrupt.is_set() {
+            if std::time::Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        true
+    }
+
+    #[test]
+    fn data_jobs_do_not_hold_up_metadata() {
+        let pool: &'static Pool = Box::leak(Box::default());
+        lock(&pool.state).limits[BlockingClass::Data.index()] = 1;
+
+        let (release, released) = mpsc::channel::<()>();
+        let (done, finished) = mpsc::channel();
+
+        let send = |name| {
+            let done = done.clone();
+            Box::new(move || done.send(name).unwrap())
+        };
+
+        pool.execute(
+            BlockingClass::Data,
+            Box::new({
+                let done = done.clone();
+                move || {
+                    released.recv().unwrap();
+                    done.send("first").unwrap();
+                }
+            }),
+        )
+        .unwrap();
+        pool.execute(BlockingClass::Data, send("second")).unwrap();
+        pool.execute(BlockingClass::Metadata, send("metadata"))
+            .unwrap();
+
+        // The second data job waits for the first; metadata does not.
+        let next = || finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(next(), "metadata");
+
+        release.send(()).unwrap();
+        assert_eq!(next(), "first");
+        assert_eq!(next(), "second");
+    }
+
+    #[test]
+    fn returns_result_and_resumes_panics() {
+        LocalExecutor::new().block_on(async {
+            assert_eq!(spawn_blocking(|| 6 * 7).await.unwrap(), 42);
+
+            let err = spawn_blocking(|| panic!("boom")).await.unwrap_err();
+            assert!(err.is_panic());
+        });
+    }
+
+    #[test]
+    fn cancellation_interrupts_job() {
+        LocalExecutor::new().block_on(async {
+            let token = CancellationToken::new();
+            let handle = spawn_blocking_with(&token, wait_for);
+
+            sleep(Duration::from_millis(5)).await;
+            token.cancel();
+
+            assert!(handle.await.unwrap());
+        });
+    }
+
+    #[test]
+    fn cancellation_cuts_blocking_syscall_short() {
+        use rustix::{io::Errno, pipe::pipe};
+
+        let (reader, _writer) = pipe().unwrap();
+
+        LocalExecutor::new().block_on(async {
+            let token = CancellationToken::new();
+            let handle = spawn_blocking_with(&token, move |interrupt| {
+                let mut buf = [0; 1];
+
+                loop {
+                    match rustix::io::read(&reader, &mut buf) {
+                        Err(Errno::INTR) if interrupt.is_set() => return true,
+                        Err(Errno::INTR) => continue,
+                        _ => return false,
+                    }
+                }
+            });
+
+            sleep(Duration::from_millis(20)).await;
+            token.cancel();
+
+            assert!(handle.await.unwrap());
+        });
+    }
+
+    #[test]
+    fn abort_interrupts_job() {
+        let (tx, rx) = mpsc::channel();
+
+        LocalExecutor::new().block_on(async {
+            let token = CancellationToken::new();
+            let handle = spawn_blocking_with(&token, move |interrupt| {
+                tx.send(wait_for(interrupt)).unwrap();
+            });
+
+            sleep(Duration::from_millis(5)).await;
+            handle.abort();
+            assert!(handle.await.unwrap_err().is_cancelled());
+        });
+
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(true));
+    }
+}
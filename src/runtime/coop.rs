@@ -0,0 +1,134 @@
+use std::{
+    cell::Cell,
+    future::poll_fn,
+    task::{Context, Poll},
+};
+
+/// Operations a task may complete per poll before it is made to yield.
+const BUDGET: u32 = 128;
+
+thread_local! {
+    static REMAINING: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Run `f` with a fresh budget, restoring the previous one afterwards.
+pub(crate) fn with_budget<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<u32>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REMAINING.set(self.0);
+        }
+    }
+
+    let _restore = Restore(REMAINING.replace(Some(BUDGET)));
+    f()
+}
+
+/// Consume one unit of the current task's budget.
+///
+/// Returns `Pending`, after scheduling a wakeup, once the budget is spent,
+/// so a task that always finds work ready still lets others run. Outside
+/// an executor the budget is unlimited.
+pub fn poll_proceed(cx: &mut Context<'_>) -> Poll<()> {
+    match REMAINING.get() {
+        Some(0) => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Some(n) => {
+            REMAINING.set(Some(n - 1));
+            Poll::Ready(())
+        }
+        None => Poll::Ready(()),
+    }
+}
+
+/// Consume one unit of budget, yielding if it is spent.
+pub async fn consume_budget() {
+    poll_fn(poll_proceed).await;
+}
+
+/// Yield once to let other tasks run.
+pub async fn yield_now() {
+    let mut yielded = false;
+
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await;
+}
+
+/// Run `f` on every item of `stream`, yielding after at most `budget` items
+/// per poll.
+///
+/// Unlike a plain `while let Some(item) = stream.next().await` loop, a
+/// stream that always has items ready, such as a deep channel, cannot hold
+/// the executor for a whole frame. The count restarts whenever the stream
+/// itself returns `Pending`. A `budget` of 0 is treated as 1.
+#[cfg(feature = "futures")]
+pub async fn for_each_budgeted<S, F>(stream: S, budget: usize, mut f: F)
+where
+    S: futures_core::Stream,
+    F: FnMut(S::Item),
+{
+    let mut stream = std::pin::pin!(stream);
+    let budget = budget.max(1);
+
+    poll_fn(|cx| {
+        for _ in 0..budget {
+            match stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => f(item),
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+
+    #[test]
+    fn budget_runs_out_and_reschedules() {
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        with_budget(|| {
+            for _ in 0..BUDGET {
+                assert!(poll_proceed(&mut cx).is_ready());
+            }
+
+            assert!(poll_proceed(&mut cx).is_pending());
+            assert_eq!(counter.count(), 1);
+
+            // A nested budget does not leak into the outer one.
+            with_budget(|| assert!(poll_proceed(&mut cx).is_ready()));
+            assert!(poll_proceed(&mut cx).is_pending());
+        });
+    }
+
+    #[test]
+    fn unlimited_outside_executor() {
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        for _ in 0..=BUDGET {
+            assert!(poll_proceed(&mut cx).is_ready());
+        }
+    }
+}
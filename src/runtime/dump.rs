@@ -0,0 +1,96 @@
+#[cfg(feature = "metrics")]
+use std::time::Duration;
+
+/// A spawned task, as described by [`LocalExecutor::dump`](super::LocalExecutor::dump).
+///
+/// With the `metrics` feature it also accounts for what the task cost the
+/// loop, to find the one that burns its time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    /// The task's id, also given by [`JoinHandle::id`](super::JoinHandle::id).
+    pub id: u64,
+    /// The task that spawned this one, if parents are tracked.
+    ///
+    /// See [`LocalExecutor::set_track_parents`](super::LocalExecutor::set_track_parents).
+    /// The parent may have finished since.
+    pub parent: Option<u64>,
+    /// Times the task was polled.
+    #[cfg(feature = "metrics")]
+    pub polls: u64,
+    /// Time spent polling the task.
+    #[cfg(feature = "metrics")]
+    pub poll_time: Duration,
+    /// Allocations made while polling the task.
+    ///
+    /// Only counted with [`CountingAlloc`](super::CountingAlloc) installed
+    /// as the global allocator; zero otherwise.
+    #[cfg(feature = "metrics")]
+    pub allocations: u64,
+    /// Bytes requested by those allocations.
+    #[cfg(feature = "metrics")]
+    pub allocated_bytes: u64,
+}
+
+impl TaskInfo {
+    pub(crate) fn new(id: u64, parent: Option<u64>) -> Self {
+        Self {
+            id,
+            parent,
+            #[cfg(feature = "metrics")]
+            polls: 0,
+            #[cfg(feature = "metrics")]
+            poll_time: Duration::ZERO,
+            #[cfg(feature = "metrics")]
+            allocations: 0,
+            #[cfg(feature = "metrics")]
+            allocated_bytes: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::{LocalExecutor, spawn_local};
+    use std::future::pending;
+
+    #[test]
+    fn dump_records_parents_when_tracked() {
+        let executor = LocalExecutor::new();
+        executor.set_track_parents(true);
+
+        let (outer, inner) = executor.block_on(async {
+            let outer = spawn_local(async { spawn_local(pending::<()>()).id() });
+            let id = outer.id();
+            (id, outer.await.unwrap())
+        });
+
+        let dump = executor.dump();
+        assert_eq!(dump.len(), 1);
+        assert_eq!(dump[0].id, inner);
+        assert_eq!(dump[0].parent, Some(outer));
+
+        executor.set_track_parents(false);
+        let untracked = executor.block_on(async {
+            spawn_local(async { spawn_local(pending::<()>()).id() })
+                .await
+                .unwrap()
+        });
+
+        let dump = executor.dump();
+        assert_eq!(dump.len(), 2);
+        assert_eq!(dump[1].id, untracked);
+        assert_eq!(dump[1].parent, None);
+    }
+
+    #[test]
+    fn dump_includes_the_calling_task() {
+        let executor = LocalExecutor::new();
+        let handle = executor.spawn({
+            let executor = executor.clone();
+            async move { executor.dump().len() }
+        });
+
+        assert_eq!(executor.block_on(handle).unwrap(), 1);
+        assert!(executor.dump().is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// One pass of the executor loop, passed to the callbacks registered with
+/// [`LocalExecutor::on_iteration_start`](super::LocalExecutor::on_iteration_start)
+/// and [`on_iteration_end`](super::LocalExecutor::on_iteration_end).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IterationStats {
+    /// Counts iterations of this executor, across `block_on` calls.
+    pub iteration: u64,
+    /// Tasks in the batch, including the future passed to `block_on`.
+    pub ready: usize,
+    /// Time spent gathering the batch, mostly waiting in the reactor.
+    pub waited: Duration,
+    /// Time spent polling the batch; zero at the start of the iteration.
+    pub busy: Duration,
+}
+
+pub(crate) type Hook = Box<dyn FnMut(&IterationStats)>;
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub(crate) start: Vec<Hook>,
+    pub(crate) end: Vec<Hook>,
+    pub(crate) iterations: u64,
+}
+
+impl Hooks {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.start.is_empty() && self.end.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, spawn_local, yield_now},
+        time::sleep,
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn brackets_every_iteration() {
+        let executor = LocalExecutor::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let starts = log.clone();
+        executor.on_iteration_start(move |stats| starts.borrow_mut().push(("start", *stats)));
+        let ends = log.clone();
+        executor.on_iteration_end(move |stats| ends.borrow_mut().push(("end", *stats)));
+
+        executor.block_on(async {
+            spawn_local(yield_now()).await.unwrap();
+            sleep(Duration::from_millis(5)).await;
+        });
+
+        let log = log.borrow();
+        assert!(log.len() >= 6, "{log:?}");
+
+        for (i, pair) in log.chunks(2).enumerate() {
+            let [("start", start), ("end", end)] = pair else {
+                panic!("unbalanced hooks: {log:?}");
+            };
+
+            assert_eq!(start.iteration, i as u64);
+            assert_eq!(start.busy, Duration::ZERO);
+            assert_eq!((end.iteration, end.ready), (start.iteration, start.ready));
+        }
+
+        // Some iteration waited out the sleep in the reactor.
+        assert!(
+            log.iter()
+                .any(|(_, s)| s.waited >= Duration::from_millis(4))
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! The live-task limit behind [`LocalExecutor::set_max_tasks`].
+//!
+//! Tasks waiting for room queue in a [`WaiterList`]. Each finished task
+//! pops one waiter, which takes the freed slot if nobody queued before it
+//! still waits; a popped waiter dropped before it could spawn passes the
+//! wakeup on. Raising the limit wakes every waiter, and re-registering
+//! keeps their places.
+//!
+//! [`LocalExecutor::set_max_tasks`]: super::LocalExecutor::set_max_tasks
+
+use crate::{runtime::Inner, utils::waiters::WaiterList};
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+#[derive(Debug, Default)]
+pub(crate) struct Limit {
+    pub(crate) max: Option<usize>,
+    pub(crate) waiters: WaiterList,
+}
+
+impl Limit {
+    /// Whether another task fits next to `live` ones.
+    pub(crate) fn has_room(&self, live: usize) -> bool {
+        self.max.is_none_or(|max| live < max)
+    }
+}
+
+/// Waits until a task may be spawned without exceeding the limit.
+pub(crate) struct Room {
+    pub(crate) inner: Rc<Inner>,
+    pub(crate) key: Option<u64>,
+}
+
+impl Future for Room {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        let live = this.inner.info.borrow().len();
+        let mut limit = this.inner.limit.borrow_mut();
+
+        if limit.has_room(live) && !limit.waiters.has_waiters_before(this.key) {
+            limit.waiters.remove(this.key.take());
+            return Poll::Ready(());
+        }
+
+        limit.waiters.register(&mut this.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Room {
+    fn drop(&mut self) {
+        if self.key.is_none() {
+            return;
+        }
+
+        let next = {
+            let mut limit = self.inner.limit.borrow_mut();
+
+            // Already popped by a finishing task: its slot is ours to pass on.
+            if limit.waiters.remove(self.key) {
+                None
+            } else {
+                limit.waiters.pop()
+            }
+        };
+
+        if let Some(waker) = next {
+            waker.wake();
+        }
+    }
+}
+
+/// Returned by [`LocalExecutor::try_spawn`](super::LocalExecutor::try_spawn)
+/// when the executor is at its task limit, with the future that was not
+/// spawned.
+pub struct SpawnError<F>(pub F);
+
+impl<F> fmt::Debug for SpawnError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SpawnError").finish_non_exhaustive()
+    }
+}
+
+impl<F> fmt::Display for SpawnError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("task limit reached")
+    }
+}
+
+impl<F> Error for SpawnError<F> {}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+/// Counters of a [`LocalExecutor`](super::LocalExecutor), kept with the
+/// `metrics` feature.
+///
+/// Taken as a snapshot by [`LocalExecutor::metrics`](super::LocalExecutor::metrics);
+/// subtract two snapshots to get the activity in between. A stalling loop
+/// typically shows as a large `max_poll`, or as `polls` outpacing `parks`
+/// while `io_events` stays flat.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeMetrics {
+    /// Iterations of the executor loop.
+    pub ticks: u64,
+    /// Tasks spawned.
+    pub spawned: u64,
+    /// Task polls, including the future passed to `block_on`.
+    pub polls: u64,
+    /// Wakeups that scheduled a task, from any thread.
+    pub wakes: u64,
+    /// Time spent polling tasks.
+    pub poll_time: Duration,
+    /// Longest single poll.
+    pub max_poll: Duration,
+    /// Waits in the reactor, including non-blocking checks for events.
+    pub parks: u64,
+    /// I/O readiness events dispatched by the reactor.
+    pub io_events: u64,
+    /// Most I/O events dispatched by a single wait.
+    pub max_io_events: u64,
+}
+
+impl RuntimeMetrics {
+    pub(crate) fn record_poll(&mut self, elapsed: Duration) {
+        self.polls += 1;
+        self.poll_time += elapsed;
+        self.max_poll = self.max_poll.max(elapsed);
+    }
+
+    pub(crate) fn record_park(&mut self, events: usize) {
+        self.parks += 1;
+        self.io_events += events as u64;
+        self.max_io_events = self.max_io_events.max(events as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        runtime::{LocalExecutor, spawn_local},
+        time::sleep,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn counts_spawns_polls_and_parks() {
+        let executor = LocalExecutor::new();
+
+        executor.block_on(async {
+            spawn_local(sleep(Duration::from_millis(2))).await.unwrap();
+        });
+
+        let metrics = executor.metrics();
+        assert_eq!(metrics.spawned, 1);
+        // The main future and the task each poll at least twice.
+        assert!(metrics.polls >= 4, "{metrics:?}");
+        assert!(metrics.wakes >= 2, "{metrics:?}");
+        assert!(metrics.parks >= 1, "{metrics:?}");
+        assert!(metrics.max_poll <= metrics.poll_time);
+    }
+}
@@ -0,0 +1,890 @@
+//! The single-threaded [`LocalExecutor`] and the tasks it runs.
+//!
+//! Tasks are `!Send` and stay on the thread that spawned them, but their
+//! wakers are `Send`: waking pushes the task id onto a mutex-guarded ready
+//! queue and, if the executor is parked in `epoll_wait`, unparks it through
+//! the reactor's eventfd. Each tick takes the whole queue as one batch, so
+//! a task woken while the batch runs is polled in the next one.
+//!
+//! While tasks keep it busy, the executor still polls for I/O and timers
+//! every 61 ticks. Fairness within a task relies on the cooperative budget
+//! of 128 operations per poll in [`consume_budget`] and [`poll_proceed`];
+//! a future that never consults it can hold the thread for as long as it
+//! likes.
+
+#[cfg(feature = "metrics")]
+mod alloc;
+pub(crate) mod blocking;
+mod coop;
+mod dump;
+mod hooks;
+mod limit;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod profile;
+mod quiescent;
+mod remote;
+mod scope;
+mod shard;
+mod task;
+mod task_set;
+
+#[cfg(feature = "metrics")]
+pub use alloc::CountingAlloc;
+pub use blocking::{
+    BlockingClass, Interrupt, set_blocking_class_limit, set_max_blocking_threads, spawn_blocking,
+    spawn_blocking_in, spawn_blocking_with,
+};
+#[cfg(feature = "futures")]
+pub use coop::for_each_budgeted;
+pub use coop::{consume_budget, poll_proceed, yield_now};
+pub use dump::TaskInfo;
+pub use hooks::IterationStats;
+pub use limit::SpawnError;
+#[cfg(feature = "metrics")]
+pub use metrics::RuntimeMetrics;
+pub use profile::traced;
+#[cfg(feature = "await-profile")]
+pub use profile::{AwaitPoint, await_profile, reset_await_profile};
+pub use remote::{RemoteHandle, spawn_with_handle};
+pub use scope::{Scope, scope};
+pub use shard::{ShardHandle, ShardJoinHandle, ShardReceiver, ShardSender, Shards, shard_channel};
+pub use task::{JoinError, JoinHandle};
+pub use task_set::TaskSet;
+
+use crate::{
+    reactor::{self, Reactor, Unparker},
+    time,
+    utils::{cancel::CancellationToken, tracked_cell::TrackedRefCell},
+};
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    future::{Future, poll_fn},
+    mem,
+    pin::{Pin, pin},
+    rc::Rc,
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    time::{Duration, Instant},
+};
+
+/// Task id reserved for the future passed to [`LocalExecutor::block_on`].
+const MAIN: u64 = 0;
+
+/// Ticks between checks for I/O events while tasks keep the executor busy.
+const EVENT_INTERVAL: u32 = 61;
+
+thread_local! {
+    static CURRENT: TrackedRefCell<Option<LocalExecutor>> = const { TrackedRefCell::new(None) };
+}
+
+/// A single-threaded executor for `!Send` futures.
+///
+/// Tasks run only while [`block_on`](Self::block_on) is driving the
+/// executor, on the calling thread. Wakers may be used from any thread.
+/// The executor also drives this thread's [`time::local_wheel`] and
+/// [`reactor::local_reactor`], parking in `epoll_wait` when idle.
+///
+/// Clones share the same executor.
+#[derive(Clone, Default)]
+pub struct LocalExecutor {
+    inner: Rc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    tasks: TrackedRefCell<HashMap<u64, TaskSlot>>,
+    /// What [`LocalExecutor::dump`] reports, kept for every live task
+    /// including the one being polled.
+    info: TrackedRefCell<HashMap<u64, TaskInfo>>,
+    next_id: Cell<u64>,
+    /// The task being polled, if it is not the `block_on` future.
+    current: Cell<Option<u64>>,
+    track_parents: Cell<bool>,
+    queue: Arc<RunQueue>,
+    idle: TrackedRefCell<quiescent::IdleWaiters>,
+    /// Cancelled by [`LocalExecutor::shutdown`].
+    shutdown: CancellationToken,
+    limit: TrackedRefCell<limit::Limit>,
+    hooks: TrackedRefCell<hooks::Hooks>,
+    #[cfg(feature = "metrics")]
+    metrics: Cell<RuntimeMetrics>,
+}
+
+struct TaskSlot {
+    fut: Pin<Box<dyn Future<Output = ()>>>,
+    waker: Arc<TaskWaker>,
+}
+
+/// Ids of woken tasks, shared with wakers on any thread.
+#[derive(Default)]
+struct RunQueue {
+    ready: Mutex<VecDeque<u64>>,
+    /// Set while the executor is blocked in the reactor.
+    parked: AtomicBool,
+    unparker: OnceLock<Unparker>,
+    /// Wakeups that scheduled a task, counted here as wakers may run on any thread.
+    #[cfg(feature = "metrics")]
+    wakes: std::sync::atomic::AtomicU64,
+}
+
+struct TaskWaker {
+    id: u64,
+    scheduled: AtomicBool,
+    queue: Arc<RunQueue>,
+}
+
+impl LocalExecutor {
+    /// Create an executor with no tasks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn `fut` onto the executor.
+    ///
+    /// The task first runs once [`block_on`](Self::block_on) is driving
+    /// the executor. A panic in the task is caught and surfaces through
+    /// the returned handle.
+    pub fn spawn<F>(&self, fut: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let id = self.inner.next_id.get() + 1;
+        self.inner.next_id.set(id);
+
+        let waker = Arc::new(TaskWaker {
+            id,
+            scheduled: AtomicBool::new(false),
+            queue: self.inner.queue.clone(),
+        });
+
+        let (task, handle) = task::new(id, fut, Waker::from(waker.clone()));
+        let parent = self
+            .inner
+            .current
+            .get()
+            .filter(|_| self.inner.track_parents.get());
+
+        self.inner
+            .info
+            .borrow_mut()
+            .insert(id, TaskInfo::new(id, parent));
+
+        self.inner.tasks.borrow_mut().insert(
+            id,
+            TaskSlot {
+                fut: Box::pin(task),
+                waker: waker.clone(),
+            },
+        );
+        waker.wake_by_ref();
+
+        #[cfg(feature = "metrics")]
+        self.record(|m| m.spawned += 1);
+
+        handle
+    }
+
+    /// Limit the number of live spawned tasks, or lift the limit with
+    /// `None`, the default.
+    ///
+    /// The limit applies to [`try_spawn`](Self::try_spawn) and
+    /// [`spawn_bounded`](Self::spawn_bounded), so a flood of requests
+    /// cannot create tasks without bound; [`spawn`](Self::spawn) ignores
+    /// it. Lowering it below the current count stops no task.
+    pub fn set_max_tasks(&self, max: Option<usize>) {
+        let waiters = {
+            let mut limit = self.inner.limit.borrow_mut();
+            limit.max = max;
+            limit.waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+
+    /// Spawn `fut` if that stays within the task limit, or hand it back.
+    pub fn try_spawn<F>(&self, fut: F) -> Result<JoinHandle<F::Output>, SpawnError<F>>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let limit = self.inner.limit.borrow();
+
+        if !limit.has_room(self.task_count()) || !limit.waiters.is_empty() {
+            return Err(SpawnError(fut));
+        }
+
+        drop(limit);
+        Ok(self.spawn(fut))
+    }
+
+    /// Spawn `fut` once that stays within the task limit.
+    ///
+    /// Callers waiting for room are served in FIFO order, one for each task
+    /// that finishes.
+    pub async fn spawn_bounded<F>(&self, fut: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        limit::Room {
+            inner: self.inner.clone(),
+            key: None,
+        }
+        .await;
+
+        self.spawn(fut)
+    }
+
+    /// Like [`spawn`](Self::spawn), but the task is dropped at its next poll
+    /// once [`shutdown`](Self::shutdown) is called, and then resolves to
+    /// `None`.
+    pub fn spawn_until_shutdown<F>(&self, fut: F) -> JoinHandle<Option<F::Output>>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let token = self.shutdown_token();
+
+        self.spawn(async move {
+            let mut fut = pin!(fut);
+            let mut cancelled = pin!(token.cancelled());
+
+            poll_fn(|cx| {
+                if cancelled.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+
+                fut.as_mut().poll(cx).map(Some)
+            })
+            .await
+        })
+    }
+
+    /// The executor's root cancellation token, cancelled by
+    /// [`shutdown`](Self::shutdown).
+    ///
+    /// Hand out [`child_token`](CancellationToken::child_token)s of it to
+    /// work that should stop with the executor, so one call stops
+    /// everything without a token threaded through every constructor.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.inner.shutdown.clone()
+    }
+
+    /// Cancel the [`shutdown_token`](Self::shutdown_token), stopping every
+    /// task tied to it.
+    ///
+    /// Tasks spawned otherwise keep running; `block_on` returns once its
+    /// own future completes, as usual.
+    pub fn shutdown(&self) {
+        self.inner.shutdown.cancel();
+    }
+
+    /// Run the executor until `fut` completes, returning its output.
+    ///
+    /// Spawned tasks still pending when `fut` completes are kept and resume
+    /// on the next call. A panic in `fut` itself propagates.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while an executor is already running on this thread,
+    /// or if the thread's reactor cannot be created or polled.
+    pub fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        let _enter = Enter::new(self);
+        let mut fut = pin!(fut);
+
+        let reactor = reactor::local_reactor().expect("failed to create reactor");
+        self.inner.queue.unparker.get_or_init(|| reactor.unparker());
+
+        let main = Arc::new(TaskWaker {
+            id: MAIN,
+            scheduled: AtomicBool::new(false),
+            queue: self.inner.queue.clone(),
+        });
+        let main_waker = Waker::from(main.clone());
+        main.wake_by_ref();
+
+        let wheel = time::local_wheel();
+        let mut tick = 0u32;
+
+        loop {
+            let gathering = Instant::now();
+            wheel.advance(gathering);
+            tick = tick.wrapping_add(1);
+
+            #[cfg(feature = "metrics")]
+            self.record(|m| m.ticks += 1);
+
+            let mut batch = self.inner.queue.take();
+
+            if batch.is_empty() && !self.inner.idle.borrow().is_empty() {
+                batch = self.settle(&reactor, &wheel);
+            }
+
+            if batch.is_empty() {
+                let timeout = wheel
+                    .next_deadline()
+                    .map(|d| d.saturating_duration_since(Instant::now()));
+
+                self.park(&reactor, timeout);
+                batch = self.inner.queue.take();
+            } else if tick.is_multiple_of(EVENT_INTERVAL) {
+                // Busy tasks must not starve I/O.
+                self.park(&reactor, Some(Duration::ZERO));
+            }
+
+            let mut stats = self.start_iteration(&batch, gathering);
+            let polling = Instant::now();
+
+            for id in batch {
+                if id != MAIN {
+                    self.run_task(id);
+                    continue;
+                }
+
+                main.scheduled.store(false, Ordering::Release);
+                let mut cx = Context::from_waker(&main_waker);
+
+                #[cfg(feature = "metrics")]
+                let started = Instant::now();
+                let poll = coop::with_budget(|| fut.as_mut().poll(&mut cx));
+                #[cfg(feature = "metrics")]
+                self.record(|m| m.record_poll(started.elapsed()));
+
+                if let Poll::Ready(output) = poll {
+                    if let Some(stats) = &mut stats {
+                        stats.busy = polling.elapsed();
+                        self.end_iteration(stats);
+                    }
+
+                    return output;
+                }
+            }
+
+            if let Some(stats) = &mut stats {
+                stats.busy = polling.elapsed();
+                self.end_iteration(stats);
+            }
+        }
+    }
+
+    /// Number of spawned tasks that have not finished.
+    pub fn task_count(&self) -> usize {
+        self.inner.info.borrow().len()
+    }
+
+    /// Record which task spawned each new task, for [`dump`](Self::dump).
+    ///
+    /// Off by default. Tasks spawned while it is off, or from the future
+    /// passed to [`block_on`](Self::block_on), have no parent.
+    pub fn set_track_parents(&self, enabled: bool) {
+        self.inner.track_parents.set(enabled);
+    }
+
+    /// Describe every spawned task that has not finished, ordered by id.
+    pub fn dump(&self) -> Vec<TaskInfo> {
+        let mut tasks: Vec<_> = self.inner.info.borrow().values().cloned().collect();
+        tasks.sort_by_key(|task| task.id);
+        tasks
+    }
+
+    /// A snapshot of the executor's counters since it was created.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> RuntimeMetrics {
+        RuntimeMetrics {
+            wakes: self.inner.queue.wakes.load(Ordering::Relaxed),
+            ..self.inner.metrics.get()
+        }
+    }
+
+    /// Call `f` at the start of each iteration of the executor loop, once
+    /// the batch of ready tasks is known and before any of it is polled.
+    ///
+    /// Together with [`on_iteration_end`](Self::on_iteration_end), this
+    /// brackets each batch, e.g. for per-frame profiling markers.
+    pub fn on_iteration_start(&self, f: impl FnMut(&IterationStats) + 'static) {
+        self.inner.hooks.borrow_mut().start.push(Box::new(f));
+    }
+
+    /// Call `f` at the end of each iteration, after its batch was polled and
+    /// before the executor waits for more work.
+    ///
+    /// A good place to flush work batched up by the tasks that just ran.
+    pub fn on_iteration_end(&self, f: impl FnMut(&IterationStats) + 'static) {
+        self.inner.hooks.borrow_mut().end.push(Box::new(f));
+    }
+
+    /// Wait until the executor has nothing to do.
+    ///
+    /// Resolves once no task is ready to run, no I/O events are pending and
+    /// no timer is due within `window`. Useful in tests to let the system
+    /// settle before asserting on it, or to snapshot state while nothing
+    /// is changing it. Idleness is only checked while this executor is
+    /// running, so await it from one of its tasks.
+    pub fn quiescent(&self, window: Duration) -> impl Future<Output = ()> + use<> {
+        quiescent::Quiescent {
+            inner: self.inner.clone(),
+            window,
+            key: None,
+        }
+    }
+
+    /// Check for idleness with tasks waiting in [`quiescent`](Self::quiescent).
+    ///
+    /// Polls the reactor without blocking; if that wakes nothing, wakes the
+    /// waiters whose window is clear. Returns the resulting batch of tasks.
+    fn settle(&self, reactor: &Reactor, wheel: &time::TimerWheel) -> VecDeque<u64> {
+        self.park(reactor, Some(Duration::ZERO));
+        let batch = self.inner.queue.take();
+
+        if !batch.is_empty() {
+            return batch;
+        }
+
+        let settled = self
+            .inner
+            .idle
+            .borrow_mut()
+            .take_settled(wheel, Instant::now());
+
+        for waker in settled {
+            waker.wake();
+        }
+
+        self.inner.queue.take()
+    }
+
+    /// Run the start hooks for `batch`; `None` if there are no hooks.
+    fn start_iteration(&self, batch: &VecDeque<u64>, gathering: Instant) -> Option<IterationStats> {
+        let stats = {
+            let mut hooks = self.inner.hooks.borrow_mut();
+
+            if hooks.is_empty() {
+                return None;
+            }
+
+            hooks.iterations += 1;
+            IterationStats {
+                iteration: hooks.iterations - 1,
+                ready: batch.len(),
+                waited: gathering.elapsed(),
+                busy: Duration::ZERO,
+            }
+        };
+
+        self.run_hooks(|hooks| &mut hooks.start, &stats);
+        Some(stats)
+    }
+
+    fn end_iteration(&self, stats: &IterationStats) {
+        self.run_hooks(|hooks| &mut hooks.end, stats);
+    }
+
+    fn run_hooks(
+        &self,
+        which: impl Fn(&mut hooks::Hooks) -> &mut Vec<hooks::Hook>,
+        stats: &IterationStats,
+    ) {
+        // Taken out so hooks can register more hooks.
+        let mut running = mem::take(which(&mut self.inner.hooks.borrow_mut()));
+
+        for hook in &mut running {
+            hook(stats);
+        }
+
+        let mut hooks = self.inner.hooks.borrow_mut();
+        let added = mem::replace(which(&mut hooks), running);
+        which(&mut hooks).extend(added);
+    }
+
+    fn run_task(&self, id: u64) {
+        // Taken out of the map so the task can spawn while being polled.
+        let Some(mut slot) = self.inner.tasks.borrow_mut().remove(&id) else {
+            return;
+        };
+
+        slot.waker.scheduled.store(false, Ordering::Release);
+        let waker = Waker::from(slot.waker.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        #[cfg(feature = "metrics")]
+        let (started, allocated) = (Instant::now(), alloc::allocated());
+        self.inner.current.set(Some(id));
+        let poll = coop::with_budget(|| slot.fut.as_mut().poll(&mut cx));
+        self.inner.current.set(None);
+        #[cfg(feature = "metrics")]
+        {
+            let elapsed = started.elapsed();
+            self.record(|m| m.record_poll(elapsed));
+
+            if let Some(info) = self.inner.info.borrow_mut().get_mut(&id) {
+                let (count, bytes) = alloc::allocated();
+                info.polls += 1;
+                info.poll_time += elapsed;
+                info.allocations += count - allocated.0;
+                info.allocated_bytes += bytes - allocated.1;
+            }
+        }
+
+        if poll.is_pending() {
+            self.inner.tasks.borrow_mut().insert(id, slot);
+        } else {
+            self.inner.info.borrow_mut().remove(&id);
+
+            // Hand the freed slot to the next task waiting for room.
+            let next = self.inner.limit.borrow_mut().waiters.pop();
+
+            if let Some(waker) = next {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Wait in the reactor; see [`RunQueue::park`].
+    fn park(&self, reactor: &Reactor, timeout: Option<Duration>) {
+        let _events = self.inner.queue.park(reactor, timeout);
+
+        #[cfg(feature = "metrics")]
+        self.record(|m| m.record_park(_events));
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record(&self, f: impl FnOnce(&mut RuntimeMetrics)) {
+        let mut metrics = self.inner.metrics.get();
+        f(&mut metrics);
+        self.inner.metrics.set(metrics);
+    }
+}
+
+impl fmt::Debug for LocalExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalExecutor")
+            .field("tasks", &self.task_count())
+            .finish()
+    }
+}
+
+/// Spawn `fut` onto the executor running on this thread.
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`].
+pub fn spawn_local<F>(fut: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let executor = CURRENT
+        .with(|c| c.borrow().clone())
+        .expect("spawn_local called outside of a running LocalExecutor");
+
+    executor.spawn(fut)
+}
+
+/// Spawn `fut` onto the executor running on this thread if that stays
+/// within its task limit.
+///
+/// See [`LocalExecutor::try_spawn`].
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`].
+pub fn try_spawn_local<F>(fut: F) -> Result<JoinHandle<F::Output>, SpawnError<F>>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let executor = CURRENT
+        .with(|c| c.borrow().clone())
+        .expect("try_spawn_local called outside of a running LocalExecutor");
+
+    executor.try_spawn(fut)
+}
+
+/// Spawn `fut` onto the executor running on this thread once that stays
+/// within its task limit.
+///
+/// See [`LocalExecutor::spawn_bounded`].
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`].
+pub async fn spawn_local_bounded<F>(fut: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let executor = CURRENT
+        .with(|c| c.borrow().clone())
+        .expect("spawn_local_bounded called outside of a running LocalExecutor");
+
+    executor.spawn_bounded(fut).await
+}
+
+/// Spawn `fut` onto the executor running on this thread, tied to its
+/// shutdown.
+///
+/// See [`LocalExecutor::spawn_until_shutdown`].
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`].
+pub fn spawn_until_shutdown<F>(fut: F) -> JoinHandle<Option<F::Output>>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let executor = CURRENT
+        .with(|c| c.borrow().clone())
+        .expect("spawn_until_shutdown called outside of a running LocalExecutor");
+
+    executor.spawn_until_shutdown(fut)
+}
+
+/// The shutdown token of the executor running on this thread.
+///
+/// See [`LocalExecutor::shutdown_token`].
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`].
+pub fn shutdown_token() -> CancellationToken {
+    let executor = CURRENT
+        .with(|c| c.borrow().clone())
+        .expect("shutdown_token called outside of a running LocalExecutor");
+
+    executor.shutdown_token()
+}
+
+/// Wait until the executor running on this thread has nothing to do.
+///
+/// See [`LocalExecutor::quiescent`].
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`].
+pub fn quiescent(window: Duration) -> impl Future<Output = ()> {
+    let executor = CURRENT
+        .with(|c| c.borrow().clone())
+        .expect("quiescent called outside of a running LocalExecutor");
+
+    executor.quiescent(window)
+}
+
+/// Marks an executor as running on this thread for the guard's lifetime.
+struct Enter;
+
+impl Enter {
+    fn new(executor: &LocalExecutor) -> Self {
+        CURRENT.with(|c| {
+            let mut current = c.borrow_mut();
+            assert!(
+                current.is_none(),
+                "cannot block_on while an executor is running on this thread"
+            );
+            *current = Some(executor.clone());
+        });
+
+        Self
+    }
+}
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        CURRENT.with(|c| c.take());
+    }
+}
+
+impl RunQueue {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<u64>> {
+        self.ready.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn take(&self) -> VecDeque<u64> {
+        mem::take(&mut *self.lock())
+    }
+
+    /// Wait in the reactor until a task is woken, I/O is ready or `timeout` passes.
+    ///
+    /// Returns the number of fds that became ready.
+    fn park(&self, reactor: &Reactor, timeout: Option<Duration>) -> usize {
+        self.parked.store(true, Ordering::SeqCst);
+
+        // Re-check after announcing the park, so a concurrent wake either
+        // shows up here or sees `parked` and unparks the reactor.
+        let timeout = if self.lock().is_empty() {
+            timeout
+        } else {
+            Some(Duration::ZERO)
+        };
+
+        let waited = reactor.wait(timeout);
+        self.parked.store(false, Ordering::SeqCst);
+        waited.expect("failed to wait for I/O events");
+
+        reactor.dispatch()
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.queue.lock().push_back(self.id);
+
+            #[cfg(feature = "metrics")]
+            self.queue.wakes.fetch_add(1, Ordering::Relaxed);
+
+            if self.queue.parked.swap(false, Ordering::SeqCst)
+                && let Some(unparker) = self.queue.unparker.get()
+            {
+                unparker.unpark();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn spawned_tasks_run_and_join() {
+        let executor = LocalExecutor::new();
+
+        let out = executor.block_on(async {
+            let a = spawn_local(async { 1 });
+            let b = spawn_local(async { 2 });
+            a.await.unwrap() + b.await.unwrap()
+        });
+
+        assert_eq!(out, 3);
+        assert_eq!(executor.task_count(), 0);
+    }
+
+    #[test]
+    fn task_panic_and_abort_surface_through_handle() {
+        LocalExecutor::new().block_on(async {
+            let panicked = spawn_local(async { panic!("boom") });
+            assert!(panicked.await.unwrap_err().is_panic());
+
+            let stuck = spawn_local(std::future::pending::<()>());
+            stuck.abort();
+            assert!(stuck.await.unwrap_err().is_cancelled());
+        });
+    }
+
+    #[test]
+    fn pending_tasks_survive_until_next_block_on() {
+        let executor = LocalExecutor::new();
+        let handle = executor.spawn(async { 5 });
+
+        assert_eq!(executor.task_count(), 1);
+        assert_eq!(executor.block_on(handle).unwrap(), 5);
+    }
+
+    #[test]
+    fn wake_from_another_thread_unparks() {
+        LocalExecutor::new().block_on(async {
+            let mut woken = false;
+
+            poll_fn(|cx| {
+                if woken {
+                    return Poll::Ready(());
+                }
+
+                woken = true;
+                let waker = cx.waker().clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    waker.wake();
+                });
+                Poll::Pending
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn busy_tasks_interleave() {
+        LocalExecutor::new().block_on(async {
+            let order = Rc::new(TrackedRefCell::new(Vec::new()));
+
+            let tasks = ["a", "b"].map(|name| {
+                let order = order.clone();
+                spawn_local(async move {
+                    for _ in 0..2 {
+                        order.borrow_mut().push(name);
+                        yield_now().await;
+                    }
+                })
+            });
+
+            for task in tasks {
+                task.await.unwrap();
+            }
+
+            assert_eq!(*order.borrow(), ["a", "b", "a", "b"]);
+        });
+    }
+
+    #[test]
+    fn shutdown_stops_tied_tasks_only() {
+        let executor = LocalExecutor::new();
+
+        executor.block_on(async {
+            let tied = spawn_until_shutdown(std::future::pending::<()>());
+            let finishes = spawn_until_shutdown(async { 7 });
+            let child = shutdown_token().child_token();
+            let untied = spawn_local(async move {
+                child.cancelled().await;
+                "child"
+            });
+
+            yield_now().await;
+            assert_eq!(finishes.await.unwrap(), Some(7));
+
+            executor.shutdown();
+            assert_eq!(tied.await.unwrap(), None);
+            assert_eq!(untied.await.unwrap(), "child");
+        });
+    }
+
+    #[test]
+    fn task_limit_applies_backpressure() {
+        let executor = LocalExecutor::new();
+        executor.set_max_tasks(Some(2));
+
+        executor.block_on(async {
+            let (tx, rx) = crate::utils::oneshot::channel::<()>();
+            let first = try_spawn_local(async move { rx.await.ok() }).unwrap();
+            let second = try_spawn_local(std::future::pending::<()>()).unwrap();
+
+            let refused = try_spawn_local(async { 3 }).unwrap_err();
+            assert_eq!(refused.0.await, 3);
+
+            let waiting = spawn_local_bounded(async { 4 });
+            let mut waiting = pin!(waiting);
+            let early = crate::time::timeout(Duration::from_millis(5), waiting.as_mut()).await;
+            assert!(early.is_err());
+
+            tx.send(()).unwrap();
+            first.await.unwrap();
+            assert_eq!(waiting.await.await.unwrap(), 4);
+
+            second.abort();
+        });
+    }
+}
@@ -0,0 +1,150 @@
+//! Where tasks wait: time parked per [`traced`] await point.
+
+use std::future::Future;
+
+#[cfg(feature = "await-profile")]
+use crate::utils::tracked_cell::TrackedRefCell;
+#[cfg(feature = "await-profile")]
+use std::{
+    collections::HashMap,
+    future::poll_fn,
+    panic::Location,
+    pin::pin,
+    time::{Duration, Instant},
+};
+
+/// Time spent parked at one await point, from [`await_profile`].
+#[cfg(feature = "await-profile")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AwaitPoint {
+    /// Where the awaited future was wrapped with [`traced`].
+    pub location: &'static Location<'static>,
+    /// Times a task parked here.
+    pub parks: u64,
+    /// Total time parked here.
+    pub parked: Duration,
+    /// Longest single park.
+    pub longest: Duration,
+}
+
+#[cfg(feature = "await-profile")]
+thread_local! {
+    static PROFILE: TrackedRefCell<HashMap<&'static Location<'static>, AwaitPoint>> =
+        TrackedRefCell::default();
+}
+
+/// Mark an await point for the profiler, by the caller's source location.
+///
+/// `traced(fut).await` behaves like `fut.await`. With the `await-profile`
+/// feature, each stretch between `fut` returning `Pending` and its next
+/// poll is added to this thread's `await_profile` under the location of
+/// the `traced` call; without it, `fut` is returned unchanged, so the
+/// markers can stay in place for free.
+#[track_caller]
+pub fn traced<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    #[cfg(feature = "await-profile")]
+    {
+        let location = Location::caller();
+
+        async move {
+            let mut fut = pin!(fut);
+            let mut parked = Parked {
+                location,
+                since: None,
+            };
+
+            poll_fn(|cx| {
+                parked.end();
+
+                let poll = fut.as_mut().poll(cx);
+
+                if poll.is_pending() {
+                    parked.since = Some(Instant::now());
+                }
+
+                poll
+            })
+            .await
+        }
+    }
+
+    #[cfg(not(feature = "await-profile"))]
+    fut
+}
+
+/// The await points of this thread's tasks, most time parked first.
+#[cfg(feature = "await-profile")]
+pub fn await_profile() -> Vec<AwaitPoint> {
+    let mut points: Vec<_> = PROFILE.with(|p| p.borrow().values().copied().collect());
+    points.sort_by_key(|point| std::cmp::Reverse(point.parked));
+    points
+}
+
+/// Forget everything recorded by [`traced`] on this thread so far.
+#[cfg(feature = "await-profile")]
+pub fn reset_await_profile() {
+    PROFILE.with(|p| p.borrow_mut().clear());
+}
+
+/// A park in progress, recorded when it ends or the future is dropped.
+#[cfg(feature = "await-profile")]
+struct Parked {
+    location: &'static Location<'static>,
+    since: Option<Instant>,
+}
+
+#[cfg(feature = "await-profile")]
+impl Parked {
+    fn end(&mut self) {
+        let Some(since) = self.since.take() else {
+            return;
+        };
+
+        let waited = since.elapsed();
+
+        PROFILE.with(|p| {
+            let mut profile = p.borrow_mut();
+            let point = profile.entry(self.location).or_insert(AwaitPoint {
+                location: self.location,
+                parks: 0,
+                parked: Duration::ZERO,
+                longest: Duration::ZERO,
+            });
+
+            point.parks += 1;
+            point.parked += waited;
+            point.longest = point.longest.max(waited);
+        });
+    }
+}
+
+#[cfg(feature = "await-profile")]
+impl Drop for Parked {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+#[cfg(all(test, feature = "await-profile"))]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, time::sleep};
+
+    #[test]
+    fn records_time_parked_per_location() {
+        reset_await_profile();
+
+        LocalExecutor::new().block_on(async {
+            traced(async {}).await;
+            for _ in 0..2 {
+                traced(sleep(Duration::from_millis(5))).await;
+            }
+        });
+
+        let profile = await_profile();
+        assert_eq!(profile.len(), 1);
+        assert_eq!(profile[0].parks, 2);
+        assert!(profile[0].parked >= Duration::from_millis(10));
+        assert_eq!(profile[0].location.file(), file!());
+    }
+}
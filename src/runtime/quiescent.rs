@@ -0,0 +1,122 @@
+use crate::{runtime::Inner, time::TimerWheel};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// Tasks waiting in [`LocalExecutor::quiescent`](super::LocalExecutor::quiescent),
+/// with the timer window each requires to be empty.
+#[derive(Default)]
+pub(crate) struct IdleWaiters {
+    next: u64,
+    waiters: BTreeMap<u64, (Duration, Waker)>,
+}
+
+impl IdleWaiters {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    /// Dequeue the waiters satisfied by the executor having gone idle at `now`.
+    pub(crate) fn take_settled(&mut self, wheel: &TimerWheel, now: Instant) -> Vec<Waker> {
+        let next = wheel.next_deadline();
+        let settled = |window: Duration| next.is_none_or(|deadline| deadline > now + window);
+
+        let keys: Vec<u64> = self
+            .waiters
+            .iter()
+            .filter(|(_, (window, _))| settled(*window))
+            .map(|(&key, _)| key)
+            .collect();
+
+        keys.iter()
+            .filter_map(|key| self.waiters.remove(key))
+            .map(|(_, waker)| waker)
+            .collect()
+    }
+}
+
+pub(crate) struct Quiescent {
+    pub(crate) inner: Rc<Inner>,
+    pub(crate) window: Duration,
+    pub(crate) key: Option<u64>,
+}
+
+impl Future for Quiescent {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        let mut idle = this.inner.idle.borrow_mut();
+
+        match this.key {
+            // Dequeued by the executor: it went idle with our window clear.
+            Some(key) if !idle.waiters.contains_key(&key) => {
+                this.key = None;
+                Poll::Ready(())
+            }
+            Some(key) => {
+                idle.waiters.insert(key, (this.window, cx.waker().clone()));
+                Poll::Pending
+            }
+            None => {
+                idle.next += 1;
+                let key = idle.next;
+                idle.waiters.insert(key, (this.window, cx.waker().clone()));
+                this.key = Some(key);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Quiescent {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.inner.idle.borrow_mut().waiters.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        runtime::{LocalExecutor, quiescent, spawn_local},
+        time::sleep,
+    };
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    fn spawn_sleeper(ms: u64) -> Rc<Cell<bool>> {
+        let done = Rc::new(Cell::new(false));
+        let flag = done.clone();
+
+        drop(spawn_local(async move {
+            sleep(Duration::from_millis(ms)).await;
+            flag.set(true);
+        }));
+
+        done
+    }
+
+    #[test]
+    fn waits_for_timers_within_window() {
+        LocalExecutor::new().block_on(async {
+            let done = spawn_sleeper(10);
+            quiescent(Duration::from_millis(500)).await;
+            assert!(done.get());
+        });
+    }
+
+    #[test]
+    fn ignores_timers_beyond_window() {
+        LocalExecutor::new().block_on(async {
+            let done = spawn_sleeper(500);
+            quiescent(Duration::from_millis(1)).await;
+            assert!(!done.get());
+        });
+    }
+}
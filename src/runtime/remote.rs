@@ -0,0 +1,126 @@
+use crate::runtime::{JoinHandle, spawn_local};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Spawn `fut` onto the executor running on this thread, cancelling it
+/// when the returned handle is dropped.
+///
+/// Suits speculative work whose result may turn out to be unwanted: drop
+/// the handle to stop the task, or [`forget`](RemoteHandle::forget) it to
+/// let the task run on alone.
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`](super::LocalExecutor::block_on).
+pub fn spawn_with_handle<F>(fut: F) -> RemoteHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    RemoteHandle {
+        handle: Some(spawn_local(fut)),
+    }
+}
+
+/// A handle to a task started by [`spawn_with_handle`] that aborts it on drop.
+///
+/// Awaiting the handle yields the task's output. A panic in the task is
+/// resumed in the awaiting task.
+#[must_use = "dropping the handle cancels the task"]
+pub struct RemoteHandle<T> {
+    /// Only `None` once forgotten.
+    handle: Option<JoinHandle<T>>,
+}
+
+impl<T> RemoteHandle<T> {
+    /// Let the task run to completion without anyone waiting for it.
+    pub fn forget(mut self) {
+        self.handle = None;
+    }
+
+    /// The task's id, as reported by [`LocalExecutor::dump`](super::LocalExecutor::dump).
+    pub fn id(&self) -> u64 {
+        self.handle().id()
+    }
+
+    fn handle(&self) -> &JoinHandle<T> {
+        self.handle
+            .as_ref()
+            .expect("RemoteHandle used after forget")
+    }
+}
+
+impl<T> Future for RemoteHandle<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let handle = self
+            .handle
+            .as_mut()
+            .expect("RemoteHandle used after forget");
+
+        match Pin::new(handle).poll(cx) {
+            Poll::Ready(Ok(value)) => Poll::Ready(value),
+            Poll::Ready(Err(e)) => e.resume(),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for RemoteHandle<T> {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.abort();
+        }
+    }
+}
+
+impl<T> fmt::Debug for RemoteHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteHandle")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, yield_now},
+        utils::tracked_cell::TrackedRefCell,
+    };
+    use std::{future::pending, rc::Rc};
+
+    #[test]
+    fn dropping_cancels_and_forgetting_detaches() {
+        let executor = LocalExecutor::new();
+
+        executor.block_on(async {
+            assert_eq!(spawn_with_handle(async { 7 }).await, 7);
+
+            drop(spawn_with_handle(pending::<()>()));
+
+            let done = Rc::new(TrackedRefCell::new(false));
+            spawn_with_handle({
+                let done = done.clone();
+                async move {
+                    yield_now().await;
+                    *done.borrow_mut() = true;
+                }
+            })
+            .forget();
+
+            for _ in 0..4 {
+                yield_now().await;
+            }
+            assert!(*done.borrow());
+        });
+
+        assert_eq!(executor.task_count(), 0);
+    }
+}
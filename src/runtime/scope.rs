@@ -0,0 +1,260 @@
+use crate::{
+    runtime::{JoinError, JoinHandle, spawn_local},
+    utils::tracked_cell::TrackedRefCell,
+};
+use std::{
+    any::Any,
+    fmt,
+    future::{Future, poll_fn},
+    mem, panic,
+    pin::{Pin, pin},
+    rc::Rc,
+    task::Poll,
+};
+
+/// Run `body` with a [`Scope`] whose tasks all end before the scope does.
+///
+/// Tasks spawned on the scope run concurrently with `body`. The scope
+/// resolves once `body` and every task have completed, with `body`'s
+/// result. If `body` or a task fails with an error instead, or a task
+/// panics, everything still running is cancelled, and once it has been
+/// dropped the scope fails with the first error or resumes the first
+/// panic. If the scope future itself is dropped, its tasks are cancelled.
+///
+/// Tasks must be `'static`, as with [`spawn_local`]; what the scope adds
+/// is that none of them can outlive it or fail unnoticed.
+///
+/// # Panics
+///
+/// Panics if called outside [`LocalExecutor::block_on`](super::LocalExecutor::block_on).
+pub async fn scope<F, Fut, T, E>(body: F) -> Result<T, E>
+where
+    F: FnOnce(Scope<E>) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: 'static,
+{
+    let scope = Scope {
+        tasks: Rc::default(),
+    };
+    let _cancel = CancelOnDrop(scope.tasks.clone());
+
+    let mut body = pin!(body(scope.clone()));
+    let mut output = None;
+    let mut failure = None;
+
+    poll_fn(|cx| {
+        if output.is_none() && failure.is_none() {
+            match body.as_mut().poll(cx) {
+                Poll::Ready(Ok(value)) => output = Some(value),
+                Poll::Ready(Err(e)) => failure = Some(Failure::Error(e)),
+                Poll::Pending => {}
+            }
+        }
+
+        // Taken out so no borrow is held while polling; polling a handle
+        // never runs its task, so nothing is spawned meanwhile.
+        let mut tasks = mem::take(&mut *scope.tasks.borrow_mut());
+
+        tasks.retain_mut(|task| {
+            let result = match Pin::new(task).poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return true,
+            };
+
+            match result {
+                Ok(Ok(())) | Err(JoinError::Cancelled) => {}
+                Ok(Err(e)) => {
+                    failure.get_or_insert(Failure::Error(e));
+                }
+                Err(JoinError::Panic(payload)) => {
+                    failure.get_or_insert(Failure::Panic(payload));
+                }
+            }
+
+            false
+        });
+
+        if failure.is_some() {
+            for task in &tasks {
+                task.abort();
+            }
+        }
+
+        let done = tasks.is_empty() && (output.is_some() || failure.is_some());
+        *scope.tasks.borrow_mut() = tasks;
+
+        if done { Poll::Ready(()) } else { Poll::Pending }
+    })
+    .await;
+
+    match failure {
+        None => Ok(output.expect("scope completed without output")),
+        Some(Failure::Error(e)) => Err(e),
+        Some(Failure::Panic(payload)) => panic::resume_unwind(payload),
+    }
+}
+
+/// Spawns tasks bounded by a [`scope`].
+///
+/// Clones refer to the same scope, so tasks can spawn siblings.
+pub struct Scope<E> {
+    tasks: Tasks<E>,
+}
+
+/// Handles of the tasks the scope has not yet seen finish.
+type Tasks<E> = Rc<TrackedRefCell<Vec<JoinHandle<Result<(), E>>>>>;
+
+enum Failure<E> {
+    Error(E),
+    Panic(Box<dyn Any + Send>),
+}
+
+impl<E: 'static> Scope<E> {
+    /// Spawn a task that the scope waits for.
+    ///
+    /// An error returned by the task fails the whole scope.
+    pub fn spawn<F>(&self, fut: F)
+    where
+        F: Future<Output = Result<(), E>> + 'static,
+    {
+        let handle = spawn_local(fut);
+        self.tasks.borrow_mut().push(handle);
+    }
+
+    /// Number of spawned tasks the scope has not yet seen finish.
+    pub fn len(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    /// Whether no spawned tasks are outstanding.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.borrow().is_empty()
+    }
+}
+
+impl<E> Clone for Scope<E> {
+    fn clone(&self) -> Self {
+        Self {
+            tasks: self.tasks.clone(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for Scope<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scope")
+            .field("tasks", &self.tasks.borrow().len())
+            .finish()
+    }
+}
+
+/// Cancels the scope's remaining tasks if the scope future is dropped.
+struct CancelOnDrop<E>(Tasks<E>);
+
+impl<E> Drop for CancelOnDrop<E> {
+    fn drop(&mut self) {
+        for task in self.0.borrow().iter() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, yield_now},
+        time::{sleep, timeout},
+    };
+    use std::{cell::Cell, time::Duration};
+
+    /// Sets the flag when dropped, i.e. when its task is cancelled or done.
+    struct SetOnDrop(Rc<Cell<bool>>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[test]
+    fn waits_for_every_task() {
+        LocalExecutor::new().block_on(async {
+            let count = Rc::new(Cell::new(0));
+
+            let out = scope(|s| {
+                let count = count.clone();
+                async move {
+                    for i in 1..=3 {
+                        let count = count.clone();
+                        s.spawn(async move {
+                            sleep(Duration::from_millis(i)).await;
+                            count.set(count.get() + 1);
+                            Ok::<_, ()>(())
+                        });
+                    }
+
+                    Ok("body")
+                }
+            })
+            .await;
+
+            assert_eq!(out, Ok("body"));
+            assert_eq!(count.get(), 3);
+        });
+    }
+
+    #[test]
+    fn error_cancels_siblings() {
+        LocalExecutor::new().block_on(async {
+            let dropped = Rc::new(Cell::new(false));
+
+            let out: Result<(), &str> = scope(|s| {
+                let guard = SetOnDrop(dropped.clone());
+                async move {
+                    s.spawn(async move {
+                        let _guard = guard;
+                        std::future::pending().await
+                    });
+                    s.spawn(async {
+                        yield_now().await;
+                        Err("failed")
+                    });
+
+                    Ok(())
+                }
+            })
+            .await;
+
+            assert_eq!(out, Err("failed"));
+            assert!(dropped.get());
+        });
+    }
+
+    #[test]
+    fn dropping_the_scope_cancels_tasks() {
+        LocalExecutor::new().block_on(async {
+            let dropped = Rc::new(Cell::new(false));
+
+            let out = timeout(
+                Duration::from_millis(5),
+                scope(|s: Scope<()>| {
+                    let guard = SetOnDrop(dropped.clone());
+                    async move {
+                        s.spawn(async move {
+                            let _guard = guard;
+                            std::future::pending().await
+                        });
+                        Ok(())
+                    }
+                }),
+            )
+            .await;
+            assert!(out.is_err());
+
+            // The aborted task is dropped once the executor gets to it.
+            yield_now().await;
+            assert!(dropped.get());
+        });
+    }
+}
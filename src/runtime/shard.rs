@@ -0,0 +1,432 @@
+use crate::{
+    runtime::{LocalExecutor, spawn_local},
+    utils::{channel::SendError, command_queue::CommandQueue},
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::{Future, poll_fn},
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+/// Independent [`LocalExecutor`]s, each on a thread of its own.
+///
+/// For thread-per-core designs: every shard runs its own loop, reactor and
+/// timers, and shares nothing with the others. Work crosses over only by
+/// [`spawn_on`](Self::spawn_on), which builds the future on the target
+/// shard so it need not be `Send`, and through [`shard_channel`]s.
+///
+/// Dropping the shards stops them, dropping their pending tasks, and
+/// joins their threads.
+pub struct Shards {
+    shards: Vec<ShardHandle>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+/// Spawns onto one shard from any thread; cheap to clone.
+#[derive(Clone)]
+pub struct ShardHandle {
+    id: usize,
+    inbox: CommandQueue<Command>,
+}
+
+enum Command {
+    Spawn(Box<dyn FnOnce() + Send>),
+    Stop,
+}
+
+impl Shards {
+    /// Start `count` shards on threads named `ars-shard-<id>`.
+    pub fn new(count: usize) -> io::Result<Self> {
+        let mut shards = Self {
+            shards: Vec::with_capacity(count),
+            threads: Vec::with_capacity(count),
+        };
+
+        for id in 0..count {
+            let inbox = CommandQueue::new();
+            let thread = thread::Builder::new()
+                .name(format!("ars-shard-{id}"))
+                .spawn({
+                    let inbox = inbox.clone();
+                    move || run(inbox)
+                })?;
+
+            shards.shards.push(ShardHandle { id, inbox });
+            shards.threads.push(thread);
+        }
+
+        Ok(shards)
+    }
+
+    /// Number of shards.
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Whether there are no shards.
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// A handle to shard `id`, e.g. to pass to tasks on other shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is out of range.
+    pub fn handle(&self, id: usize) -> ShardHandle {
+        self.shards[id].clone()
+    }
+
+    /// Run the future made by `make` on shard `id`.
+    ///
+    /// See [`ShardHandle::spawn`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is out of range.
+    pub fn spawn_on<M, F>(&self, id: usize, make: M) -> ShardJoinHandle<F::Output>
+    where
+        M: FnOnce() -> F + Send + 'static,
+        F: Future + 'static,
+        F::Output: Send + 'static,
+    {
+        self.shards[id].spawn(make)
+    }
+}
+
+impl Drop for Shards {
+    fn drop(&mut self) {
+        for shard in &self.shards {
+            shard.inbox.post(0, Command::Stop);
+        }
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl fmt::Debug for Shards {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shards")
+            .field("len", &self.shards.len())
+            .finish()
+    }
+}
+
+impl ShardHandle {
+    /// The shard's index in its [`Shards`].
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Call `make` on the shard and spawn the future it returns there.
+    ///
+    /// The returned handle resolves to the future's output, or to `None`
+    /// if the task panicked or the shard stopped before it completed.
+    pub fn spawn<M, F>(&self, make: M) -> ShardJoinHandle<F::Output>
+    where
+        M: FnOnce() -> F + Send + 'static,
+        F: Future + 'static,
+        F::Output: Send + 'static,
+    {
+        let reply = Arc::new(Mutex::new(Reply {
+            value: None,
+            done: false,
+            waker: None,
+        }));
+        let responder = Responder(reply.clone());
+
+        self.inbox.post(
+            0,
+            Command::Spawn(Box::new(move || {
+                drop(spawn_local(async move {
+                    let value = make().await;
+                    responder.send(value);
+                }));
+            })),
+        );
+
+        ShardJoinHandle { reply }
+    }
+}
+
+impl fmt::Debug for ShardHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardHandle").field("id", &self.id).finish()
+    }
+}
+
+fn run(inbox: CommandQueue<Command>) {
+    LocalExecutor::new().block_on(async {
+        loop {
+            inbox.ready().await;
+
+            for command in inbox.drain() {
+                match command {
+                    Command::Spawn(spawn) => spawn(),
+                    Command::Stop => return,
+                }
+            }
+        }
+    });
+}
+
+/// The output of a task spawned on a shard, awaitable from any thread.
+#[must_use = "dropping the handle detaches the task"]
+pub struct ShardJoinHandle<T> {
+    reply: Arc<Mutex<Reply<T>>>,
+}
+
+struct Reply<T> {
+    value: Option<T>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// Completes the [`ShardJoinHandle`]; dropped unsent if the task is.
+struct Responder<T>(Arc<Mutex<Reply<T>>>);
+
+impl<T> Responder<T> {
+    fn send(self, value: T) {
+        lock(&self.0).value = Some(value);
+    }
+}
+
+impl<T> Drop for Responder<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut reply = lock(&self.0);
+            reply.done = true;
+            reply.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for ShardJoinHandle<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut reply = lock(&self.reply);
+
+        if reply.done {
+            return Poll::Ready(reply.value.take());
+        }
+
+        match &mut reply.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            slot => *slot = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T> fmt::Debug for ShardJoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardJoinHandle")
+            .field("done", &lock(&self.reply).done)
+            .finish()
+    }
+}
+
+/// Create an unbounded channel for sending values between shards.
+///
+/// Both halves are `Send`; the [`ShardSender`] can be cloned, and the
+/// [`ShardReceiver`] yields `None` once every sender is dropped and the
+/// queue is drained.
+pub fn shard_channel<T: Send>() -> (ShardSender<T>, ShardReceiver<T>) {
+    let chan = Arc::new(Mutex::new(Chan {
+        queue: VecDeque::new(),
+        senders: 1,
+        receiver_alive: true,
+        waker: None,
+    }));
+
+    (ShardSender { chan: chan.clone() }, ShardReceiver { chan })
+}
+
+struct Chan<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    receiver_alive: bool,
+    waker: Option<Waker>,
+}
+
+/// The sending half of a [`shard_channel`].
+pub struct ShardSender<T> {
+    chan: Arc<Mutex<Chan<T>>>,
+}
+
+/// The receiving half of a [`shard_channel`].
+pub struct ShardReceiver<T> {
+    chan: Arc<Mutex<Chan<T>>>,
+}
+
+impl<T> ShardSender<T> {
+    /// Queue `value` for the receiver, waking it.
+    ///
+    /// Fails, giving the value back, if the receiver has been dropped.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let waker = {
+            let mut chan = lock(&self.chan);
+
+            if !chan.receiver_alive {
+                return Err(SendError(value));
+            }
+
+            chan.queue.push_back(value);
+            chan.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Clone for ShardSender<T> {
+    fn clone(&self) -> Self {
+        lock(&self.chan).senders += 1;
+
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T> Drop for ShardSender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut chan = lock(&self.chan);
+            chan.senders -= 1;
+
+            match chan.senders {
+                0 => chan.waker.take(),
+                _ => None,
+            }
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for ShardSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardSender").finish_non_exhaustive()
+    }
+}
+
+impl<T> ShardReceiver<T> {
+    /// Wait for the next value; `None` once every sender is gone.
+    pub fn recv(&mut self) -> impl Future<Output = Option<T>> + '_ {
+        poll_fn(|cx| self.poll_recv(cx))
+    }
+
+    /// Poll-based [`recv`](Self::recv).
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut chan = lock(&self.chan);
+
+        if let Some(value) = chan.queue.pop_front() {
+            return Poll::Ready(Some(value));
+        }
+
+        if chan.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        chan.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for ShardReceiver<T> {
+    fn drop(&mut self) {
+        let queue = {
+            let mut chan = lock(&self.chan);
+            chan.receiver_alive = false;
+            std::mem::take(&mut chan.queue)
+        };
+
+        drop(queue);
+    }
+}
+
+impl<T> fmt::Debug for ShardReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardReceiver").finish_non_exhaustive()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Only plain data is kept behind these locks.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn spawns_on_the_chosen_shard() {
+        let shards = Shards::new(2).unwrap();
+
+        LocalExecutor::new().block_on(async {
+            for id in 0..2 {
+                let name = shards
+                    .spawn_on(id, || async {
+                        // Built on the shard, so it may hold `!Send` state.
+                        let local = Rc::new(thread::current().name().map(str::to_owned));
+                        (*local).clone()
+                    })
+                    .await;
+
+                assert_eq!(name, Some(Some(format!("ars-shard-{id}"))));
+            }
+
+            let panicked = shards.spawn_on(0, || async { panic!("boom") });
+            assert_eq!(panicked.await, None::<()>);
+        });
+    }
+
+    #[test]
+    fn channel_crosses_shards() {
+        let shards = Shards::new(2).unwrap();
+        let (tx, mut rx) = shard_channel();
+
+        let sum = shards.spawn_on(0, move || async move {
+            let mut sum = 0;
+            while let Some(n) = rx.recv().await {
+                sum += n;
+            }
+            sum
+        });
+
+        // Dropping one of several senders must not lose the receiver's wakeup.
+        drop(tx.clone());
+
+        let producer = shards.handle(1);
+        drop(producer.spawn(move || async move {
+            for n in 1..=10 {
+                tx.send(n).unwrap();
+            }
+        }));
+
+        assert_eq!(LocalExecutor::new().block_on(sum), Some(55));
+    }
+}
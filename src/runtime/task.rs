@@ -0,0 +1,228 @@
+use crate::{runtime::coop, utils::tracked_cell::TrackedRefCell};
+use std::{
+    any::Any,
+    error::Error,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Completion state shared by a task and its [`JoinHandle`].
+struct JoinState<T> {
+    result: Option<Result<T, JoinError>>,
+    join_waker: Option<Waker>,
+    task_waker: Waker,
+    aborted: bool,
+}
+
+impl<T> JoinState<T> {
+    fn finish(&mut self, result: Result<T, JoinError>) -> Option<Waker> {
+        self.result = Some(result);
+        self.join_waker.take()
+    }
+}
+
+/// A spawned future, wrapped to report its outcome to the [`JoinHandle`].
+pub(crate) struct Task<F: Future> {
+    fut: Option<F>,
+    join: Rc<TrackedRefCell<JoinState<F::Output>>>,
+}
+
+/// Wrap `fut` into task `id`, woken through `task_waker`.
+pub(crate) fn new<F: Future>(
+    id: u64,
+    fut: F,
+    task_waker: Waker,
+) -> (Task<F>, JoinHandle<F::Output>) {
+    let join = Rc::new(TrackedRefCell::new(JoinState {
+        result: None,
+        join_waker: None,
+        task_waker,
+        aborted: false,
+    }));
+
+    (
+        Task {
+            fut: Some(fut),
+            join: join.clone(),
+        },
+        JoinHandle { id, join },
+    )
+}
+
+impl<F: Future> Future for Task<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // SAFETY: `fut` is structurally pinned: it is never moved out, only
+        // polled in place and dropped in place by overwriting the option.
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut fut = unsafe { Pin::new_unchecked(&mut this.fut) };
+
+        let result = if this.join.borrow().aborted {
+            Err(JoinError::Cancelled)
+        } else {
+            let Some(inner) = fut.as_mut().as_pin_mut() else {
+                return Poll::Ready(());
+            };
+
+            match panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+                Ok(Poll::Pending) => return Poll::Pending,
+                Ok(Poll::Ready(value)) => Ok(value),
+                Err(payload) => Err(JoinError::Panic(payload)),
+            }
+        };
+
+        // Drop the future before the handle observes the result.
+        fut.set(None);
+
+        let waker = this.join.borrow_mut().finish(result);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        Poll::Ready(())
+    }
+}
+
+impl<F: Future> Drop for Task<F> {
+    fn drop(&mut self) {
+        if self.fut.is_none() {
+            return;
+        }
+
+        // Dropped unfinished, i.e. the executor went away.
+        let waker = self.join.borrow_mut().finish(Err(JoinError::Cancelled));
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// An owned permission to await a spawned task's output.
+///
+/// Dropping the handle detaches the task; it keeps running.
+#[must_use = "dropping the handle detaches the task"]
+pub struct JoinHandle<T> {
+    id: u64,
+    join: Rc<TrackedRefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Cancel the task.
+    ///
+    /// The task's future is dropped the next time the executor gets to it,
+    /// and awaiting the handle yields a cancelled [`JoinError`]. A task that
+    /// already completed is unaffected.
+    pub fn abort(&self) {
+        let waker = {
+            let mut join = self.join.borrow_mut();
+
+            if join.result.is_some() {
+                return;
+            }
+
+            join.aborted = true;
+            join.task_waker.clone()
+        };
+
+        waker.wake();
+    }
+
+    /// Whether the task has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        self.join.borrow().result.is_some()
+    }
+
+    /// The task's id, as reported by [`LocalExecutor::dump`](super::LocalExecutor::dump).
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if coop::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let mut join = self.join.borrow_mut();
+
+        if let Some(result) = join.result.take() {
+            return Poll::Ready(result);
+        }
+
+        match &mut join.join_waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            slot => *slot = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("id", &self.id)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// Why a task did not produce its output.
+pub enum JoinError {
+    /// The task was aborted, or its executor was dropped.
+    Cancelled,
+    /// The task panicked; holds the panic payload.
+    Panic(Box<dyn Any + Send>),
+}
+
+impl JoinError {
+    /// Whether the task was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled)
+    }
+
+    /// Whether the task panicked.
+    pub fn is_panic(&self) -> bool {
+        matches!(self, Self::Panic(_))
+    }
+
+    /// Continue the task's panic on the current thread.
+    ///
+    /// A cancelled task resumes as a panic with this error's message.
+    pub fn resume(self) -> ! {
+        match self {
+            Self::Panic(payload) => panic::resume_unwind(payload),
+            Self::Cancelled => panic!("{self}"),
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => f.write_str("Cancelled"),
+            Self::Panic(_) => f.write_str("Panic(..)"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => f.write_str("task was cancelled"),
+            Self::Panic(_) => f.write_str("task panicked"),
+        }
+    }
+}
+
+impl Error for JoinError {}
@@ -0,0 +1,248 @@
+use crate::{
+    runtime::{JoinError, JoinHandle, spawn_local},
+    utils::tracked_cell::TrackedRefCell,
+};
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    future::{Future, poll_fn},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// A set of spawned tasks whose outputs are collected in completion order.
+///
+/// Each task runs on this thread's executor like any other, so it shows up
+/// in [`dump`](super::LocalExecutor::dump) and the runtime metrics under
+/// the id [`spawn`](Self::spawn) returns. A finishing task queues its id
+/// for the set, so [`join_next`](Self::join_next) only looks at tasks that
+/// are done instead of polling every handle.
+///
+/// Dropping the set aborts the tasks still in it.
+pub struct TaskSet<T> {
+    tasks: HashMap<u64, JoinHandle<T>>,
+    done: Rc<TrackedRefCell<Done>>,
+}
+
+#[derive(Debug, Default)]
+struct Done {
+    ids: VecDeque<u64>,
+    waker: Option<Waker>,
+}
+
+/// Queues its task's id when the task's future goes away, whether it
+/// completed, panicked or was aborted, even before its first poll.
+struct OnDone {
+    id: Rc<Cell<u64>>,
+    done: Rc<TrackedRefCell<Done>>,
+}
+
+impl Drop for OnDone {
+    fn drop(&mut self) {
+        let waker = {
+            let mut done = self.done.borrow_mut();
+            done.ids.push_back(self.id.get());
+            done.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: 'static> TaskSet<T> {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self {
+            tasks: HashMap::new(),
+            done: Rc::default(),
+        }
+    }
+
+    /// Spawn `fut` into the set, returning its task id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside [`LocalExecutor::block_on`](super::LocalExecutor::block_on).
+    pub fn spawn<F>(&mut self, fut: F) -> u64
+    where
+        F: Future<Output = T> + 'static,
+    {
+        let guard_id = Rc::new(Cell::new(0));
+        let guard = OnDone {
+            id: guard_id.clone(),
+            done: self.done.clone(),
+        };
+
+        let handle = spawn_local(async move {
+            let _guard = guard;
+            fut.await
+        });
+
+        // Nothing has run the task yet, so the guard will see the id.
+        let id = handle.id();
+        guard_id.set(id);
+        self.tasks.insert(id, handle);
+        id
+    }
+
+    /// Abort the task with `id`; it is still reported by
+    /// [`join_next`](Self::join_next), as cancelled unless it had
+    /// already finished.
+    ///
+    /// Returns `false` if no such task is in the set.
+    pub fn abort(&self, id: u64) -> bool {
+        match self.tasks.get(&id) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Abort every task in the set.
+    pub fn abort_all(&self) {
+        for handle in self.tasks.values() {
+            handle.abort();
+        }
+    }
+
+    /// Number of tasks not yet returned by [`join_next`](Self::join_next).
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Whether the set has no tasks left.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Wait for the next task to finish, returning its id and result.
+    ///
+    /// Returns `None` once the set is empty.
+    pub async fn join_next(&mut self) -> Option<(u64, Result<T, JoinError>)> {
+        poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    /// Poll for the next task to finish; see [`join_next`](Self::join_next).
+    pub fn poll_join_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<(u64, Result<T, JoinError>)>> {
+        if self.tasks.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            let Some(id) = self.done.borrow_mut().ids.pop_front() else {
+                break;
+            };
+
+            let Some(handle) = self.tasks.get_mut(&id) else {
+                continue;
+            };
+
+            match Pin::new(handle).poll(cx) {
+                Poll::Ready(result) => {
+                    self.tasks.remove(&id);
+                    return Poll::Ready(Some((id, result)));
+                }
+                // Out of budget or not quite done; the handle wakes us.
+                Poll::Pending => {
+                    self.done.borrow_mut().ids.push_front(id);
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        let mut done = self.done.borrow_mut();
+
+        match &mut done.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            slot => *slot = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T: 'static> Default for TaskSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for TaskSet<T> {
+    fn drop(&mut self) {
+        for handle in self.tasks.values() {
+            handle.abort();
+        }
+    }
+}
+
+impl<T> fmt::Debug for TaskSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskSet")
+            .field("tasks", &self.tasks.len())
+            .field("done", &self.done.borrow().ids.len())
+            .finish()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T: 'static> futures_core::Stream for TaskSet<T> {
+    type Item = (u64, Result<T, JoinError>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_join_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, time::sleep};
+    use std::time::Duration;
+
+    #[test]
+    fn yields_in_completion_order() {
+        LocalExecutor::new().block_on(async {
+            let mut set = TaskSet::new();
+            let slow = set.spawn(async {
+                sleep(Duration::from_millis(20)).await;
+                "slow"
+            });
+            let fast = set.spawn(async { "fast" });
+
+            let (id, result) = set.join_next().await.unwrap();
+            assert_eq!((id, result.unwrap()), (fast, "fast"));
+            let (id, result) = set.join_next().await.unwrap();
+            assert_eq!((id, result.unwrap()), (slow, "slow"));
+            assert!(set.join_next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn aborted_task_reports_cancelled() {
+        LocalExecutor::new().block_on(async {
+            let mut set = TaskSet::new();
+            let stuck = set.spawn(std::future::pending::<()>());
+            let ok = set.spawn(async {});
+
+            assert!(set.abort(stuck));
+            let mut seen = Vec::new();
+
+            while let Some((id, result)) = set.join_next().await {
+                seen.push((id, result.is_err_and(|e| e.is_cancelled())));
+            }
+
+            seen.sort();
+            assert_eq!(seen, [(stuck, true), (ok, false)]);
+            assert!(!set.abort(stuck));
+        });
+    }
+}
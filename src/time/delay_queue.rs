@@ -0,0 +1,285 @@
+//! [`DelayQueue`], a set of values keyed by deadline behind a single timer.
+//!
+//! Values live in a map, their deadlines in a `BTreeSet` ordered by
+//! deadline and then key, so equal deadlines expire in insertion order and
+//! insert, remove and reset are `O(log n)`. Only the head is armed, through
+//! one [`Sleep`] that is reset whenever the head changes. Keys are never
+//! reused, so a stale [`DelayKey`] matches nothing rather than a newer
+//! value. Deadlines share the timer wheel's millisecond resolution, and
+//! ones that would overflow [`Instant`] are saturated far into the future.
+
+use crate::{
+    runtime,
+    time::{Sleep, deadline_after, sleep},
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    future::{Future, poll_fn},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// A queue of values that become available at their deadlines.
+///
+/// However many values are queued, only the earliest deadline is armed
+/// with the timer driver, so this suits per-connection idle timeouts or
+/// scheduled retries where a timer task per item would be wasteful.
+/// Values can be removed or rescheduled through the [`DelayKey`] returned
+/// on insertion.
+pub struct DelayQueue<T> {
+    entries: HashMap<u64, (T, Instant)>,
+    /// Pending deadlines; the key breaks ties so equal deadlines expire FIFO.
+    order: BTreeSet<(Instant, u64)>,
+    next_key: u64,
+    sleep: Sleep,
+    /// Task polling an empty or not-yet-due queue, woken when an insert may
+    /// have moved the earliest deadline.
+    waker: Option<Waker>,
+}
+
+/// Identifies a value in a [`DelayQueue`].
+///
+/// Keys are never reused, so a key whose value has expired or been removed
+/// simply matches nothing.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct DelayKey(u64);
+
+/// A value whose deadline has passed.
+#[derive(Debug)]
+pub struct Expired<T> {
+    value: T,
+    key: DelayKey,
+    deadline: Instant,
+}
+
+impl<T> Expired<T> {
+    /// The expired value.
+    pub fn get_ref(&self) -> &T {
+        &self.value
+    }
+
+    /// Take the expired value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// The key the value was inserted under.
+    pub fn key(&self) -> DelayKey {
+        self.key
+    }
+
+    /// The deadline the value was due at.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> DelayQueue<T> {
+    /// Create an empty queue on this thread's timer driver.
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeSet::new(),
+            next_key: 0,
+            sleep: sleep(Duration::ZERO),
+            waker: None,
+        }
+    }
+
+    /// Queue `value` to expire after `timeout`.
+    pub fn insert(&mut self, value: T, timeout: Duration) -> DelayKey {
+        let deadline = deadline_after(self.now(), timeout);
+        self.insert_at(value, deadline)
+    }
+
+    /// Queue `value` to expire at `deadline`.
+    pub fn insert_at(&mut self, value: T, deadline: Instant) -> DelayKey {
+        self.next_key += 1;
+        let key = self.next_key;
+
+        self.entries.insert(key, (value, deadline));
+        self.order.insert((deadline, key));
+        self.wake();
+
+        DelayKey(key)
+    }
+
+    /// Remove the value under `key`, if it has not expired yet.
+    pub fn remove(&mut self, key: &DelayKey) -> Option<T> {
+        let (value, deadline) = self.entries.remove(&key.0)?;
+        self.order.remove(&(deadline, key.0));
+        Some(value)
+    }
+
+    /// Reschedule the value under `key` to expire after `timeout`.
+    ///
+    /// Returns `false` if the value has already expired or been removed.
+    pub fn reset(&mut self, key: &DelayKey, timeout: Duration) -> bool {
+        let deadline = deadline_after(self.now(), timeout);
+        self.reset_at(key, deadline)
+    }
+
+    /// Reschedule the value under `key` to expire at `deadline`.
+    ///
+    /// Returns `false` if the value has already expired or been removed.
+    pub fn reset_at(&mut self, key: &DelayKey, deadline: Instant) -> bool {
+        let Some((_, current)) = self.entries.get_mut(&key.0) else {
+            return false;
+        };
+
+        self.order.remove(&(*current, key.0));
+        self.order.insert((deadline, key.0));
+        *current = deadline;
+        self.wake();
+
+        true
+    }
+
+    /// The deadline of the value under `key`, if it is still queued.
+    pub fn deadline(&self, key: &DelayKey) -> Option<Instant> {
+        self.entries.get(&key.0).map(|&(_, deadline)| deadline)
+    }
+
+    /// Whether `key` still refers to a queued value.
+    pub fn contains(&self, key: &DelayKey) -> bool {
+        self.entries.contains_key(&key.0)
+    }
+
+    /// Number of queued values.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no values are queued.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop every queued value.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// Wait for the next value to expire.
+    ///
+    /// Values expire in deadline order. While the queue is empty this
+    /// waits for something to be inserted, so it is typically raced against
+    /// other events in a loop that also inserts.
+    pub async fn next_expired(&mut self) -> Expired<T> {
+        poll_fn(|cx| self.poll_expired(cx)).await
+    }
+
+    /// Poll for the next expired value.
+    pub fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<Expired<T>> {
+        if runtime::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let Some(&(deadline, key)) = self.order.first() else {
+            self.register(cx);
+            return Poll::Pending;
+        };
+
+        self.sleep.reset(deadline);
+
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            self.register(cx);
+            return Poll::Pending;
+        }
+
+        self.order.pop_first();
+        let (value, deadline) = self.entries.remove(&key).expect("ordered keys are queued");
+
+        Poll::Ready(Expired {
+            value,
+            key: DelayKey(key),
+            deadline,
+        })
+    }
+
+    fn now(&self) -> Instant {
+        self.sleep.driver.now()
+    }
+
+    fn register(&mut self, cx: &Context<'_>) {
+        match &self.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => self.waker = Some(cx.waker().clone()),
+        }
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+// Queued values live on the heap and are never pinned.
+impl<T> Unpin for DelayQueue<T> {}
+
+/// Never ends; an empty queue waits for insertions.
+#[cfg(feature = "futures")]
+impl<T> futures_core::Stream for DelayQueue<T> {
+    type Item = Expired<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Expired<T>>> {
+        self.get_mut().poll_expired(cx).map(Some)
+    }
+}
+
+impl<T> fmt::Debug for DelayQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DelayQueue")
+            .field("len", &self.entries.len())
+            .field("next", &self.order.first().map(|&(deadline, _)| deadline))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+
+    #[test]
+    fn expires_in_deadline_order() {
+        LocalExecutor::new().block_on(async {
+            let mut queue = DelayQueue::new();
+            queue.insert("late", Duration::from_millis(20));
+            let early = queue.insert("early", Duration::from_millis(5));
+            queue.insert("never", Duration::MAX);
+
+            let first = queue.next_expired().await;
+            assert_eq!(first.key(), early);
+            assert_eq!(first.into_inner(), "early");
+            assert_eq!(queue.next_expired().await.into_inner(), "late");
+            assert_eq!(queue.len(), 1);
+        });
+    }
+
+    #[test]
+    fn removed_and_reset_entries() {
+        LocalExecutor::new().block_on(async {
+            let mut queue = DelayQueue::new();
+            let a = queue.insert(1, Duration::from_millis(5));
+            let b = queue.insert(2, Duration::from_secs(60));
+
+            assert_eq!(queue.remove(&a), Some(1));
+            assert!(!queue.reset(&a, Duration::ZERO));
+            assert!(queue.reset(&b, Duration::from_millis(1)));
+
+            assert_eq!(queue.next_expired().await.into_inner(), 2);
+            assert!(queue.is_empty());
+        });
+    }
+}
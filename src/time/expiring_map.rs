@@ -0,0 +1,250 @@
+//! [`ExpiringMap`], a map whose entries remove themselves after a timeout.
+//!
+//! Deadlines live in a [`DelayQueue`] of keys, so only the earliest one is
+//! armed with the timer driver. A task spawned with the map awaits that
+//! queue and removes each entry as it expires, handing it to the cleanup
+//! callback if one was given; the task is aborted when the map is dropped.
+
+use crate::{
+    runtime::{JoinHandle, spawn_local},
+    time::{DelayKey, DelayQueue},
+    utils::tracked_cell::TrackedRefCell,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{Future, poll_fn},
+    hash::Hash,
+    pin::Pin,
+    rc::Rc,
+    time::Duration,
+};
+
+type Cleanup<K, V> = Box<dyn FnMut(K, V) -> Pin<Box<dyn Future<Output = ()>>>>;
+
+/// A map whose entries expire a fixed time after insertion.
+///
+/// Replaces a periodic task scanning a `HashMap` for stale entries: each
+/// entry is removed when its own timeout passes, and
+/// [`with_cleanup`](Self::with_cleanup) runs an async callback on it in a
+/// task of its own. Entries removed with [`remove`](Self::remove) or
+/// replaced by [`insert`](Self::insert) do not go through the callback.
+pub struct ExpiringMap<K, V> {
+    inner: Rc<TrackedRefCell<Inner<K, V>>>,
+    expiry: JoinHandle<()>,
+}
+
+struct Inner<K, V> {
+    entries: HashMap<K, (V, DelayKey)>,
+    queue: DelayQueue<K>,
+    cleanup: Option<Cleanup<K, V>>,
+}
+
+impl<K, V> ExpiringMap<K, V>
+where
+    K: Eq + Hash + Clone + 'static,
+    V: 'static,
+{
+    /// Create an empty map whose expired entries are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside [`LocalExecutor::block_on`](crate::runtime::LocalExecutor::block_on),
+    /// which runs the task expiring entries.
+    pub fn new() -> Self {
+        Self::with(None)
+    }
+
+    /// Create an empty map that spawns `cleanup(key, value)` for each
+    /// entry that expires.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside [`LocalExecutor::block_on`](crate::runtime::LocalExecutor::block_on).
+    pub fn with_cleanup<F, Fut>(mut cleanup: F) -> Self
+    where
+        F: FnMut(K, V) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        Self::with(Some(Box::new(move |key, value| {
+            Box::pin(cleanup(key, value))
+        })))
+    }
+
+    fn with(cleanup: Option<Cleanup<K, V>>) -> Self {
+        let inner = Rc::new(TrackedRefCell::new(Inner {
+            entries: HashMap::new(),
+            queue: DelayQueue::new(),
+            cleanup,
+        }));
+
+        Self {
+            expiry: spawn_local(expire(inner.clone())),
+            inner,
+        }
+    }
+
+    /// Insert `value` under `key` to expire after `ttl`, returning the
+    /// value it replaces.
+    pub fn insert(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let mut inner = self.inner.borrow_mut();
+        let delay = inner.queue.insert(key.clone(), ttl);
+
+        let (old, old_delay) = inner.entries.insert(key, (value, delay))?;
+        inner.queue.remove(&old_delay);
+        Some(old)
+    }
+
+    /// A clone of the value under `key`.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.inner
+            .borrow()
+            .entries
+            .get(key)
+            .map(|(value, _)| value.clone())
+    }
+
+    /// Call `f` with the value under `key`.
+    pub fn with_value<R>(&self, key: &K, f: impl FnOnce(&V) -> R) -> Option<R> {
+        self.inner
+            .borrow()
+            .entries
+            .get(key)
+            .map(|(value, _)| f(value))
+    }
+
+    /// Restart the timeout of the entry under `key` at `ttl`.
+    ///
+    /// Returns `false` if there is no such entry.
+    pub fn touch(&self, key: &K, ttl: Duration) -> bool {
+        let mut inner = self.inner.borrow_mut();
+
+        let Some(&(_, delay)) = inner.entries.get(key) else {
+            return false;
+        };
+
+        inner.queue.reset(&delay, ttl)
+    }
+
+    /// Remove the entry under `key` before it expires.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.borrow_mut();
+        let (value, delay) = inner.entries.remove(key)?;
+
+        inner.queue.remove(&delay);
+        Some(value)
+    }
+
+    /// Whether there is an entry under `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.inner.borrow().entries.contains_key(key)
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.inner.borrow().entries.len()
+    }
+
+    /// Whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.inner.borrow().entries.is_empty()
+    }
+}
+
+impl<K, V> Default for ExpiringMap<K, V>
+where
+    K: Eq + Hash + Clone + 'static,
+    V: 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for ExpiringMap<K, V> {
+    fn drop(&mut self) {
+        self.expiry.abort();
+    }
+}
+
+impl<K, V> fmt::Debug for ExpiringMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpiringMap")
+            .field("len", &self.inner.borrow().entries.len())
+            .finish()
+    }
+}
+
+async fn expire<K, V>(inner: Rc<TrackedRefCell<Inner<K, V>>>)
+where
+    K: Eq + Hash + 'static,
+    V: 'static,
+{
+    loop {
+        let key = poll_fn(|cx| inner.borrow_mut().queue.poll_expired(cx))
+            .await
+            .into_inner();
+
+        let Some((value, _)) = inner.borrow_mut().entries.remove(&key) else {
+            continue;
+        };
+
+        // Taken out so the callback runs without the map borrowed.
+        let Some(mut cleanup) = inner.borrow_mut().cleanup.take() else {
+            continue;
+        };
+
+        drop(spawn_local(cleanup(key, value)));
+        inner.borrow_mut().cleanup = Some(cleanup);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, time::sleep};
+    use std::cell::RefCell;
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        LocalExecutor::new().block_on(async {
+            let map = ExpiringMap::new();
+            map.insert("short", 1, Duration::from_millis(5));
+            map.insert("long", 2, Duration::from_millis(50));
+
+            sleep(Duration::from_millis(20)).await;
+            assert_eq!(map.get(&"short"), None);
+            assert_eq!(map.get(&"long"), Some(2));
+
+            assert!(map.touch(&"long", Duration::from_millis(50)));
+            assert_eq!(map.insert("long", 3, Duration::from_millis(50)), Some(2));
+            assert_eq!(map.remove(&"long"), Some(3));
+            assert!(map.is_empty());
+        });
+    }
+
+    #[test]
+    fn cleanup_runs_for_expired_entries_only() {
+        LocalExecutor::new().block_on(async {
+            let cleaned = Rc::new(RefCell::new(Vec::new()));
+            let map = ExpiringMap::with_cleanup({
+                let cleaned = cleaned.clone();
+                move |key, value| {
+                    let cleaned = cleaned.clone();
+                    async move { cleaned.borrow_mut().push((key, value)) }
+                }
+            });
+
+            map.insert(1, "expires", Duration::from_millis(5));
+            map.insert(2, "removed", Duration::from_millis(5));
+            map.remove(&2);
+
+            sleep(Duration::from_millis(20)).await;
+            assert_eq!(*cleaned.borrow(), [(1, "expires")]);
+            assert_eq!(map.len(), 0);
+        });
+    }
+}
@@ -0,0 +1,253 @@
+//! [`FrameClock`], frame pacing on a fixed grid of instants.
+//!
+//! Frames are due at `phase + k * period` for whole `k`. Unlike an
+//! [`Interval`](super::Interval), which restarts its schedule from a late
+//! tick, a late frame is delivered at the latest grid point that has
+//! passed and the ones before it are counted as missed, so the grid never
+//! drifts. Re-aligning moves the grid but not the frame count.
+
+use crate::time::{Sleep, deadline_after, sleep_until};
+use std::{
+    future::{Future, poll_fn},
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+
+/// Paces a render loop at a target refresh period, like vsync without a
+/// display to report it.
+#[derive(Debug)]
+pub struct FrameClock {
+    sleep: Sleep,
+    period: Duration,
+    /// Index of the frame due at the sleep's deadline.
+    index: u64,
+}
+
+/// A frame handed out by a [`FrameClock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Frames due since the clock started, counting missed ones.
+    pub index: u64,
+    /// The grid point this frame was due at.
+    pub scheduled: Instant,
+    /// Frames skipped since the previous one because it came too late.
+    pub missed: u64,
+}
+
+impl FrameClock {
+    /// A clock whose first frame is due now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(period: Duration) -> Self {
+        Self::aligned(period, super::now())
+    }
+
+    /// A clock with a frame due at `phase`, e.g. the last known
+    /// presentation time, and every `period` before and after it.
+    ///
+    /// The first frame is the first one due at or after now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn aligned(period: Duration, phase: Instant) -> Self {
+        assert!(!period.is_zero(), "frame period must be non-zero");
+
+        let mut sleep = sleep_until(phase);
+        sleep.reset(next_on_grid(phase, period, sleep.driver.now()));
+
+        Self {
+            sleep,
+            period,
+            index: 0,
+        }
+    }
+
+    /// Wait for the next frame.
+    pub async fn next_frame(&mut self) -> Frame {
+        poll_fn(|cx| self.poll_next_frame(cx)).await
+    }
+
+    /// Poll for the next frame.
+    pub fn poll_next_frame(&mut self, cx: &mut Context<'_>) -> Poll<Frame> {
+        ready!(Pin::new(&mut self.sleep).poll(cx));
+
+        let due = self.sleep.deadline();
+        let now = self.sleep.driver.now();
+        let missed = periods_between(due, now, self.period);
+        let scheduled = deadline_after(due, times(self.period, missed));
+
+        let frame = Frame {
+            index: self.index + missed,
+            scheduled,
+            missed,
+        };
+
+        self.index = frame.index + 1;
+        self.sleep.reset(deadline_after(scheduled, self.period));
+        Poll::Ready(frame)
+    }
+
+    /// Move the grid so a frame falls on `phase`, keeping the period.
+    ///
+    /// The next frame is the first one due at or after now on the new
+    /// grid. Frames skipped by the move are not reported as missed.
+    pub fn align(&mut self, phase: Instant) {
+        let now = self.sleep.driver.now();
+        self.sleep.reset(next_on_grid(phase, self.period, now));
+    }
+
+    /// Change the period from the next frame on, which stays where it is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn set_period(&mut self, period: Duration) {
+        assert!(!period.is_zero(), "frame period must be non-zero");
+        self.period = period;
+    }
+
+    /// The frame period.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// When the next frame is due.
+    pub fn next_deadline(&self) -> Instant {
+        self.sleep.deadline()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures_core::Stream for FrameClock {
+    type Item = Frame;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Frame>> {
+        self.get_mut().poll_next_frame(cx).map(Some)
+    }
+}
+
+/// The first instant at or after `now` on the grid through `phase`.
+fn next_on_grid(phase: Instant, period: Duration, now: Instant) -> Instant {
+    if phase >= now {
+        let back = periods_between(now, phase, period);
+        phase - times(period, back)
+    } else {
+        let forward = periods_between(phase, now, period);
+        let at = deadline_after(phase, times(period, forward));
+
+        if at < now {
+            deadline_after(at, period)
+        } else {
+            at
+        }
+    }
+}
+
+/// Whole periods from `from` to `to`, or 0 if `to` is earlier.
+fn periods_between(from: Instant, to: Instant, period: Duration) -> u64 {
+    let gap = to.saturating_duration_since(from);
+    (gap.as_nanos() / period.as_nanos())
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+fn times(period: Duration, n: u64) -> Duration {
+    let nanos = period.as_nanos().saturating_mul(u128::from(n));
+    Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::CountingWaker,
+        time::{TimerDriver, set_driver},
+    };
+    use std::{cell::Cell, rc::Rc, task::Waker};
+
+    /// A driver whose time only moves when told to.
+    struct Manual(Cell<Instant>);
+
+    impl TimerDriver for Manual {
+        fn register(&self, _: &mut Option<u64>, _: Instant, _: &Waker) {}
+
+        fn cancel(&self, _: u64) {}
+
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    fn poll(clock: &mut FrameClock) -> Poll<Frame> {
+        let waker = CountingWaker::new().waker();
+        clock.poll_next_frame(&mut Context::from_waker(&waker))
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn stays_on_grid_and_reports_missed_frames() {
+        let t0 = Instant::now();
+        let driver = Rc::new(Manual(Cell::new(t0 + ms(3))));
+        let previous = set_driver(Some(driver.clone()));
+
+        let mut clock = FrameClock::aligned(ms(10), t0);
+        assert_eq!(clock.next_deadline(), t0 + ms(10));
+        assert!(poll(&mut clock).is_pending());
+
+        driver.0.set(t0 + ms(12));
+        let frame = poll(&mut clock);
+        assert_eq!(
+            frame,
+            Poll::Ready(Frame {
+                index: 0,
+                scheduled: t0 + ms(10),
+                missed: 0
+            })
+        );
+
+        driver.0.set(t0 + ms(47));
+        let frame = poll(&mut clock);
+        assert_eq!(
+            frame,
+            Poll::Ready(Frame {
+                index: 3,
+                scheduled: t0 + ms(40),
+                missed: 2
+            })
+        );
+        assert_eq!(clock.next_deadline(), t0 + ms(50));
+
+        // Half a period later, without counting anything as missed.
+        clock.align(t0 + ms(5));
+        assert_eq!(clock.next_deadline(), t0 + ms(55));
+        driver.0.set(t0 + ms(55));
+        assert!(matches!(poll(&mut clock), Poll::Ready(f) if f.index == 4 && f.missed == 0));
+
+        clock.set_period(ms(20));
+        assert_eq!(clock.next_deadline(), t0 + ms(65));
+        driver.0.set(t0 + ms(65));
+        assert!(poll(&mut clock).is_ready());
+        assert_eq!(clock.next_deadline(), t0 + ms(85));
+
+        set_driver(previous);
+    }
+
+    #[test]
+    fn aligns_to_a_phase_in_the_future() {
+        let t0 = Instant::now();
+        let previous = set_driver(Some(Rc::new(Manual(Cell::new(t0)))));
+
+        let clock = FrameClock::aligned(ms(10), t0 + ms(35));
+        assert_eq!(clock.next_deadline(), t0 + ms(5));
+        assert_eq!(FrameClock::new(ms(10)).next_deadline(), t0);
+
+        set_driver(previous);
+    }
+}
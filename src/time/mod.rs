@@ -0,0 +1,479 @@
+//! Timers: [`sleep`], [`timeout`], [`Interval`], [`FrameClock`] and
+//! [`DelayQueue`], all driven by a [`TimerDriver`] with millisecond
+//! resolution.
+//!
+//! An [`Interval`] keeps its schedule on unjittered base times, so
+//! [`IntervalOptions::jitter`] delays single ticks without the delays adding
+//! up to drift, and a stalled interval skips the ticks it missed. The random
+//! phase and jitter come from a per-thread xorshift seeded from std's hash
+//! keys, which spreads timers apart but is not cryptographic. A jitter
+//! larger than the period can push a tick past the next base time; the
+//! interval then still averages one tick per period.
+
+mod delay_queue;
+mod expiring_map;
+mod frame_clock;
+mod pausable;
+mod wheel;
+
+pub use delay_queue::{DelayKey, DelayQueue, Expired};
+pub use expiring_map::ExpiringMap;
+pub use frame_clock::{Frame, FrameClock};
+pub use pausable::{PausableSleep, sleep_pausable};
+pub use wheel::TimerWheel;
+
+use crate::utils::tracked_cell::TrackedRefCell;
+use std::{
+    cell::Cell,
+    error::Error,
+    fmt,
+    future::{Future, poll_fn},
+    hash::{BuildHasher, Hasher, RandomState},
+    pin::{Pin, pin},
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// A source of timer wakeups, and the clock they are measured against.
+///
+/// The thread-local [`TimerWheel`] is used unless another driver is
+/// installed with [`set_driver`], e.g. one backed by a timerfd. Every time
+/// source in the crate reads the current time through [`now`](Self::now):
+/// sleeps, intervals, frame clocks, delay queues, expiring maps, rate
+/// limiters and the resolver cache. A driver with its own notion of time,
+/// such as one replaying a recorded session faster or slower than real
+/// time, therefore moves all of them together.
+pub trait TimerDriver {
+    /// Arrange for `waker` to be woken once `deadline` has passed.
+    ///
+    /// `key` identifies the caller's current registration, if any; it is
+    /// updated in place. A driver may wake immediately and leave `key`
+    /// empty if the deadline has already passed.
+    fn register(&self, key: &mut Option<u64>, deadline: Instant, waker: &Waker);
+
+    /// Drop the registration identified by `key`.
+    fn cancel(&self, key: u64);
+
+    /// The driver's notion of the current time.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Stands in for deadlines too far out to represent, such as `Duration::MAX` from now.
+const FAR_FUTURE: Duration = Duration::from_secs(30 * 365 * 24 * 3600);
+
+thread_local! {
+    static WHEEL: Rc<TimerWheel> = Rc::new(TimerWheel::new());
+    static DRIVER: TrackedRefCell<Option<Rc<dyn TimerDriver>>> = const { TrackedRefCell::new(None) };
+}
+
+/// The timer wheel that backs this thread's timers by default.
+///
+/// Whatever loop runs the thread's tasks must call
+/// [`TimerWheel::advance`] on it for timers to fire.
+pub fn local_wheel() -> Rc<TimerWheel> {
+    WHEEL.with(Rc::clone)
+}
+
+/// Replace this thread's timer driver, returning the previous one.
+///
+/// `None` restores the [`local_wheel`]. Timers already created keep the
+/// driver that was current when they were created.
+pub fn set_driver(driver: Option<Rc<dyn TimerDriver>>) -> Option<Rc<dyn TimerDriver>> {
+    DRIVER.with(|d| d.replace(driver))
+}
+
+fn driver() -> Rc<dyn TimerDriver> {
+    DRIVER
+        .with(|d| d.borrow().clone())
+        .unwrap_or_else(|| local_wheel())
+}
+
+/// The current time according to this thread's timer driver.
+pub fn now() -> Instant {
+    driver().now()
+}
+
+/// Wait until `duration` has passed.
+pub fn sleep(duration: Duration) -> Sleep {
+    let driver = driver();
+    let deadline = deadline_after(driver.now(), duration);
+
+    Sleep {
+        driver,
+        deadline,
+        key: None,
+    }
+}
+
+/// Wait until `deadline`.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        driver: driver(),
+        deadline,
+        key: None,
+    }
+}
+
+/// Wait at least `duration`, letting the deadline move up to `slack` later
+/// so that it lines up with other loose timers.
+///
+/// The deadline is rounded up to a multiple of the largest power-of-two
+/// number of milliseconds within `slack`, counted from a per-thread anchor.
+/// Timers whose rounded deadlines agree fire on the same wheel tick, so a
+/// batch of them costs one wakeup instead of one each. A slack under 2ms
+/// behaves like [`sleep`].
+pub fn sleep_with_slack(duration: Duration, slack: Duration) -> Sleep {
+    let driver = driver();
+    let deadline = coalesce(deadline_after(driver.now(), duration), slack);
+
+    Sleep {
+        driver,
+        deadline,
+        key: None,
+    }
+}
+
+/// Future returned by [`sleep`], [`sleep_until`] and [`sleep_with_slack`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    driver: Rc<dyn TimerDriver>,
+    deadline: Instant,
+    key: Option<u64>,
+}
+
+impl Sleep {
+    /// The instant at which the sleep completes.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Whether the deadline has passed.
+    pub fn is_elapsed(&self) -> bool {
+        self.driver.now() >= self.deadline
+    }
+
+    /// Move the deadline, re-arming a completed sleep.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+
+        if this.is_elapsed() {
+            if let Some(key) = this.key.take() {
+                this.driver.cancel(key);
+            }
+            return Poll::Ready(());
+        }
+
+        this.driver
+            .register(&mut this.key, this.deadline, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.driver.cancel(key);
+        }
+    }
+}
+
+impl fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sleep")
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Run `fut`, giving up once `duration` has passed.
+pub async fn timeout<F: Future>(duration: Duration, fut: F) -> Result<F::Output, Elapsed> {
+    let deadline = deadline_after(driver().now(), duration);
+    timeout_at(deadline, fut).await
+}
+
+/// Run `fut`, giving up at `deadline`.
+///
+/// `fut` is polled before the deadline is checked, so a ready future
+/// always wins.
+pub async fn timeout_at<F: Future>(deadline: Instant, fut: F) -> Result<F::Output, Elapsed> {
+    let mut fut = pin!(fut);
+    let mut sleep = sleep_until(deadline);
+
+    poll_fn(|cx| {
+        if let Poll::Ready(value) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Ok(value));
+        }
+
+        Pin::new(&mut sleep).poll(cx).map(|()| Err(Elapsed))
+    })
+    .await
+}
+
+/// Error returned by [`timeout`] when the deadline passes first.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+/// Create an [`Interval`] ticking every `period`, starting immediately.
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    interval_with(period, IntervalOptions::default())
+}
+
+/// Create an [`Interval`] ticking every `period`, shaped by `options`.
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn interval_with(period: Duration, options: IntervalOptions) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+
+    let offset = match options.phase {
+        Phase::Immediate => Duration::ZERO,
+        Phase::Offset(offset) => offset,
+        Phase::Random => random_below(period),
+    };
+
+    let mut sleep = sleep(offset);
+    let base = sleep.deadline();
+    sleep.reset(deadline_after(base, random_below(options.jitter)));
+
+    Interval {
+        sleep,
+        base,
+        period,
+        jitter: options.jitter,
+    }
+}
+
+/// Options for [`interval_with`].
+///
+/// Periodic tasks started together, in one process or many, tick in
+/// lockstep and contend for the CPU at the same moments. A random phase
+/// spreads their first ticks over a period; jitter keeps them from
+/// drifting back together.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IntervalOptions {
+    /// When the first tick happens.
+    pub phase: Phase,
+    /// Delay each tick by a random amount below this.
+    ///
+    /// Jitter does not accumulate: each tick is offset from the regular
+    /// schedule, so the average rate is unchanged. Keep it well below the
+    /// period.
+    pub jitter: Duration,
+}
+
+/// When an [`Interval`] ticks first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Phase {
+    /// Tick right away.
+    #[default]
+    Immediate,
+    /// Tick after a fixed offset.
+    Offset(Duration),
+    /// Tick after a random offset below one period.
+    Random,
+}
+
+/// Ticks at a fixed period.
+///
+/// Missed ticks are skipped rather than delivered in a burst: after a
+/// stall, the next tick is scheduled one period after the late one is
+/// delivered.
+#[derive(Debug)]
+pub struct Interval {
+    sleep: Sleep,
+    /// The unjittered time of the pending tick.
+    base: Instant,
+    period: Duration,
+    jitter: Duration,
+}
+
+impl Interval {
+    /// Wait for the next tick, returning its scheduled time.
+    pub async fn tick(&mut self) -> Instant {
+        poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    /// Poll for the next tick.
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        let scheduled = self.sleep.deadline();
+
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let now = self.sleep.driver.now();
+        self.base = deadline_after(self.base, self.period);
+
+        if self.base <= now {
+            self.base = deadline_after(now, self.period);
+        }
+
+        self.sleep
+            .reset(deadline_after(self.base, random_below(self.jitter)));
+
+        Poll::Ready(scheduled)
+    }
+
+    /// The tick period.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Restart the interval so the next tick is one period from now.
+    pub fn reset(&mut self) {
+        self.base = deadline_after(self.sleep.driver.now(), self.period);
+        self.sleep
+            .reset(deadline_after(self.base, random_below(self.jitter)));
+    }
+}
+
+/// `now + duration`, or [`FAR_FUTURE`] from `now` if that overflows.
+pub(crate) fn deadline_after(now: Instant, duration: Duration) -> Instant {
+    now.checked_add(duration)
+        .unwrap_or_else(|| now + FAR_FUTURE)
+}
+
+/// Round `deadline` up to the coalescing grid for `slack`; see
+/// [`sleep_with_slack`].
+fn coalesce(deadline: Instant, slack: Duration) -> Instant {
+    thread_local! {
+        static ANCHOR: Instant = Instant::now();
+    }
+
+    let slack_ms = u64::try_from(slack.as_millis()).unwrap_or(u64::MAX);
+
+    if slack_ms < 2 {
+        return deadline;
+    }
+
+    let grain = 1 << slack_ms.ilog2();
+    let anchor = ANCHOR.with(|a| *a);
+    let Some(offset) = deadline.checked_duration_since(anchor) else {
+        return deadline;
+    };
+
+    let ms = u64::try_from(offset.as_millis()).unwrap_or(u64::MAX);
+    let rounded = ms.div_ceil(grain).saturating_mul(grain);
+
+    anchor
+        .checked_add(Duration::from_millis(rounded))
+        .filter(|&t| t >= deadline)
+        .unwrap_or(deadline)
+}
+
+/// A uniformly distributed duration in `[0, bound)`; zero if `bound` is.
+///
+/// Not cryptographic: a per-thread xorshift seeded from std's hash keys.
+fn random_below(bound: Duration) -> Duration {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(
+            RandomState::new().build_hasher().finish() | 1,
+        );
+    }
+
+    let nanos = bound.as_nanos().min(u128::from(u64::MAX)) as u64;
+
+    if nanos == 0 {
+        return Duration::ZERO;
+    }
+
+    let x = STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    });
+
+    Duration::from_nanos(((u128::from(x) * u128::from(nanos)) >> 64) as u64)
+}
+
+#[cfg(feature = "futures")]
+impl futures_core::Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Instant>> {
+        self.get_mut().poll_tick(cx).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+
+    #[test]
+    fn sleep_for_max_duration_does_not_overflow() {
+        let sleep = sleep(Duration::MAX);
+        assert!(sleep.deadline() > now() + Duration::from_secs(365 * 24 * 3600));
+    }
+
+    #[test]
+    fn timeout_for_max_duration_lets_future_finish() {
+        let result = LocalExecutor::new().block_on(timeout(Duration::MAX, async { 7 }));
+        assert_eq!(result, Ok(7));
+    }
+
+    #[test]
+    fn timeout_elapses() {
+        let result = LocalExecutor::new().block_on(timeout(
+            Duration::from_millis(5),
+            std::future::pending::<()>(),
+        ));
+        assert_eq!(result, Err(Elapsed));
+    }
+
+    #[test]
+    fn slack_lines_nearby_deadlines_up() {
+        let slack = Duration::from_millis(64);
+        let before = now();
+        let a = sleep_with_slack(Duration::from_millis(100), slack);
+        let b = sleep_with_slack(Duration::from_millis(101), slack);
+
+        assert!(a.deadline() >= before + Duration::from_millis(100));
+        assert!(a.deadline() <= now() + Duration::from_millis(100) + slack);
+        // Both land on the same 64ms boundary unless one straddles it.
+        let gap = b.deadline().saturating_duration_since(a.deadline());
+        assert!(gap == Duration::ZERO || gap == slack);
+
+        let before = now();
+        let exact = sleep_with_slack(Duration::from_millis(10), Duration::from_millis(1));
+        assert!(exact.deadline() >= before + Duration::from_millis(10));
+        assert!(exact.deadline() <= now() + Duration::from_millis(10));
+    }
+
+    #[test]
+    fn interval_with_max_period_does_not_overflow() {
+        LocalExecutor::new().block_on(async {
+            let mut interval = interval(Duration::MAX);
+            interval.tick().await;
+            interval.reset();
+        });
+    }
+}
@@ -0,0 +1,152 @@
+//! [`PausableSleep`], a sleep whose countdown can be stopped and resumed.
+//!
+//! While paused, the remaining time is kept instead of the deadline and
+//! nothing is registered with the driver; resuming turns it back into a
+//! deadline from the driver's current time. The task that polled a paused
+//! sleep is woken on resume, so it can register the new deadline.
+
+use crate::time::{Sleep, deadline_after, sleep};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// Create a [`PausableSleep`] running for `duration`.
+pub fn sleep_pausable(duration: Duration) -> PausableSleep {
+    PausableSleep {
+        sleep: sleep(duration),
+        paused: None,
+        waker: None,
+    }
+}
+
+/// A sleep that can be paused and resumed, e.g. an idle timeout that
+/// should not run out while a dialog is open.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct PausableSleep {
+    sleep: Sleep,
+    /// Time left when paused.
+    paused: Option<Duration>,
+    /// The task that polled while paused.
+    waker: Option<Waker>,
+}
+
+impl PausableSleep {
+    /// Stop the countdown, keeping the time left. Does nothing if paused.
+    pub fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.paused = Some(self.remaining());
+
+            if let Some(key) = self.sleep.key.take() {
+                self.sleep.driver.cancel(key);
+            }
+        }
+    }
+
+    /// Continue the countdown from where it was paused. Does nothing if
+    /// running.
+    pub fn resume(&mut self) {
+        if let Some(left) = self.paused.take() {
+            let now = self.sleep.driver.now();
+            self.sleep.reset(deadline_after(now, left));
+
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Whether the countdown is stopped.
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// Time left before the sleep completes.
+    pub fn remaining(&self) -> Duration {
+        self.paused.unwrap_or_else(|| {
+            self.sleep
+                .deadline()
+                .saturating_duration_since(self.sleep.driver.now())
+        })
+    }
+
+    /// The instant the sleep completes, or `None` while paused.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.paused.is_none().then(|| self.sleep.deadline())
+    }
+
+    /// Restart the countdown with `duration` left, keeping it paused if it
+    /// is.
+    pub fn reset(&mut self, duration: Duration) {
+        match &mut self.paused {
+            Some(left) => *left = duration,
+            None => {
+                let now = self.sleep.driver.now();
+                self.sleep.reset(deadline_after(now, duration));
+            }
+        }
+    }
+}
+
+impl Future for PausableSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+
+        if this.paused.is_some() {
+            match &mut this.waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                waker => *waker = Some(cx.waker().clone()),
+            }
+
+            return Poll::Pending;
+        }
+
+        Pin::new(&mut this.sleep).poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::CountingWaker, time::timeout};
+
+    #[test]
+    fn paused_sleep_keeps_its_remaining_time() {
+        LocalExecutor::new().block_on(async {
+            let mut idle = sleep_pausable(Duration::from_millis(30));
+            idle.pause();
+            assert!(idle.deadline().is_none());
+            let left = idle.remaining();
+            assert!(left > Duration::from_millis(20));
+
+            // Would have run out by now if it were not paused.
+            assert!(timeout(Duration::from_millis(40), &mut idle).await.is_err());
+            assert_eq!(idle.remaining(), left);
+
+            idle.reset(Duration::from_millis(5));
+            idle.resume();
+            assert!(!idle.is_paused());
+            timeout(Duration::from_secs(1), idle).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn resume_wakes_a_waiting_task() {
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut idle = sleep_pausable(Duration::ZERO);
+        idle.pause();
+        assert!(Pin::new(&mut idle).poll(&mut cx).is_pending());
+
+        idle.resume();
+        assert_eq!(counter.count(), 1);
+        assert!(Pin::new(&mut idle).poll(&mut cx).is_ready());
+    }
+}
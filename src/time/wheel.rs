@@ -0,0 +1,390 @@
+//! The hierarchical timer wheel behind [`sleep`](super::sleep) and friends.
+//!
+//! Level `n` has 64 slots of `64^n` ms each, so six levels span `2^36` ms,
+//! about two years. A timer sits in the lowest level whose slot size still
+//! separates its deadline from now, and moves down a level each time the
+//! wheel reaches its slot, until it fires from level 0.
+//!
+//! Deadlines are kept in whole milliseconds and round up, so nothing fires
+//! early but a sub-millisecond sleep lasts until the next tick. A deadline
+//! past the horizon is parked in the top-level slot just before the current
+//! one and re-placed when that comes up, about once per two-year turn.
+//! Durations that would overflow [`Instant`] never reach the wheel: they
+//! are saturated to roughly 30 years out by the sleep and timeout helpers.
+
+use crate::{time::TimerDriver, utils::tracked_cell::TrackedRefCell};
+use std::{
+    collections::HashMap,
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+/// Bits of the tick counter covered by one level.
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+const LEVELS: usize = 6;
+
+/// Ticks covered by one slot of the top level.
+const TOP_SLOT_TICKS: u64 = 1 << (LEVEL_BITS * (LEVELS as u32 - 1));
+
+/// Ticks covered by one turn of the top level.
+const WHEEL_TICKS: u64 = 1 << (LEVEL_BITS * LEVELS as u32);
+
+/// A hierarchical timer wheel with millisecond resolution.
+///
+/// Six levels of 64 slots each cover about two years; timers further out
+/// are parked at the end of that range and re-placed from there until they
+/// are in range, and all timers cascade down as the wheel turns. The wheel
+/// does not keep time by itself: the owning loop calls
+/// [`advance`](Self::advance) with the current time, and sleeps no longer
+/// than [`next_deadline`](Self::next_deadline) in between.
+#[derive(Debug)]
+pub struct TimerWheel {
+    start: Instant,
+    inner: TrackedRefCell<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    elapsed: u64,
+    next_key: u64,
+    entries: HashMap<u64, Entry>,
+    levels: [Level; LEVELS],
+}
+
+#[derive(Debug)]
+struct Entry {
+    when: u64,
+    waker: Waker,
+}
+
+#[derive(Debug)]
+struct Level {
+    occupied: u64,
+    // Cancelled timers are removed from `entries` only; their keys are
+    // skipped when the slot is drained.
+    slots: [Vec<u64>; SLOTS],
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            occupied: 0,
+            slots: std::array::from_fn(|_| Vec::new()),
+        }
+    }
+}
+
+impl Default for TimerWheel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimerWheel {
+    /// Create an empty wheel starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            inner: TrackedRefCell::new(Inner {
+                elapsed: 0,
+                next_key: 0,
+                entries: HashMap::new(),
+                levels: Default::default(),
+            }),
+        }
+    }
+
+    /// Wake every timer whose deadline is at or before `now`.
+    ///
+    /// Returns the number of timers fired.
+    pub fn advance(&self, now: Instant) -> usize {
+        let target = self.floor_ticks(now);
+        let mut fired = Vec::new();
+
+        {
+            let mut inner = self.inner.borrow_mut();
+
+            while let Some((level, slot, tick)) = inner.next_expiration() {
+                if tick > target {
+                    break;
+                }
+
+                inner.elapsed = tick;
+                inner.levels[level].occupied &= !(1 << slot);
+
+                for key in std::mem::take(&mut inner.levels[level].slots[slot]) {
+                    let Some(entry) = inner.entries.get(&key) else {
+                        continue;
+                    };
+
+                    if entry.when <= tick {
+                        fired.extend(inner.entries.remove(&key).map(|e| e.waker));
+                    } else {
+                        let when = entry.when;
+                        inner.place(key, when);
+                    }
+                }
+            }
+
+            inner.elapsed = inner.elapsed.max(target);
+        }
+
+        let count = fired.len();
+
+        for waker in fired {
+            waker.wake();
+        }
+
+        count
+    }
+
+    /// The earliest time at which a timer may fire, if any are pending.
+    ///
+    /// This can be earlier than the actual deadline of any timer while
+    /// far-off timers are still cascading down; advancing at that point
+    /// simply fires nothing.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let (_, _, tick) = self.inner.borrow().next_expiration()?;
+        Some(self.start + Duration::from_millis(tick))
+    }
+
+    /// Number of pending timers.
+    pub fn len(&self) -> usize {
+        self.inner.borrow().entries.len()
+    }
+
+    /// Whether no timers are pending.
+    pub fn is_empty(&self) -> bool {
+        self.inner.borrow().entries.is_empty()
+    }
+
+    fn floor_ticks(&self, t: Instant) -> u64 {
+        let ms = t.saturating_duration_since(self.start).as_millis();
+        u64::try_from(ms).unwrap_or(u64::MAX)
+    }
+
+    fn ceil_ticks(&self, t: Instant) -> u64 {
+        let d = t.saturating_duration_since(self.start);
+        let ms = d.as_millis() + u128::from(!d.subsec_nanos().is_multiple_of(1_000_000));
+        u64::try_from(ms).unwrap_or(u64::MAX)
+    }
+}
+
+impl TimerDriver for TimerWheel {
+    fn register(&self, key: &mut Option<u64>, deadline: Instant, waker: &Waker) {
+        let when = self.ceil_ticks(deadline);
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(entry) = key.and_then(|k| inner.entries.get_mut(&k)) {
+            if !entry.waker.will_wake(waker) {
+                entry.waker = waker.clone();
+            }
+
+            if entry.when == when {
+                return;
+            }
+
+            // Rescheduled: drop the old registration and place a fresh one.
+            inner.entries.remove(&key.unwrap());
+        }
+
+        if when <= inner.elapsed {
+            *key = None;
+            drop(inner);
+            waker.wake_by_ref();
+            return;
+        }
+
+        inner.next_key += 1;
+        let k = inner.next_key;
+        *key = Some(k);
+
+        inner.entries.insert(
+            k,
+            Entry {
+                when,
+                waker: waker.clone(),
+            },
+        );
+        inner.place(k, when);
+    }
+
+    fn cancel(&self, key: u64) {
+        self.inner.borrow_mut().entries.remove(&key);
+    }
+}
+
+impl Inner {
+    fn place(&mut self, key: u64, when: u64) {
+        // The top level wraps, so its current slot cannot also stand for
+        // the same slot a turn later. Later deadlines are parked at the end
+        // of the slot just before it and re-placed from there on expiry.
+        let horizon = (self.elapsed & !(TOP_SLOT_TICKS - 1)) + WHEEL_TICKS - 1;
+        let when = when.min(horizon);
+        let level = level_for(self.elapsed, when);
+        let slot = slot_for(when, level);
+
+        self.levels[level].occupied |= 1 << slot;
+        self.levels[level].slots[slot].push(key);
+    }
+
+    /// The lowest-level occupied slot that comes up next, with its start tick.
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        self.levels
+            .iter()
+            .enumerate()
+            .filter_map(|(level, l)| {
+                let tick = l.next_occupied(level, self.elapsed)?;
+                Some((level, slot_for(tick, level), tick))
+            })
+            .min_by_key(|&(level, _, tick)| (tick, level))
+    }
+}
+
+impl Level {
+    fn next_occupied(&self, level: usize, now: u64) -> Option<u64> {
+        if self.occupied == 0 {
+            return None;
+        }
+
+        let shift = LEVEL_BITS * level as u32;
+        let slot_range = 1u64 << shift;
+        let level_range = slot_range << LEVEL_BITS;
+
+        let now_slot = slot_for(now, level) as u32;
+        let offset = self.occupied.rotate_right(now_slot).trailing_zeros();
+        let slot = (now_slot + offset) % SLOTS as u32;
+
+        let level_start = now & !(level_range - 1);
+        let mut tick = level_start + u64::from(slot) * slot_range;
+
+        // The top level wraps around for deadlines past its current turn.
+        if slot < now_slot {
+            tick += level_range;
+        }
+
+        Some(tick.max(now))
+    }
+}
+
+fn level_for(elapsed: u64, when: u64) -> usize {
+    let masked = (elapsed ^ when) | (SLOTS as u64 - 1);
+    let significant = 63 - masked.leading_zeros();
+    ((significant / LEVEL_BITS) as usize).min(LEVELS - 1)
+}
+
+fn slot_for(tick: u64, level: usize) -> usize {
+    ((tick >> (LEVEL_BITS * level as u32)) as usize) & (SLOTS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+
+    fn at(wheel: &TimerWheel, ms: u64) -> Instant {
+        wheel.start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn fires_at_deadline() {
+        let wheel = TimerWheel::new();
+        let waker = CountingWaker::new();
+        let mut key = None;
+
+        wheel.register(&mut key, at(&wheel, 5), &waker.waker());
+
+        assert_eq!(wheel.advance(at(&wheel, 4)), 0);
+        assert_eq!(wheel.advance(at(&wheel, 5)), 1);
+        assert_eq!(waker.count(), 1);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn cascades_through_levels() {
+        let wheel = TimerWheel::new();
+        let waker = CountingWaker::new();
+        let deadlines = [70, 5_000, 300_000, 20_000_000, 2_000_000_000];
+
+        for ms in deadlines {
+            wheel.register(&mut None, at(&wheel, ms), &waker.waker());
+        }
+
+        for (i, ms) in deadlines.into_iter().enumerate() {
+            assert_eq!(wheel.advance(at(&wheel, ms - 1)), 0, "early at {ms}");
+            assert_eq!(wheel.advance(at(&wheel, ms)), 1, "late at {ms}");
+            assert_eq!(waker.count(), i + 1);
+        }
+
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn cancelled_timer_does_not_fire() {
+        let wheel = TimerWheel::new();
+        let waker = CountingWaker::new();
+        let mut key = None;
+
+        wheel.register(&mut key, at(&wheel, 10), &waker.waker());
+        wheel.cancel(key.unwrap());
+
+        assert_eq!(wheel.advance(at(&wheel, 20)), 0);
+        assert_eq!(waker.count(), 0);
+    }
+
+    #[test]
+    fn far_future_deadline_does_not_hang() {
+        let wheel = TimerWheel::new();
+        let waker = CountingWaker::new();
+
+        wheel.advance(at(&wheel, 5));
+        let three_years = Duration::from_secs(3 * 365 * 24 * 3600);
+        wheel.register(&mut None, wheel.start + three_years, &waker.waker());
+
+        assert_eq!(wheel.advance(at(&wheel, 10)), 0);
+        assert!(wheel.next_deadline().unwrap() > at(&wheel, 10));
+
+        assert_eq!(wheel.advance(wheel.start + three_years), 1);
+        assert_eq!(waker.count(), 1);
+    }
+
+    #[test]
+    fn far_future_deadline_is_parked_at_the_horizon() {
+        let wheel = TimerWheel::new();
+        let waker = CountingWaker::new();
+
+        wheel.advance(at(&wheel, 5));
+        let thirty_years = Duration::from_secs(30 * 365 * 24 * 3600);
+        wheel.register(&mut None, wheel.start + thirty_years, &waker.waker());
+
+        // The last top-level slot before the current one comes round again.
+        let parked = WHEEL_TICKS - TOP_SLOT_TICKS;
+        assert_eq!(wheel.next_deadline(), Some(at(&wheel, parked)));
+
+        // From there it is parked a turn further on instead of firing.
+        assert_eq!(wheel.advance(at(&wheel, parked)), 0);
+        assert_eq!(
+            wheel.next_deadline(),
+            Some(at(&wheel, parked + WHEEL_TICKS - TOP_SLOT_TICKS))
+        );
+        assert_eq!(waker.count(), 0);
+    }
+
+    #[test]
+    fn deadline_one_turn_ahead_in_current_slot() {
+        let wheel = TimerWheel::new();
+        let waker = CountingWaker::new();
+
+        // Late in the first top-level slot, a deadline in the same slot of
+        // the next turn is within the nominal range of the wheel.
+        let now = TOP_SLOT_TICKS - 1;
+        wheel.advance(at(&wheel, now));
+        let when = now + WHEEL_TICKS - TOP_SLOT_TICKS + 2;
+        wheel.register(&mut None, at(&wheel, when), &waker.waker());
+
+        assert_eq!(wheel.advance(at(&wheel, now + 1)), 0);
+        assert_eq!(wheel.advance(at(&wheel, when - 1)), 0);
+        assert_eq!(wheel.advance(at(&wheel, when)), 1);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::utils::tracked_cell::TrackedRefCell;
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Runs chunked maintenance work, such as cache eviction or compaction,
+/// only in the time a loop iteration has left before its deadline.
+///
+/// Each job is a closure doing one bounded chunk of work per call. The
+/// loop calls [`run_until`](Self::run_until) with its frame deadline, e.g.
+/// [`FrameClock::next_deadline`](crate::time::FrameClock::next_deadline),
+/// and jobs take turns, round-robin, for as long as the next chunk is
+/// expected to finish in time. A chunk's cost is estimated from that job's
+/// previous chunks, so one slow chunk can still overrun once; keep chunks
+/// small and even.
+#[derive(Default)]
+pub struct BackgroundScheduler {
+    jobs: TrackedRefCell<VecDeque<Job>>,
+    next_id: Cell<u64>,
+}
+
+/// Identifies a job registered with a [`BackgroundScheduler`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct JobId(u64);
+
+/// What a job reports after each chunk.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Step {
+    /// More work is left; run another chunk when there is time.
+    Continue,
+    /// The job is finished and is removed.
+    Done,
+}
+
+struct Job {
+    id: u64,
+    chunk: Box<dyn FnMut() -> Step>,
+    /// Moving average of this job's chunk durations; zero until it ran.
+    estimate: Duration,
+}
+
+impl BackgroundScheduler {
+    /// Create a scheduler with no jobs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a job; `chunk` is called once per slice of spare time until it
+    /// returns [`Step::Done`].
+    pub fn register(&self, chunk: impl FnMut() -> Step + 'static) -> JobId {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+
+        self.jobs.borrow_mut().push_back(Job {
+            id,
+            chunk: Box::new(chunk),
+            estimate: Duration::ZERO,
+        });
+
+        JobId(id)
+    }
+
+    /// Remove a job before it is done; returns whether it was registered.
+    ///
+    /// A job cannot cancel itself from within its chunk; return
+    /// [`Step::Done`] instead.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut jobs = self.jobs.borrow_mut();
+        let before = jobs.len();
+        jobs.retain(|job| job.id != id.0);
+        jobs.len() != before
+    }
+
+    /// Run chunks while the next one is expected to end by `deadline`,
+    /// returning how many ran.
+    ///
+    /// Every job is offered a turn per round; a job whose estimate does
+    /// not fit is skipped in favour of cheaper ones behind it.
+    pub fn run_until(&self, deadline: Instant) -> usize {
+        let mut ran = 0;
+        let mut skipped = 0;
+
+        // Stop once a whole round of jobs was skipped.
+        while skipped < self.len() {
+            // Taken out so the chunk can register more jobs.
+            let Some(mut job) = self.jobs.borrow_mut().pop_front() else {
+                break;
+            };
+
+            let started = Instant::now();
+
+            if started + job.estimate > deadline || started >= deadline {
+                self.jobs.borrow_mut().push_back(job);
+                skipped += 1;
+                continue;
+            }
+
+            let step = (job.chunk)();
+            let took = started.elapsed();
+            job.estimate = if job.estimate.is_zero() {
+                took
+            } else {
+                (job.estimate * 3 + took) / 4
+            };
+
+            ran += 1;
+            skipped = 0;
+
+            if step == Step::Continue {
+                self.jobs.borrow_mut().push_back(job);
+            }
+        }
+
+        ran
+    }
+
+    /// Like [`run_until`](Self::run_until), with a deadline `budget` from now.
+    pub fn run_for(&self, budget: Duration) -> usize {
+        self.run_until(Instant::now() + budget)
+    }
+
+    /// Number of jobs not yet done.
+    pub fn len(&self) -> usize {
+        self.jobs.borrow().len()
+    }
+
+    /// Whether all jobs are done.
+    pub fn is_empty(&self) -> bool {
+        self.jobs.borrow().is_empty()
+    }
+}
+
+impl fmt::Debug for BackgroundScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackgroundScheduler")
+            .field("jobs", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{rc::Rc, thread};
+
+    #[test]
+    fn jobs_take_turns_until_done() {
+        let scheduler = BackgroundScheduler::new();
+        let order = Rc::new(TrackedRefCell::new(Vec::new()));
+
+        for (name, chunks) in [("a", 2), ("b", 1)] {
+            let order = order.clone();
+            let mut left = chunks;
+
+            scheduler.register(move || {
+                order.borrow_mut().push(name);
+                left -= 1;
+                if left == 0 {
+                    Step::Done
+                } else {
+                    Step::Continue
+                }
+            });
+        }
+
+        let cancelled = scheduler.register(|| panic!("cancelled job ran"));
+        assert!(scheduler.cancel(cancelled));
+        assert!(!scheduler.cancel(cancelled));
+
+        assert_eq!(scheduler.run_for(Duration::from_secs(1)), 3);
+        assert_eq!(*order.borrow(), ["a", "b", "a"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn slow_jobs_wait_for_a_larger_budget() {
+        let scheduler = BackgroundScheduler::new();
+        let fast = Rc::new(Cell::new(0));
+
+        scheduler.register(|| {
+            thread::sleep(Duration::from_millis(20));
+            Step::Continue
+        });
+        scheduler.register({
+            let fast = fast.clone();
+            move || {
+                fast.set(fast.get() + 1);
+                if fast.get() == 50 {
+                    Step::Done
+                } else {
+                    Step::Continue
+                }
+            }
+        });
+
+        // Learns the slow job's cost on its first chunk.
+        scheduler.run_for(Duration::from_millis(1));
+        let before = fast.get();
+
+        // Too little time for the slow job, but the fast one keeps going.
+        let started = Instant::now();
+        scheduler.run_for(Duration::from_millis(5));
+        assert!(started.elapsed() < Duration::from_millis(15));
+        assert!(fast.get() > before);
+        assert_eq!(scheduler.len(), if fast.get() == 50 { 1 } else { 2 });
+    }
+}
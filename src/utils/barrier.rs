@@ -0,0 +1,168 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Lets a fixed number of tasks wait until all of them have arrived.
+///
+/// Once the `n`th task calls [`wait`](Self::wait), all `n` are released and
+/// the barrier starts over for the next generation.
+#[derive(Debug)]
+pub struct Barrier {
+    n: usize,
+    state: TrackedRefCell<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    arrived: usize,
+    generation: u64,
+    waiters: WaiterList,
+}
+
+/// Returned by [`Barrier::wait`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Whether this task was the last to arrive; exactly one per generation is.
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl Barrier {
+    /// Create a barrier releasing groups of `n` tasks; 0 behaves like 1.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n: if n == 0 { 1 } else { n },
+            state: TrackedRefCell::new(State {
+                arrived: 0,
+                generation: 0,
+                waiters: WaiterList::new(),
+            }),
+        }
+    }
+
+    /// Arrive at the barrier and wait for the rest of the group.
+    ///
+    /// Dropping the future before the group is complete withdraws the
+    /// arrival.
+    pub fn wait(&self) -> impl Future<Output = BarrierWaitResult> + '_ {
+        Wait {
+            barrier: self,
+            generation: None,
+            key: None,
+        }
+    }
+}
+
+struct Wait<'a> {
+    barrier: &'a Barrier,
+    /// The generation this task arrived in.
+    generation: Option<u64>,
+    key: Option<u64>,
+}
+
+impl Future for Wait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<BarrierWaitResult> {
+        let barrier = self.barrier;
+        let mut state = barrier.state.borrow_mut();
+
+        match self.generation {
+            Some(generation) if generation != state.generation => {
+                self.generation = None;
+                self.key = None;
+                return Poll::Ready(BarrierWaitResult { leader: false });
+            }
+            Some(_) => {}
+            None => {
+                state.arrived += 1;
+
+                if state.arrived == barrier.n {
+                    state.arrived = 0;
+                    state.generation += 1;
+                    let waiters = state.waiters.take_all();
+                    drop(state);
+
+                    for waker in waiters {
+                        waker.wake();
+                    }
+
+                    return Poll::Ready(BarrierWaitResult { leader: true });
+                }
+
+                self.generation = Some(state.generation);
+            }
+        }
+
+        state.waiters.register(&mut self.key, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        let Some(generation) = self.generation else {
+            return;
+        };
+
+        let mut state = self.barrier.state.borrow_mut();
+
+        if generation == state.generation {
+            state.arrived -= 1;
+            state.waiters.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn releases_group_with_one_leader() {
+        let barrier = Barrier::new(2);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        for _ in 0..2 {
+            let mut first = pin!(barrier.wait());
+            assert!(first.as_mut().poll(&mut cx).is_pending());
+
+            let last = pin!(barrier.wait()).as_mut().poll(&mut cx);
+            assert_eq!(last, Poll::Ready(BarrierWaitResult { leader: true }));
+            assert_eq!(
+                first.as_mut().poll(&mut cx),
+                Poll::Ready(BarrierWaitResult { leader: false })
+            );
+        }
+
+        assert_eq!(counter.count(), 2);
+    }
+
+    #[test]
+    fn dropped_wait_withdraws_arrival() {
+        let barrier = Barrier::new(2);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut gone = Box::pin(barrier.wait());
+        assert!(gone.as_mut().poll(&mut cx).is_pending());
+        drop(gone);
+
+        let mut first = pin!(barrier.wait());
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+    }
+}
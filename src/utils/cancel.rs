@@ -0,0 +1,221 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    future::Future,
+    pin::Pin,
+    rc::{Rc, Weak},
+    task::{Context, Poll},
+};
+
+/// A single-threaded token for cooperative cancellation of a tree of tasks.
+///
+/// Clones share the same state. Cancelling a token also cancels every
+/// token derived from it through [`child_token`](Self::child_token), but
+/// not the other way round.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    node: Rc<TrackedRefCell<Node>>,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    cancelled: bool,
+    waiters: WaiterList,
+    children: Vec<Weak<TrackedRefCell<Node>>>,
+    /// Keeps the path from the root alive while descendants exist, even if
+    /// the tokens in between are dropped.
+    parent: Option<Rc<TrackedRefCell<Node>>>,
+}
+
+impl CancellationToken {
+    /// Create a new, uncancelled root token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a token that is cancelled together with this one.
+    pub fn child_token(&self) -> Self {
+        let child = Self::new();
+        let mut node = self.node.borrow_mut();
+
+        if node.cancelled {
+            child.node.borrow_mut().cancelled = true;
+        } else {
+            child.node.borrow_mut().parent = Some(self.node.clone());
+            node.children.retain(|c| c.strong_count() > 0);
+            node.children.push(Rc::downgrade(&child.node));
+        }
+
+        child
+    }
+
+    /// Cancel this token and all of its descendants.
+    pub fn cancel(&self) {
+        let mut pending = vec![self.node.clone()];
+
+        while let Some(node) = pending.pop() {
+            let waiters = {
+                let mut node = node.borrow_mut();
+
+                if node.cancelled {
+                    continue;
+                }
+
+                node.cancelled = true;
+                // Cancellation is final; the path to the root is no longer needed.
+                node.parent = None;
+                pending.extend(node.children.drain(..).filter_map(|c| c.upgrade()));
+                node.waiters.take_all()
+            };
+
+            for waker in waiters {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.node.borrow().cancelled
+    }
+
+    /// Wait until the token is cancelled.
+    pub fn cancelled(&self) -> impl Future<Output = ()> + '_ {
+        Cancelled {
+            node: &self.node,
+            key: None,
+        }
+    }
+
+    /// Wrap the token in a guard that cancels it when dropped.
+    pub fn drop_guard(self) -> DropGuard {
+        DropGuard { token: Some(self) }
+    }
+}
+
+struct Cancelled<'a> {
+    node: &'a TrackedRefCell<Node>,
+    key: Option<u64>,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let node = self.node;
+        let mut node = node.borrow_mut();
+
+        if node.cancelled {
+            self.key = None;
+            return Poll::Ready(());
+        }
+
+        node.waiters.register(&mut self.key, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancelled<'_> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.node.borrow_mut().waiters.remove(self.key);
+        }
+    }
+}
+
+/// Cancels the wrapped [`CancellationToken`] when dropped.
+#[derive(Debug)]
+pub struct DropGuard {
+    token: Option<CancellationToken>,
+}
+
+impl DropGuard {
+    /// Defuse the guard, returning the token without cancelling it.
+    pub fn disarm(mut self) -> CancellationToken {
+        self.token.take().expect("token present until drop")
+    }
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(token) = &self.token {
+            token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn cancel_reaches_descendants() {
+        let root = CancellationToken::new();
+        let child = root.child_token();
+        let grandchild = child.child_token();
+
+        root.cancel();
+
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+    }
+
+    #[test]
+    fn dropped_middle_token_keeps_descendants_attached() {
+        let root = CancellationToken::new();
+        let grandchild = root.child_token().child_token();
+
+        root.cancel();
+
+        assert!(grandchild.is_cancelled());
+    }
+
+    #[test]
+    fn child_cancel_does_not_reach_parent() {
+        let root = CancellationToken::new();
+        let child = root.child_token();
+        let sibling = root.child_token();
+
+        child.cancel();
+
+        assert!(!root.is_cancelled());
+        assert!(!sibling.is_cancelled());
+    }
+
+    #[test]
+    fn child_of_cancelled_token_starts_cancelled() {
+        let root = CancellationToken::new();
+        root.cancel();
+
+        assert!(root.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn cancelled_wakes_waiter() {
+        let token = CancellationToken::new();
+        let child = token.child_token();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(child.cancelled());
+
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        token.cancel();
+
+        assert_eq!(counter.count(), 1);
+        assert!(fut.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn drop_guard_cancels_unless_disarmed() {
+        let token = CancellationToken::new();
+        drop(token.clone().drop_guard());
+        assert!(token.is_cancelled());
+
+        let token = CancellationToken::new();
+        let _ = token.clone().drop_guard().disarm();
+        assert!(!token.is_cancelled());
+    }
+}
@@ -0,0 +1,545 @@
+use crate::{
+    runtime,
+    utils::{tracked_cell::TrackedRefCell, waiters::WaiterList},
+};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    future::{Future, poll_fn},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Create an unbounded single-threaded channel.
+///
+/// The [`Sender`] can be cloned; the [`Receiver`] completes `recv` with
+/// `None` once every sender is dropped and the queue is drained.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Shared::new(None);
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// Create a bounded single-threaded channel holding at most `capacity` values.
+///
+/// [`BoundedSender::send`] suspends while the channel is full and resumes,
+/// in FIFO order, as the [`Receiver`] drains it.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn channel_with_capacity<T>(capacity: usize) -> (BoundedSender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be non-zero");
+
+    let shared = Shared::new(Some(capacity));
+
+    (
+        BoundedSender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    queue: VecDeque<T>,
+    capacity: Option<usize>,
+    recv_waker: Option<Waker>,
+    send_waiters: WaiterList,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+impl<T> Shared<T> {
+    fn new(capacity: Option<usize>) -> Rc<TrackedRefCell<Self>> {
+        Rc::new(TrackedRefCell::new(Self {
+            queue: VecDeque::new(),
+            capacity,
+            recv_waker: None,
+            send_waiters: WaiterList::default(),
+            senders: 1,
+            receiver_alive: true,
+        }))
+    }
+
+    fn has_room(&self) -> bool {
+        self.capacity.is_none_or(|cap| self.queue.len() < cap)
+    }
+
+    /// Pop the next value, handing freed capacity to the next waiting sender.
+    fn pop(&mut self) -> (Option<T>, Option<Waker>) {
+        let value = self.queue.pop_front();
+        let waker = value.as_ref().and_then(|_| self.send_waiters.pop());
+
+        (value, waker)
+    }
+}
+
+fn add_sender<T>(shared: &Rc<TrackedRefCell<Shared<T>>>) -> Rc<TrackedRefCell<Shared<T>>> {
+    shared.borrow_mut().senders += 1;
+    shared.clone()
+}
+
+fn release_sender<T>(shared: &TrackedRefCell<Shared<T>>) {
+    let waker = {
+        let mut shared = shared.borrow_mut();
+        shared.senders -= 1;
+
+        if shared.senders > 0 {
+            return;
+        }
+
+        shared.recv_waker.take()
+    };
+
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+/// The sending half of a [`channel`].
+#[derive(Debug)]
+pub struct Sender<T> {
+    shared: Rc<TrackedRefCell<Shared<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Queue a value for the receiver.
+    ///
+    /// Fails, giving the value back, if the receiver has been dropped.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let waker = {
+            let mut shared = self.shared.borrow_mut();
+
+            if !shared.receiver_alive {
+                return Err(SendError(value));
+            }
+
+            shared.queue.push_back(value);
+            shared.recv_waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    /// Whether the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.borrow().receiver_alive
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: add_sender(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        release_sender(&self.shared);
+    }
+}
+
+/// The sending half of a [`channel_with_capacity`].
+#[derive(Debug)]
+pub struct BoundedSender<T> {
+    shared: Rc<TrackedRefCell<Shared<T>>>,
+}
+
+impl<T> BoundedSender<T> {
+    /// Queue a value, waiting for capacity if the channel is full.
+    ///
+    /// Fails, giving the value back, if the receiver has been dropped.
+    pub fn send(&self, value: T) -> impl Future<Output = Result<(), SendError<T>>> + '_ {
+        SendFuture {
+            shared: &self.shared,
+            value: Some(value),
+            key: None,
+        }
+    }
+
+    /// Queue a value without waiting.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let waker = {
+            let mut shared = self.shared.borrow_mut();
+
+            if !shared.receiver_alive {
+                return Err(TrySendError::Closed(value));
+            }
+
+            if !shared.has_room() || !shared.send_waiters.is_empty() {
+                return Err(TrySendError::Full(value));
+            }
+
+            shared.queue.push_back(value);
+            shared.recv_waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    /// Whether the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.borrow().receiver_alive
+    }
+
+    /// Maximum number of queued values.
+    pub fn capacity(&self) -> usize {
+        self.shared.borrow().capacity.unwrap_or(usize::MAX)
+    }
+}
+
+impl<T> Clone for BoundedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: add_sender(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for BoundedSender<T> {
+    fn drop(&mut self) {
+        release_sender(&self.shared);
+    }
+}
+
+struct SendFuture<'a, T> {
+    shared: &'a TrackedRefCell<Shared<T>>,
+    value: Option<T>,
+    key: Option<u64>,
+}
+
+// The value is moved out by value and never pinned.
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut shared = this.shared.borrow_mut();
+
+        if !shared.receiver_alive {
+            shared.send_waiters.remove(this.key.take());
+            let value = this.value.take().expect("polled after completion");
+            return Poll::Ready(Err(SendError(value)));
+        }
+
+        // Senders that queued up earlier go first.
+        if !shared.has_room() || shared.send_waiters.has_waiters_before(this.key) {
+            shared.send_waiters.register(&mut this.key, cx.waker());
+            return Poll::Pending;
+        }
+
+        shared.send_waiters.remove(this.key.take());
+        shared
+            .queue
+            .push_back(this.value.take().expect("polled after completion"));
+
+        let recv = shared.recv_waker.take();
+        let next = if shared.has_room() {
+            shared.send_waiters.pop()
+        } else {
+            None
+        };
+
+        drop(shared);
+
+        for waker in [recv, next].into_iter().flatten() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if self.key.is_none() {
+            return;
+        }
+
+        let next = {
+            let mut shared = self.shared.borrow_mut();
+            let queued = shared.send_waiters.remove(self.key);
+
+            // A wakeup meant for us must not be lost if we are cancelled.
+            if !queued && shared.has_room() {
+                shared.send_waiters.pop()
+            } else {
+                None
+            }
+        };
+
+        if let Some(waker) = next {
+            waker.wake();
+        }
+    }
+}
+
+/// The receiving half of a [`channel`].
+#[derive(Debug)]
+pub struct Receiver<T> {
+    shared: Rc<TrackedRefCell<Shared<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next value.
+    ///
+    /// Resolves to `None` once all senders are dropped and the queue is empty.
+    pub fn recv(&mut self) -> impl Future<Output = Option<T>> + '_ {
+        poll_fn(|cx| self.poll_recv(cx))
+    }
+
+    /// Poll for the next value, registering the task to be woken if none is queued.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if runtime::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let mut shared = self.shared.borrow_mut();
+
+        if let (Some(value), waker) = shared.pop() {
+            drop(shared);
+
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+
+            return Poll::Ready(Some(value));
+        }
+
+        if shared.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        match &mut shared.recv_waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            slot => *slot = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+
+    /// Receive a value without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut shared = self.shared.borrow_mut();
+
+        match shared.pop() {
+            (Some(value), waker) => {
+                drop(shared);
+
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+
+                Ok(value)
+            }
+            (None, _) if shared.senders == 0 => Err(TryRecvError::Closed),
+            (None, _) => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Number of values currently queued.
+    pub fn len(&self) -> usize {
+        self.shared.borrow().queue.len()
+    }
+
+    /// Whether no values are currently queued.
+    pub fn is_empty(&self) -> bool {
+        self.shared.borrow().queue.is_empty()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> futures_core::Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let (queue, senders) = {
+            let mut shared = self.shared.borrow_mut();
+            shared.receiver_alive = false;
+            (
+                std::mem::take(&mut shared.queue),
+                shared.send_waiters.take_all(),
+            )
+        };
+
+        // Values are dropped outside the borrow, as their destructors may
+        // touch the channel again.
+        drop(queue);
+
+        for waker in senders {
+            waker.wake();
+        }
+    }
+}
+
+/// Error returned by [`Sender::send`] when the receiver is gone.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("channel closed")
+    }
+}
+
+impl<T: fmt::Debug> Error for SendError<T> {}
+
+/// Error returned by [`BoundedSender::try_send`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// The channel is at capacity.
+    Full(T),
+    /// The receiver has been dropped.
+    Closed(T),
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("channel full"),
+            Self::Closed(_) => f.write_str("channel closed"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> Error for TrySendError<T> {}
+
+/// Error returned by [`Receiver::try_recv`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    /// No value is queued, but senders are still alive.
+    Empty,
+    /// No value is queued and all senders are gone.
+    Closed,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("channel empty"),
+            Self::Closed => f.write_str("channel closed"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::CountingWaker};
+    use std::pin::pin;
+
+    #[test]
+    fn delivers_in_order_then_closes() {
+        LocalExecutor::new().block_on(async {
+            let (tx, mut rx) = channel();
+            let tx2 = tx.clone();
+
+            tx.send(1).unwrap();
+            tx2.send(2).unwrap();
+            drop((tx, tx2));
+
+            assert_eq!(rx.recv().await, Some(1));
+            assert_eq!(rx.recv().await, Some(2));
+            assert_eq!(rx.recv().await, None);
+        });
+    }
+
+    #[test]
+    fn send_fails_once_receiver_is_gone() {
+        let (tx, rx) = channel();
+        drop(rx);
+
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(7), Err(SendError(7)));
+    }
+
+    #[test]
+    fn bounded_send_waits_for_room() {
+        let (tx, mut rx) = channel_with_capacity(1);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        tx.try_send(1).unwrap();
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+
+        let mut send = pin!(tx.send(2));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(counter.count(), 1);
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn cancelled_send_passes_its_wakeup_on() {
+        let (tx, mut rx) = channel_with_capacity(1);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        tx.try_send(0).unwrap();
+        let mut first = Box::pin(tx.send(1));
+        let mut second = pin!(tx.send(2));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        assert_eq!(rx.try_recv(), Ok(0));
+        assert_eq!(counter.count(), 1);
+
+        // Woken for the free slot, then cancelled without sending.
+        drop(first);
+
+        assert_eq!(counter.count(), 2);
+        assert_eq!(second.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn dropping_receiver_fails_waiting_senders() {
+        let (tx, rx) = channel_with_capacity(1);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        tx.try_send(0).unwrap();
+        let mut send = pin!(tx.send(1));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+
+        drop(rx);
+
+        assert_eq!(counter.count(), 1);
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Err(SendError(1))));
+    }
+}
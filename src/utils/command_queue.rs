@@ -0,0 +1,218 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    future::{Future, poll_fn},
+    hash::Hash,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Poll, Waker},
+};
+
+/// A queue of commands posted to a loop from any thread.
+///
+/// The loop takes everything pending once per iteration with
+/// [`drain`](Self::drain), highest priority first and in posting order
+/// within a priority. Commands posted with a key replace a still-pending
+/// command with the same key, so e.g. repeated redraw requests collapse
+/// into one.
+///
+/// Clones share the same queue.
+pub struct CommandQueue<C, K = ()> {
+    inner: Arc<Mutex<Inner<C, K>>>,
+}
+
+struct Inner<C, K> {
+    next_seq: u64,
+    pending: BTreeMap<(Reverse<u8>, u64), C>,
+    keyed: HashMap<K, (Reverse<u8>, u64)>,
+    waker: Option<Waker>,
+}
+
+impl<C, K> CommandQueue<C, K>
+where
+    K: Hash + Eq,
+{
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                next_seq: 0,
+                pending: BTreeMap::new(),
+                keyed: HashMap::new(),
+                waker: None,
+            })),
+        }
+    }
+
+    /// Post `cmd` with the given priority; higher priorities drain first.
+    pub fn post(&self, priority: u8, cmd: C) {
+        self.insert(None, priority, cmd);
+    }
+
+    /// Post `cmd`, replacing any pending command posted under `key`.
+    ///
+    /// The replacement keeps the place in line of the command it replaces,
+    /// but takes the new priority.
+    pub fn post_coalesced(&self, key: K, priority: u8, cmd: C) {
+        self.insert(Some(key), priority, cmd);
+    }
+
+    /// Take every pending command, in drain order.
+    ///
+    /// Commands posted while the result is being processed are left for
+    /// the next call.
+    pub fn drain(&self) -> Vec<C> {
+        let mut inner = self.lock();
+        inner.keyed.clear();
+
+        std::mem::take(&mut inner.pending).into_values().collect()
+    }
+
+    /// Wait until at least one command is pending.
+    ///
+    /// Only the most recent waiting task is woken; the queue is meant to be
+    /// drained by a single loop.
+    pub fn ready(&self) -> impl Future<Output = ()> + '_ {
+        poll_fn(|cx| {
+            let mut inner = self.lock();
+
+            if !inner.pending.is_empty() {
+                inner.waker = None;
+                return Poll::Ready(());
+            }
+
+            inner.waker = Some(cx.waker().clone());
+
+            Poll::Pending
+        })
+    }
+
+    /// Number of pending commands.
+    pub fn len(&self) -> usize {
+        self.lock().pending.len()
+    }
+
+    /// Whether no commands are pending.
+    pub fn is_empty(&self) -> bool {
+        self.lock().pending.is_empty()
+    }
+
+    fn insert(&self, key: Option<K>, priority: u8, cmd: C) {
+        let waker = {
+            let mut inner = self.lock();
+
+            let seq = match key.as_ref().and_then(|k| inner.keyed.get(k)) {
+                Some(&slot) => {
+                    inner.pending.remove(&slot);
+                    slot.1
+                }
+                None => {
+                    inner.next_seq += 1;
+                    inner.next_seq
+                }
+            };
+
+            let slot = (Reverse(priority), seq);
+
+            if let Some(key) = key {
+                inner.keyed.insert(key, slot);
+            }
+
+            inner.pending.insert(slot, cmd);
+            inner.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner<C, K>> {
+        // Commands are plain data; a panic elsewhere leaves them usable.
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<C, K> Default for CommandQueue<C, K>
+where
+    K: Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C, K> Clone for CommandQueue<C, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C, K> fmt::Debug for CommandQueue<C, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pending = self
+            .inner
+            .lock()
+            .map_or_else(|e| e.into_inner().pending.len(), |i| i.pending.len());
+
+        f.debug_struct("CommandQueue")
+            .field("pending", &pending)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+    use std::thread;
+
+    #[test]
+    fn drains_by_priority_then_posting_order() {
+        let queue = CommandQueue::<&str>::new();
+
+        queue.post(0, "low");
+        queue.post(5, "high a");
+        queue.post(5, "high b");
+        queue.post(1, "mid");
+
+        assert_eq!(queue.drain(), ["high a", "high b", "mid", "low"]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn coalesced_posts_replace_in_place() {
+        let queue = CommandQueue::new();
+
+        queue.post_coalesced("redraw", 1, "redraw 1");
+        queue.post(1, "input");
+        queue.post_coalesced("redraw", 1, "redraw 2");
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.drain(), ["redraw 2", "input"]);
+
+        // The key is free again after a drain.
+        queue.post_coalesced("redraw", 1, "redraw 3");
+        queue.post_coalesced("redraw", 1, "redraw 4");
+        assert_eq!(queue.drain(), ["redraw 4"]);
+    }
+
+    #[test]
+    fn ready_wakes_on_post_from_another_thread() {
+        LocalExecutor::new().block_on(async {
+            let queue = CommandQueue::<u32>::new();
+            let poster = queue.clone();
+
+            let thread = thread::spawn(move || {
+                thread::sleep(std::time::Duration::from_millis(5));
+                poster.post(0, 7);
+            });
+
+            queue.ready().await;
+            assert_eq!(queue.drain(), [7]);
+            thread.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,158 @@
+use crate::reactor::AsyncFd;
+use rustix::{
+    event::{EventfdFlags, eventfd},
+    fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+};
+use std::{
+    future::poll_fn,
+    io,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+/// Wakes a task on this thread from other threads or from C code.
+///
+/// Owns an eventfd registered with the reactor. Producers write to it
+/// through an [`EventFdNotifier`], which is `Send + Sync`, or directly
+/// through [`raw_fd`](Self::raw_fd), e.g. with `eventfd_write(fd, 1)` from
+/// a C callback; the consumer awaits [`notified`](Self::notified).
+#[derive(Debug)]
+pub struct EventFdNotify {
+    fd: AsyncFd<Arc<OwnedFd>>,
+}
+
+/// The producer side of an [`EventFdNotify`], usable from any thread.
+///
+/// Keeps the eventfd open, so notifying after the consumer has been
+/// dropped is harmless.
+#[derive(Debug, Clone)]
+pub struct EventFdNotifier {
+    fd: Arc<OwnedFd>,
+}
+
+impl EventFdNotify {
+    /// Create an eventfd and register it with this thread's reactor.
+    pub fn new() -> io::Result<Self> {
+        let fd = eventfd(0, EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Self::from_fd(fd)
+    }
+
+    /// Register an existing non-blocking eventfd, e.g. one received from
+    /// another process.
+    pub fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            fd: AsyncFd::new(Arc::new(fd))?,
+        })
+    }
+
+    /// A handle for notifying from other threads.
+    pub fn notifier(&self) -> EventFdNotifier {
+        EventFdNotifier {
+            fd: self.fd.get_ref().clone(),
+        }
+    }
+
+    /// The eventfd, for producers outside Rust; valid while `self` lives.
+    pub fn raw_fd(&self) -> RawFd {
+        self.fd.get_ref().as_raw_fd()
+    }
+
+    /// Notify from the consumer's own thread.
+    pub fn notify(&self) {
+        notify(self.fd.get_ref());
+    }
+
+    /// Wait for notifications, returning how many arrived since the last call.
+    pub async fn notified(&self) -> u64 {
+        poll_fn(|cx| self.poll_notified(cx)).await
+    }
+
+    /// Poll-based [`notified`](Self::notified).
+    pub fn poll_notified(&self, cx: &mut Context<'_>) -> Poll<u64> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            let result = ready.try_io(|fd| {
+                let mut buf = [0; 8];
+
+                rustix::io::read(fd, &mut buf)
+                    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+                Ok(u64::from_ne_bytes(buf))
+            });
+
+            // Reading an eventfd only fails with EAGAIN, i.e. `Err(TryIoError)`.
+            if let Ok(Ok(count)) = result {
+                return Poll::Ready(count);
+            }
+        }
+    }
+}
+
+impl AsFd for EventFdNotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl EventFdNotifier {
+    /// Wake the consumer; notifications before it runs are counted.
+    pub fn notify_from_any_thread(&self) {
+        notify(&self.fd);
+    }
+
+    /// The eventfd; valid while this handle lives.
+    pub fn raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for EventFdNotifier {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+fn notify(fd: &OwnedFd) {
+    // Only fails if the counter would overflow, i.e. a wakeup is pending anyway.
+    let _ = rustix::io::write(fd, &1u64.to_ne_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn counts_notifications_since_last_wait() {
+        LocalExecutor::new().block_on(async {
+            let notify = EventFdNotify::new().unwrap();
+
+            notify.notify();
+            notify.notify();
+            assert_eq!(notify.notified().await, 2);
+
+            notify.notify();
+            assert_eq!(notify.notified().await, 1);
+        });
+    }
+
+    #[test]
+    fn wakes_from_another_thread() {
+        LocalExecutor::new().block_on(async {
+            let notify = EventFdNotify::new().unwrap();
+            let notifier = notify.notifier();
+
+            let thread = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(5));
+                notifier.notify_from_any_thread();
+            });
+
+            assert_eq!(notify.notified().await, 1);
+            thread.join().unwrap();
+        });
+    }
+}
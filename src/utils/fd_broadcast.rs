@@ -0,0 +1,587 @@
+use crate::utils::{
+    channel::{SendError, TrySendError},
+    tracked_cell::TrackedRefCell,
+    waiters::WaiterList,
+};
+use rustix::{fd::OwnedFd, io::fcntl_dupfd_cloexec};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// A message that can be duplicated along with the fds it owns.
+///
+/// Implement this for message types that carry [`OwnedFd`]s: each
+/// duplicate must own fresh descriptors (`dup`ed, close-on-exec), so that
+/// every copy closes its own fds when dropped.
+pub trait DupFds: Sized {
+    /// Duplicate the message and every fd in it.
+    fn dup_fds(&self) -> io::Result<Self>;
+}
+
+impl DupFds for OwnedFd {
+    fn dup_fds(&self) -> io::Result<Self> {
+        fcntl_dupfd_cloexec(self, 0).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+}
+
+impl<T: DupFds> DupFds for Option<T> {
+    fn dup_fds(&self) -> io::Result<Self> {
+        self.as_ref().map(T::dup_fds).transpose()
+    }
+}
+
+impl<T: DupFds> DupFds for Box<T> {
+    fn dup_fds(&self) -> io::Result<Self> {
+        T::dup_fds(self).map(Box::new)
+    }
+}
+
+impl<T: DupFds> DupFds for Vec<T> {
+    fn dup_fds(&self) -> io::Result<Self> {
+        self.iter().map(T::dup_fds).collect()
+    }
+}
+
+impl<A: DupFds, B: DupFds> DupFds for (A, B) {
+    fn dup_fds(&self) -> io::Result<Self> {
+        Ok((self.0.dup_fds()?, self.1.dup_fds()?))
+    }
+}
+
+impl<A: DupFds, B: DupFds, C: DupFds> DupFds for (A, B, C) {
+    fn dup_fds(&self) -> io::Result<Self> {
+        Ok((self.0.dup_fds()?, self.1.dup_fds()?, self.2.dup_fds()?))
+    }
+}
+
+/// Create a bounded single-threaded broadcast channel for fd-carrying messages.
+///
+/// Every [`Receiver`] sees every message sent after it was created. Each
+/// message is stored once; receivers get their own [`DupFds::dup_fds`]
+/// copy, except the last one to receive it, which takes the original. A
+/// message's fds are therefore closed exactly when every receiver has
+/// dropped its copy.
+///
+/// [`Sender::send`] suspends while the slowest receiver lags `capacity`
+/// messages behind.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn channel<T: DupFds>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be non-zero");
+
+    let shared = Rc::new(TrackedRefCell::new(Shared {
+        slots: VecDeque::new(),
+        head: 0,
+        capacity,
+        receivers: 1,
+        senders: 1,
+        recv_waiters: WaiterList::new(),
+        send_waiters: WaiterList::new(),
+    }));
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            next: 0,
+            key: None,
+        },
+    )
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    slots: VecDeque<Slot<T>>,
+    /// Sequence number of `slots[0]`.
+    head: u64,
+    capacity: usize,
+    receivers: usize,
+    senders: usize,
+    recv_waiters: WaiterList,
+    send_waiters: WaiterList,
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    value: Option<T>,
+    /// Receivers that have yet to take this message.
+    remaining: usize,
+}
+
+impl<T> Shared<T> {
+    fn tail(&self) -> u64 {
+        self.head + self.slots.len() as u64
+    }
+
+    fn has_room(&self) -> bool {
+        self.slots.len() < self.capacity
+    }
+
+    /// Mark the messages from `from` on as no longer needed by one receiver.
+    ///
+    /// Returns the fully consumed messages, to be dropped outside the borrow.
+    fn release(&mut self, from: u64) -> Vec<T> {
+        let start = (from - self.head) as usize;
+
+        for slot in self.slots.range_mut(start..) {
+            slot.remaining -= 1;
+        }
+
+        self.pop_consumed()
+    }
+
+    /// Mark the message `seq` as taken by one receiver.
+    fn release_one(&mut self, seq: u64) -> Vec<T> {
+        let index = (seq - self.head) as usize;
+        self.slots[index].remaining -= 1;
+        self.pop_consumed()
+    }
+
+    fn pop_consumed(&mut self) -> Vec<T> {
+        let mut freed = Vec::new();
+
+        while self.slots.front().is_some_and(|s| s.remaining == 0) {
+            let slot = self.slots.pop_front().expect("front checked");
+            freed.extend(slot.value);
+            self.head += 1;
+        }
+
+        freed
+    }
+
+    fn wake_sender(&mut self) -> Option<Waker> {
+        if self.has_room() {
+            self.send_waiters.pop()
+        } else {
+            None
+        }
+    }
+}
+
+/// The sending half of a broadcast [`channel`].
+#[derive(Debug)]
+pub struct Sender<T> {
+    shared: Rc<TrackedRefCell<Shared<T>>>,
+}
+
+impl<T: DupFds> Sender<T> {
+    /// Broadcast a message, waiting for room if a receiver lags behind.
+    ///
+    /// Fails, giving the message back, if there are no receivers.
+    pub fn send(&self, value: T) -> impl Future<Output = Result<(), SendError<T>>> + '_ {
+        SendFuture {
+            shared: &self.shared,
+            value: Some(value),
+            key: None,
+        }
+    }
+
+    /// Broadcast a message without waiting.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let waiters = {
+            let mut shared = self.shared.borrow_mut();
+
+            if shared.receivers == 0 {
+                return Err(TrySendError::Closed(value));
+            }
+
+            if !shared.has_room() || !shared.send_waiters.is_empty() {
+                return Err(TrySendError::Full(value));
+            }
+
+            push(&mut shared, value)
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    /// Create a receiver that sees messages sent from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut shared = self.shared.borrow_mut();
+        shared.receivers += 1;
+
+        Receiver {
+            shared: self.shared.clone(),
+            next: shared.tail(),
+            key: None,
+        }
+    }
+
+    /// Number of live receivers.
+    pub fn receiver_count(&self) -> usize {
+        self.shared.borrow().receivers
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.borrow_mut().senders += 1;
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut shared = self.shared.borrow_mut();
+            shared.senders -= 1;
+
+            if shared.senders > 0 {
+                return;
+            }
+
+            shared.recv_waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+fn push<T>(shared: &mut Shared<T>, value: T) -> impl Iterator<Item = Waker> + use<T> {
+    let remaining = shared.receivers;
+
+    shared.slots.push_back(Slot {
+        value: Some(value),
+        remaining,
+    });
+
+    let next = shared.wake_sender();
+    shared.recv_waiters.take_all().chain(next)
+}
+
+struct SendFuture<'a, T> {
+    shared: &'a TrackedRefCell<Shared<T>>,
+    value: Option<T>,
+    key: Option<u64>,
+}
+
+// The value is moved out by value and never pinned.
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut shared = this.shared.borrow_mut();
+
+        if shared.receivers == 0 {
+            shared.send_waiters.remove(this.key.take());
+            let value = this.value.take().expect("polled after completion");
+            return Poll::Ready(Err(SendError(value)));
+        }
+
+        // Senders that queued up earlier go first.
+        if !shared.has_room() || shared.send_waiters.has_waiters_before(this.key) {
+            shared.send_waiters.register(&mut this.key, cx.waker());
+            return Poll::Pending;
+        }
+
+        shared.send_waiters.remove(this.key.take());
+        let value = this.value.take().expect("polled after completion");
+        let waiters = push(&mut shared, value);
+
+        drop(shared);
+
+        for waker in waiters {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if self.key.is_none() {
+            return;
+        }
+
+        let next = {
+            let mut shared = self.shared.borrow_mut();
+
+            // A wakeup meant for us must not be lost if we are cancelled.
+            if shared.send_waiters.remove(self.key) {
+                None
+            } else {
+                shared.wake_sender()
+            }
+        };
+
+        if let Some(waker) = next {
+            waker.wake();
+        }
+    }
+}
+
+/// The receiving half of a broadcast [`channel`].
+///
+/// Cloning a receiver yields one at the same position in the stream.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    shared: Rc<TrackedRefCell<Shared<T>>>,
+    next: u64,
+    /// Registration of [`poll_recv`](Self::poll_recv).
+    key: Option<u64>,
+}
+
+impl<T: DupFds> Receiver<T> {
+    /// Receive the next message.
+    ///
+    /// Resolves to `Ok(None)` once all senders are dropped and every
+    /// message has been received. If duplicating the message's fds fails,
+    /// the error is returned and the message stays queued for a retry.
+    pub fn recv(&mut self) -> impl Future<Output = io::Result<Option<T>>> + '_ {
+        Recv {
+            rx: self,
+            key: None,
+        }
+    }
+
+    /// Poll-based [`recv`](Self::recv).
+    ///
+    /// Only the waker of the latest call is kept.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<T>>> {
+        let mut key = self.key.take();
+        let result = self.poll_recv_with(&mut key, cx);
+        self.key = key;
+        result
+    }
+
+    fn poll_recv_with(
+        &mut self,
+        key: &mut Option<u64>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<Option<T>>> {
+        let mut shared = self.shared.borrow_mut();
+
+        if self.next == shared.tail() {
+            if shared.senders == 0 {
+                shared.recv_waiters.remove(key.take());
+                return Poll::Ready(Ok(None));
+            }
+
+            shared.recv_waiters.register(key, cx.waker());
+            return Poll::Pending;
+        }
+
+        shared.recv_waiters.remove(key.take());
+        drop(shared);
+
+        Poll::Ready(self.take().map(Some))
+    }
+
+    /// Receive a message without waiting.
+    ///
+    /// Returns `Ok(None)` if no message is queued for this receiver.
+    pub fn try_recv(&mut self) -> io::Result<Option<T>> {
+        if self.next == self.shared.borrow().tail() {
+            return Ok(None);
+        }
+
+        self.take().map(Some)
+    }
+
+    /// Whether all senders are gone and every message has been received.
+    pub fn is_closed(&self) -> bool {
+        let shared = self.shared.borrow();
+        shared.senders == 0 && self.next == shared.tail()
+    }
+
+    /// Number of messages queued for this receiver.
+    pub fn len(&self) -> usize {
+        (self.shared.borrow().tail() - self.next) as usize
+    }
+
+    /// Whether no messages are queued for this receiver.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Take the message at `self.next`, which must exist.
+    fn take(&mut self) -> io::Result<T> {
+        let (value, freed, next) = {
+            let mut shared = self.shared.borrow_mut();
+            let index = (self.next - shared.head) as usize;
+            let slot = &mut shared.slots[index];
+
+            let value = if slot.remaining == 1 {
+                slot.value.take().expect("taken by last receiver only")
+            } else {
+                slot.value.as_ref().expect("present").dup_fds()?
+            };
+
+            let freed = shared.release_one(self.next);
+            self.next += 1;
+
+            (value, freed, shared.wake_sender())
+        };
+
+        drop(freed);
+
+        if let Some(waker) = next {
+            waker.wake();
+        }
+
+        Ok(value)
+    }
+}
+
+struct Recv<'a, T> {
+    rx: &'a mut Receiver<T>,
+    key: Option<u64>,
+}
+
+impl<T: DupFds> Future for Recv<'_, T> {
+    type Output = io::Result<Option<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.rx.poll_recv_with(&mut this.key, cx)
+    }
+}
+
+impl<T> Drop for Recv<'_, T> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.rx.shared.borrow_mut().recv_waiters.remove(self.key);
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut shared = self.shared.borrow_mut();
+        shared.receivers += 1;
+
+        let start = (self.next - shared.head) as usize;
+
+        for slot in shared.slots.range_mut(start..) {
+            slot.remaining += 1;
+        }
+
+        Self {
+            shared: self.shared.clone(),
+            next: self.next,
+            key: None,
+        }
+    }
+}
+
+/// Yields each message, or the error of a failed duplication, ending once
+/// all senders are dropped.
+#[cfg(feature = "futures")]
+impl<T: DupFds> futures_core::Stream for Receiver<T> {
+    type Item = io::Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx).map(Result::transpose)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let (freed, senders) = {
+            let mut shared = self.shared.borrow_mut();
+            shared.receivers -= 1;
+            shared.recv_waiters.remove(self.key);
+
+            let freed = shared.release(self.next);
+
+            // Without receivers, every waiting send fails.
+            let senders: Vec<_> = if shared.receivers == 0 {
+                shared.send_waiters.take_all().collect()
+            } else {
+                shared.wake_sender().into_iter().collect()
+            };
+
+            (freed, senders)
+        };
+
+        // Messages are dropped outside the borrow, as their destructors may
+        // touch the channel again.
+        drop(freed);
+
+        for waker in senders {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustix::{fd::AsRawFd, pipe::pipe};
+
+    #[test]
+    fn every_receiver_gets_its_own_fd() {
+        let (tx, mut a) = channel(4);
+        let mut b = tx.subscribe();
+        let (reader, writer) = pipe().unwrap();
+
+        tx.try_send(writer).unwrap();
+        let wa = a.try_recv().unwrap().unwrap();
+        let wb = b.try_recv().unwrap().unwrap();
+        assert_ne!(wa.as_raw_fd(), wb.as_raw_fd());
+
+        rustix::io::write(&wa, b"a").unwrap();
+        rustix::io::write(&wb, b"b").unwrap();
+
+        // Once every copy is gone, the write end is closed.
+        drop((wa, wb));
+        let mut buf = [0; 4];
+        assert_eq!(rustix::io::read(&reader, &mut buf), Ok(2));
+        assert_eq!(rustix::io::read(&reader, &mut buf), Ok(0));
+    }
+
+    #[test]
+    fn lagging_receiver_holds_back_senders() {
+        let (tx, mut fast) = channel(1);
+        let slow = tx.subscribe();
+        let (_, fd) = pipe().unwrap();
+
+        tx.try_send(fd).unwrap();
+        assert!(fast.try_recv().unwrap().is_some());
+
+        let (_, fd) = pipe().unwrap();
+        let fd = match tx.try_send(fd) {
+            Err(TrySendError::Full(fd)) => fd,
+            other => panic!("expected a full channel, got {other:?}"),
+        };
+
+        drop(slow);
+        tx.try_send(fd).unwrap();
+        assert!(fast.try_recv().unwrap().is_some());
+    }
+
+    #[test]
+    fn closes_once_senders_are_gone() {
+        let (tx, mut rx) = channel::<OwnedFd>(1);
+        let (_, fd) = pipe().unwrap();
+
+        tx.try_send(fd).unwrap();
+        drop(tx);
+
+        assert!(!rx.is_closed());
+        assert!(rx.try_recv().unwrap().is_some());
+        assert!(rx.is_closed());
+    }
+}
@@ -1,13 +1,40 @@
+use crate::{
+    fs::{EventKind, Watcher},
+    time::{self, sleep},
+    utils::{tracked_cell::TrackedRefCell, waiters::WaiterList},
+};
 use rustix::{
     fd::{AsFd, OwnedFd},
     fs::{self, FlockOperation, Mode, OFlags},
+    io::{Errno, pwrite},
+    mm::{self, MapFlags, ProtFlags},
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+    fs::File,
+    future::{Future, poll_fn},
+    io,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    pin::Pin,
+    ptr::{self, NonNull},
+    rc::Rc,
+    slice,
+    task::{Context, Poll, ready},
+    thread,
+    time::{Duration, Instant},
 };
-use std::{io, path::Path};
+
+/// Upper bound for the retry interval of [`Flock::lock_timeout`],
+/// [`Flock::lock_async`] and [`LockDir::lock_wait`].
+const MAX_BACKOFF: Duration = Duration::from_millis(50);
 
 /// A RAII file lock.
 #[derive(Debug)]
 pub struct Flock {
     fd: OwnedFd,
+    poison: Option<PathBuf>,
 }
 
 impl Flock {
@@ -16,23 +43,1063 @@ impl Flock {
     /// If the file does not exist, it will be created.
     /// The lock is released when the returned [`Flock`] is dropped.
     pub fn lock(path: &Path) -> io::Result<Self> {
-        let fd = fs::openat(
-            fs::CWD,
+        Self::lock_with(
             path,
-            OFlags::CREATE | OFlags::WRONLY,
-            Mode::RUSR | Mode::WUSR,
+            OFlags::WRONLY,
+            FlockOperation::NonBlockingLockExclusive,
         )
+    }
+
+    /// Acquire a shared lock on a file.
+    ///
+    /// Any number of shared locks can be held at once, but not together
+    /// with an exclusive one. If the file does not exist, it will be created.
+    pub fn lock_shared(path: &Path) -> io::Result<Self> {
+        Self::lock_with(path, OFlags::RDONLY, FlockOperation::NonBlockingLockShared)
+    }
+
+    /// Acquire an exclusive lock on a file, waiting until it is available.
+    ///
+    /// Blocks the thread; from async code use [`Flock::lock_async`].
+    pub fn lock_blocking(path: &Path) -> io::Result<Self> {
+        loop {
+            let lock = Self {
+                fd: open(path, OFlags::WRONLY)?,
+                poison: None,
+            };
+
+            lock.relock(FlockOperation::LockExclusive)?;
+
+            if is_current(&lock.fd, path)? {
+                return Ok(lock);
+            }
+        }
+    }
+
+    /// Acquire an exclusive lock on a file, waiting at most `timeout`.
+    ///
+    /// Fails with [`io::ErrorKind::TimedOut`] if the lock is still held
+    /// when the timeout expires. Blocks the thread; from async code use
+    /// [`Flock::lock_timeout_async`].
+    pub fn lock_timeout(path: &Path, timeout: Duration) -> io::Result<Self> {
+        let mut fd = open(path, OFlags::WRONLY)?;
+        let deadline = Instant::now() + timeout;
+        let mut backoff = Duration::from_millis(1);
+
+        loop {
+            match fs::flock(fd.as_fd(), FlockOperation::NonBlockingLockExclusive) {
+                Ok(()) if is_current(&fd, path)? => return Ok(Self { fd, poison: None }),
+                Ok(()) => {
+                    fd = open(path, OFlags::WRONLY)?;
+                    continue;
+                }
+                Err(Errno::WOULDBLOCK | Errno::INTR) => {}
+                Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Timed out waiting for lock",
+                ));
+            }
+
+            thread::sleep(backoff.min(deadline - now));
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Watch for other holders releasing their lock on `path`, so a standby
+    /// can take over as soon as they do instead of polling.
+    ///
+    /// The directory holding `path` is watched through inotify. When the
+    /// lock file is closed after writing, as when an exclusive [`Flock`] is
+    /// dropped or its process exits, or when it is deleted, `/proc/locks`
+    /// is checked and a release is reported if no `flock` is left on it.
+    /// Holders that opened the file read-only, such as shared locks, close
+    /// it silently, so their release is only noticed with the next event.
+    pub fn contention_events(path: &Path) -> io::Result<Contention> {
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Lock path has no file name")
+        })?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut watcher = Watcher::new()?;
+        watcher.watch(dir)?;
+
+        Ok(Contention {
+            watcher,
+            path: dir.join(name),
+        })
+    }
+
+    /// Acquire an exclusive lock on a file, waiting asynchronously until it is available.
+    ///
+    /// `flock(2)` cannot wait without blocking the thread, so the lock is
+    /// retried with backoff on the thread's timer, as in
+    /// [`Flock::lock_timeout`], and other tasks keep running meanwhile.
+    pub async fn lock_async(path: &Path) -> io::Result<Self> {
+        Self::lock_retrying(path, None).await
+    }
+
+    /// Acquire an exclusive lock on a file, waiting asynchronously at most `timeout`.
+    ///
+    /// Fails with [`io::ErrorKind::TimedOut`] if the lock is still held
+    /// when the timeout expires.
+    pub async fn lock_timeout_async(path: &Path, timeout: Duration) -> io::Result<Self> {
+        Self::lock_retrying(path, Some(timeout)).await
+    }
+
+    async fn lock_retrying(path: &Path, timeout: Option<Duration>) -> io::Result<Self> {
+        let deadline = timeout.map(|t| time::deadline_after(time::now(), t));
+        let mut backoff = Duration::from_millis(1);
+
+        loop {
+            match Self::lock(path) {
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => {}
+                result => return result,
+            }
+
+            let mut wait = backoff;
+
+            if let Some(deadline) = deadline {
+                let now = time::now();
+                if now >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Timed out waiting for lock",
+                    ));
+                }
+
+                wait = wait.min(deadline - now);
+            }
+
+            sleep(wait).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Acquire an exclusive lock on a PID file and write the current PID to it.
+    ///
+    /// If the lock is already held, the error names the holder's PID.
+    pub fn lock_pidfile(path: &Path) -> io::Result<Self> {
+        let lock = match Self::lock(path) {
+            Ok(lock) => lock,
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                return Err(match Self::try_read_holder(path) {
+                    Ok(Some(pid)) => io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("Already running as pid {pid}"),
+                    ),
+                    _ => e,
+                });
+            }
+            Err(e) => return Err(e),
+        };
+
+        let pid = format!("{}\n", std::process::id());
+
+        // Written before truncating, so the file is never seen empty.
+        pwrite(&lock.fd, pid.as_bytes(), 0)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        fs::ftruncate(&lock.fd, pid.len() as u64)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(lock)
+    }
+
+    /// Read the PID of the process holding the lock on a PID file.
+    ///
+    /// Returns `None` if the file does not exist or nobody holds the lock.
+    /// The lock is looked up in `/proc/locks` rather than probed, so this
+    /// never makes a concurrent locker fail. If the holder has not written
+    /// its PID yet, or the file is a plain lock file, the PID the kernel
+    /// reports for the lock is returned.
+    pub fn try_read_holder(path: &Path) -> io::Result<Option<u32>> {
+        let stat = match fs::stat(path) {
+            Ok(stat) => stat,
+            Err(Errno::NOENT) => return Ok(None),
+            Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        };
+
+        let Some(lock) = locks_on(&stat)?
+            .into_iter()
+            .find(|lock| lock.kind == LockKind::Flock)
+        else {
+            return Ok(None);
+        };
+
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        match contents.trim().parse() {
+            Ok(pid) => Ok(Some(pid)),
+            Err(_) => u32::try_from(lock.pid)
+                .ok()
+                .filter(|&pid| pid > 0)
+                .map(Some)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "PID file holds no PID")),
+        }
+    }
+
+    /// Acquire an exclusive lock on a file that is poisoned on panic.
+    ///
+    /// If the thread panics while the returned [`Flock`] is held, a
+    /// `<path>.poisoned` marker is written before the lock is released.
+    /// Later holders see it through [`Flock::is_poisoned`] until it is
+    /// cleared with [`Flock::clear_poison`].
+    pub fn lock_poisonable(path: &Path) -> io::Result<Self> {
+        let mut lock = Self::lock(path)?;
+
+        let mut marker = path.as_os_str().to_owned();
+        marker.push(".poisoned");
+        lock.poison = Some(marker.into());
+
+        Ok(lock)
+    }
+
+    /// Whether a previous holder panicked while holding the lock.
+    ///
+    /// Always `false` for locks not acquired with [`Flock::lock_poisonable`].
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.as_deref().is_some_and(Path::exists)
+    }
+
+    /// Remove the poison marker, typically after recovering the protected file.
+    pub fn clear_poison(&self) -> io::Result<()> {
+        match self.poison.as_deref().map(std::fs::remove_file) {
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Acquire an exclusive lock on a file and map its first `len` bytes.
+    ///
+    /// The file is created and extended to `len` bytes as needed.
+    /// The mapping is removed before the lock is released on drop.
+    pub fn lock_and_map(path: &Path, len: usize) -> io::Result<MappedFlock> {
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot map an empty region",
+            ));
+        }
+
+        let lock = Self::lock_with(path, OFlags::RDWR, FlockOperation::NonBlockingLockExclusive)?;
+
+        let stat =
+            fs::fstat(&lock.fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        if (stat.st_size as u64) < len as u64 {
+            fs::ftruncate(&lock.fd, len as u64)
+                .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+        }
+
+        // SAFETY: A fresh shared mapping is created; no existing memory is
+        // affected.
+        let ptr = unsafe {
+            mm::mmap(
+                ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED,
+                &lock.fd,
+                0,
+            )
+        }
         .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
 
-        fs::flock(fd.as_fd(), FlockOperation::NonBlockingLockExclusive)
-            .map_err(|_| io::Error::new(io::ErrorKind::AddrInUse, "Lock already held"))?;
+        Ok(MappedFlock {
+            ptr: NonNull::new(ptr.cast()).expect("mmap returned null"),
+            len,
+            _lock: lock,
+        })
+    }
+
+    /// Convert a shared lock into an exclusive one without waiting.
+    ///
+    /// As with `flock(2)`, the conversion is not atomic: if another process
+    /// also holds a shared lock, the shared lock is briefly released and
+    /// re-acquired, and the lock is handed back with an error of kind
+    /// [`io::ErrorKind::AddrInUse`].
+    ///
+    /// If another process takes an exclusive lock in that window, the
+    /// shared lock cannot be re-acquired without waiting. The lock is then
+    /// gone, so no `Flock` comes back with the error, which is of kind
+    /// [`io::ErrorKind::Other`].
+    pub fn upgrade(self) -> Result<Flock, (Option<Flock>, io::Error)> {
+        let err = match fs::flock(self.fd.as_fd(), FlockOperation::NonBlockingLockExclusive) {
+            Ok(()) => return Ok(self),
+            Err(e) => lock_error(e),
+        };
 
-        Ok(Self { fd })
+        match fs::flock(self.fd.as_fd(), FlockOperation::NonBlockingLockShared) {
+            Ok(()) => Err((Some(self), err)),
+            Err(Errno::WOULDBLOCK) => Err((None, io::Error::other("Lock lost while upgrading"))),
+            Err(e) => Err((None, io::Error::from_raw_os_error(e.raw_os_error()))),
+        }
+    }
+
+    /// Convert an exclusive lock into a shared one.
+    pub fn downgrade(&mut self) -> io::Result<()> {
+        self.relock(FlockOperation::LockShared)
+    }
+
+    fn relock(&self, op: FlockOperation) -> io::Result<()> {
+        loop {
+            match fs::flock(self.fd.as_fd(), op) {
+                Ok(()) => return Ok(()),
+                Err(Errno::INTR) => continue,
+                Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+            }
+        }
+    }
+
+    fn lock_with(path: &Path, access: OFlags, op: FlockOperation) -> io::Result<Self> {
+        loop {
+            let fd = open(path, access)?;
+
+            fs::flock(fd.as_fd(), op).map_err(lock_error)?;
+
+            if is_current(&fd, path)? {
+                return Ok(Self { fd, poison: None });
+            }
+        }
     }
 }
 
 impl Drop for Flock {
     fn drop(&mut self) {
+        if let Some(marker) = &self.poison
+            && thread::panicking()
+        {
+            let _ = File::create(marker);
+        }
+
         let _ = fs::flock(self.fd.as_fd(), FlockOperation::Unlock);
     }
 }
+
+/// Reports releases of a contended lock; see [`Flock::contention_events`].
+#[derive(Debug)]
+pub struct Contention {
+    watcher: Watcher,
+    path: PathBuf,
+}
+
+impl Contention {
+    /// Wait until the lock is seen to be released.
+    pub async fn next_release(&mut self) -> io::Result<()> {
+        poll_fn(|cx| self.poll_next_release(cx)).await
+    }
+
+    /// Poll-based [`next_release`](Self::next_release).
+    pub fn poll_next_release(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let event = ready!(self.watcher.poll_next_event(cx))?;
+
+            match event.kind {
+                EventKind::CloseWrite | EventKind::Delete | EventKind::MovedFrom { .. }
+                    if event.path == self.path => {}
+                EventKind::Overflow => {}
+                EventKind::Unwatched => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "Lock directory is gone",
+                    )));
+                }
+                _ => continue,
+            }
+
+            if self.is_free()? {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+
+    /// Take the lock, waiting for releases while it is held elsewhere.
+    pub async fn acquire(&mut self) -> io::Result<Flock> {
+        loop {
+            match Flock::lock(&self.path) {
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => self.next_release().await?,
+                result => return result,
+            }
+        }
+    }
+
+    /// The watched lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether no `flock` is held on the lock file, checked without taking
+    /// one, which would itself stand in a contender's way.
+    fn is_free(&self) -> io::Result<bool> {
+        match fs::stat(&self.path) {
+            Ok(stat) => Ok(!locks_on(&stat)?
+                .iter()
+                .any(|lock| lock.kind == LockKind::Flock)),
+            Err(Errno::NOENT) => Ok(true),
+            Err(e) => Err(io::Error::from_raw_os_error(e.raw_os_error())),
+        }
+    }
+}
+
+/// Yields each release; ends only with the watched directory.
+#[cfg(feature = "futures")]
+impl futures_core::Stream for Contention {
+    type Item = io::Result<()>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_release(cx).map(Some)
+    }
+}
+
+/// An exclusive [`Flock`] together with a shared mapping of the locked file.
+///
+/// The mapping is only valid as long as other processes honour the lock;
+/// truncating the file behind its back results in `SIGBUS` on access.
+#[derive(Debug)]
+pub struct MappedFlock {
+    ptr: NonNull<u8>,
+    len: usize,
+    _lock: Flock,
+}
+
+impl Deref for MappedFlock {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `ptr` points to a live mapping of `len` bytes.
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for MappedFlock {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: `ptr` points to a live, writable mapping of `len` bytes,
+        // and the exclusive lock keeps cooperating writers out.
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for MappedFlock {
+    fn drop(&mut self) {
+        // SAFETY: The mapping was created in `lock_and_map` and no borrows
+        // of it outlive `self`. The lock is released afterwards, when
+        // `_lock` is dropped.
+        let _ = unsafe { mm::munmap(self.ptr.as_ptr().cast(), self.len) };
+    }
+}
+
+/// Exclusive locks on string keys, one lock file per key in a directory.
+///
+/// Each key maps to `<key>.lock` in the directory, with bytes other than
+/// ASCII alphanumerics, `-` and `_` (and a leading `.`) percent-encoded, so
+/// any key yields a single plain file name. A lock file is unlinked when
+/// its lock is released, while still locked, so the directory only holds
+/// files for keys in use; as with [`collect_stale`](crate::utils::lock_gc::collect_stale),
+/// a racing locker notices and moves to a fresh file.
+///
+/// Waiting is tracked per key: [`lock_wait`](Self::lock_wait) wakes as
+/// soon as a lock held through the same `LockDir` is released, and retries
+/// with backoff while another process holds it. Clones share the registry.
+#[derive(Debug, Clone)]
+pub struct LockDir {
+    dir: PathBuf,
+    shared: Rc<TrackedRefCell<Registry>>,
+}
+
+/// A lock on one key of a [`LockDir`], released when dropped.
+#[derive(Debug)]
+pub struct KeyLock {
+    key: String,
+    path: PathBuf,
+    lock: Option<Flock>,
+    shared: Rc<TrackedRefCell<Registry>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    held: BTreeSet<String>,
+    /// Waiters per key, with the generation of their list. A list is torn
+    /// down when its key is released, and its successor restarts its keys,
+    /// so waiters only trust their key while the generation matches.
+    waiters: HashMap<String, (u64, WaiterList)>,
+    next_generation: u64,
+}
+
+impl LockDir {
+    /// Manage locks in `dir`, creating it if needed.
+    pub fn new(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            shared: Rc::default(),
+        })
+    }
+
+    /// The managed directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The lock file used for `key`.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `key` is empty.
+    pub fn path_for(&self, key: &str) -> io::Result<PathBuf> {
+        if key.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Lock key must not be empty",
+            ));
+        }
+
+        let mut name = String::with_capacity(key.len() + 5);
+
+        for (i, b) in key.bytes().enumerate() {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || (b == b'.' && i > 0) {
+                name.push(b as char);
+            } else {
+                let _ = write!(name, "%{b:02X}");
+            }
+        }
+
+        name.push_str(".lock");
+        Ok(self.dir.join(name))
+    }
+
+    /// Lock `key`, blocking the thread until it is available.
+    ///
+    /// Fails with [`io::ErrorKind::Deadlock`] if `key` is already held
+    /// through this `LockDir`, which would otherwise wait forever.
+    pub fn lock(&self, key: &str) -> io::Result<KeyLock> {
+        if self.shared.borrow().held.contains(key) {
+            return Err(io::Error::new(
+                io::ErrorKind::Deadlock,
+                "Lock already held by this process",
+            ));
+        }
+
+        let path = self.path_for(key)?;
+        let lock = Flock::lock_blocking(&path)?;
+
+        Ok(self.held(key, path, lock))
+    }
+
+    /// Lock `key` without waiting.
+    ///
+    /// Fails with [`io::ErrorKind::AddrInUse`] if it is already held.
+    pub fn try_lock(&self, key: &str) -> io::Result<KeyLock> {
+        let path = self.path_for(key)?;
+
+        if self.shared.borrow().held.contains(key) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "Lock already held",
+            ));
+        }
+
+        let lock = Flock::lock(&path)?;
+
+        Ok(self.held(key, path, lock))
+    }
+
+    /// Lock `key`, waiting asynchronously until it is available.
+    pub async fn lock_wait(&self, key: &str) -> io::Result<KeyLock> {
+        let mut backoff = Duration::from_millis(1);
+
+        loop {
+            match self.try_lock(key) {
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => {}
+                result => return result,
+            }
+
+            if self.shared.borrow().held.contains(key) {
+                Released {
+                    shared: &self.shared,
+                    key,
+                    waiter: None,
+                    generation: 0,
+                }
+                .await;
+            } else {
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+
+    /// Keys currently locked through this `LockDir`, in sorted order.
+    pub fn held_keys(&self) -> Vec<String> {
+        self.shared.borrow().held.iter().cloned().collect()
+    }
+
+    fn held(&self, key: &str, path: PathBuf, lock: Flock) -> KeyLock {
+        self.shared.borrow_mut().held.insert(key.to_owned());
+
+        KeyLock {
+            key: key.to_owned(),
+            path,
+            lock: Some(lock),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl KeyLock {
+    /// The locked key.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for KeyLock {
+    fn drop(&mut self) {
+        if let Some(lock) = self.lock.take() {
+            // Unlinked while still locked; see `LockDir`.
+            if is_current(&lock.fd, &self.path).unwrap_or(false) {
+                let _ = std::fs::remove_file(&self.path);
+            }
+        }
+
+        let waiters = {
+            let mut shared = self.shared.borrow_mut();
+            shared.held.remove(&self.key);
+
+            shared.waiters.remove(&self.key)
+        };
+
+        for waker in waiters
+            .into_iter()
+            .flat_map(|(_, mut list)| list.take_all())
+        {
+            waker.wake();
+        }
+    }
+}
+
+/// Waits until a key held through the same [`LockDir`] is released.
+struct Released<'a> {
+    shared: &'a TrackedRefCell<Registry>,
+    key: &'a str,
+    waiter: Option<u64>,
+    /// Generation of the list `waiter` belongs to.
+    generation: u64,
+}
+
+impl Future for Released<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        let mut shared = this.shared.borrow_mut();
+
+        if !shared.held.contains(this.key) {
+            this.waiter = None;
+            return Poll::Ready(());
+        }
+
+        let shared = &mut *shared;
+        let (generation, list) = shared
+            .waiters
+            .entry(this.key.to_owned())
+            .or_insert_with(|| {
+                shared.next_generation += 1;
+                (shared.next_generation, WaiterList::new())
+            });
+
+        // Our list was torn down by a release and the key locked again.
+        if *generation != this.generation {
+            this.waiter = None;
+            this.generation = *generation;
+        }
+
+        list.register(&mut this.waiter, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Released<'_> {
+    fn drop(&mut self) {
+        if self.waiter.is_none() {
+            return;
+        }
+
+        let mut shared = self.shared.borrow_mut();
+
+        if let Some((generation, list)) = shared.waiters.get_mut(self.key)
+            && *generation == self.generation
+        {
+            list.remove(self.waiter);
+
+            if list.is_empty() {
+                shared.waiters.remove(self.key);
+            }
+        }
+    }
+}
+
+/// The kind of a lock listed in `/proc/locks`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum LockKind {
+    /// A whole-file `flock(2)` lock, as taken by [`Flock`].
+    Flock,
+    /// A process-associated `fcntl` record lock.
+    Posix,
+    /// An open file description `fcntl` record lock, as taken by
+    /// [`RangeLock`](crate::utils::range_lock::RangeLock).
+    Ofd,
+}
+
+/// A lock held on a file.
+#[derive(Debug)]
+pub(crate) struct LockEntry {
+    pub(crate) kind: LockKind,
+    /// The locking process as the kernel reports it; not meaningful for
+    /// every kind, and possibly from another PID namespace.
+    pub(crate) pid: i32,
+}
+
+/// The locks held on the file described by `stat`, read from `/proc/locks`.
+///
+/// Waiters queued for a lock are not included.
+pub(crate) fn locks_on(stat: &fs::Stat) -> io::Result<Vec<LockEntry>> {
+    let (major, minor, ino) = (fs::major(stat.st_dev), fs::minor(stat.st_dev), stat.st_ino);
+    let mut locks = Vec::new();
+
+    // Lines look like `1: FLOCK  ADVISORY  WRITE 1234 fe:00:5678 0 EOF`,
+    // with `->` after the id for blocked waiters.
+    for line in std::fs::read_to_string("/proc/locks")?.lines() {
+        let mut fields = line.split_whitespace().skip(1);
+
+        let kind = match fields.next() {
+            Some("FLOCK") => LockKind::Flock,
+            Some("POSIX") => LockKind::Posix,
+            Some("OFDLCK") => LockKind::Ofd,
+            _ => continue,
+        };
+
+        let (Some(pid), Some(file)) = (fields.nth(2), fields.next()) else {
+            continue;
+        };
+
+        let mut id = file.split(':');
+        let matches = id.next().and_then(|m| u32::from_str_radix(m, 16).ok()) == Some(major)
+            && id.next().and_then(|m| u32::from_str_radix(m, 16).ok()) == Some(minor)
+            && id.next().and_then(|i| i.parse().ok()) == Some(ino);
+
+        if matches {
+            locks.push(LockEntry {
+                kind,
+                pid: pid.parse().unwrap_or(-1),
+            });
+        }
+    }
+
+    Ok(locks)
+}
+
+/// Map a failed non-blocking `flock(2)`; only contention means "held".
+fn lock_error(e: Errno) -> io::Error {
+    match e {
+        Errno::WOULDBLOCK => io::Error::new(io::ErrorKind::AddrInUse, "Lock already held"),
+        e => io::Error::from_raw_os_error(e.raw_os_error()),
+    }
+}
+
+fn open(path: &Path, access: OFlags) -> io::Result<OwnedFd> {
+    fs::openat(
+        fs::CWD,
+        path,
+        OFlags::CREATE | access,
+        Mode::RUSR | Mode::WUSR,
+    )
+    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+/// Whether `fd` is still the file at `path`.
+///
+/// A stale lock file may be unlinked by
+/// [`collect_stale`](crate::utils::lock_gc::collect_stale) between our
+/// `open` and `flock`; the lock is then on an orphaned inode and must be
+/// taken again on a fresh file.
+pub(crate) fn is_current(fd: &OwnedFd, path: &Path) -> io::Result<bool> {
+    let held = fs::fstat(fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+    match fs::stat(path) {
+        Ok(stat) => Ok(stat.st_dev == held.st_dev && stat.st_ino == held.st_ino),
+        Err(Errno::NOENT) => Ok(false),
+        Err(e) => Err(io::Error::from_raw_os_error(e.raw_os_error())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, spawn_local},
+        test_util::{CountingWaker, TempDir},
+    };
+
+    #[test]
+    fn exclusive_locks_contend() {
+        let dir = TempDir::new();
+        let path = dir.join("a.lock");
+
+        let held = Flock::lock(&path).unwrap();
+        let err = Flock::lock(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(
+            Flock::lock_shared(&path).unwrap_err().kind(),
+            io::ErrorKind::AddrInUse
+        );
+
+        drop(held);
+        Flock::lock(&path).unwrap();
+    }
+
+    #[test]
+    fn shared_locks_coexist() {
+        let dir = TempDir::new();
+        let path = dir.join("a.lock");
+
+        let _a = Flock::lock_shared(&path).unwrap();
+        let _b = Flock::lock_shared(&path).unwrap();
+        assert_eq!(
+            Flock::lock(&path).unwrap_err().kind(),
+            io::ErrorKind::AddrInUse
+        );
+    }
+
+    #[test]
+    fn only_contention_is_reported_as_held() {
+        assert_eq!(
+            lock_error(Errno::WOULDBLOCK).kind(),
+            io::ErrorKind::AddrInUse
+        );
+
+        for errno in [Errno::INTR, Errno::NOLCK, Errno::BADF] {
+            assert_eq!(lock_error(errno).raw_os_error(), Some(errno.raw_os_error()));
+        }
+    }
+
+    #[test]
+    fn upgrade_fails_while_shared_elsewhere() {
+        let dir = TempDir::new();
+        let path = dir.join("a.lock");
+
+        let a = Flock::lock_shared(&path).unwrap();
+        let b = Flock::lock_shared(&path).unwrap();
+
+        let (a, err) = a.upgrade().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        // Still shared: an exclusive lock is refused, another shared one is not.
+        assert!(Flock::lock(&path).is_err());
+        drop(Flock::lock_shared(&path).unwrap());
+
+        drop(b);
+        let mut a = a.expect("shared lock handed back").upgrade().unwrap();
+        assert!(Flock::lock_shared(&path).is_err());
+
+        a.downgrade().unwrap();
+        drop(Flock::lock_shared(&path).unwrap());
+    }
+
+    #[test]
+    fn pidfile_names_holder() {
+        let dir = TempDir::new();
+        let path = dir.join("a.pid");
+
+        assert_eq!(Flock::try_read_holder(&path).unwrap(), None);
+
+        let lock = Flock::lock_pidfile(&path).unwrap();
+        assert_eq!(
+            Flock::try_read_holder(&path).unwrap(),
+            Some(std::process::id())
+        );
+
+        let err = Flock::lock_pidfile(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(err.to_string().contains(&std::process::id().to_string()));
+
+        drop(lock);
+        assert_eq!(Flock::try_read_holder(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn holder_of_plain_lock_comes_from_the_kernel() {
+        let dir = TempDir::new();
+        let path = dir.join("a.lock");
+
+        let _lock = Flock::lock(&path).unwrap();
+        assert_eq!(
+            Flock::try_read_holder(&path).unwrap(),
+            Some(std::process::id())
+        );
+    }
+
+    #[test]
+    fn reading_holder_does_not_disturb_lockers() {
+        let dir = TempDir::new();
+        let path = dir.join("a.pid");
+        drop(Flock::lock_pidfile(&path).unwrap());
+
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader = {
+            let (path, done) = (path.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    let _ = Flock::try_read_holder(&path);
+                }
+            })
+        };
+
+        for _ in 0..500 {
+            drop(Flock::lock(&path).unwrap());
+        }
+
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn lock_async_waits_for_release() {
+        let dir = TempDir::new();
+        let path = dir.join("a.lock");
+
+        LocalExecutor::new().block_on(async {
+            let held = Flock::lock(&path).unwrap();
+
+            let release = spawn_local(async move {
+                sleep(Duration::from_millis(20)).await;
+                drop(held);
+            });
+
+            let started = time::now();
+            let _lock = Flock::lock_async(&path).await.unwrap();
+            assert!(started.elapsed() >= Duration::from_millis(20));
+            release.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn lock_timeout_async_expires_while_held() {
+        let dir = TempDir::new();
+        let path = dir.join("a.lock");
+        let _held = Flock::lock(&path).unwrap();
+
+        let err = LocalExecutor::new()
+            .block_on(Flock::lock_timeout_async(&path, Duration::from_millis(20)))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn lock_timeout_expires_while_held() {
+        let dir = TempDir::new();
+        let path = dir.join("a.lock");
+
+        let _held = Flock::lock(&path).unwrap();
+        let err = Flock::lock_timeout(&path, Duration::from_millis(20)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn lock_dir_encodes_keys_and_refuses_relock() {
+        let dir = TempDir::new();
+        let locks = LockDir::new(dir.join("locks")).unwrap();
+
+        assert_eq!(
+            locks.path_for("a/b").unwrap(),
+            dir.join("locks").join("a%2Fb.lock")
+        );
+
+        let held = locks.lock("a/b").unwrap();
+        assert_eq!(locks.held_keys(), ["a/b"]);
+        assert_eq!(
+            locks.lock("a/b").unwrap_err().kind(),
+            io::ErrorKind::Deadlock
+        );
+
+        drop(held);
+        assert!(locks.held_keys().is_empty());
+        assert!(!dir.join("locks").join("a%2Fb.lock").exists());
+    }
+
+    #[test]
+    fn waiters_survive_release_and_relock() {
+        let dir = TempDir::new();
+        let locks = LockDir::new(dir.path()).unwrap();
+        let (b_counter, d_counter) = (CountingWaker::new(), CountingWaker::new());
+        let (b_waker, d_waker) = (b_counter.waker(), d_counter.waker());
+        let mut b_cx = Context::from_waker(&b_waker);
+        let mut d_cx = Context::from_waker(&d_waker);
+
+        let a = locks.try_lock("k").unwrap();
+        let mut b = Box::pin(locks.lock_wait("k"));
+        assert!(b.as_mut().poll(&mut b_cx).is_pending());
+
+        // A's release wakes B, but C takes the key before B runs, and D
+        // queues on the list that replaces B's.
+        drop(a);
+        assert_eq!(b_counter.count(), 1);
+        let c = locks.try_lock("k").unwrap();
+        let mut d = Box::pin(locks.lock_wait("k"));
+        assert!(d.as_mut().poll(&mut d_cx).is_pending());
+
+        // B re-queues without taking over D's place, and leaves it intact.
+        assert!(b.as_mut().poll(&mut b_cx).is_pending());
+        drop(b);
+
+        drop(c);
+        assert_eq!(d_counter.count(), 1);
+        let Poll::Ready(d) = d.as_mut().poll(&mut d_cx) else {
+            panic!("D not granted the released key");
+        };
+        assert_eq!(d.unwrap().key(), "k");
+    }
+
+    #[test]
+    fn contention_reports_release_by_other_holder() {
+        let dir = TempDir::new();
+        let path = dir.join("leader.lock");
+
+        LocalExecutor::new().block_on(async {
+            let leader = Flock::lock(&path).unwrap();
+            let mut standby = Flock::contention_events(&path).unwrap();
+
+            // Unrelated writes in the directory, and our own failed attempt,
+            // do not count as releases.
+            std::fs::write(dir.join("other"), b"x").unwrap();
+            assert!(Flock::lock(&path).is_err());
+            let early =
+                crate::time::timeout(Duration::from_millis(20), standby.next_release()).await;
+            assert!(early.is_err());
+
+            drop(spawn_local(async move {
+                sleep(Duration::from_millis(5)).await;
+                drop(leader);
+            }));
+
+            let lock = standby.acquire().await.unwrap();
+            assert!(Flock::lock(&path).is_err());
+            drop(lock);
+        });
+    }
+}
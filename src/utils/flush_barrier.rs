@@ -0,0 +1,195 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    collections::BTreeSet,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+/// Waits for the writes registered up to a point, ignoring later ones.
+///
+/// Writers register each pending write with [`begin`](Self::begin) and
+/// drop the returned guard once it has completed. A flusher then awaits
+/// [`flush`](Self::flush), which resolves once every write registered
+/// before the call is done, even while new writes keep arriving: this is
+/// what a frame-consistent commit across several outputs needs, where a
+/// [`WaitGroup`](crate::utils::wait_group::WaitGroup) would wait for the
+/// count to reach zero and could starve.
+///
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct FlushBarrier {
+    shared: Rc<TrackedRefCell<Shared>>,
+}
+
+/// A write registered with a [`FlushBarrier`], completed when dropped.
+#[derive(Debug)]
+#[must_use = "the write is completed as soon as the guard is dropped"]
+pub struct PendingWrite {
+    shared: Rc<TrackedRefCell<Shared>>,
+    seq: u64,
+}
+
+/// A position in a [`FlushBarrier`]'s sequence of writes.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct FlushPoint(u64);
+
+#[derive(Debug, Default)]
+struct Shared {
+    /// Sequence number of the next write.
+    next: u64,
+    /// Sequence numbers of writes still in progress.
+    pending: BTreeSet<u64>,
+    waiters: WaiterList,
+}
+
+impl Shared {
+    fn reached(&self, point: FlushPoint) -> bool {
+        self.pending.first().is_none_or(|&oldest| oldest >= point.0)
+    }
+}
+
+impl FlushBarrier {
+    /// Create a barrier with no pending writes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a pending write.
+    pub fn begin(&self) -> PendingWrite {
+        let mut shared = self.shared.borrow_mut();
+        let seq = shared.next;
+        shared.next += 1;
+        shared.pending.insert(seq);
+
+        PendingWrite {
+            shared: self.shared.clone(),
+            seq,
+        }
+    }
+
+    /// The current point: after every write registered so far.
+    pub fn point(&self) -> FlushPoint {
+        FlushPoint(self.shared.borrow().next)
+    }
+
+    /// Whether every write registered before `point` has completed.
+    pub fn is_reached(&self, point: FlushPoint) -> bool {
+        self.shared.borrow().reached(point)
+    }
+
+    /// Wait until every write registered before `point` has completed.
+    pub fn wait_for(&self, point: FlushPoint) -> impl Future<Output = ()> + '_ {
+        Reached {
+            shared: &self.shared,
+            point,
+            key: None,
+        }
+    }
+
+    /// Wait until every write registered so far has completed.
+    pub fn flush(&self) -> impl Future<Output = ()> + '_ {
+        self.wait_for(self.point())
+    }
+
+    /// Number of writes in progress.
+    pub fn pending(&self) -> usize {
+        self.shared.borrow().pending.len()
+    }
+}
+
+impl Drop for PendingWrite {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut shared = self.shared.borrow_mut();
+            let oldest = shared.pending.first() == Some(&self.seq);
+            shared.pending.remove(&self.seq);
+
+            // Only completing the oldest write can let a flush through.
+            if !oldest {
+                return;
+            }
+
+            shared.waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+struct Reached<'a> {
+    shared: &'a TrackedRefCell<Shared>,
+    point: FlushPoint,
+    key: Option<u64>,
+}
+
+impl Future for Reached<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let shared = self.shared;
+        let mut shared = shared.borrow_mut();
+
+        if shared.reached(self.point) {
+            shared.waiters.remove(self.key.take());
+            return Poll::Ready(());
+        }
+
+        shared.waiters.register(&mut self.key, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Reached<'_> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.shared.borrow_mut().waiters.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn flush_ignores_later_writes() {
+        let barrier = FlushBarrier::new();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let early = barrier.begin();
+        let mut flush = pin!(barrier.flush());
+        assert!(flush.as_mut().poll(&mut cx).is_pending());
+
+        let late = barrier.begin();
+        drop(early);
+
+        assert_eq!(counter.count(), 1);
+        assert!(flush.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(barrier.pending(), 1);
+        drop(late);
+    }
+
+    #[test]
+    fn point_is_reached_once_older_writes_finish() {
+        let barrier = FlushBarrier::new();
+        let a = barrier.begin();
+        let b = barrier.begin();
+        let point = barrier.point();
+        let _c = barrier.begin();
+
+        assert!(!barrier.is_reached(point));
+        drop(b);
+        assert!(!barrier.is_reached(point));
+        drop(a);
+        assert!(barrier.is_reached(point));
+    }
+}
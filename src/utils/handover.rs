@@ -0,0 +1,367 @@
+use rustix::{
+    fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+    fs::{self, MemfdFlags, SealFlags},
+    io::{self as rio, FdFlags},
+};
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{self, Read, Seek, Write},
+    os::unix::process::CommandExt,
+    process::{self, Command},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Environment variable carrying the handover table to the new image.
+const ENV: &str = "ARS_HANDOVER";
+
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Live fds and a state blob to pass across `exec` of a new binary.
+///
+/// The fds survive `exec` at the same numbers with `CLOEXEC` cleared and
+/// are listed, by name, in the `ARS_HANDOVER` environment variable. The
+/// state blob travels in a sealed memfd.
+#[derive(Debug, Default)]
+pub struct Handover {
+    fds: Vec<(String, OwnedFd)>,
+    state: Vec<u8>,
+}
+
+impl Handover {
+    /// Create an empty handover.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an fd to pass under `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is empty or contains `:`, `,` or `=`.
+    pub fn add_fd(&mut self, name: impl Into<String>, fd: impl Into<OwnedFd>) -> &mut Self {
+        let name = name.into();
+        assert!(
+            !name.is_empty() && !name.contains([':', ',', '=']),
+            "invalid handover fd name: {name:?}"
+        );
+
+        self.fds.push((name, fd.into()));
+        self
+    }
+
+    /// Set the opaque state blob passed to the new image.
+    pub fn set_state(&mut self, state: impl Into<Vec<u8>>) -> &mut Self {
+        self.state = state.into();
+        self
+    }
+
+    /// Replace the current process with `cmd`, handing over the fds and state.
+    ///
+    /// Like [`CommandExt::exec`], this only returns on failure, in which case
+    /// the fds are restored to `CLOEXEC` and remain owned by `self`.
+    pub fn exec(&mut self, cmd: &mut Command) -> io::Error {
+        let state = match self.state_memfd() {
+            Ok(fd) => fd,
+            Err(e) => return e,
+        };
+
+        let table = self
+            .fds
+            .iter()
+            .map(|(name, fd)| format!("{name}={}", fd.as_raw_fd()))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        // exec() keeps the pid, which lets the receiver ignore a stale table
+        // inherited by its own children.
+        cmd.env(
+            ENV,
+            format!("{}:{}:{table}", process::id(), state.as_raw_fd()),
+        );
+
+        let fds = self.fds.iter().map(|(_, fd)| fd.as_fd());
+        for fd in fds.clone().chain([state.as_fd()]) {
+            if let Err(e) = rio::fcntl_setfd(fd, FdFlags::empty()) {
+                restore_cloexec(fds);
+                return io::Error::from_raw_os_error(e.raw_os_error());
+            }
+        }
+
+        let err = cmd.exec();
+        restore_cloexec(fds);
+        err
+    }
+
+    fn state_memfd(&self) -> io::Result<OwnedFd> {
+        let fd = fs::memfd_create(
+            "ars-handover-state",
+            MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        let mut file = File::from(fd);
+        file.write_all(&self.state)?;
+        file.rewind()?;
+
+        fs::fcntl_add_seals(
+            &file,
+            SealFlags::SHRINK | SealFlags::GROW | SealFlags::WRITE | SealFlags::SEAL,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(file.into())
+    }
+}
+
+/// Fds and state received from a previous image via [`Handover::exec`].
+#[derive(Debug)]
+pub struct Received {
+    fds: HashMap<String, OwnedFd>,
+    state: Vec<u8>,
+}
+
+impl Received {
+    /// Take ownership of the handover passed to this process, if any.
+    ///
+    /// Returns `None` if the process was not started through
+    /// [`Handover::exec`], or if the handover was already taken.
+    /// Received fds are set back to `CLOEXEC`, and `ARS_HANDOVER` is
+    /// removed from the environment, so a later `exec` of this process
+    /// without a handover cannot mistake the table for its own.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the table is malformed
+    /// or lists an fd that is not open; the listed fds that are open are
+    /// closed then.
+    ///
+    /// # Safety
+    ///
+    /// As with [`env::remove_var`], no other thread may access the
+    /// environment at the same time. Call this early in `main`, before
+    /// spawning threads.
+    pub unsafe fn take() -> io::Result<Option<Self>> {
+        let Ok(value) = env::var(ENV) else {
+            return Ok(None);
+        };
+
+        if RECEIVED.swap(true, Ordering::AcqRel) {
+            return Ok(None);
+        }
+
+        // SAFETY: Upheld by the caller.
+        unsafe { env::remove_var(ENV) };
+
+        let Some(table) = Table::parse(&value, process::id())? else {
+            return Ok(None);
+        };
+
+        // SAFETY: The previous image passed these fds to us across exec,
+        // `RECEIVED` and the removed variable ensure they are claimed at most
+        // once, and `Table::parse` rejects fds listed twice.
+        let (state, fds) = unsafe { table.claim() }.ok_or_else(|| invalid(&value))?;
+
+        let mut file = File::from(state);
+        let mut state = Vec::new();
+        file.read_to_end(&mut state)?;
+
+        Ok(Some(Self { fds, state }))
+    }
+
+    /// Take the fd handed over under `name`.
+    pub fn take_fd(&mut self, name: &str) -> Option<OwnedFd> {
+        self.fds.remove(name)
+    }
+
+    /// Names of the fds not yet taken.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.fds.keys().map(String::as_str)
+    }
+
+    /// The state blob.
+    pub fn state(&self) -> &[u8] {
+        &self.state
+    }
+}
+
+/// The parsed value of `ARS_HANDOVER`: `<pid>:<state fd>:<name>=<fd>,...`.
+#[derive(Debug, PartialEq, Eq)]
+struct Table<'a> {
+    state: RawFd,
+    fds: Vec<(&'a str, RawFd)>,
+}
+
+impl<'a> Table<'a> {
+    /// Parse `value`, returning `None` if it was meant for another pid.
+    fn parse(value: &'a str, pid: u32) -> io::Result<Option<Self>> {
+        let mut parts = value.splitn(3, ':');
+        let (Some(for_pid), Some(state), Some(table)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid(value));
+        };
+
+        if for_pid.parse::<u32>().map_err(|_| invalid(value))? != pid {
+            return Ok(None);
+        }
+
+        let state = parse_fd(state).ok_or_else(|| invalid(value))?;
+        let mut fds = Vec::new();
+
+        for entry in table.split(',').filter(|e| !e.is_empty()) {
+            let (name, fd) = entry.split_once('=').ok_or_else(|| invalid(value))?;
+            fds.push((name, parse_fd(fd).ok_or_else(|| invalid(value))?));
+        }
+
+        // Each fd may only be wrapped once.
+        let mut seen: Vec<_> = fds.iter().map(|&(_, fd)| fd).chain([state]).collect();
+        seen.sort_unstable();
+        if seen.windows(2).any(|w| w[0] == w[1]) {
+            return Err(invalid(value));
+        }
+
+        Ok(Some(Self { state, fds }))
+    }
+
+    /// Take ownership of the listed fds, setting them back to `CLOEXEC`.
+    ///
+    /// Returns `None` if any of them is not open, after closing the others.
+    ///
+    /// # Safety
+    ///
+    /// The listed fds must not be owned by anything else.
+    unsafe fn claim(&self) -> Option<(OwnedFd, HashMap<String, OwnedFd>)> {
+        // SAFETY: Forwarded to the caller.
+        let state = unsafe { claim_fd(self.state) };
+        let mut fds = HashMap::new();
+        let mut complete = state.is_some();
+
+        for &(name, raw) in &self.fds {
+            // SAFETY: Forwarded to the caller.
+            match unsafe { claim_fd(raw) } {
+                Some(fd) => {
+                    fds.insert(name.to_owned(), fd);
+                }
+                None => complete = false,
+            }
+        }
+
+        // Otherwise the fds claimed so far are closed as they are dropped.
+        if !complete {
+            return None;
+        }
+
+        Some((state?, fds))
+    }
+}
+
+fn parse_fd(s: &str) -> Option<RawFd> {
+    s.parse::<RawFd>().ok().filter(|&fd| fd >= 0)
+}
+
+/// Wrap `raw` if it is open, setting it back to `CLOEXEC`.
+///
+/// # Safety
+///
+/// `raw` must not be owned by anything else.
+unsafe fn claim_fd(raw: RawFd) -> Option<OwnedFd> {
+    // SAFETY: The caller guarantees `raw` is unowned; it is only wrapped
+    // after fcntl() confirmed it is open.
+    unsafe {
+        rio::fcntl_setfd(BorrowedFd::borrow_raw(raw), FdFlags::CLOEXEC).ok()?;
+        Some(OwnedFd::from_raw_fd(raw))
+    }
+}
+
+fn restore_cloexec<'a>(fds: impl Iterator<Item = BorrowedFd<'a>>) {
+    for fd in fds {
+        let _ = rio::fcntl_setfd(fd, FdFlags::CLOEXEC);
+    }
+}
+
+fn invalid(value: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Malformed {ENV} value: {value:?}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustix::{io::Errno, pipe::pipe};
+    use std::os::fd::IntoRawFd;
+
+    /// Far above any open fd, so never open.
+    const CLOSED_FD: RawFd = 1 << 20;
+
+    #[test]
+    fn parses_table() {
+        let table = Table::parse("42:7:listener=3,seat=5", 42).unwrap().unwrap();
+
+        assert_eq!(
+            table,
+            Table {
+                state: 7,
+                fds: vec![("listener", 3), ("seat", 5)],
+            }
+        );
+        assert_eq!(Table::parse("42:7:", 42).unwrap().unwrap().fds, []);
+    }
+
+    #[test]
+    fn ignores_table_for_other_pid() {
+        assert_eq!(Table::parse("42:7:listener=3", 43).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        for value in [
+            "",
+            "42",
+            "42:7",
+            "x:7:a=3",
+            "42:-1:a=3",
+            "42:7:a",
+            "42:7:a=x",
+            "42:7:a=3,b=3",
+            "42:7:a=7",
+        ] {
+            let err = Table::parse(value, 42).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn claims_listed_fds() {
+        let (state, _) = pipe().unwrap();
+        let (read, write) = pipe().unwrap();
+        let table = Table {
+            state: state.into_raw_fd(),
+            fds: vec![("read", read.into_raw_fd())],
+        };
+
+        // SAFETY: The fds were just released by their owners.
+        let (_state, mut fds) = unsafe { table.claim() }.unwrap();
+        let read = fds.remove("read").unwrap();
+
+        rustix::io::write(&write, b"x").unwrap();
+        let mut buf = [0; 1];
+        assert_eq!(rustix::io::read(&read, &mut buf), Ok(1));
+    }
+
+    #[test]
+    fn closes_claimed_fds_when_one_is_missing() {
+        let (state, _) = pipe().unwrap();
+        let (read, write) = pipe().unwrap();
+        let table = Table {
+            state: state.into_raw_fd(),
+            fds: vec![("read", read.into_raw_fd()), ("gone", CLOSED_FD)],
+        };
+
+        // SAFETY: The fds were just released by their owners.
+        assert!(unsafe { table.claim() }.is_none());
+
+        // The read end was closed rather than leaked.
+        assert_eq!(rustix::io::write(&write, b"x"), Err(Errno::PIPE));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::reactor::AsyncFd;
+use rustix::{
+    event::{EventfdFlags, eventfd},
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    io::Errno,
+};
+use std::{
+    future::poll_fn,
+    io,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+/// A counting semaphore shared with other threads through a kernel
+/// eventfd in semaphore mode.
+///
+/// Producer threads add permits through a [`KernelReleaser`], a single
+/// `write(2)` that needs no lock; the consumer on this thread takes them
+/// one at a time with [`acquire`](Self::acquire), which parks in the
+/// reactor while none are available. Permits are plain counts rather than
+/// guards, so each unit of work a producer hands over is one permit.
+#[derive(Debug)]
+pub struct KernelSemaphore {
+    fd: AsyncFd<Arc<OwnedFd>>,
+}
+
+/// Adds permits to a [`KernelSemaphore`] from any thread.
+///
+/// Keeps the eventfd open, so releasing after the semaphore has been
+/// dropped is harmless.
+#[derive(Debug, Clone)]
+pub struct KernelReleaser {
+    fd: Arc<OwnedFd>,
+}
+
+impl KernelSemaphore {
+    /// Create a semaphore holding `permits` and register it with this
+    /// thread's reactor.
+    pub fn new(permits: u32) -> io::Result<Self> {
+        let fd = eventfd(
+            permits,
+            EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK | EventfdFlags::SEMAPHORE,
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            fd: AsyncFd::new(Arc::new(fd))?,
+        })
+    }
+
+    /// A handle for releasing permits from other threads.
+    pub fn releaser(&self) -> KernelReleaser {
+        KernelReleaser {
+            fd: self.fd.get_ref().clone(),
+        }
+    }
+
+    /// Take one permit, waiting until one is available.
+    pub async fn acquire(&self) {
+        poll_fn(|cx| self.poll_acquire(cx)).await
+    }
+
+    /// Poll-based [`acquire`](Self::acquire).
+    pub fn poll_acquire(&self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            let mut ready = ready!(self.fd.poll_read_ready(cx));
+
+            // Reading only fails with EAGAIN, i.e. `Err(TryIoError)`.
+            if let Ok(Ok(())) = ready.try_io(take) {
+                return Poll::Ready(());
+            }
+        }
+    }
+
+    /// Take one permit if one is available.
+    pub fn try_acquire(&self) -> bool {
+        take(self.fd.get_ref()).is_ok()
+    }
+
+    /// Add `n` permits from this thread.
+    pub fn release(&self, n: u32) -> io::Result<()> {
+        release(self.fd.get_ref(), n)
+    }
+}
+
+impl AsFd for KernelSemaphore {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl KernelReleaser {
+    /// Add `n` permits.
+    ///
+    /// Fails with `WouldBlock` if the count would exceed the eventfd's
+    /// maximum of `u64::MAX - 1`.
+    pub fn release(&self, n: u32) -> io::Result<()> {
+        release(&self.fd, n)
+    }
+}
+
+fn take(fd: &Arc<OwnedFd>) -> io::Result<()> {
+    let mut buf = [0; 8];
+
+    match rustix::io::read(&**fd, &mut buf) {
+        Ok(_) => Ok(()),
+        Err(Errno::AGAIN) => Err(io::ErrorKind::WouldBlock.into()),
+        Err(e) => Err(io::Error::from_raw_os_error(e.raw_os_error())),
+    }
+}
+
+fn release(fd: &OwnedFd, n: u32) -> io::Result<()> {
+    rustix::io::write(fd, &u64::from(n).to_ne_bytes())
+        .map(drop)
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn hands_out_one_permit_per_acquire() {
+        LocalExecutor::new().block_on(async {
+            let sem = KernelSemaphore::new(1).unwrap();
+
+            sem.acquire().await;
+            assert!(!sem.try_acquire());
+
+            sem.release(2).unwrap();
+            assert!(sem.try_acquire());
+            sem.acquire().await;
+            assert!(!sem.try_acquire());
+        });
+    }
+
+    #[test]
+    fn permits_released_by_other_threads() {
+        LocalExecutor::new().block_on(async {
+            let sem = KernelSemaphore::new(0).unwrap();
+            let producers: Vec<_> = (0..4)
+                .map(|_| {
+                    let releaser = sem.releaser();
+                    thread::spawn(move || {
+                        thread::sleep(Duration::from_millis(2));
+                        releaser.release(1).unwrap();
+                    })
+                })
+                .collect();
+
+            for _ in 0..4 {
+                sem.acquire().await;
+            }
+            assert!(!sem.try_acquire());
+
+            for producer in producers {
+                producer.join().unwrap();
+            }
+        });
+    }
+}
@@ -0,0 +1,258 @@
+use crate::utils::{
+    semaphore::{Permit, Semaphore},
+    tracked_cell::TrackedRefCell,
+};
+use std::{
+    cell::{Ref, RefMut},
+    ops::{Deref, DerefMut},
+};
+
+/// Permits making up a [`RwLock`]; a writer takes all of them.
+const MAX_READERS: usize = u32::MAX as usize;
+
+/// A single-threaded async mutex.
+///
+/// Unlike a `RefCell` borrow, the guard may be held across `.await`;
+/// other tasks calling [`lock`](Mutex::lock) wait in FIFO order.
+#[derive(Debug)]
+pub struct Mutex<T> {
+    sem: Semaphore,
+    value: TrackedRefCell<T>,
+}
+
+impl<T> Mutex<T> {
+    /// Create a new unlocked mutex.
+    pub const fn new(value: T) -> Self {
+        Self {
+            sem: Semaphore::new(1),
+            value: TrackedRefCell::new(value),
+        }
+    }
+
+    /// Lock the mutex, waiting until it is available.
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        let permit = self.sem.acquire(1).await;
+
+        MutexGuard {
+            value: self.value.borrow_mut(),
+            _permit: permit,
+        }
+    }
+
+    /// Lock the mutex without waiting.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let permit = self.sem.try_acquire(1)?;
+
+        Some(MutexGuard {
+            value: self.value.borrow_mut(),
+            _permit: permit,
+        })
+    }
+
+    /// Mutable access through a unique reference, without locking.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Consume the mutex, returning the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// RAII guard of a locked [`Mutex`].
+#[derive(Debug)]
+pub struct MutexGuard<'a, T> {
+    // Declared first so the borrow ends before the next waiter is woken.
+    value: RefMut<'a, T>,
+    _permit: Permit<'a>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// A single-threaded async reader-writer lock.
+///
+/// Readers and writers are queued together in FIFO order, so a waiting
+/// writer is not starved by a stream of new readers.
+#[derive(Debug)]
+pub struct RwLock<T> {
+    sem: Semaphore,
+    value: TrackedRefCell<T>,
+}
+
+impl<T> RwLock<T> {
+    /// Create a new unlocked lock.
+    pub const fn new(value: T) -> Self {
+        Self {
+            sem: Semaphore::new(MAX_READERS),
+            value: TrackedRefCell::new(value),
+        }
+    }
+
+    /// Acquire shared read access, waiting until it is available.
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        let permit = self.sem.acquire(1).await;
+
+        RwLockReadGuard {
+            value: self.value.borrow(),
+            _permit: permit,
+        }
+    }
+
+    /// Acquire exclusive write access, waiting until it is available.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let permit = self.sem.acquire(MAX_READERS).await;
+
+        RwLockWriteGuard {
+            value: self.value.borrow_mut(),
+            _permit: permit,
+        }
+    }
+
+    /// Acquire shared read access without waiting.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let permit = self.sem.try_acquire(1)?;
+
+        Some(RwLockReadGuard {
+            value: self.value.borrow(),
+            _permit: permit,
+        })
+    }
+
+    /// Acquire exclusive write access without waiting.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let permit = self.sem.try_acquire(MAX_READERS)?;
+
+        Some(RwLockWriteGuard {
+            value: self.value.borrow_mut(),
+            _permit: permit,
+        })
+    }
+
+    /// Mutable access through a unique reference, without locking.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Consume the lock, returning the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// RAII guard of shared access to a [`RwLock`].
+#[derive(Debug)]
+pub struct RwLockReadGuard<'a, T> {
+    value: Ref<'a, T>,
+    _permit: Permit<'a>,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+/// RAII guard of exclusive access to a [`RwLock`].
+#[derive(Debug)]
+pub struct RwLockWriteGuard<'a, T> {
+    value: RefMut<'a, T>,
+    _permit: Permit<'a>,
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, spawn_local, yield_now},
+        time::{sleep, timeout},
+    };
+    use std::{rc::Rc, time::Duration};
+
+    #[test]
+    fn mutex_guard_excludes_across_await() {
+        LocalExecutor::new().block_on(async {
+            let mutex = Rc::new(Mutex::new(Vec::new()));
+
+            let tasks = [1, 2].map(|id| {
+                let mutex = mutex.clone();
+                spawn_local(async move {
+                    let mut guard = mutex.lock().await;
+                    guard.push(id);
+                    yield_now().await;
+                    guard.push(id);
+                })
+            });
+
+            for task in tasks {
+                task.await.unwrap();
+            }
+
+            assert_eq!(*mutex.lock().await, [1, 1, 2, 2]);
+        });
+    }
+
+    #[test]
+    fn readers_share_and_writers_exclude() {
+        let lock = RwLock::new(0);
+
+        let a = lock.try_read().unwrap();
+        let b = lock.try_read().unwrap();
+        assert!(lock.try_write().is_none());
+        drop((a, b));
+
+        *lock.try_write().unwrap() += 1;
+        let w = lock.try_write().unwrap();
+        assert!(lock.try_read().is_none());
+        drop(w);
+
+        assert_eq!(*lock.try_read().unwrap(), 1);
+    }
+
+    #[test]
+    fn cancelled_writer_lets_readers_through() {
+        LocalExecutor::new().block_on(async {
+            let lock = RwLock::new(());
+            let held = lock.read().await;
+
+            // The writer queues behind the reader, and a new reader behind it.
+            let writer = timeout(Duration::from_millis(5), lock.write());
+            let reader = async {
+                sleep(Duration::from_millis(1)).await;
+                lock.read().await
+            };
+
+            let (writer, reader) = crate::join!(writer, reader);
+            assert!(writer.is_err());
+            drop((reader, held));
+        });
+    }
+}
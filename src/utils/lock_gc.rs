@@ -0,0 +1,280 @@
+//! Removal of lock files nobody holds any more.
+//!
+//! A pass takes [`META_LOCK`] in the directory, so passes never overlap,
+//! and treats a file as stale only if `flock(2)` shows no holder and
+//! `/proc/locks` lists no process-associated or OFD record lock on its
+//! inode. Each candidate is then locked exclusively, checked again for
+//! record locks and for still being the file at its path, and unlinked
+//! while locked. [`Flock`] and
+//! [`RangeLock`](crate::utils::range_lock::RangeLock) re-check their file
+//! after locking and retry on an unlinked one, so a racing locker ends up
+//! on a fresh file.
+//!
+//! Record locks are only visible through procfs; without it, a file
+//! protected only by record locks looks stale. A process that takes a
+//! record lock without that post-lock re-check can still end up holding
+//! it on an unlinked inode. PID files whose lock outlives the named
+//! process, because a child inherited the fd, are reported, not removed.
+
+use crate::{
+    runtime::{BlockingClass, blocking},
+    time::interval,
+    utils::{
+        cancel::CancellationToken,
+        flock::{self, Flock, LockKind},
+    },
+};
+use rustix::{
+    fs::{self, FlockOperation, Mode, OFlags},
+    io::Errno,
+};
+use std::{
+    future::{Future, poll_fn},
+    io,
+    path::{Path, PathBuf},
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+/// Name of the lock serialising collections of a directory.
+pub const META_LOCK: &str = ".gc.lock";
+
+/// Outcome of one [`collect_stale`] pass.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GcReport {
+    /// Files that were unlinked because nobody held them.
+    pub removed: Vec<PathBuf>,
+    /// Files still locked by a live holder.
+    pub in_use: usize,
+    /// PID files still locked, but naming a process that no longer exists.
+    ///
+    /// The lock is kept alive by a descendant that inherited the fd, so
+    /// these are reported rather than removed.
+    pub orphaned: Vec<(PathBuf, u32)>,
+}
+
+/// Remove lock and PID files in `dir` whose holders are gone.
+///
+/// A file is stale when nobody holds a lock on it, as reported by
+/// [`Flock::try_read_holder`], and no byte-range lock is held on it
+/// either, such as a [`RangeLock`](crate::utils::range_lock::RangeLock);
+/// these are invisible to `flock(2)` and looked up in `/proc/locks`
+/// instead. Each stale file is locked exclusively,
+/// checked to still be the file at its path, and unlinked while locked, so
+/// a process racing to take it ends up on a fresh file instead (every
+/// [`Flock`] constructor re-checks its file after locking). Poison markers
+/// and subdirectories are left alone.
+///
+/// Collections are serialised through [`META_LOCK`] in `dir`; if another
+/// one is running, this fails with [`io::ErrorKind::AddrInUse`]. Since
+/// every step is guarded by a lock the kernel releases on exit, a crash
+/// midway leaves nothing to clean up.
+pub fn collect_stale(dir: &Path) -> io::Result<GcReport> {
+    let _meta = Flock::lock(&dir.join(META_LOCK))?;
+    let mut report = GcReport::default();
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let name = entry.file_name();
+        if name == META_LOCK
+            || Path::new(&name)
+                .extension()
+                .is_some_and(|e| e == "poisoned")
+        {
+            continue;
+        }
+
+        let path = entry.path();
+
+        if has_record_locks(&path)? {
+            report.in_use += 1;
+            continue;
+        }
+
+        match Flock::try_read_holder(&path) {
+            Ok(None) => {
+                if remove_if_unlocked(&path)? {
+                    report.removed.push(path);
+                } else {
+                    report.in_use += 1;
+                }
+            }
+            Ok(Some(pid)) if !is_alive(pid) => report.orphaned.push((path, pid)),
+            // Held, either by a live PID or as a plain lock file without one.
+            Ok(Some(_)) => report.in_use += 1,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => report.in_use += 1,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Run [`collect_stale`] on `dir` every `period` until `cancel` fires.
+///
+/// Each pass runs on the blocking thread pool, so scanning a large
+/// directory does not stall the executor. Passes that find another
+/// collection running are skipped; any other error ends the task.
+pub async fn collect_periodically(
+    dir: &Path,
+    period: Duration,
+    cancel: &CancellationToken,
+) -> io::Result<()> {
+    let mut interval = interval(period);
+    let mut cancelled = pin!(cancel.cancelled());
+
+    loop {
+        let ticked = poll_fn(|cx| {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(false);
+            }
+
+            interval.poll_tick(cx).map(|_| true)
+        })
+        .await;
+
+        if !ticked {
+            return Ok(());
+        }
+
+        let owned = dir.to_owned();
+
+        match blocking::run_in(BlockingClass::Metadata, move || collect_stale(&owned)).await {
+            Err(e) if e.kind() != io::ErrorKind::AddrInUse => return Err(e),
+            _ => {}
+        }
+    }
+}
+
+/// Unlink `path` if it can be locked exclusively; `false` if it is held.
+fn remove_if_unlocked(path: &Path) -> io::Result<bool> {
+    // No O_CREAT: a file that vanished meanwhile must not be recreated.
+    let fd = match fs::open(path, OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty()) {
+        Ok(fd) => fd,
+        Err(Errno::NOENT) => return Ok(false),
+        Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+    };
+
+    if fs::flock(&fd, FlockOperation::NonBlockingLockExclusive).is_err() {
+        return Ok(false);
+    }
+
+    // Someone may have replaced the file since it was inspected, or taken
+    // a byte-range lock, which the flock above does not exclude.
+    if !flock::is_current(&fd, path)? || has_record_locks(path)? {
+        return Ok(false);
+    }
+
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `fcntl` record locks, process-associated or OFD, are held on `path`.
+fn has_record_locks(path: &Path) -> io::Result<bool> {
+    let stat = match fs::stat(path) {
+        Ok(stat) => stat,
+        Err(Errno::NOENT) => return Ok(false),
+        Err(e) => return Err(io::Error::from_raw_os_error(e.raw_os_error())),
+    };
+
+    Ok(flock::locks_on(&stat)?
+        .iter()
+        .any(|lock| lock.kind != LockKind::Flock))
+}
+
+fn is_alive(pid: u32) -> bool {
+    let Ok(pid) = i32::try_from(pid) else {
+        return false;
+    };
+
+    // SAFETY: Signal 0 performs only the existence and permission checks.
+    let ret = unsafe { libc::kill(pid, 0) };
+
+    ret == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::LocalExecutor, test_util::TempDir, utils::range_lock::RangeLock};
+
+    #[test]
+    fn removes_only_unlocked_files() {
+        let dir = TempDir::new();
+        let stale = dir.join("stale.lock");
+        let held = dir.join("held.lock");
+        let pid = dir.join("app.pid");
+        let marker = dir.join("held.lock.poisoned");
+
+        drop(Flock::lock(&stale).unwrap());
+        let _held = Flock::lock(&held).unwrap();
+        let _pid = Flock::lock_pidfile(&pid).unwrap();
+        std::fs::write(&marker, "").unwrap();
+
+        let report = collect_stale(dir.path()).unwrap();
+
+        assert_eq!(report.removed, std::slice::from_ref(&stale));
+        assert_eq!(report.in_use, 2);
+        assert!(report.orphaned.is_empty());
+        assert!(!stale.exists());
+        assert!(held.exists() && pid.exists() && marker.exists());
+    }
+
+    #[test]
+    fn keeps_files_with_byte_range_locks() {
+        let dir = TempDir::new();
+        let path = dir.join("ranges");
+
+        let range = RangeLock::lock(&path, 0, 16).unwrap();
+        let report = collect_stale(dir.path()).unwrap();
+        assert!(report.removed.is_empty());
+        assert_eq!(report.in_use, 1);
+        assert!(path.exists());
+
+        drop(range);
+        let report = collect_stale(dir.path()).unwrap();
+        assert_eq!(report.removed, [path]);
+    }
+
+    #[test]
+    fn concurrent_collection_is_refused() {
+        let dir = TempDir::new();
+        let _meta = Flock::lock(&dir.join(META_LOCK)).unwrap();
+
+        let err = collect_stale(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn periodic_collection_stops_on_cancel() {
+        let dir = TempDir::new();
+        let stale = dir.join("stale.lock");
+        drop(Flock::lock(&stale).unwrap());
+
+        LocalExecutor::new().block_on(async {
+            let cancel = CancellationToken::new();
+            let guard = cancel.clone().drop_guard();
+
+            let canceller = crate::runtime::spawn_local(async move {
+                crate::time::sleep(Duration::from_millis(30)).await;
+                drop(guard);
+            });
+
+            collect_periodically(dir.path(), Duration::from_millis(5), &cancel)
+                .await
+                .unwrap();
+            canceller.await.unwrap();
+        });
+
+        assert!(!stale.exists());
+    }
+}
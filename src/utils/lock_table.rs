@@ -0,0 +1,286 @@
+use crate::{
+    runtime::blocking,
+    utils::{range_lock::RangeLock, tracked_cell::TrackedRefCell, waiters::WaiterList},
+};
+use std::{
+    collections::HashMap,
+    future::poll_fn,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+    task::Poll,
+};
+
+/// Byte-range locks shared by the tasks of one thread and other processes.
+///
+/// A [`RangeLock`] fails when the range is taken, whether by another
+/// process or by another task. Requests through a [`LockTable`] instead
+/// wait: conflicts with local holders are queued in order and granted when
+/// the holder drops its [`TableLock`], and only then is the kernel lock
+/// taken, waiting on the blocking pool if another process holds it.
+///
+/// Files are keyed by the path as given, so use one spelling per file.
+/// Clones share the same table.
+#[derive(Debug, Clone, Default)]
+pub struct LockTable {
+    files: Rc<TrackedRefCell<HashMap<PathBuf, File>>>,
+}
+
+#[derive(Debug, Default)]
+struct File {
+    /// Granted requests, whose kernel lock may still be pending.
+    held: Vec<Request>,
+    /// Requests waiting for a local conflict to clear, oldest first.
+    queued: Vec<Request>,
+    waiters: WaiterList,
+    next_id: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Request {
+    id: u64,
+    start: u64,
+    end: u64,
+    shared: bool,
+}
+
+/// A byte-range lock granted by a [`LockTable`]; released on drop.
+#[derive(Debug)]
+pub struct TableLock {
+    _kernel: RangeLock,
+    slot: Slot,
+}
+
+/// Removes a request from its file when dropped, letting later ones in.
+#[derive(Debug)]
+struct Slot {
+    files: Rc<TrackedRefCell<HashMap<PathBuf, File>>>,
+    path: PathBuf,
+    request: Request,
+    offset: u64,
+    len: u64,
+}
+
+impl LockTable {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Acquire an exclusive lock on `len` bytes from `offset`, where a `len`
+    /// of 0 extends to the end of the file.
+    ///
+    /// If the file does not exist, it will be created.
+    pub async fn lock(&self, path: &Path, offset: u64, len: u64) -> io::Result<TableLock> {
+        self.acquire(path, offset, len, false).await
+    }
+
+    /// Acquire a shared lock on a byte range.
+    pub async fn lock_shared(&self, path: &Path, offset: u64, len: u64) -> io::Result<TableLock> {
+        self.acquire(path, offset, len, true).await
+    }
+
+    async fn acquire(
+        &self,
+        path: &Path,
+        offset: u64,
+        len: u64,
+        shared: bool,
+    ) -> io::Result<TableLock> {
+        let end = match len {
+            0 => u64::MAX,
+            len => offset.saturating_add(len),
+        };
+
+        let slot = {
+            let mut files = self.files.borrow_mut();
+            let file = files.entry(path.to_owned()).or_default();
+            let request = Request {
+                id: file.next_id,
+                start: offset,
+                end,
+                shared,
+            };
+            file.next_id += 1;
+            file.queued.push(request);
+
+            Slot {
+                files: self.files.clone(),
+                path: path.to_owned(),
+                request,
+                offset,
+                len,
+            }
+        };
+
+        slot.granted().await;
+
+        let path = path.to_owned();
+        let kernel = match try_kernel_lock(&path, offset, len, shared) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                blocking::run(move || match shared {
+                    false => RangeLock::lock_blocking(&path, offset, len),
+                    true => RangeLock::lock_shared_blocking(&path, offset, len),
+                })
+                .await?
+            }
+            result => result?,
+        };
+
+        Ok(TableLock {
+            _kernel: kernel,
+            slot,
+        })
+    }
+}
+
+impl TableLock {
+    /// The locked range as `(offset, len)`.
+    pub fn range(&self) -> (u64, u64) {
+        (self.slot.offset, self.slot.len)
+    }
+}
+
+impl Slot {
+    /// Wait until no held or earlier queued request conflicts with this one,
+    /// then move it to the held ones.
+    async fn granted(&self) {
+        let mut waiter = None;
+
+        poll_fn(|cx| {
+            let mut files = self.files.borrow_mut();
+            let file = files
+                .get_mut(&self.path)
+                .expect("file entry outlives its requests");
+
+            let pos = file
+                .queued
+                .iter()
+                .position(|r| r.id == self.request.id)
+                .expect("request is queued until granted");
+
+            let blocked = file.held.iter().chain(&file.queued[..pos]);
+            if blocked
+                .into_iter()
+                .any(|other| conflicts(other, &self.request))
+            {
+                file.waiters.register(&mut waiter, cx.waker());
+                return Poll::Pending;
+            }
+
+            file.waiters.remove(waiter.take());
+            file.queued.remove(pos);
+            file.held.push(self.request);
+            Poll::Ready(())
+        })
+        .await
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut files = self.files.borrow_mut();
+            let Some(file) = files.get_mut(&self.path) else {
+                return;
+            };
+
+            file.held.retain(|r| r.id != self.request.id);
+            file.queued.retain(|r| r.id != self.request.id);
+            let wakers = file.waiters.take_all();
+
+            if file.held.is_empty() && file.queued.is_empty() {
+                files.remove(&self.path);
+            }
+
+            wakers
+        };
+
+        // Let every waiter re-check; any of them may have been behind us.
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+fn conflicts(a: &Request, b: &Request) -> bool {
+    a.start < b.end && b.start < a.end && !(a.shared && b.shared)
+}
+
+fn try_kernel_lock(path: &Path, offset: u64, len: u64, shared: bool) -> io::Result<RangeLock> {
+    match shared {
+        false => RangeLock::lock(path, offset, len),
+        true => RangeLock::lock_shared(path, offset, len),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, spawn_local, yield_now},
+        test_util::TempDir,
+        time::sleep,
+    };
+    use std::{cell::RefCell, time::Duration};
+
+    #[test]
+    fn queues_local_conflicts_in_order() {
+        let dir = TempDir::new();
+        let path = dir.join("table");
+
+        LocalExecutor::new().block_on(async {
+            let table = LockTable::new();
+            let order = Rc::new(RefCell::new(Vec::new()));
+
+            let first = table.lock(&path, 0, 10).await.unwrap();
+
+            let mut tasks = Vec::new();
+            for (name, offset) in [("overlap", 5), ("after", 8)] {
+                let (table, path, order) = (table.clone(), path.clone(), order.clone());
+                tasks.push(spawn_local(async move {
+                    let lock = table.lock(&path, offset, 10).await.unwrap();
+                    order.borrow_mut().push(name);
+                    drop(lock);
+                }));
+            }
+
+            // Disjoint ranges are not held up by the queue.
+            let disjoint = table.lock_shared(&path, 100, 1).await.unwrap();
+            assert_eq!(disjoint.range(), (100, 1));
+
+            yield_now().await;
+            assert!(order.borrow().is_empty());
+
+            drop(first);
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(*order.borrow(), ["overlap", "after"]);
+        });
+    }
+
+    #[test]
+    fn waits_for_other_processes() {
+        let dir = TempDir::new();
+        let path = dir.join("table");
+
+        // A separate open file description stands in for another process.
+        let foreign = RangeLock::lock(&path, 0, 0).unwrap();
+
+        LocalExecutor::new().block_on(async {
+            let table = LockTable::new();
+            let waiting = spawn_local({
+                let (table, path) = (table.clone(), path.clone());
+                async move { table.lock_shared(&path, 0, 1).await.unwrap().range() }
+            });
+
+            sleep(Duration::from_millis(20)).await;
+            assert!(!waiting.is_finished());
+
+            drop(foreign);
+            assert_eq!(waiting.await.unwrap(), (0, 1));
+            assert!(table.files.borrow().is_empty());
+        });
+    }
+}
@@ -1 +1,32 @@
+pub mod background;
+pub mod barrier;
+pub mod cancel;
+pub mod channel;
+pub mod command_queue;
+pub mod eventfd_notify;
+pub mod fd_broadcast;
 pub mod flock;
+pub mod flush_barrier;
+pub mod handover;
+pub mod kernel_semaphore;
+pub mod lock;
+pub mod lock_gc;
+pub mod lock_table;
+pub mod named_event;
+pub mod once;
+pub mod oneshot;
+pub mod path_handle;
+pub mod range_lock;
+pub mod rate;
+pub mod registry;
+pub mod rotating;
+pub mod semaphore;
+pub mod shm_handshake;
+pub mod shm_value;
+pub mod suspend;
+pub mod tracked_cell;
+pub mod wait_group;
+pub mod watch;
+pub mod watchdog;
+
+pub(crate) mod waiters;
@@ -0,0 +1,172 @@
+use crate::{
+    net::{UnixListener, UnixStream},
+    runtime::{JoinHandle, spawn_local},
+    utils::eventfd_notify::{EventFdNotifier, EventFdNotify},
+};
+use rustix::fd::AsFd;
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+};
+
+/// An [`EventFdNotify`] shared between unrelated processes by name.
+///
+/// [`create`](Self::create) makes the eventfd and serves it on a socket
+/// named after the event in `$XDG_RUNTIME_DIR`, or the temporary directory
+/// if that is unset; [`open`](Self::open) connects to that socket and
+/// receives the same eventfd. Every side then has the usual
+/// [`notify`](Self::notify) and [`notified`](Self::notified).
+///
+/// As with any eventfd, a wakeup is consumed by whichever side reads it
+/// first, so each event should have a single waiting process.
+#[derive(Debug)]
+pub struct NamedEvent {
+    notify: EventFdNotify,
+    /// Held by [`create`](Self::create) for the event's lifetime.
+    _server: Option<Server>,
+}
+
+/// Hands out the eventfd to openers while the creating [`NamedEvent`] lives.
+#[derive(Debug)]
+struct Server {
+    task: JoinHandle<()>,
+    path: PathBuf,
+}
+
+impl NamedEvent {
+    /// Create the event `name`, replacing one left by a dead process.
+    ///
+    /// Fails with `AddrInUse` if a live process serves the name already.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside [`LocalExecutor::block_on`](crate::runtime::LocalExecutor::block_on),
+    /// which runs the task serving openers.
+    pub async fn create(name: &str) -> io::Result<Self> {
+        let path = socket_path(name)?;
+        let listener = UnixListener::bind_replace(&path, 0o600).await?;
+        let notify = EventFdNotify::new()?;
+        let notifier = notify.notifier();
+
+        let task = spawn_local(async move {
+            loop {
+                match listener.accept().await {
+                    Ok(conn) => serve(&conn, &notifier).await,
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionAborted => {}
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(Self {
+            notify,
+            _server: Some(Server { task, path }),
+        })
+    }
+
+    /// Open the event `name` made by [`create`](Self::create), possibly in
+    /// another process.
+    ///
+    /// Fails with `NotFound` or `ConnectionRefused` if it was never created
+    /// or its creator is gone.
+    pub async fn open(name: &str) -> io::Result<Self> {
+        let conn = UnixStream::connect(socket_path(name)?).await?;
+        let mut fds = Vec::new();
+
+        conn.recv_with_fds(&mut [0], &mut fds).await?;
+
+        let fd = fds
+            .pop()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No eventfd received"))?;
+
+        Ok(Self {
+            notify: EventFdNotify::from_fd(fd)?,
+            _server: None,
+        })
+    }
+
+    /// A handle for notifying from other threads.
+    pub fn notifier(&self) -> EventFdNotifier {
+        self.notify.notifier()
+    }
+
+    /// Wake the process waiting on the event, possibly this one.
+    pub fn notify(&self) {
+        self.notify.notify();
+    }
+
+    /// Wait for notifications, returning how many arrived since the last call.
+    pub async fn notified(&self) -> u64 {
+        self.notify.notified().await
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn serve(conn: &UnixStream, notifier: &EventFdNotifier) {
+    // An opener that went away already is not our problem.
+    let _ = conn.send_with_fds(b"e", &[notifier.as_fd()]).await;
+}
+
+fn socket_path(name: &str) -> io::Result<PathBuf> {
+    if name.is_empty() || name.contains('/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid event name",
+        ));
+    }
+
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+
+    Ok(Path::new(&dir).join(format!("ars-event-{name}.sock")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+
+    fn unique(name: &str) -> String {
+        format!("{name}-{}", std::process::id())
+    }
+
+    #[test]
+    fn opened_event_shares_the_eventfd() {
+        LocalExecutor::new().block_on(async {
+            let name = unique("shared");
+            let created = NamedEvent::create(&name).await.unwrap();
+            let opened = NamedEvent::open(&name).await.unwrap();
+
+            opened.notify();
+            opened.notify();
+            assert_eq!(created.notified().await, 2);
+
+            created.notifier().notify_from_any_thread();
+            assert_eq!(opened.notified().await, 1);
+
+            let err = NamedEvent::create(&name).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        });
+    }
+
+    #[test]
+    fn open_fails_once_creator_is_gone() {
+        LocalExecutor::new().block_on(async {
+            let name = unique("gone");
+            drop(NamedEvent::create(&name).await.unwrap());
+
+            assert!(NamedEvent::open(&name).await.is_err());
+            assert_eq!(
+                NamedEvent::open("a/b").await.unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        });
+    }
+}
@@ -0,0 +1,205 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    cell, fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// A cell initialized once by an async initializer.
+///
+/// When several tasks call [`get_or_init`](Self::get_or_init) at once, the
+/// first runs its initializer and the others park until the value is set.
+/// If the running initializer is cancelled, a parked caller takes over with
+/// its own.
+pub struct OnceCell<T> {
+    value: cell::OnceCell<T>,
+    state: TrackedRefCell<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    initializing: bool,
+    waiters: WaiterList,
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> OnceCell<T> {
+    /// Create an empty cell.
+    pub fn new() -> Self {
+        Self {
+            value: cell::OnceCell::new(),
+            state: TrackedRefCell::default(),
+        }
+    }
+
+    /// The value, if set.
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    /// Set the value, returning it back if the cell was already set.
+    ///
+    /// Callers parked in [`get_or_init`](Self::get_or_init) receive this
+    /// value; an initializer still running has its result dropped.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.value.set(value)?;
+        self.wake_all();
+        Ok(())
+    }
+
+    /// The value, running `init` to produce it if the cell is empty and no
+    /// other initializer is running.
+    pub async fn get_or_init<F, Fut>(&self, init: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        loop {
+            if let Some(value) = self.value.get() {
+                return value;
+            }
+
+            if !self.state.borrow().initializing {
+                break;
+            }
+
+            Initialized {
+                cell: self,
+                key: None,
+            }
+            .await;
+        }
+
+        self.state.borrow_mut().initializing = true;
+        let guard = InitGuard { cell: self };
+
+        // Loses to a concurrent `set`, in which case `value` is dropped.
+        let _ = self.value.set(init().await);
+        drop(guard);
+
+        self.value.get().expect("set above")
+    }
+
+    /// Take the value out of the cell.
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+
+    fn wake_all(&self) {
+        let waiters = self.state.borrow_mut().waiters.take_all();
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnceCell")
+            .field("value", &self.value.get())
+            .field("initializing", &self.state.borrow().initializing)
+            .finish()
+    }
+}
+
+/// Ends an initialization, completed or cancelled, and wakes parked callers.
+struct InitGuard<'a, T> {
+    cell: &'a OnceCell<T>,
+}
+
+impl<T> Drop for InitGuard<'_, T> {
+    fn drop(&mut self) {
+        self.cell.state.borrow_mut().initializing = false;
+        self.cell.wake_all();
+    }
+}
+
+/// Waits until the cell is set or no initializer is running.
+struct Initialized<'a, T> {
+    cell: &'a OnceCell<T>,
+    key: Option<u64>,
+}
+
+impl<T> Future for Initialized<'_, T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let cell = self.cell;
+        let mut state = cell.state.borrow_mut();
+
+        if cell.value.get().is_some() || !state.initializing {
+            state.waiters.remove(self.key.take());
+            return Poll::Ready(());
+        }
+
+        state.waiters.register(&mut self.key, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Initialized<'_, T> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.cell.state.borrow_mut().waiters.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::LocalExecutor,
+        time::{sleep, timeout},
+    };
+    use std::{cell::Cell, time::Duration};
+
+    #[test]
+    fn concurrent_callers_share_one_initialization() {
+        LocalExecutor::new().block_on(async {
+            let cell = OnceCell::new();
+            let runs = Cell::new(0);
+            let init = || async {
+                runs.set(runs.get() + 1);
+                sleep(Duration::from_millis(2)).await;
+                runs.get()
+            };
+
+            let (a, b) = crate::join!(cell.get_or_init(init), cell.get_or_init(init));
+
+            assert_eq!((*a, *b), (1, 1));
+            assert_eq!(runs.get(), 1);
+            assert_eq!(cell.set(9), Err(9));
+        });
+    }
+
+    #[test]
+    fn cancelled_initializer_hands_over() {
+        LocalExecutor::new().block_on(async {
+            let cell = OnceCell::new();
+
+            let slow = timeout(
+                Duration::from_millis(2),
+                cell.get_or_init(|| async {
+                    sleep(Duration::from_secs(60)).await;
+                    "slow"
+                }),
+            );
+            let fallback = cell.get_or_init(|| async { "fallback" });
+
+            let (slow, fallback) = crate::join!(slow, fallback);
+
+            assert!(slow.is_err());
+            assert_eq!(*fallback, "fallback");
+            assert_eq!(cell.into_inner(), Some("fallback"));
+        });
+    }
+}
@@ -0,0 +1,341 @@
+use crate::utils::tracked_cell::TrackedRefCell;
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Create a single-threaded channel carrying exactly one value.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(TrackedRefCell::new(Shared::new()));
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+impl<T> Shared<T> {
+    fn new() -> Self {
+        Self {
+            value: None,
+            waker: None,
+            sender_alive: true,
+            receiver_alive: true,
+        }
+    }
+}
+
+/// The sending half of a oneshot [`channel`].
+#[derive(Debug)]
+pub struct Sender<T> {
+    shared: Rc<TrackedRefCell<Shared<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Send the value, consuming the sender.
+    ///
+    /// Fails, giving the value back, if the receiver has been dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        send(&self.shared, value)
+    }
+
+    /// Whether the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.borrow().receiver_alive
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        drop_sender(&self.shared);
+    }
+}
+
+/// The receiving half of a oneshot [`channel`].
+///
+/// Awaiting it resolves to the sent value, or to [`Closed`] if the
+/// [`Sender`] was dropped without sending.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    shared: Rc<TrackedRefCell<Shared<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// Take the value if it has been sent, without waiting.
+    ///
+    /// Returns `Ok(None)` while the sender is still alive.
+    pub fn try_recv(&mut self) -> Result<Option<T>, Closed> {
+        try_recv(&self.shared)
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Closed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        poll_recv(&self.shared, cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        drop_receiver(&self.shared);
+    }
+}
+
+/// Caller-provided storage for one oneshot channel at a time.
+///
+/// [`channel`](Self::channel) hands out a sender and receiver borrowing
+/// the slot instead of sharing an `Rc`, so a slot kept in a request struct
+/// or on the stack makes each round trip allocation-free. The borrow keeps
+/// the slot in place and unused by anything else until both halves are
+/// dropped, after which it can be reused.
+#[derive(Debug)]
+pub struct Slot<T> {
+    shared: TrackedRefCell<Shared<T>>,
+}
+
+/// The sending half of a channel in a [`Slot`].
+#[derive(Debug)]
+pub struct SlotSender<'a, T> {
+    shared: &'a TrackedRefCell<Shared<T>>,
+}
+
+/// The receiving half of a channel in a [`Slot`]; awaits like [`Receiver`].
+#[derive(Debug)]
+pub struct SlotReceiver<'a, T> {
+    shared: &'a TrackedRefCell<Shared<T>>,
+}
+
+impl<T> Slot<T> {
+    /// Create empty storage.
+    pub fn new() -> Self {
+        Self {
+            shared: TrackedRefCell::new(Shared::new()),
+        }
+    }
+
+    /// Start a fresh channel in this slot, dropping any value left unreceived
+    /// by the previous one.
+    pub fn channel(&mut self) -> (SlotSender<'_, T>, SlotReceiver<'_, T>) {
+        *self.shared.get_mut() = Shared::new();
+
+        (
+            SlotSender {
+                shared: &self.shared,
+            },
+            SlotReceiver {
+                shared: &self.shared,
+            },
+        )
+    }
+}
+
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SlotSender<'_, T> {
+    /// Send the value, consuming the sender.
+    ///
+    /// Fails, giving the value back, if the receiver has been dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        send(self.shared, value)
+    }
+
+    /// Whether the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.borrow().receiver_alive
+    }
+}
+
+impl<T> Drop for SlotSender<'_, T> {
+    fn drop(&mut self) {
+        drop_sender(self.shared);
+    }
+}
+
+impl<T> SlotReceiver<'_, T> {
+    /// Take the value if it has been sent, without waiting.
+    pub fn try_recv(&mut self) -> Result<Option<T>, Closed> {
+        try_recv(self.shared)
+    }
+}
+
+impl<T> Future for SlotReceiver<'_, T> {
+    type Output = Result<T, Closed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        poll_recv(self.shared, cx)
+    }
+}
+
+impl<T> Drop for SlotReceiver<'_, T> {
+    fn drop(&mut self) {
+        drop_receiver(self.shared);
+    }
+}
+
+fn send<T>(shared: &TrackedRefCell<Shared<T>>, value: T) -> Result<(), T> {
+    let waker = {
+        let mut shared = shared.borrow_mut();
+
+        if !shared.receiver_alive {
+            return Err(value);
+        }
+
+        shared.value = Some(value);
+        shared.waker.take()
+    };
+
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+
+    Ok(())
+}
+
+fn drop_sender<T>(shared: &TrackedRefCell<Shared<T>>) {
+    let waker = {
+        let mut shared = shared.borrow_mut();
+        shared.sender_alive = false;
+        shared.waker.take()
+    };
+
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+fn try_recv<T>(shared: &TrackedRefCell<Shared<T>>) -> Result<Option<T>, Closed> {
+    let mut shared = shared.borrow_mut();
+
+    match shared.value.take() {
+        Some(value) => Ok(Some(value)),
+        None if shared.sender_alive => Ok(None),
+        None => Err(Closed),
+    }
+}
+
+fn poll_recv<T>(
+    shared: &TrackedRefCell<Shared<T>>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<T, Closed>> {
+    let mut shared = shared.borrow_mut();
+
+    if let Some(value) = shared.value.take() {
+        return Poll::Ready(Ok(value));
+    }
+
+    if !shared.sender_alive {
+        return Poll::Ready(Err(Closed));
+    }
+
+    match &mut shared.waker {
+        Some(waker) if waker.will_wake(cx.waker()) => {}
+        slot => *slot = Some(cx.waker().clone()),
+    }
+
+    Poll::Pending
+}
+
+fn drop_receiver<T>(shared: &TrackedRefCell<Shared<T>>) {
+    let value = {
+        let mut shared = shared.borrow_mut();
+        shared.receiver_alive = false;
+        shared.value.take()
+    };
+
+    drop(value);
+}
+
+/// Error returned when the [`Sender`] is dropped without sending.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("oneshot sender dropped")
+    }
+}
+
+impl Error for Closed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn delivers_value_and_wakes_receiver() {
+        let (tx, rx) = channel();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut rx = pin!(rx);
+
+        assert!(rx.as_mut().poll(&mut cx).is_pending());
+        tx.send(5).unwrap();
+
+        assert_eq!(counter.count(), 1);
+        assert_eq!(rx.as_mut().poll(&mut cx), Poll::Ready(Ok(5)));
+    }
+
+    #[test]
+    fn dropped_sender_closes() {
+        let (tx, mut rx) = channel::<u32>();
+        assert_eq!(rx.try_recv(), Ok(None));
+
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(Closed));
+    }
+
+    #[test]
+    fn send_fails_once_receiver_is_gone() {
+        let (tx, rx) = channel();
+        drop(rx);
+
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(1), Err(1));
+    }
+
+    #[test]
+    fn slot_is_reused_across_channels() {
+        let mut slot = Slot::new();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        for i in 0..3 {
+            let (tx, rx) = slot.channel();
+            let mut rx = pin!(rx);
+
+            assert!(rx.as_mut().poll(&mut cx).is_pending());
+            tx.send(i).unwrap();
+            assert_eq!(rx.as_mut().poll(&mut cx), Poll::Ready(Ok(i)));
+        }
+
+        assert_eq!(counter.count(), 3);
+
+        let (tx, mut rx) = slot.channel();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(Closed));
+    }
+}
@@ -0,0 +1,133 @@
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    fs::{self, AtFlags, Mode, OFlags, Statx, StatxFlags},
+};
+use std::{ffi::OsString, io, os::unix::ffi::OsStringExt, path::Path, path::PathBuf};
+
+/// An `O_PATH` handle to a file or directory.
+///
+/// All operations resolve paths relative to the handle, so the handle
+/// can be used to manipulate a directory tree without racing against
+/// renames of its ancestors.
+#[derive(Debug)]
+pub struct PathHandle {
+    fd: OwnedFd,
+}
+
+impl PathHandle {
+    /// Open a handle to `path`, following symlinks.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        open_path(fs::CWD, path)
+    }
+
+    /// Open a handle to `path` relative to this one, following symlinks.
+    pub fn at(&self, path: &Path) -> io::Result<Self> {
+        open_path(self.fd.as_fd(), path)
+    }
+
+    /// Open `path` relative to this handle for IO.
+    ///
+    /// `O_CLOEXEC` is always added to `flags`.
+    pub fn openat(&self, path: &Path, flags: OFlags, mode: Mode) -> io::Result<OwnedFd> {
+        fs::openat(&self.fd, path, flags | OFlags::CLOEXEC, mode)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    /// Query `statx` for `path` relative to this handle.
+    ///
+    /// An empty `path` refers to the handle itself.
+    pub fn statx(&self, path: &Path, flags: AtFlags, mask: StatxFlags) -> io::Result<Statx> {
+        let flags = if path.as_os_str().is_empty() {
+            flags | AtFlags::EMPTY_PATH
+        } else {
+            flags
+        };
+
+        fs::statx(&self.fd, path, flags, mask)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+
+    /// Read the target of the symlink at `path` relative to this handle.
+    ///
+    /// An empty `path` reads the handle itself, which must then have been
+    /// opened on a symlink.
+    pub fn readlinkat(&self, path: &Path) -> io::Result<PathBuf> {
+        let target = fs::readlinkat(&self.fd, path, Vec::new())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(OsString::from_vec(target.into_bytes()).into())
+    }
+
+    /// Create a hard link at `new` relative to `new_dir` for `old` relative
+    /// to this handle.
+    pub fn linkat(&self, old: &Path, new_dir: &PathHandle, new: &Path) -> io::Result<()> {
+        fs::linkat(&self.fd, old, &new_dir.fd, new, AtFlags::empty())
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))
+    }
+}
+
+impl AsFd for PathHandle {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl From<PathHandle> for OwnedFd {
+    fn from(handle: PathHandle) -> Self {
+        handle.fd
+    }
+}
+
+fn open_path(dir: BorrowedFd<'_>, path: &Path) -> io::Result<PathHandle> {
+    let fd = fs::openat(dir, path, OFlags::PATH | OFlags::CLOEXEC, Mode::empty())
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+    Ok(PathHandle { fd })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn resolves_relative_to_handle() {
+        let dir = TempDir::new();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/file"), b"data").unwrap();
+
+        let root = PathHandle::open(dir.path()).unwrap();
+        let sub = root.at(Path::new("sub")).unwrap();
+
+        // Renaming the directory does not invalidate the handle.
+        std::fs::rename(dir.join("sub"), dir.join("moved")).unwrap();
+
+        let stat = sub
+            .statx(Path::new("file"), AtFlags::empty(), StatxFlags::SIZE)
+            .unwrap();
+        assert_eq!(stat.stx_size, 4);
+
+        let fd = sub
+            .openat(Path::new("file"), OFlags::RDONLY, Mode::empty())
+            .unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(rustix::io::read(&fd, &mut buf), Ok(4));
+    }
+
+    #[test]
+    fn reads_links_and_creates_hard_links() {
+        let dir = TempDir::new();
+        std::fs::write(dir.join("file"), b"").unwrap();
+        std::os::unix::fs::symlink("file", dir.join("link")).unwrap();
+
+        let root = PathHandle::open(dir.path()).unwrap();
+        assert_eq!(
+            root.readlinkat(Path::new("link")).unwrap(),
+            Path::new("file")
+        );
+
+        root.linkat(Path::new("file"), &root, Path::new("hard"))
+            .unwrap();
+        assert!(dir.join("hard").exists());
+    }
+}
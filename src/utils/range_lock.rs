@@ -0,0 +1,163 @@
+use crate::utils::flock;
+use rustix::{
+    fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+    fs::{self, Mode, OFlags},
+};
+use std::{io, mem, path::Path};
+
+/// A RAII byte-range lock using open file description (OFD) locks.
+///
+/// Unlike [`Flock`](super::flock::Flock), the lock only covers `len` bytes
+/// from `offset`, where a `len` of 0 extends to the end of the file.
+/// Each [`RangeLock`] owns its own open file description, so overlapping
+/// locks conflict even within one process.
+#[derive(Debug)]
+pub struct RangeLock {
+    fd: OwnedFd,
+    offset: u64,
+    len: u64,
+}
+
+impl RangeLock {
+    /// Acquire an exclusive lock on a byte range of a file.
+    ///
+    /// If the file does not exist, it will be created.
+    /// The lock is released when the returned [`RangeLock`] is dropped.
+    pub fn lock(path: &Path, offset: u64, len: u64) -> io::Result<Self> {
+        Self::lock_with(path, OFlags::WRONLY, libc::F_WRLCK, offset, len, false)
+    }
+
+    /// Acquire a shared lock on a byte range of a file.
+    pub fn lock_shared(path: &Path, offset: u64, len: u64) -> io::Result<Self> {
+        Self::lock_with(path, OFlags::RDONLY, libc::F_RDLCK, offset, len, false)
+    }
+
+    /// Acquire an exclusive lock on a byte range, waiting until it is available.
+    pub fn lock_blocking(path: &Path, offset: u64, len: u64) -> io::Result<Self> {
+        Self::lock_with(path, OFlags::WRONLY, libc::F_WRLCK, offset, len, true)
+    }
+
+    /// Acquire a shared lock on a byte range, waiting until it is available.
+    pub fn lock_shared_blocking(path: &Path, offset: u64, len: u64) -> io::Result<Self> {
+        Self::lock_with(path, OFlags::RDONLY, libc::F_RDLCK, offset, len, true)
+    }
+
+    /// The locked range as `(offset, len)`.
+    pub fn range(&self) -> (u64, u64) {
+        (self.offset, self.len)
+    }
+
+    fn lock_with(
+        path: &Path,
+        access: OFlags,
+        kind: libc::c_int,
+        offset: u64,
+        len: u64,
+        wait: bool,
+    ) -> io::Result<Self> {
+        loop {
+            let fd = fs::openat(
+                fs::CWD,
+                path,
+                OFlags::CREATE | OFlags::CLOEXEC | access,
+                Mode::RUSR | Mode::WUSR,
+            )
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+            set_lock(fd.as_fd(), kind, offset, len, wait).map_err(|e| match e.raw_os_error() {
+                Some(libc::EAGAIN | libc::EACCES) => {
+                    io::Error::new(io::ErrorKind::AddrInUse, "Lock already held")
+                }
+                _ => e,
+            })?;
+
+            // The file may have been unlinked, e.g. by `lock_gc`, between
+            // opening and locking it; a lock on it would protect nothing.
+            if flock::is_current(&fd, path)? {
+                return Ok(Self { fd, offset, len });
+            }
+        }
+    }
+}
+
+impl Drop for RangeLock {
+    fn drop(&mut self) {
+        let _ = set_lock(self.fd.as_fd(), libc::F_UNLCK, self.offset, self.len, false);
+    }
+}
+
+fn set_lock(
+    fd: BorrowedFd<'_>,
+    kind: libc::c_int,
+    offset: u64,
+    len: u64,
+    wait: bool,
+) -> io::Result<()> {
+    let invalid = |_| io::Error::new(io::ErrorKind::InvalidInput, "Lock range out of bounds");
+
+    // SAFETY: `flock` is a plain C struct for which all-zeroes is valid;
+    // OFD locks additionally require `l_pid` to be 0.
+    let mut fl: libc::flock = unsafe { mem::zeroed() };
+    fl.l_type = kind as _;
+    fl.l_whence = libc::SEEK_SET as _;
+    fl.l_start = offset.try_into().map_err(invalid)?;
+    fl.l_len = len.try_into().map_err(invalid)?;
+
+    let cmd = if wait {
+        libc::F_OFD_SETLKW
+    } else {
+        libc::F_OFD_SETLK
+    };
+
+    loop {
+        // SAFETY: `fd` is a valid descriptor and `fl` outlives the call.
+        if unsafe { libc::fcntl(fd.as_raw_fd(), cmd, &fl) } == 0 {
+            return Ok(());
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn overlapping_ranges_conflict_within_process() {
+        let dir = TempDir::new();
+        let path = dir.join("ranges");
+
+        let first = RangeLock::lock(&path, 0, 10).unwrap();
+        let err = RangeLock::lock(&path, 5, 10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        let _disjoint = RangeLock::lock(&path, 10, 10).unwrap();
+
+        drop(first);
+        let second = RangeLock::lock(&path, 5, 5).unwrap();
+        assert_eq!(second.range(), (5, 5));
+    }
+
+    #[test]
+    fn shared_locks_coexist() {
+        let dir = TempDir::new();
+        let path = dir.join("ranges");
+        drop(RangeLock::lock(&path, 0, 0).unwrap());
+
+        let _a = RangeLock::lock_shared(&path, 0, 0).unwrap();
+        let _b = RangeLock::lock_shared(&path, 0, 0).unwrap();
+        assert!(RangeLock::lock(&path, 100, 1).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_bounds_range() {
+        let dir = TempDir::new();
+        let err = RangeLock::lock(&dir.join("ranges"), u64::MAX, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::time::{self, sleep_until};
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+/// A token-bucket rate limiter.
+///
+/// Tokens accrue at a fixed rate up to a burst capacity. Each acquisition
+/// reserves its tokens on the timeline right away and sleeps until they
+/// are due, so callers are served in FIFO order and a steady caller keeps
+/// the exact rate instead of drifting like a loop of sleeps.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Time to accrue one token.
+    interval: Duration,
+    burst: u32,
+    /// When the bucket will be full again, given all reservations so far.
+    full_at: Cell<Instant>,
+}
+
+impl RateLimiter {
+    /// Allow `rate` tokens per second, with up to `burst` available at once.
+    ///
+    /// The bucket starts full. Tokens are timed with nanosecond
+    /// resolution, so rates above 10⁹ per second are capped at that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` or `burst` is zero.
+    pub fn new(rate: u32, burst: u32) -> Self {
+        assert!(rate > 0 && burst > 0, "rate and burst must be positive");
+
+        Self {
+            interval: (Duration::from_secs(1) / rate).max(Duration::from_nanos(1)),
+            burst,
+            full_at: Cell::new(time::now()),
+        }
+    }
+
+    /// Take `n` tokens, waiting until they are available.
+    ///
+    /// Dropping the future before it completes gives the tokens back if no
+    /// later acquisition has been made in the meantime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` exceeds the burst capacity, as it could never be granted.
+    pub async fn acquire(&self, n: u32) {
+        assert!(n <= self.burst, "cannot acquire more tokens than the burst");
+
+        let now = time::now();
+        let full_at = self.full_at.get().max(now) + self.interval * n;
+        self.full_at.set(full_at);
+
+        // The tokens are due once the bucket has room for the whole burst
+        // again, counting this reservation.
+        let due = full_at
+            .checked_sub(self.interval * self.burst)
+            .unwrap_or(now);
+
+        if due <= now {
+            return;
+        }
+
+        let refund = Refund {
+            limiter: self,
+            full_at,
+            n,
+        };
+
+        sleep_until(due).await;
+        std::mem::forget(refund);
+    }
+
+    /// Take `n` tokens if they are available now.
+    pub fn try_acquire(&self, n: u32) -> bool {
+        let now = time::now();
+        let full_at = self.full_at.get().max(now) + self.interval * n;
+
+        if full_at > now + self.interval * self.burst {
+            return false;
+        }
+
+        self.full_at.set(full_at);
+        true
+    }
+
+    /// Tokens that could be acquired without waiting.
+    pub fn available(&self) -> u32 {
+        let deficit = self.full_at.get().saturating_duration_since(time::now());
+        let missing = deficit.as_nanos().div_ceil(self.interval.as_nanos());
+
+        self.burst
+            .saturating_sub(missing.min(u32::MAX as u128) as u32)
+    }
+}
+
+/// Returns the tokens of a cancelled acquisition, if it was the latest.
+struct Refund<'a> {
+    limiter: &'a RateLimiter,
+    full_at: Instant,
+    n: u32,
+}
+
+impl Drop for Refund<'_> {
+    fn drop(&mut self) {
+        if self.limiter.full_at.get() == self.full_at {
+            self.limiter
+                .full_at
+                .set(self.full_at - self.limiter.interval * self.n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::LocalExecutor,
+        time::{TimerDriver, set_driver, timeout},
+    };
+    use std::{rc::Rc, task::Waker};
+
+    #[test]
+    fn starts_full_and_refuses_beyond_burst() {
+        LocalExecutor::new().block_on(async {
+            let limiter = RateLimiter::new(10, 3);
+            assert_eq!(limiter.available(), 3);
+
+            assert!(limiter.try_acquire(2));
+            assert!(limiter.try_acquire(1));
+            assert!(!limiter.try_acquire(1));
+            assert_eq!(limiter.available(), 0);
+        });
+    }
+
+    #[test]
+    fn huge_rate_is_capped_instead_of_dividing_by_zero() {
+        LocalExecutor::new().block_on(async {
+            let limiter = RateLimiter::new(2_000_000_000, 4);
+            assert_eq!(limiter.available(), 4);
+            assert!(limiter.try_acquire(4));
+            limiter.acquire(4).await;
+        });
+    }
+
+    #[test]
+    fn acquire_waits_for_tokens() {
+        LocalExecutor::new().block_on(async {
+            let limiter = RateLimiter::new(100, 1);
+            limiter.acquire(1).await;
+
+            let started = Instant::now();
+            limiter.acquire(1).await;
+            assert!(started.elapsed() >= Duration::from_millis(9));
+        });
+    }
+
+    #[test]
+    fn cancelled_acquire_refunds_tokens() {
+        LocalExecutor::new().block_on(async {
+            let limiter = RateLimiter::new(1, 1);
+            limiter.acquire(1).await;
+
+            let cancelled = timeout(Duration::from_millis(5), limiter.acquire(1)).await;
+            assert!(cancelled.is_err());
+
+            // Only the first token is owed, not the cancelled one too.
+            let deficit = limiter.full_at.get().saturating_duration_since(time::now());
+            assert!(deficit <= Duration::from_secs(1));
+        });
+    }
+
+    #[test]
+    fn refills_by_the_drivers_clock() {
+        struct Manual(Cell<Instant>);
+
+        impl TimerDriver for Manual {
+            fn register(&self, _: &mut Option<u64>, _: Instant, _: &Waker) {}
+
+            fn cancel(&self, _: u64) {}
+
+            fn now(&self) -> Instant {
+                self.0.get()
+            }
+        }
+
+        let driver = Rc::new(Manual(Cell::new(Instant::now())));
+        let previous = set_driver(Some(driver.clone()));
+
+        let limiter = RateLimiter::new(1, 2);
+        assert!(limiter.try_acquire(2));
+        assert_eq!(limiter.available(), 0);
+
+        // An hour of replayed time passes instantly.
+        driver.0.set(driver.0.get() + Duration::from_secs(3600));
+        assert_eq!(limiter.available(), 2);
+
+        set_driver(previous);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::utils::{cancel::CancellationToken, tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    hash::Hash,
+    ops::Deref,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+/// Per-key contexts, such as per-connection state, that remove themselves.
+///
+/// [`insert`](Self::insert) returns a [`Registered`] handle owning the
+/// entry: lookups find it until the handle is dropped, which removes it,
+/// cancels its [`token`](Registered::token) and wakes every task waiting
+/// in [`removed`](Self::removed) for that key. Other parts of a server can
+/// look a context up by key, ask its owner to stop through
+/// [`cancel`](Self::cancel), and wait for it to be gone.
+///
+/// Clones share the same entries.
+pub struct Registry<K, C> {
+    shared: Rc<TrackedRefCell<Shared<K, C>>>,
+}
+
+struct Shared<K, C> {
+    entries: HashMap<K, Entry<C>>,
+    /// Tells a re-inserted key from the entry a waiter was waiting for.
+    next_generation: u64,
+}
+
+struct Entry<C> {
+    ctx: Rc<C>,
+    token: CancellationToken,
+    generation: u64,
+    waiters: WaiterList,
+}
+
+/// Owns an entry of a [`Registry`]; dropping it removes the entry.
+#[must_use = "the entry is removed when the handle is dropped"]
+pub struct Registered<K: Eq + Hash, C> {
+    shared: Rc<TrackedRefCell<Shared<K, C>>>,
+    key: K,
+    ctx: Rc<C>,
+    token: CancellationToken,
+    generation: u64,
+}
+
+impl<K: Eq + Hash + Clone, C> Registry<K, C> {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self {
+            shared: Rc::new(TrackedRefCell::new(Shared {
+                entries: HashMap::new(),
+                next_generation: 0,
+            })),
+        }
+    }
+
+    /// Add `ctx` under `key`, or hand it back if the key is taken.
+    pub fn insert(&self, key: K, ctx: C) -> Result<Registered<K, C>, C> {
+        let mut shared = self.shared.borrow_mut();
+
+        if shared.entries.contains_key(&key) {
+            return Err(ctx);
+        }
+
+        shared.next_generation += 1;
+        let generation = shared.next_generation;
+        let ctx = Rc::new(ctx);
+        let token = CancellationToken::new();
+
+        shared.entries.insert(
+            key.clone(),
+            Entry {
+                ctx: ctx.clone(),
+                token: token.clone(),
+                generation,
+                waiters: WaiterList::new(),
+            },
+        );
+
+        Ok(Registered {
+            shared: self.shared.clone(),
+            key,
+            ctx,
+            token,
+            generation,
+        })
+    }
+
+    /// The context under `key`.
+    pub fn get(&self, key: &K) -> Option<Rc<C>> {
+        self.shared
+            .borrow()
+            .entries
+            .get(key)
+            .map(|entry| entry.ctx.clone())
+    }
+
+    /// Cancel the token of the entry under `key`, asking its owner to stop.
+    ///
+    /// Returns whether there was one. The entry stays until its handle is
+    /// dropped.
+    pub fn cancel(&self, key: &K) -> bool {
+        let token = self
+            .shared
+            .borrow()
+            .entries
+            .get(key)
+            .map(|entry| entry.token.clone());
+
+        token.map(|token| token.cancel()).is_some()
+    }
+
+    /// Wait until the entry now under `key` is removed; ready at once if
+    /// there is none.
+    pub fn removed(&self, key: &K) -> impl Future<Output = ()> + use<K, C> {
+        let generation = self
+            .shared
+            .borrow()
+            .entries
+            .get(key)
+            .map(|entry| entry.generation);
+
+        Removed {
+            shared: self.shared.clone(),
+            key: key.clone(),
+            generation,
+            waiter: None,
+        }
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.shared.borrow().entries.len()
+    }
+
+    /// Whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.shared.borrow().entries.is_empty()
+    }
+}
+
+impl<K: Eq + Hash + Clone, C> Default for Registry<K, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, C> Clone for Registry<K, C> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K, C> fmt::Debug for Registry<K, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("len", &self.shared.borrow().entries.len())
+            .finish()
+    }
+}
+
+impl<K: Eq + Hash, C> Registered<K, C> {
+    /// The entry's key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Cancelled by [`Registry::cancel`] and when this handle is dropped;
+    /// derive child tokens from it for the entry's tasks.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl<K: Eq + Hash, C> Deref for Registered<K, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.ctx
+    }
+}
+
+impl<K: Eq + Hash, C> Drop for Registered<K, C> {
+    fn drop(&mut self) {
+        let entry = {
+            let mut shared = self.shared.borrow_mut();
+            shared.entries.remove(&self.key)
+        };
+
+        if let Some(mut entry) = entry {
+            debug_assert_eq!(entry.generation, self.generation);
+
+            for waker in entry.waiters.take_all() {
+                waker.wake();
+            }
+        }
+
+        self.token.cancel();
+    }
+}
+
+impl<K: Eq + Hash + fmt::Debug, C> fmt::Debug for Registered<K, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registered")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Removed<K: Eq + Hash, C> {
+    shared: Rc<TrackedRefCell<Shared<K, C>>>,
+    key: K,
+    /// The entry waited for; `None` if there was none.
+    generation: Option<u64>,
+    waiter: Option<u64>,
+}
+
+// Nothing is pinned structurally.
+impl<K: Eq + Hash, C> Unpin for Removed<K, C> {}
+
+impl<K: Eq + Hash, C> Future for Removed<K, C> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let mut shared = this.shared.borrow_mut();
+
+        match shared.entries.get_mut(&this.key) {
+            Some(entry) if Some(entry.generation) == this.generation => {
+                entry.waiters.register(&mut this.waiter, cx.waker());
+                Poll::Pending
+            }
+            _ => {
+                this.waiter = None;
+                Poll::Ready(())
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash, C> Drop for Removed<K, C> {
+    fn drop(&mut self) {
+        if self.waiter.is_none() {
+            return;
+        }
+
+        if let Some(entry) = self.shared.borrow_mut().entries.get_mut(&self.key)
+            && Some(entry.generation) == self.generation
+        {
+            entry.waiters.remove(self.waiter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{LocalExecutor, spawn_local};
+
+    #[test]
+    fn handle_owns_the_entry() {
+        let registry = Registry::new();
+
+        let conn = registry.insert(7, "ctx").unwrap();
+        assert_eq!(*conn, "ctx");
+        assert_eq!(registry.insert(7, "other").unwrap_err(), "other");
+        assert_eq!(registry.get(&7).as_deref(), Some(&"ctx"));
+
+        let token = conn.token().child_token();
+        drop(conn);
+
+        assert!(registry.get(&7).is_none());
+        assert!(registry.is_empty());
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn listeners_see_removal_after_cancel() {
+        LocalExecutor::new().block_on(async {
+            let registry = Registry::new();
+            let conn = registry.insert("client", ()).unwrap();
+
+            let owner = spawn_local(async move {
+                conn.token().cancelled().await;
+                drop(conn);
+            });
+
+            assert!(registry.cancel(&"client"));
+            registry.removed(&"client").await;
+            assert!(registry.get(&"client").is_none());
+            owner.await.unwrap();
+
+            // A key that is not there is already removed.
+            registry.removed(&"client").await;
+        });
+    }
+}
@@ -0,0 +1,205 @@
+use crate::utils::flock::Flock;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Rotation thresholds for a [`RotatingWriter`].
+#[derive(Debug, Clone)]
+pub struct RotatePolicy {
+    /// Rotate before the file would grow beyond this many bytes.
+    pub max_size: Option<u64>,
+    /// Rotate once the file is older than this.
+    pub max_age: Option<Duration>,
+    /// Number of rotated files to keep (`app.log.1` .. `app.log.N`).
+    pub keep: usize,
+}
+
+impl Default for RotatePolicy {
+    fn default() -> Self {
+        Self {
+            max_size: Some(16 * 1024 * 1024),
+            max_age: None,
+            keep: 5,
+        }
+    }
+}
+
+/// A log file writer that rotates by size and age.
+///
+/// Rotation only happens at line boundaries, so a record written in
+/// several pieces (e.g. by `write!`) never straddles two files.
+///
+/// A [`Flock`] on `<path>.lock` is held for the lifetime of the writer,
+/// so a second process cannot write to or rotate the same log.
+#[derive(Debug)]
+pub struct RotatingWriter {
+    path: PathBuf,
+    policy: RotatePolicy,
+    file: File,
+    size: u64,
+    created: SystemTime,
+    line_start: bool,
+    _lock: Flock,
+}
+
+impl RotatingWriter {
+    /// Open `path` for appending, rotating according to `policy`.
+    pub fn open(path: impl Into<PathBuf>, policy: RotatePolicy) -> io::Result<Self> {
+        let path = path.into();
+        let lock = Flock::lock(&with_suffix(&path, "lock"))?;
+        let (file, size, created) = open_log(&path)?;
+
+        Ok(Self {
+            path,
+            policy,
+            file,
+            size,
+            created,
+            line_start: true,
+            _lock: lock,
+        })
+    }
+
+    /// Path of the live log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rotate now, regardless of the policy.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.policy.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..self.policy.keep).rev() {
+                let from = with_suffix(&self.path, &i.to_string());
+                if from.exists() {
+                    fs::rename(&from, with_suffix(&self.path, &(i + 1).to_string()))?;
+                }
+            }
+            fs::rename(&self.path, with_suffix(&self.path, "1"))?;
+        }
+
+        let (file, size, created) = open_log(&self.path)?;
+        self.file = file;
+        self.size = size;
+        self.created = created;
+
+        Ok(())
+    }
+
+    fn should_rotate(&self, incoming: usize) -> bool {
+        if self.size == 0 || !self.line_start {
+            return false;
+        }
+
+        let too_big = self
+            .policy
+            .max_size
+            .is_some_and(|max| self.size + incoming as u64 > max);
+
+        let too_old = self
+            .policy
+            .max_age
+            .is_some_and(|max| self.created.elapsed().is_ok_and(|age| age >= max));
+
+        too_big || too_old
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            self.rotate()?;
+        }
+
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+
+        if let Some(&last) = buf[..n].last() {
+            self.line_start = last == b'\n';
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_log(path: &Path) -> io::Result<(File, u64, SystemTime)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let meta = file.metadata()?;
+    let created = meta.created().unwrap_or_else(|_| SystemTime::now());
+
+    Ok((file, meta.len(), created))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".");
+    s.push(suffix);
+    s.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn policy(max_size: u64, keep: usize) -> RotatePolicy {
+        RotatePolicy {
+            max_size: Some(max_size),
+            max_age: None,
+            keep,
+        }
+    }
+
+    #[test]
+    fn rotates_by_size_at_line_boundaries() {
+        let dir = TempDir::new();
+        let path = dir.join("app.log");
+        let mut log = RotatingWriter::open(&path, policy(8, 2)).unwrap();
+
+        // The partial line stays in one file even past the limit.
+        write!(log, "aaaa").unwrap();
+        writeln!(log, "bbbbbb").unwrap();
+        log.write_all(b"cc\n").unwrap();
+        log.write_all(b"dddddddd\n").unwrap();
+        log.flush().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"dddddddd\n");
+        assert_eq!(fs::read(with_suffix(&path, "1")).unwrap(), b"cc\n");
+        assert_eq!(fs::read(with_suffix(&path, "2")).unwrap(), b"aaaabbbbbb\n");
+        assert!(!with_suffix(&path, "3").exists());
+    }
+
+    #[test]
+    fn second_writer_is_locked_out() {
+        let dir = TempDir::new();
+        let path = dir.join("app.log");
+        let _log = RotatingWriter::open(&path, RotatePolicy::default()).unwrap();
+
+        let err = RotatingWriter::open(&path, RotatePolicy::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn keep_zero_discards_old_log() {
+        let dir = TempDir::new();
+        let path = dir.join("app.log");
+        let mut log = RotatingWriter::open(&path, policy(1024, 0)).unwrap();
+
+        log.write_all(b"old\n").unwrap();
+        log.rotate().unwrap();
+        log.write_all(b"new\n").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new\n");
+        assert!(!with_suffix(&path, "1").exists());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// A single-threaded async counting semaphore.
+///
+/// Waiters are served strictly in FIFO order: a large request at the head
+/// of the queue is not overtaken by smaller ones behind it. Released
+/// permits are handed straight to the waiters at the head that they cover,
+/// before those are even woken, so nobody arriving in between can take
+/// them; each served waiter is woken once, and the rest stay asleep.
+#[derive(Debug)]
+pub struct Semaphore {
+    state: TrackedRefCell<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    permits: usize,
+    waiters: WaiterList,
+    /// Permits wanted by each queued waiter, under its waiter key.
+    wanted: BTreeMap<u64, usize>,
+    /// Waiters whose permits have been set aside but not yet picked up.
+    granted: BTreeSet<u64>,
+}
+
+impl Semaphore {
+    /// Create a semaphore with `permits` initial permits.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: TrackedRefCell::new(State {
+                permits,
+                waiters: WaiterList::new(),
+                wanted: BTreeMap::new(),
+                granted: BTreeSet::new(),
+            }),
+        }
+    }
+
+    /// Acquire `n` permits, waiting until they are available.
+    pub fn acquire(&self, n: usize) -> impl Future<Output = Permit<'_>> + '_ {
+        Acquire {
+            sem: self,
+            n,
+            key: None,
+        }
+    }
+
+    /// Like [`acquire`](Self::acquire), but the permits keep the semaphore
+    /// alive instead of borrowing it, so they can move into spawned tasks.
+    pub async fn acquire_owned(self: Rc<Self>, n: usize) -> OwnedPermit {
+        self.acquire(n).await.forget();
+
+        OwnedPermit { sem: self, n }
+    }
+
+    /// Acquire `n` permits without waiting.
+    ///
+    /// Fails if not enough permits are available or others are already waiting.
+    pub fn try_acquire(&self, n: usize) -> Option<Permit<'_>> {
+        let mut state = self.state.borrow_mut();
+
+        if state.permits < n || !state.wanted.is_empty() {
+            return None;
+        }
+
+        state.permits -= n;
+
+        Some(Permit { sem: self, n })
+    }
+
+    /// Add `n` permits, handing them to waiters as far as they go.
+    pub fn add_permits(&self, n: usize) {
+        let served = {
+            let mut state = self.state.borrow_mut();
+            state.permits += n;
+            state.grant()
+        };
+
+        for waker in served {
+            waker.wake();
+        }
+    }
+
+    /// Number of permits currently available, not counting ones handed to
+    /// waiters that have yet to pick them up.
+    pub fn available_permits(&self) -> usize {
+        self.state.borrow().permits
+    }
+}
+
+impl State {
+    /// Set permits aside for waiters from the head of the queue, returning
+    /// their wakers to be woken once the state is released.
+    fn grant(&mut self) -> Vec<Waker> {
+        let mut served = Vec::new();
+
+        while let Some((&key, &n)) = self.wanted.first_key_value() {
+            if self.permits < n {
+                break;
+            }
+
+            self.permits -= n;
+            self.wanted.remove(&key);
+            self.granted.insert(key);
+            // `wanted` and `waiters` hold the same keys, so this is `key`.
+            served.extend(self.waiters.pop());
+        }
+
+        served
+    }
+}
+
+/// RAII permits acquired from a [`Semaphore`], returned on drop.
+#[derive(Debug)]
+#[must_use = "permits are released immediately if unused"]
+pub struct Permit<'a> {
+    sem: &'a Semaphore,
+    n: usize,
+}
+
+impl Permit<'_> {
+    /// Number of permits held.
+    pub fn count(&self) -> usize {
+        self.n
+    }
+
+    /// Keep the permits out of the semaphore for good.
+    pub fn forget(mut self) {
+        self.n = 0;
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.n > 0 {
+            self.sem.add_permits(self.n);
+        }
+    }
+}
+
+/// Permits acquired by [`Semaphore::acquire_owned`], returned on drop.
+#[derive(Debug)]
+#[must_use = "permits are released immediately if unused"]
+pub struct OwnedPermit {
+    sem: Rc<Semaphore>,
+    n: usize,
+}
+
+impl OwnedPermit {
+    /// Number of permits held.
+    pub fn count(&self) -> usize {
+        self.n
+    }
+}
+
+impl Drop for OwnedPermit {
+    fn drop(&mut self) {
+        if self.n > 0 {
+            self.sem.add_permits(self.n);
+        }
+    }
+}
+
+struct Acquire<'a> {
+    sem: &'a Semaphore,
+    n: usize,
+    key: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Permit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let sem = self.sem;
+        let n = self.n;
+        let mut state = sem.state.borrow_mut();
+
+        match self.key {
+            Some(key) if state.granted.remove(&key) => {
+                self.key = None;
+                return Poll::Ready(Permit { sem, n });
+            }
+            None if state.permits >= n && state.wanted.is_empty() => {
+                state.permits -= n;
+                return Poll::Ready(Permit { sem, n });
+            }
+            _ => {}
+        }
+
+        state.waiters.register(&mut self.key, cx.waker());
+
+        if let Some(key) = self.key {
+            state.wanted.insert(key, n);
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(key) = self.key else {
+            return;
+        };
+
+        let served = {
+            let mut state = self.sem.state.borrow_mut();
+
+            // Permits handed over but never picked up go back to the pool.
+            if state.granted.remove(&key) {
+                state.permits += self.n;
+            } else {
+                state.waiters.remove(self.key);
+                state.wanted.remove(&key);
+            }
+
+            // We may have held up smaller requests behind us.
+            state.grant()
+        };
+
+        for waker in served {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn permits_return_on_drop() {
+        let sem = Semaphore::new(3);
+
+        let permit = sem.try_acquire(2).unwrap();
+        assert_eq!(sem.available_permits(), 1);
+        assert!(sem.try_acquire(2).is_none());
+
+        drop(permit);
+        assert_eq!(sem.available_permits(), 3);
+
+        sem.try_acquire(1).unwrap().forget();
+        assert_eq!(sem.available_permits(), 2);
+    }
+
+    #[test]
+    fn waiters_are_served_in_order() {
+        let sem = Semaphore::new(1);
+        let held = sem.try_acquire(1).unwrap();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut big = pin!(sem.acquire(2));
+        let mut small = pin!(sem.acquire(1));
+        assert!(big.as_mut().poll(&mut cx).is_pending());
+        assert!(small.as_mut().poll(&mut cx).is_pending());
+
+        // The small request does not overtake the big one at the head.
+        drop(held);
+        assert!(small.as_mut().poll(&mut cx).is_pending());
+
+        sem.add_permits(1);
+        let permit = match big.as_mut().poll(&mut cx) {
+            Poll::Ready(permit) => permit,
+            Poll::Pending => panic!("head waiter not served"),
+        };
+        assert_eq!(permit.count(), 2);
+    }
+
+    #[test]
+    fn cancelled_head_waiter_lets_the_next_through() {
+        let sem = Semaphore::new(1);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut big = Box::pin(sem.acquire(2));
+        let mut small = pin!(sem.acquire(1));
+        assert!(big.as_mut().poll(&mut cx).is_pending());
+        assert!(small.as_mut().poll(&mut cx).is_pending());
+
+        drop(big);
+
+        assert_eq!(counter.count(), 1);
+        assert!(small.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn cancelled_woken_waiter_passes_the_wakeup_on() {
+        let sem = Semaphore::new(0);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut first = Box::pin(sem.acquire(1));
+        let mut second = pin!(sem.acquire(1));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        sem.add_permits(1);
+        assert_eq!(counter.count(), 1);
+
+        // Woken but cancelled before it could take the permit.
+        drop(first);
+
+        assert_eq!(counter.count(), 2);
+        let permit = second.as_mut().poll(&mut cx);
+        assert!(permit.is_ready());
+        assert_eq!(sem.available_permits(), 0);
+    }
+
+    #[test]
+    fn released_permits_are_handed_over_without_barging() {
+        let sem = Semaphore::new(1);
+        let held = sem.try_acquire(1).unwrap();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut waiter = pin!(sem.acquire(1));
+        assert!(waiter.as_mut().poll(&mut cx).is_pending());
+
+        drop(held);
+        assert_eq!(counter.count(), 1);
+        assert_eq!(sem.available_permits(), 0);
+
+        // Arrivals between the release and the waiter's poll get nothing.
+        assert!(sem.try_acquire(1).is_none());
+        let mut late = pin!(sem.acquire(1));
+        assert!(late.as_mut().poll(&mut cx).is_pending());
+
+        let permit = waiter.as_mut().poll(&mut cx);
+        assert!(matches!(permit, Poll::Ready(ref p) if p.count() == 1));
+        assert_eq!(counter.count(), 1);
+        drop(permit);
+
+        assert_eq!(counter.count(), 2);
+        assert!(late.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn release_serves_as_many_head_waiters_as_it_covers() {
+        let sem = Semaphore::new(0);
+        let (a, b, c) = (
+            CountingWaker::new(),
+            CountingWaker::new(),
+            CountingWaker::new(),
+        );
+
+        let mut first = pin!(sem.acquire(1));
+        let mut second = pin!(sem.acquire(1));
+        let mut third = pin!(sem.acquire(2));
+        assert!(
+            first
+                .as_mut()
+                .poll(&mut Context::from_waker(&a.waker()))
+                .is_pending()
+        );
+        assert!(
+            second
+                .as_mut()
+                .poll(&mut Context::from_waker(&b.waker()))
+                .is_pending()
+        );
+        assert!(
+            third
+                .as_mut()
+                .poll(&mut Context::from_waker(&c.waker()))
+                .is_pending()
+        );
+
+        sem.add_permits(3);
+        assert_eq!((a.count(), b.count(), c.count()), (1, 1, 0));
+        assert_eq!(sem.available_permits(), 1);
+    }
+
+    #[test]
+    fn cancelled_served_waiter_returns_its_permits() {
+        let sem = Semaphore::new(0);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut served = Box::pin(sem.acquire(2));
+        assert!(served.as_mut().poll(&mut cx).is_pending());
+
+        sem.add_permits(2);
+        assert_eq!(sem.available_permits(), 0);
+
+        drop(served);
+        assert_eq!(sem.available_permits(), 2);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::net::UnixStream;
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    fs::{self, MemfdFlags, SealFlags},
+    mm::{self, MapFlags, ProtFlags},
+};
+use std::{io, ptr::NonNull};
+
+/// Version of the handshake header; bumped on incompatible changes.
+pub const VERSION: u32 = 1;
+
+const MAGIC: [u8; 4] = *b"ARSM";
+
+/// Magic, version and region size.
+const HEADER_LEN: usize = 16;
+
+/// Seals a region must carry before the receiver maps it.
+const REQUIRED_SEALS: SealFlags = SealFlags::SHRINK
+    .union(SealFlags::GROW)
+    .union(SealFlags::SEAL);
+
+/// Shares a sealed memfd over a [`UnixStream`].
+///
+/// [`offer`](Self::offer) creates a memfd of the requested size, seals it
+/// against resizing and sends it with a versioned header;
+/// [`accept`](Self::accept) receives it and checks the header, the seals
+/// and the size before mapping, so a peer cannot make us fault by
+/// shrinking the region under us or exhaust memory with a huge one.
+#[derive(Debug)]
+pub struct ShmHandshake;
+
+/// A shared memory region mapped read-write by both ends of a handshake.
+///
+/// The other process may change the contents at any time, so the region is
+/// only exposed through copies and raw pointers, never as a slice.
+#[derive(Debug)]
+pub struct ShmRegion {
+    fd: OwnedFd,
+    map: NonNull<u8>,
+    len: usize,
+}
+
+impl ShmHandshake {
+    /// Create a sealed region of `size` bytes and send it to the peer.
+    pub async fn offer(stream: &UnixStream, name: &str, size: usize) -> io::Result<ShmRegion> {
+        if size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "shared region must not be empty",
+            ));
+        }
+
+        let fd = fs::memfd_create(name, MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        fs::ftruncate(&fd, size as u64)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        fs::fcntl_add_seals(&fd, REQUIRED_SEALS)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        let region = ShmRegion::map(fd, size)?;
+
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..].copy_from_slice(&(size as u64).to_le_bytes());
+
+        let sent = stream.send_with_fds(&header, &[region.fd.as_fd()]).await?;
+        stream.write_all(&header[sent..]).await?;
+
+        Ok(region)
+    }
+
+    /// Receive a region offered by the peer and map it.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the header is malformed
+    /// or of another version, if not exactly one fd came with it, if the fd
+    /// is not sealed against resizing, or if its size does not match the
+    /// header or exceeds `max_size`.
+    pub async fn accept(stream: &UnixStream, max_size: usize) -> io::Result<ShmRegion> {
+        let mut header = [0; HEADER_LEN];
+        let mut fds = Vec::new();
+        let mut filled = 0;
+
+        while filled < HEADER_LEN {
+            match stream
+                .recv_with_fds(&mut header[filled..], &mut fds)
+                .await?
+            {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+
+        if header[..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a shared memory handshake",
+            ));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().expect("4 bytes"));
+
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported shared memory handshake version",
+            ));
+        }
+
+        let size = u64::from_le_bytes(header[8..].try_into().expect("8 bytes"));
+
+        let fd = match <[OwnedFd; 1]>::try_from(fds) {
+            Ok([fd]) => fd,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "expected exactly one fd with the handshake",
+                ));
+            }
+        };
+
+        let seals =
+            fs::fcntl_get_seals(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        if !seals.contains(REQUIRED_SEALS) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared region is not sealed",
+            ));
+        }
+
+        let stat = fs::fstat(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        if stat.st_size as u64 != size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared region size does not match the header",
+            ));
+        }
+
+        if size == 0 || size > max_size as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared region size out of bounds",
+            ));
+        }
+
+        ShmRegion::map(fd, size as usize)
+    }
+}
+
+impl ShmRegion {
+    fn map(fd: OwnedFd, len: usize) -> io::Result<Self> {
+        // SAFETY: A fresh shared mapping is created; no existing memory is
+        // affected.
+        let ptr = unsafe {
+            mm::mmap(
+                std::ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED,
+                &fd,
+                0,
+            )
+        }
+        .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        Ok(Self {
+            fd,
+            map: NonNull::new(ptr.cast()).expect("mmap returned null"),
+            len,
+        })
+    }
+
+    /// Size of the region in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the region is empty; never true for a mapped region.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The start of the mapping, valid until the region is dropped.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.map.as_ptr()
+    }
+
+    /// Copy bytes out of the region, starting at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range exceeds the region.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) {
+        assert!(
+            offset
+                .checked_add(buf.len())
+                .is_some_and(|end| end <= self.len),
+            "range exceeds the shared region"
+        );
+
+        // SAFETY: The range is within the live mapping, and `buf` cannot
+        // overlap it since the region is never exposed as a slice.
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.as_ptr().add(offset), buf.as_mut_ptr(), buf.len())
+        };
+    }
+
+    /// Copy bytes into the region, starting at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range exceeds the region.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) {
+        assert!(
+            offset
+                .checked_add(buf.len())
+                .is_some_and(|end| end <= self.len),
+            "range exceeds the shared region"
+        );
+
+        // SAFETY: As in `read_at`; the mapping is writable.
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), self.as_ptr().add(offset), buf.len())
+        };
+    }
+}
+
+impl AsFd for ShmRegion {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for ShmRegion {
+    fn drop(&mut self) {
+        // SAFETY: The mapping was created in `map` with this length and no
+        // borrows of it outlive the region.
+        let _ = unsafe { mm::munmap(self.map.as_ptr().cast(), self.len) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::LocalExecutor;
+
+    fn header(size: u64) -> [u8; HEADER_LEN] {
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..].copy_from_slice(&size.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn both_ends_share_the_region() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+
+            let offered = ShmHandshake::offer(&a, "test", 4096).await.unwrap();
+            let accepted = ShmHandshake::accept(&b, 4096).await.unwrap();
+            assert_eq!(accepted.len(), 4096);
+
+            offered.write_at(100, b"hello");
+            let mut buf = [0; 5];
+            accepted.read_at(100, &mut buf);
+            assert_eq!(&buf, b"hello");
+        });
+    }
+
+    #[test]
+    fn rejects_oversized_region() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+
+            let _offered = ShmHandshake::offer(&a, "test", 8192).await.unwrap();
+            let err = ShmHandshake::accept(&b, 4096).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test]
+    fn rejects_unsealed_region() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+
+            let fd = fs::memfd_create("test", MemfdFlags::CLOEXEC).unwrap();
+            fs::ftruncate(&fd, 4096).unwrap();
+            a.send_with_fds(&header(4096), &[fd.as_fd()]).await.unwrap();
+
+            let err = ShmHandshake::accept(&b, 4096).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test]
+    fn rejects_other_version_and_missing_fd() {
+        LocalExecutor::new().block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+
+            let mut bad = header(4096);
+            bad[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+            a.write_all(&bad).await.unwrap();
+            let err = ShmHandshake::accept(&b, 4096).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            a.write_all(&header(4096)).await.unwrap();
+            let err = ShmHandshake::accept(&b, 4096).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "range exceeds the shared region")]
+    fn out_of_range_access_panics() {
+        LocalExecutor::new().block_on(async {
+            let (a, _b) = UnixStream::pair().unwrap();
+
+            let region = ShmHandshake::offer(&a, "test", 16).await.unwrap();
+            region.write_at(10, &[0; 7]);
+        });
+    }
+}
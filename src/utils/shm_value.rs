@@ -0,0 +1,242 @@
+use rustix::{
+    fd::{AsFd, BorrowedFd, OwnedFd},
+    fs::{self, MemfdFlags, SealFlags},
+    mm::{self, MapFlags, ProtFlags},
+};
+use std::{
+    hint, io, mem,
+    ptr::{self, NonNull},
+    sync::atomic::{self, AtomicU64, Ordering},
+};
+
+/// Plain data that can be shared as raw bytes between processes.
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(C)]` or `#[repr(transparent)]`, contain no
+/// pointers or references, and be valid for every bit pattern, since a
+/// reader may observe a torn value before discarding it.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Layout of the shared mapping.
+#[repr(C)]
+struct Shared<T> {
+    /// Seqlock counter; odd while a write is in progress.
+    seq: AtomicU64,
+    value: T,
+}
+
+/// A `#[repr(C)]` value in a sealed memfd, written by this process.
+///
+/// The memfd is sealed against resizing, so the fd obtained through
+/// [`AsFd`] can be passed to another process and opened with
+/// [`ShmView::open`]. Every write bumps a version counter that readers use
+/// to detect torn reads.
+#[derive(Debug)]
+pub struct ShmValue<T: Pod> {
+    fd: OwnedFd,
+    map: NonNull<Shared<T>>,
+}
+
+impl<T: Pod> ShmValue<T> {
+    /// Create a new shared value holding `initial`.
+    pub fn create(name: &str, initial: T) -> io::Result<Self> {
+        let fd = fs::memfd_create(name, MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        fs::ftruncate(&fd, mem::size_of::<Shared<T>>() as u64)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        fs::fcntl_add_seals(&fd, SealFlags::SHRINK | SealFlags::GROW | SealFlags::SEAL)
+            .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        let map = map::<T>(fd.as_fd(), ProtFlags::READ | ProtFlags::WRITE)?;
+
+        // SAFETY: The mapping is freshly created, zero-filled and sized for
+        // `Shared<T>`; nobody else can have it mapped yet.
+        unsafe { ptr::addr_of_mut!((*map.as_ptr()).value).write_volatile(initial) };
+
+        Ok(Self { fd, map })
+    }
+
+    /// Store a new value and bump the version.
+    pub fn write(&mut self, value: T) {
+        let shared = self.map.as_ptr();
+
+        // SAFETY: The mapping is live for the lifetime of `self` and `&mut`
+        // makes this the only writer.
+        unsafe {
+            let seq = &(*shared).seq;
+            let start = seq.load(Ordering::Relaxed);
+
+            seq.store(start + 1, Ordering::Relaxed);
+            atomic::fence(Ordering::Release);
+            ptr::addr_of_mut!((*shared).value).write_volatile(value);
+            seq.store(start + 2, Ordering::Release);
+        }
+    }
+
+    /// Read the current value.
+    pub fn read(&self) -> T {
+        // SAFETY: Only `write` modifies the value, and it requires `&mut self`.
+        unsafe { ptr::addr_of!((*self.map.as_ptr()).value).read_volatile() }
+    }
+
+    /// Number of writes since creation.
+    pub fn version(&self) -> u64 {
+        // SAFETY: The mapping is live for the lifetime of `self`.
+        unsafe { (*self.map.as_ptr()).seq.load(Ordering::Acquire) / 2 }
+    }
+}
+
+impl<T: Pod> AsFd for ShmValue<T> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl<T: Pod> Drop for ShmValue<T> {
+    fn drop(&mut self) {
+        unmap(self.map);
+    }
+}
+
+/// A read-only view of a [`ShmValue`] owned by another process.
+#[derive(Debug)]
+pub struct ShmView<T: Pod> {
+    _fd: OwnedFd,
+    map: NonNull<Shared<T>>,
+}
+
+impl<T: Pod> ShmView<T> {
+    /// Map a memfd received from the process owning the [`ShmValue`].
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] unless the memfd is sealed
+    /// against resizing and has exactly the size of a shared `T`.
+    pub fn open(fd: OwnedFd) -> io::Result<Self> {
+        let seals =
+            fs::fcntl_get_seals(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        if !seals.contains(SealFlags::SHRINK | SealFlags::GROW) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Shared value is not sealed",
+            ));
+        }
+
+        let stat = fs::fstat(&fd).map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+        if stat.st_size as u64 != mem::size_of::<Shared<T>>() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Shared value has unexpected size",
+            ));
+        }
+
+        let map = map::<T>(fd.as_fd(), ProtFlags::READ)?;
+
+        Ok(Self { _fd: fd, map })
+    }
+
+    /// Read a consistent snapshot of the value together with its version.
+    ///
+    /// Retries while the writer is in the middle of an update.
+    pub fn read(&self) -> (T, u64) {
+        let shared = self.map.as_ptr();
+
+        loop {
+            // SAFETY: The mapping is live for the lifetime of `self`, and
+            // `T: Pod` makes even a torn value valid to hold until the
+            // sequence check rejects it.
+            unsafe {
+                let start = (*shared).seq.load(Ordering::Acquire);
+
+                if start.is_multiple_of(2) {
+                    let value = ptr::addr_of!((*shared).value).read_volatile();
+                    atomic::fence(Ordering::Acquire);
+
+                    if (*shared).seq.load(Ordering::Relaxed) == start {
+                        return (value, start / 2);
+                    }
+                }
+            }
+
+            hint::spin_loop();
+        }
+    }
+}
+
+impl<T: Pod> Drop for ShmView<T> {
+    fn drop(&mut self) {
+        unmap(self.map);
+    }
+}
+
+fn map<T>(fd: BorrowedFd<'_>, prot: ProtFlags) -> io::Result<NonNull<Shared<T>>> {
+    // SAFETY: A fresh shared mapping is created; no existing memory is
+    // affected.
+    let ptr = unsafe {
+        mm::mmap(
+            ptr::null_mut(),
+            mem::size_of::<Shared<T>>(),
+            prot,
+            MapFlags::SHARED,
+            fd,
+            0,
+        )
+    }
+    .map_err(|e| io::Error::from_raw_os_error(e.raw_os_error()))?;
+
+    Ok(NonNull::new(ptr.cast()).expect("mmap returned null"))
+}
+
+fn unmap<T>(map: NonNull<Shared<T>>) {
+    // SAFETY: The mapping was created by `map` with the same size and no
+    // borrows of it outlive its owner.
+    let _ = unsafe { mm::munmap(map.as_ptr().cast(), mem::size_of::<Shared<T>>()) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustix::io::fcntl_dupfd_cloexec;
+
+    #[test]
+    fn view_sees_writes_with_versions() {
+        let mut value = ShmValue::create("test", [1u32, 2]).unwrap();
+        let view = ShmView::<[u32; 2]>::open(fcntl_dupfd_cloexec(&value, 0).unwrap()).unwrap();
+
+        assert_eq!(view.read(), ([1, 2], 0));
+
+        value.write([3, 4]);
+        value.write([5, 6]);
+
+        assert_eq!(value.read(), [5, 6]);
+        assert_eq!(value.version(), 2);
+        assert_eq!(view.read(), ([5, 6], 2));
+    }
+
+    #[test]
+    fn rejects_unsealed_or_mismatched_memfd() {
+        let unsealed = fs::memfd_create("test", MemfdFlags::CLOEXEC).unwrap();
+        let err = ShmView::<u64>::open(unsealed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let value = ShmValue::create("test", 0u8).unwrap();
+        let fd = fcntl_dupfd_cloexec(&value, 0).unwrap();
+        let err = ShmView::<[u64; 4]>::open(fd).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,366 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Coordinates subsystems around a suspend/resume cycle.
+///
+/// [`suspend`](Self::suspend) notifies every [`SuspendListener`] and
+/// waits until each of them has acknowledged by dropping the
+/// [`SuspendAck`] it received, e.g. before a VT switch or system sleep.
+/// [`resume`](Self::resume) then wakes the listeners' `resumed()`.
+#[derive(Debug, Clone, Default)]
+pub struct SuspendResume {
+    state: Rc<TrackedRefCell<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    suspended: bool,
+    suspend_gen: u64,
+    resume_gen: u64,
+    listeners: usize,
+    pending_acks: usize,
+    listener_waiters: WaiterList,
+    notifier_waiters: WaiterList,
+}
+
+impl State {
+    fn ack(&mut self, generation: u64) -> impl Iterator<Item = Waker> + use<> {
+        if self.suspended && generation == self.suspend_gen && self.pending_acks > 0 {
+            self.pending_acks -= 1;
+        }
+
+        if self.pending_acks == 0 {
+            Some(self.notifier_waiters.take_all())
+        } else {
+            None
+        }
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl SuspendResume {
+    /// Create a new coordinator in the running state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a listener that must acknowledge future suspends.
+    pub fn listener(&self) -> SuspendListener {
+        let mut state = self.state.borrow_mut();
+        state.listeners += 1;
+
+        SuspendListener {
+            state: self.state.clone(),
+            suspend_seen: state.suspend_gen,
+            resume_seen: state.resume_gen,
+        }
+    }
+
+    /// Enter the suspended state and wait until every listener has acknowledged.
+    ///
+    /// The state changes as soon as this is called; the returned future only
+    /// waits. Listeners registered afterwards are not waited for.
+    pub fn suspend(&self) -> impl Future<Output = ()> + '_ {
+        let waiters = {
+            let mut state = self.state.borrow_mut();
+            state.suspended = true;
+            state.suspend_gen += 1;
+            state.pending_acks = state.listeners;
+            state.listener_waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+
+        AllAcked {
+            state: &self.state,
+            key: None,
+        }
+    }
+
+    /// Leave the suspended state and wake listeners waiting in `resumed()`.
+    ///
+    /// Any `suspend()` still waiting for acknowledgements completes.
+    pub fn resume(&self) {
+        let waiters = {
+            let mut state = self.state.borrow_mut();
+
+            if !state.suspended {
+                return;
+            }
+
+            state.suspended = false;
+            state.resume_gen += 1;
+            state.pending_acks = 0;
+
+            let notifiers = state.notifier_waiters.take_all();
+            state.listener_waiters.take_all().chain(notifiers)
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+
+    /// Whether the coordinator is currently suspended.
+    pub fn is_suspended(&self) -> bool {
+        self.state.borrow().suspended
+    }
+}
+
+struct AllAcked<'a> {
+    state: &'a TrackedRefCell<State>,
+    key: Option<u64>,
+}
+
+impl Future for AllAcked<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let state = self.state;
+        let mut state = state.borrow_mut();
+
+        if state.pending_acks == 0 {
+            state.notifier_waiters.remove(self.key.take());
+            return Poll::Ready(());
+        }
+
+        state.notifier_waiters.register(&mut self.key, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for AllAcked<'_> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.state.borrow_mut().notifier_waiters.remove(self.key);
+        }
+    }
+}
+
+/// A subsystem taking part in [`SuspendResume`] cycles.
+#[derive(Debug)]
+pub struct SuspendListener {
+    state: Rc<TrackedRefCell<State>>,
+    suspend_seen: u64,
+    resume_seen: u64,
+}
+
+impl SuspendListener {
+    /// Wait for the next suspend.
+    ///
+    /// The suspend is acknowledged when the returned [`SuspendAck`] is dropped.
+    pub async fn suspending(&mut self) -> SuspendAck {
+        let generation = Phase {
+            listener: self,
+            key: None,
+            resume: false,
+        }
+        .await;
+
+        SuspendAck {
+            state: self.state.clone(),
+            generation,
+        }
+    }
+
+    /// Wait for the next resume.
+    pub async fn resumed(&mut self) {
+        Phase {
+            listener: self,
+            key: None,
+            resume: true,
+        }
+        .await;
+    }
+}
+
+impl Drop for SuspendListener {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = self.state.borrow_mut();
+            state.listeners -= 1;
+
+            // A suspend we never observed still counts us; acknowledge it.
+            if self.suspend_seen != state.suspend_gen {
+                let generation = state.suspend_gen;
+                Some(state.ack(generation))
+            } else {
+                None
+            }
+        };
+
+        for waker in waiters.into_iter().flatten() {
+            waker.wake();
+        }
+    }
+}
+
+struct Phase<'a> {
+    listener: &'a mut SuspendListener,
+    key: Option<u64>,
+    resume: bool,
+}
+
+impl Future for Phase<'_> {
+    type Output = u64;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        let this = &mut *self;
+        let mut state = this.listener.state.borrow_mut();
+
+        if this.resume && state.resume_gen != this.listener.resume_seen {
+            this.listener.resume_seen = state.resume_gen;
+        } else if !this.resume && state.suspend_gen != this.listener.suspend_seen {
+            this.listener.suspend_seen = state.suspend_gen;
+        } else {
+            state.listener_waiters.register(&mut this.key, cx.waker());
+            return Poll::Pending;
+        }
+
+        state.listener_waiters.remove(this.key.take());
+
+        Poll::Ready(state.suspend_gen)
+    }
+}
+
+impl Drop for Phase<'_> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.listener
+                .state
+                .borrow_mut()
+                .listener_waiters
+                .remove(self.key);
+        }
+    }
+}
+
+/// Acknowledges a suspend when dropped.
+#[derive(Debug)]
+#[must_use = "the suspend is acknowledged as soon as this is dropped"]
+pub struct SuspendAck {
+    state: Rc<TrackedRefCell<State>>,
+    generation: u64,
+}
+
+impl Drop for SuspendAck {
+    fn drop(&mut self) {
+        let waiters = self.state.borrow_mut().ack(self.generation);
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn suspend_waits_for_every_ack() {
+        let coordinator = SuspendResume::new();
+        let mut a = coordinator.listener();
+        let mut b = coordinator.listener();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut suspend = pin!(coordinator.suspend());
+        assert!(coordinator.is_suspended());
+        assert!(suspend.as_mut().poll(&mut cx).is_pending());
+
+        let Poll::Ready(ack_a) = pin!(a.suspending()).poll(&mut cx) else {
+            panic!("suspend not observed");
+        };
+        let Poll::Ready(ack_b) = pin!(b.suspending()).poll(&mut cx) else {
+            panic!("suspend not observed");
+        };
+
+        drop(ack_a);
+        assert!(suspend.as_mut().poll(&mut cx).is_pending());
+
+        drop(ack_b);
+        assert_eq!(counter.count(), 1);
+        assert!(suspend.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn resume_wakes_listeners() {
+        let coordinator = SuspendResume::new();
+        let mut listener = coordinator.listener();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut resumed = pin!(listener.resumed());
+        assert!(resumed.as_mut().poll(&mut cx).is_pending());
+
+        // Resuming while running is a no-op.
+        coordinator.resume();
+        assert_eq!(counter.count(), 0);
+
+        drop(coordinator.suspend());
+        coordinator.resume();
+        assert!(!coordinator.is_suspended());
+        assert!(counter.count() >= 1);
+        assert!(resumed.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn dropped_listener_counts_as_acked() {
+        let coordinator = SuspendResume::new();
+        let listener = coordinator.listener();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut suspend = pin!(coordinator.suspend());
+        assert!(suspend.as_mut().poll(&mut cx).is_pending());
+
+        drop(listener);
+        assert!(suspend.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn stale_ack_does_not_count_for_next_suspend() {
+        let coordinator = SuspendResume::new();
+        let mut listener = coordinator.listener();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        drop(coordinator.suspend());
+        let Poll::Ready(stale) = pin!(listener.suspending()).poll(&mut cx) else {
+            panic!("suspend not observed");
+        };
+        coordinator.resume();
+
+        let mut suspend = pin!(coordinator.suspend());
+        drop(stale);
+        assert!(suspend.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn late_listener_is_not_waited_for() {
+        let coordinator = SuspendResume::new();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut suspend = pin!(coordinator.suspend());
+        let _late = coordinator.listener();
+        assert!(suspend.as_mut().poll(&mut cx).is_ready());
+    }
+}
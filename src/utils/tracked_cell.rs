@@ -0,0 +1,168 @@
+use std::{
+    cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut},
+    fmt,
+};
+
+#[cfg(feature = "track-borrows")]
+use std::{cell::Cell, panic::Location};
+
+/// A [`RefCell`] that reports where conflicting borrows were taken.
+///
+/// With the `track-borrows` feature enabled, the call sites of the latest
+/// mutable and shared borrows are recorded, and a failed [`borrow`] or
+/// [`borrow_mut`] panics naming both the conflicting site and the caller.
+/// Without the feature it is a plain [`RefCell`].
+///
+/// [`borrow`]: TrackedRefCell::borrow
+/// [`borrow_mut`]: TrackedRefCell::borrow_mut
+pub struct TrackedRefCell<T: ?Sized> {
+    #[cfg(feature = "track-borrows")]
+    shared_at: Cell<Option<&'static Location<'static>>>,
+    #[cfg(feature = "track-borrows")]
+    mut_at: Cell<Option<&'static Location<'static>>>,
+    inner: RefCell<T>,
+}
+
+impl<T> TrackedRefCell<T> {
+    /// Create a new cell holding `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            #[cfg(feature = "track-borrows")]
+            shared_at: Cell::new(None),
+            #[cfg(feature = "track-borrows")]
+            mut_at: Cell::new(None),
+            inner: RefCell::new(value),
+        }
+    }
+
+    /// Consume the cell, returning the wrapped value.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    /// Replace the wrapped value, returning the old one.
+    #[track_caller]
+    pub fn replace(&self, value: T) -> T {
+        std::mem::replace(&mut *self.borrow_mut(), value)
+    }
+}
+
+impl<T: ?Sized> TrackedRefCell<T> {
+    /// Immutably borrow the wrapped value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        match self.try_borrow() {
+            Ok(r) => r,
+            #[cfg(feature = "track-borrows")]
+            Err(_) => panic!("already mutably borrowed at {}", display(self.mut_at.get())),
+            #[cfg(not(feature = "track-borrows"))]
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Mutably borrow the wrapped value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    #[track_caller]
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(r) => r,
+            #[cfg(feature = "track-borrows")]
+            Err(_) => panic!(
+                "already borrowed (last shared borrow at {}, last mutable borrow at {})",
+                display(self.shared_at.get()),
+                display(self.mut_at.get())
+            ),
+            #[cfg(not(feature = "track-borrows"))]
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Immutably borrow the wrapped value, failing if it is mutably borrowed.
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        let r = self.inner.try_borrow()?;
+
+        #[cfg(feature = "track-borrows")]
+        self.shared_at.set(Some(Location::caller()));
+
+        Ok(r)
+    }
+
+    /// Mutably borrow the wrapped value, failing if it is borrowed.
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
+        let r = self.inner.try_borrow_mut()?;
+
+        #[cfg(feature = "track-borrows")]
+        self.mut_at.set(Some(Location::caller()));
+
+        Ok(r)
+    }
+
+    /// Mutable access through a unique reference, without any runtime check.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: Default> TrackedRefCell<T> {
+    /// Take the wrapped value, leaving `Default::default()` in its place.
+    #[track_caller]
+    pub fn take(&self) -> T {
+        self.replace(T::default())
+    }
+}
+
+impl<T: Default> Default for TrackedRefCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TrackedRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(feature = "track-borrows")]
+fn display(location: Option<&'static Location<'static>>) -> String {
+    location.map_or_else(|| "<unknown>".to_owned(), ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borrows_like_refcell() {
+        let cell = TrackedRefCell::new(vec![1]);
+
+        cell.borrow_mut().push(2);
+        {
+            let shared = cell.borrow();
+            assert!(cell.try_borrow().is_ok());
+            assert!(cell.try_borrow_mut().is_err());
+            assert_eq!(*shared, [1, 2]);
+        }
+
+        assert_eq!(cell.replace(vec![3]), [1, 2]);
+        assert_eq!(cell.take(), [3]);
+        assert!(cell.into_inner().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn conflicting_borrow_panics() {
+        let cell = TrackedRefCell::new(0);
+        let _held = cell.borrow_mut();
+        let _ = cell.borrow();
+    }
+}
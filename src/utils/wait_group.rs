@@ -0,0 +1,176 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+/// Waits for a set of tasks to finish, e.g. before releasing a lock and
+/// exiting.
+///
+/// Each task holds a [`Worker`] guard; [`wait`](Self::wait) resolves once
+/// every guard has been dropped. Clones of the group share the same count.
+#[derive(Debug, Clone, Default)]
+pub struct WaitGroup {
+    shared: Rc<TrackedRefCell<Shared>>,
+}
+
+/// Keeps a [`WaitGroup`] from completing while alive.
+///
+/// Cloning adds another worker; dropping removes one.
+#[derive(Debug)]
+pub struct Worker {
+    shared: Rc<TrackedRefCell<Shared>>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    count: usize,
+    /// Bumped whenever the count drops to zero.
+    epoch: u64,
+    waiters: WaiterList,
+}
+
+impl WaitGroup {
+    /// Create a group with no workers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a worker.
+    pub fn worker(&self) -> Worker {
+        self.shared.borrow_mut().count += 1;
+
+        Worker {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Number of live workers.
+    pub fn count(&self) -> usize {
+        self.shared.borrow().count
+    }
+
+    /// Wait until no workers are left.
+    ///
+    /// Resolves immediately if there are none. Once the count has dropped
+    /// to zero, workers added afterwards do not hold this wait back.
+    pub fn wait(&self) -> impl Future<Output = ()> + '_ {
+        Wait {
+            shared: &self.shared,
+            epoch: None,
+            key: None,
+        }
+    }
+}
+
+impl Clone for Worker {
+    fn clone(&self) -> Self {
+        self.shared.borrow_mut().count += 1;
+
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut shared = self.shared.borrow_mut();
+            shared.count -= 1;
+
+            if shared.count > 0 {
+                return;
+            }
+
+            shared.epoch += 1;
+            shared.waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+struct Wait<'a> {
+    shared: &'a TrackedRefCell<Shared>,
+    /// The epoch seen on the first poll.
+    epoch: Option<u64>,
+    key: Option<u64>,
+}
+
+impl Future for Wait<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let shared = self.shared;
+        let mut shared = shared.borrow_mut();
+        let epoch = *self.epoch.get_or_insert(shared.epoch);
+
+        if shared.count == 0 || shared.epoch != epoch {
+            shared.waiters.remove(self.key.take());
+            return Poll::Ready(());
+        }
+
+        shared.waiters.register(&mut self.key, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.shared.borrow_mut().waiters.remove(self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn waits_for_every_worker() {
+        let group = WaitGroup::new();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let a = group.worker();
+        let b = a.clone();
+        assert_eq!(group.count(), 2);
+
+        let mut wait = pin!(group.wait());
+        assert!(wait.as_mut().poll(&mut cx).is_pending());
+
+        drop(a);
+        assert_eq!(counter.count(), 0);
+        drop(b);
+
+        assert_eq!(counter.count(), 1);
+        assert!(wait.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn later_workers_do_not_hold_back_a_finished_wait() {
+        let group = WaitGroup::new();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let worker = group.worker();
+        let mut wait = pin!(group.wait());
+        assert!(wait.as_mut().poll(&mut cx).is_pending());
+
+        drop(worker);
+        let _late = group.worker();
+
+        assert!(wait.as_mut().poll(&mut cx).is_ready());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::BTreeMap, task::Waker};
+
+/// A FIFO list of parked tasks.
+///
+/// Each waiting future keeps an `Option<u64>` key. Keys are handed out in
+/// increasing order, so a future that re-registers with its old key after
+/// a wakeup keeps its place in the queue.
+#[derive(Debug, Default)]
+pub(crate) struct WaiterList {
+    next: u64,
+    waiters: BTreeMap<u64, Waker>,
+}
+
+impl WaiterList {
+    pub(crate) const fn new() -> Self {
+        Self {
+            next: 0,
+            waiters: BTreeMap::new(),
+        }
+    }
+
+    /// Register `waker` under `key`, allocating a key on first use.
+    ///
+    /// A still-queued waker is only replaced if it would wake a different task.
+    pub(crate) fn register(&mut self, key: &mut Option<u64>, waker: &Waker) {
+        let k = *key.get_or_insert_with(|| {
+            self.next += 1;
+            self.next
+        });
+
+        match self.waiters.get_mut(&k) {
+            Some(old) if old.will_wake(waker) => {}
+            Some(old) => *old = waker.clone(),
+            None => {
+                self.waiters.insert(k, waker.clone());
+            }
+        }
+    }
+
+    /// Remove the waiter registered under `key`, if it is still queued.
+    ///
+    /// Returns whether it was queued.
+    pub(crate) fn remove(&mut self, key: Option<u64>) -> bool {
+        key.is_some_and(|k| self.waiters.remove(&k).is_some())
+    }
+
+    /// Whether anyone queued before `key` is still waiting.
+    ///
+    /// A future without a key is behind everyone.
+    pub(crate) fn has_waiters_before(&self, key: Option<u64>) -> bool {
+        match (self.waiters.keys().next(), key) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(&first), Some(k)) => first < k,
+        }
+    }
+
+    /// Dequeue the longest-waiting waker.
+    ///
+    /// The caller should wake it after releasing any borrow of the list.
+    pub(crate) fn pop(&mut self) -> Option<Waker> {
+        self.waiters.pop_first().map(|(_, waker)| waker)
+    }
+
+    /// Dequeue all wakers in FIFO order.
+    pub(crate) fn take_all(&mut self) -> impl Iterator<Item = Waker> + use<> {
+        std::mem::take(&mut self.waiters).into_values()
+    }
+
+    /// Whether nobody is waiting.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+
+    #[test]
+    fn serves_in_fifo_order_and_keeps_place_on_reregister() {
+        let (a, b) = (CountingWaker::new(), CountingWaker::new());
+        let mut list = WaiterList::new();
+        let (mut key_a, mut key_b) = (None, None);
+
+        list.register(&mut key_a, &a.waker());
+        list.register(&mut key_b, &b.waker());
+        list.register(&mut key_a, &a.waker());
+
+        assert!(!list.has_waiters_before(key_a));
+        assert!(list.has_waiters_before(key_b));
+        assert!(list.has_waiters_before(None));
+
+        assert!(!list.is_empty());
+        list.pop().unwrap().wake();
+        assert_eq!(a.count(), 1);
+        list.pop().unwrap().wake();
+        assert_eq!(b.count(), 1);
+        assert!(list.pop().is_none());
+    }
+
+    #[test]
+    fn reregistering_replaces_a_different_waker() {
+        let (old, new) = (CountingWaker::new(), CountingWaker::new());
+        let mut list = WaiterList::new();
+        let mut key = None;
+
+        list.register(&mut key, &old.waker());
+        list.register(&mut key, &new.waker());
+
+        for waker in list.take_all() {
+            waker.wake();
+        }
+        assert_eq!((old.count(), new.count()), (0, 1));
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn remove_reports_whether_still_queued() {
+        let counter = CountingWaker::new();
+        let mut list = WaiterList::new();
+        let mut key = None;
+
+        assert!(!list.remove(None));
+        list.register(&mut key, &counter.waker());
+        assert!(list.remove(key));
+        assert!(!list.remove(key));
+        assert!(list.is_empty());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::utils::{tracked_cell::TrackedRefCell, waiters::WaiterList};
+use std::{
+    cell::Ref,
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+/// Create a single-threaded channel broadcasting the latest value.
+///
+/// Every [`Sender::send`] overwrites the stored value and bumps a version;
+/// each [`Receiver`] tracks the last version it has seen, so slow
+/// receivers skip intermediate values but never miss that a change happened.
+///
+/// Wakeups coalesce on their own: a send wakes the receivers waiting at
+/// that point and unregisters them, so further sends before they run wake
+/// nobody, and each receiver is woken once per burst however many values
+/// the burst holds.
+pub fn channel<T>(initial: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(Shared {
+        value: TrackedRefCell::new(initial),
+        state: TrackedRefCell::new(State {
+            version: 0,
+            waiters: WaiterList::default(),
+            sender_alive: true,
+        }),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            seen: 0,
+            key: None,
+        },
+    )
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    value: TrackedRefCell<T>,
+    state: TrackedRefCell<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    version: u64,
+    waiters: WaiterList,
+    sender_alive: bool,
+}
+
+impl<T> Shared<T> {
+    fn version(&self) -> u64 {
+        self.state.borrow().version
+    }
+}
+
+/// The sending half of a watch [`channel`].
+#[derive(Debug)]
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Replace the value and notify all receivers.
+    pub fn send(&self, value: T) {
+        self.send_modify(|old| *old = value);
+    }
+
+    /// Modify the value in place and notify all receivers.
+    pub fn send_modify(&self, modify: impl FnOnce(&mut T)) {
+        modify(&mut self.shared.value.borrow_mut());
+
+        let waiters = {
+            let mut state = self.shared.state.borrow_mut();
+            state.version += 1;
+            state.waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+
+    /// Borrow the current value.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.shared.value.borrow()
+    }
+
+    /// Create a new receiver that has seen the current value.
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.shared.version(),
+            key: None,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = self.shared.state.borrow_mut();
+            state.sender_alive = false;
+            state.waiters.take_all()
+        };
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+/// The receiving half of a watch [`channel`].
+///
+/// Cloning a receiver yields one that has seen the same version.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    shared: Rc<Shared<T>>,
+    seen: u64,
+    /// Registration of [`poll_changed`](Self::poll_changed).
+    key: Option<u64>,
+}
+
+impl<T> Receiver<T> {
+    /// Borrow the current value without marking it as seen.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.shared.value.borrow()
+    }
+
+    /// Borrow the current value and mark it as seen.
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        self.seen = self.shared.version();
+        self.shared.value.borrow()
+    }
+
+    /// Whether a value newer than the last seen one has been sent.
+    pub fn has_changed(&self) -> bool {
+        self.shared.version() != self.seen
+    }
+
+    /// Wait for a value newer than the last seen one, and mark it as seen.
+    ///
+    /// Fails with [`Closed`] once the sender is dropped and every sent
+    /// value has been seen.
+    pub fn changed(&mut self) -> impl Future<Output = Result<(), Closed>> + '_ {
+        Changed {
+            rx: self,
+            key: None,
+        }
+    }
+
+    /// Poll-based [`changed`](Self::changed).
+    ///
+    /// Only the waker of the latest call is kept.
+    pub fn poll_changed(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Closed>> {
+        let mut key = self.key.take();
+        let result = self.poll_changed_with(&mut key, cx);
+        self.key = key;
+        result
+    }
+
+    fn poll_changed_with(
+        &mut self,
+        key: &mut Option<u64>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Closed>> {
+        let mut state = self.shared.state.borrow_mut();
+
+        if state.version != self.seen {
+            state.waiters.remove(key.take());
+            self.seen = state.version;
+            return Poll::Ready(Ok(()));
+        }
+
+        if !state.sender_alive {
+            state.waiters.remove(key.take());
+            return Poll::Ready(Err(Closed));
+        }
+
+        state.waiters.register(key, cx.waker());
+
+        Poll::Pending
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            seen: self.seen,
+            key: None,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.shared.state.borrow_mut().waiters.remove(self.key);
+        }
+    }
+}
+
+/// Yields a clone of the value each time it changes, ending once the
+/// sender is dropped.
+#[cfg(feature = "futures")]
+impl<T: Clone> futures_core::Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+
+        match this.poll_changed(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Some(this.borrow().clone())),
+            Poll::Ready(Err(Closed)) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+struct Changed<'a, T> {
+    rx: &'a mut Receiver<T>,
+    key: Option<u64>,
+}
+
+impl<T> Future for Changed<'_, T> {
+    type Output = Result<(), Closed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.rx.poll_changed_with(&mut this.key, cx)
+    }
+}
+
+impl<T> Drop for Changed<'_, T> {
+    fn drop(&mut self) {
+        if self.key.is_some() {
+            self.rx.shared.state.borrow_mut().waiters.remove(self.key);
+        }
+    }
+}
+
+/// Error returned by [`Receiver::changed`] once the sender is gone.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("watch sender dropped")
+    }
+}
+
+impl Error for Closed {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::CountingWaker;
+    use std::pin::pin;
+
+    #[test]
+    fn receivers_see_latest_value_once() {
+        let (tx, mut rx) = channel(0);
+        assert!(!rx.has_changed());
+
+        tx.send(1);
+        tx.send(2);
+        assert!(rx.has_changed());
+        assert_eq!(*rx.borrow_and_update(), 2);
+        assert!(!rx.has_changed());
+
+        let late = tx.subscribe();
+        assert!(!late.has_changed());
+    }
+
+    #[test]
+    fn changed_wakes_every_receiver() {
+        let (tx, mut a) = channel("old");
+        let mut b = a.clone();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut fa = pin!(a.changed());
+        let mut fb = pin!(b.changed());
+        assert!(fa.as_mut().poll(&mut cx).is_pending());
+        assert!(fb.as_mut().poll(&mut cx).is_pending());
+
+        tx.send_modify(|v| *v = "new");
+
+        assert_eq!(counter.count(), 2);
+        assert_eq!(fa.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(fb.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+    }
+
+    #[test]
+    fn burst_of_sends_wakes_each_receiver_once() {
+        let (tx, mut a) = channel(0);
+        let mut b = a.clone();
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(a.poll_changed(&mut cx).is_pending());
+        assert!(b.poll_changed(&mut cx).is_pending());
+
+        for i in 1..=10 {
+            tx.send(i);
+        }
+
+        assert_eq!(counter.count(), 2);
+        assert_eq!(a.poll_changed(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(*a.borrow(), 10);
+    }
+
+    #[test]
+    fn closed_only_after_last_value_is_seen() {
+        let (tx, mut rx) = channel(0);
+        let counter = CountingWaker::new();
+        let waker = counter.waker();
+        let mut cx = Context::from_waker(&waker);
+
+        tx.send(1);
+        drop(tx);
+
+        assert_eq!(rx.poll_changed(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(*rx.borrow(), 1);
+        assert_eq!(rx.poll_changed(&mut cx), Poll::Ready(Err(Closed)));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::time::{self, sleep_until};
+use std::{
+    cell::Cell,
+    env, io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    time::{Duration, Instant},
+};
+
+/// Detects a loop that stopped making progress.
+///
+/// The loop calls [`pet`](Self::pet) whenever it gets something done, and a
+/// separate task awaits [`starved`](Self::starved), which resolves once
+/// `timeout` passes without a pet. That task can then log, dump state or
+/// restart whatever is stuck. Only a loop that still runs tasks can notice
+/// this, so the watchdog catches stuck work, not a blocked thread; for
+/// that, let systemd watch through [`from_systemd`](Self::from_systemd).
+#[derive(Debug)]
+pub struct Watchdog {
+    timeout: Duration,
+    last_pet: Cell<Instant>,
+    systemd: Option<Systemd>,
+}
+
+/// The service manager's watchdog, fed by [`Watchdog::pet`].
+#[derive(Debug)]
+struct Systemd {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    last_sent: Cell<Option<Instant>>,
+}
+
+impl Watchdog {
+    /// Consider the loop starved after `timeout` without a pet.
+    ///
+    /// The clock starts now, as if just petted.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_pet: Cell::new(time::now()),
+            systemd: None,
+        }
+    }
+
+    /// A watchdog whose pets also keep systemd's service watchdog happy.
+    ///
+    /// Takes the timeout from `WATCHDOG_USEC` and sends `WATCHDOG=1` to
+    /// `NOTIFY_SOCKET`, at most every half timeout. Returns `None` if the
+    /// service has no watchdog enabled, or it is meant for another process
+    /// as told by `WATCHDOG_PID`.
+    pub fn from_systemd() -> io::Result<Option<Self>> {
+        if let Some(pid) = env::var_os("WATCHDOG_PID")
+            && pid.to_str() != Some(&std::process::id().to_string())
+        {
+            return Ok(None);
+        }
+
+        let (Some(usec), Some(path)) = (env::var_os("WATCHDOG_USEC"), env::var_os("NOTIFY_SOCKET"))
+        else {
+            return Ok(None);
+        };
+
+        let usec: u64 = usec
+            .to_str()
+            .and_then(|usec| usec.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid WATCHDOG_USEC"))?;
+
+        let addr = match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+
+        Self::with_systemd(Duration::from_micros(usec), addr).map(Some)
+    }
+
+    fn with_systemd(timeout: Duration, addr: SocketAddr) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.set_nonblocking(true)?;
+
+        let watchdog = Self {
+            systemd: Some(Systemd {
+                socket,
+                addr,
+                last_sent: Cell::new(None),
+            }),
+            ..Self::new(timeout)
+        };
+
+        watchdog.pet();
+        Ok(watchdog)
+    }
+
+    /// Record that the loop made progress.
+    ///
+    /// With systemd integration this may send a notification; failures to
+    /// send are ignored, as systemd acts on missing ones anyway.
+    pub fn pet(&self) {
+        let now = time::now();
+        self.last_pet.set(now);
+
+        if let Some(systemd) = &self.systemd {
+            let due = systemd
+                .last_sent
+                .get()
+                .is_none_or(|sent| now.saturating_duration_since(sent) >= self.timeout / 2);
+
+            if due {
+                systemd.last_sent.set(Some(now));
+                let _ = systemd.socket.send_to_addr(b"WATCHDOG=1", &systemd.addr);
+            }
+        }
+    }
+
+    /// Wait until `timeout` passes without a pet.
+    ///
+    /// Resolves right away if that has already happened. Awaiting it again
+    /// after a pet waits for the next starvation.
+    pub async fn starved(&self) {
+        loop {
+            let deadline = self.last_pet.get() + self.timeout;
+
+            if time::now() >= deadline {
+                return;
+            }
+
+            sleep_until(deadline).await;
+        }
+    }
+
+    /// Time since the last pet.
+    pub fn elapsed(&self) -> Duration {
+        time::now().saturating_duration_since(self.last_pet.get())
+    }
+
+    /// The starvation timeout.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{LocalExecutor, spawn_local},
+        test_util::TempDir,
+        time::sleep,
+    };
+    use std::rc::Rc;
+
+    #[test]
+    fn starved_waits_for_missing_pets() {
+        LocalExecutor::new().block_on(async {
+            let watchdog = Rc::new(Watchdog::new(Duration::from_millis(20)));
+
+            let petter = spawn_local({
+                let watchdog = watchdog.clone();
+                async move {
+                    for _ in 0..4 {
+                        sleep(Duration::from_millis(10)).await;
+                        watchdog.pet();
+                    }
+                }
+            });
+
+            let started = Instant::now();
+            watchdog.starved().await;
+            assert!(started.elapsed() >= Duration::from_millis(55));
+            assert!(watchdog.elapsed() >= watchdog.timeout());
+            petter.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn pets_notify_systemd() {
+        let dir = TempDir::new();
+        let path = dir.join("notify");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager.set_nonblocking(true).unwrap();
+
+        LocalExecutor::new().block_on(async {
+            let addr = SocketAddr::from_pathname(&path).unwrap();
+            let watchdog = Watchdog::with_systemd(Duration::from_millis(20), addr).unwrap();
+
+            let mut buf = [0; 16];
+            let n = manager.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..n], b"WATCHDOG=1");
+
+            // Too soon after the last notification.
+            watchdog.pet();
+            assert!(manager.recv(&mut buf).is_err());
+
+            sleep(Duration::from_millis(12)).await;
+            watchdog.pet();
+            let n = manager.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..n], b"WATCHDOG=1");
+        });
+    }
+}